@@ -7,6 +7,7 @@ mod macros;
 pub mod command_request;
 pub mod command_response;
 pub mod handler;
+pub mod memory_region;
 pub mod meta_report;
 pub mod send;
 
@@ -1,6 +1,7 @@
 use super::EmulatorCsrsHandler;
 use super::command_request::EmulatorRegistersCommandRequestHandler;
 use super::command_response::EmulatorRegistersCommandResponseHandler;
+use super::memory_region::EmulatorRegisterMemoryRegionKeyIndexBitsHandler;
 use super::meta_report::EmulatorRegistersMetaReportHandler;
 use super::reset::EmulatorRegisterResetHandler;
 use super::send::EmulatorRegistersSendHandler;
@@ -28,4 +29,8 @@ impl<UA: Agent, DC: Client> ControlStatusRegisters for EmulatorCsrsHandler<'_, U
     fn reset(&self) -> impl csr::RegisterReset {
         EmulatorRegisterResetHandler::new(&self.csrs.reset, self.dev)
     }
+
+    fn mr_key_index_bits(&self) -> impl csr::RegisterMemoryRegionKeyIndexBits {
+        EmulatorRegisterMemoryRegionKeyIndexBitsHandler::new(self.dev)
+    }
 }
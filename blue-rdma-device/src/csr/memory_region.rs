@@ -0,0 +1,42 @@
+use super::Agent;
+use crate::DeviceInner;
+use crate::device_api::csr::{RegisterMemoryRegionKeyIndexBits, RegisterOperation};
+use crate::dma::Client;
+
+/// Read only, the number of key indexes of the memory region table
+pub const REGISTERS_MR_TABLE_SIZE: u64 = 0x0002_0004;
+/// Read only, the number of page table entries
+pub const REGISTERS_PAGE_TABLE_SIZE: u64 = 0x0002_0008;
+/// The number of high bits of a key, which index the memory region table
+pub const REGISTERS_MR_KEY_INDEX_BITS: u64 = 0x0002_000C;
+
+/// The key layout is kept by the memory region table, which resolves the keys
+pub struct EmulatorRegisterMemoryRegionKeyIndexBitsHandler<'h, UA: Agent, DC: Client> {
+    dev: &'h DeviceInner<UA, DC>,
+}
+
+impl<'h, UA: Agent, DC: Client> EmulatorRegisterMemoryRegionKeyIndexBitsHandler<'h, UA, DC> {
+    pub const fn new<'d>(dev: &'d DeviceInner<UA, DC>) -> Self
+    where
+        'd: 'h,
+    {
+        Self { dev }
+    }
+}
+
+impl<UA: Agent, DC: Client> RegisterMemoryRegionKeyIndexBits
+    for EmulatorRegisterMemoryRegionKeyIndexBitsHandler<'_, UA, DC>
+{
+}
+impl<UA: Agent, DC: Client> RegisterOperation for EmulatorRegisterMemoryRegionKeyIndexBitsHandler<'_, UA, DC> {
+    type Output = u32;
+
+    fn read(&self) -> Self::Output {
+        self.dev.memory_region_table().key_index_bits()
+    }
+
+    fn write(&self, val: Self::Output) {
+        log::trace!("Write memory region key index bits {val}");
+        self.dev.memory_region_table().set_key_index_bits(val);
+    }
+}
@@ -8,6 +8,7 @@ pub trait ControlStatusRegisters {
     fn meta_report(&self) -> impl csr::RegistersMetaReport;
     fn send(&self) -> impl csr::RegistersSend;
     fn reset(&self) -> impl csr::RegisterReset;
+    fn mr_key_index_bits(&self) -> impl csr::RegisterMemoryRegionKeyIndexBits;
 }
 
 pub trait RawDevice {
@@ -38,3 +38,4 @@ pub trait RegistersCommandResponse: RegistersQueue {}
 pub trait RegistersSend: RegistersQueue {}
 pub trait RegistersMetaReport: RegistersQueue {}
 pub trait RegisterReset: RegisterOperation<Output = u32> {}
+pub trait RegisterMemoryRegionKeyIndexBits: RegisterOperation<Output = u32> {}
@@ -1,8 +1,13 @@
 //! Blue Rdma Emulator implementation
 
-use core::net::Ipv4Addr;
-use core::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use core::net::{IpAddr, Ipv4Addr};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
 
 use eui48::MacAddress;
 use flume::{Receiver, Sender};
@@ -10,17 +15,35 @@ use flume::{Receiver, Sender};
 use super::address::DmaAddress;
 use super::csr::{EmulatorCsrs, EmulatorCsrsHandler};
 use super::device_api::{ControlStatusRegisters, RawDevice};
-use super::mr_table::{self, MemoryRegionTable};
-use super::{dma, memory_region, net, queue_pair};
+use super::mr_table::{self, MemoryRegionTable, Translation};
+use super::queue_pair::ReadBuffer;
+use super::{dma, memory_region, net, queue_pair, shared_receive_queue};
 use crate::address::VirtualAddress;
 use crate::dma::PointerMut;
-use crate::third_party::net::{Metadata, PacketProcessor, RdmaMessage};
+use crate::errors::Error;
+use crate::third_party::net::{AtomicEthHeader, Metadata, PacketProcessor, RdmaMessage, SGListElement};
+use crate::types::{MemoryAccessFlag, MemoryRegionKey};
+
+/// How often the receiving thread checks the stop signal before the network is set
+const NET_PARAMETER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a parked request waits for the driver to resolve its page fault
+pub(crate) const PAGE_FAULT_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug)]
 enum State {
     NotReady,
 }
 
+/// A packet waiting for the driver to resolve its page fault
+struct ParkedPacket {
+    qpn: u32,
+    buf: Vec<u8>,
+    len: usize,
+    src: IpAddr,
+    deadline: Instant,
+}
+
 #[derive(Debug)]
 pub struct NetParameter {
     pub ip: Ipv4Addr,
@@ -61,6 +84,9 @@ where
     /// Thread Stop signal, may move out of this structure if I change this structure into `EmulatorInner`
     pub(crate) stop: AtomicBool,
 
+    /// Threads started by `start_net` and `start_work_queue`, joined by `shutdown`
+    handlers: Mutex<Vec<JoinHandle<()>>>,
+
     /// Emulator State
     #[expect(unused, reason = "may use later")]
     state: State,
@@ -68,15 +94,25 @@ where
     /// Memory Region Table (Key -> Context)
     pub(crate) mr_table: MRT,
 
-    /// Page Table (index -> Vec<DmaAddress>)
-    pub(crate) page_table: papaya::HashMap<u32, Vec<DmaAddress>>,
+    /// Page Table (index -> DmaAddress)
+    pub(crate) page_table: papaya::HashMap<u32, DmaAddress>,
 
     /// Queue Pair Table (QPN -> Context)
     pub(crate) qp_table: queue_pair::Table,
 
+    /// Shared Receive Queue Table (SRQN -> Context)
+    pub(crate) srq_table: shared_receive_queue::Table,
+
     pub(crate) tx_command_request: Sender<()>,
     pub(crate) rx_command_request: Receiver<()>,
 
+    /// Page faults reported to the driver by the command request thread, which owns the command response queue
+    pub(crate) tx_page_fault: Sender<(MemoryRegionKey, VirtualAddress)>,
+    pub(crate) rx_page_fault: Receiver<(MemoryRegionKey, VirtualAddress)>,
+
+    /// Notified whenever the page table is updated, which may resolve the page faults of parked requests
+    page_table_watchers: Mutex<Vec<Sender<()>>>,
+
     pub(crate) tx_send: Sender<()>,
     pub(crate) rx_send: Receiver<()>,
 }
@@ -85,6 +121,7 @@ impl<UA: net::Agent, DC: dma::Client, MRT: MemoryRegionTable> DeviceInner<UA, DC
     pub fn new(dma_client: DC, mr_table: MRT) -> Self {
         let (tx_command_request, rx_command_request) = flume::unbounded();
         let (tx_send, rx_send) = flume::unbounded();
+        let (tx_page_fault, rx_page_fault) = flume::unbounded();
         Self {
             udp_agent: Default::default(),
             net_parameter: Default::default(),
@@ -93,9 +130,14 @@ impl<UA: net::Agent, DC: dma::Client, MRT: MemoryRegionTable> DeviceInner<UA, DC
             csrs: EmulatorCsrs::default(),
             state: State::NotReady,
             stop: AtomicBool::default(),
+            handlers: Mutex::default(),
             qp_table: Default::default(),
+            srq_table: Default::default(),
             tx_command_request,
             rx_command_request,
+            tx_page_fault,
+            rx_page_fault,
+            page_table_watchers: Mutex::default(),
             tx_send,
             rx_send,
             page_table: papaya::HashMap::new(),
@@ -109,6 +151,59 @@ impl<UA: net::Agent, DC: dma::Client, MRT: MemoryRegionTable> DeviceInner<UA, DC
     pub(crate) const fn queue_pair_table(&self) -> &queue_pair::Table {
         &self.qp_table
     }
+
+    /// subscribe to the updates of page table
+    pub(crate) fn watch_page_table(&self) -> Receiver<()> {
+        let (tx, rx) = flume::unbounded();
+        self.page_table_watchers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// notify the watchers of page table, the dropped ones are forgotten
+    pub(crate) fn notify_page_table_updated(&self) {
+        self.page_table_watchers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(()).is_ok());
+    }
+
+    pub(crate) const fn shared_receive_queue_table(&self) -> &shared_receive_queue::Table {
+        &self.srq_table
+    }
+
+    /// get the shared receive queue the queue pair is attached to
+    pub(crate) fn shared_receive_queue_of<'guard>(
+        &self,
+        qp_context: &queue_pair::Context,
+        guard: &'guard impl papaya::Guard,
+    ) -> Option<&'guard shared_receive_queue::Context> {
+        qp_context
+            .srqn()
+            .and_then(|srqn| self.shared_receive_queue_table().get(srqn, guard))
+    }
+
+    /// Stop the emulator and join all threads it started.
+    ///
+    /// Requests left in the queues are dropped. It must not be called by the threads of the emulator.
+    pub fn shutdown(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // wake up the work queue threads to see the stop signal
+        let _ = self.tx_command_request.send(());
+        let _ = self.tx_send.send(());
+        self.notify_page_table_updated();
+
+        let handlers = core::mem::take(&mut *self.handlers.lock().unwrap());
+        for handler in handlers {
+            if handler.join().is_err() {
+                log::error!("emulator thread panicked");
+            }
+        }
+        log::info!("emulator is stopped");
+    }
+
+    fn keep_handler(&self, handler: JoinHandle<()>) {
+        self.handlers.lock().unwrap().push(handler);
+    }
 }
 
 impl<UA, DC> DeviceInner<UA, DC>
@@ -125,55 +220,147 @@ where
         let _ = self.net_parameter.get_or_init(move || tx_net_para);
 
         let (tx, rx) = flume::unbounded();
-        // TODO(fh): Store this handler properly
-        let _handler_recv = std::thread::spawn(move || {
-            let Ok(para) = rx_net_para.recv() else {
-                return;
+        let handler_recv = std::thread::spawn(move || {
+            let para = loop {
+                match rx_net_para.recv_timeout(NET_PARAMETER_POLL_INTERVAL) {
+                    Ok(para) => break para,
+                    Err(flume::RecvTimeoutError::Timeout) if !dev.stop.load(Ordering::Relaxed) => {}
+                    Err(_) => return,
+                }
             };
             log::info!("network started with para: {para:?}");
             let udp_agent = f(para);
             let _ = dev.udp_agent.get_or_init(move || udp_agent);
 
-            while !dev.stop.load(core::sync::atomic::Ordering::Relaxed) {
+            while !dev.stop.load(Ordering::Relaxed) {
                 // TODO(fh): Alloc buffer from MemoryPool.
                 let mut buf = vec![0u8; 8192];
-                let (len, src) = dev.udp_agent.get().unwrap().recv_from(&mut buf).expect("recv error");
-
-                let ok = tx.send((buf, len, src)).is_ok();
-                assert!(ok);
+                let (len, src) = match dev.udp_agent.get().unwrap().recv_from(&mut buf) {
+                    Ok(res) => res,
+                    Err(net::Error::Io(e))
+                        if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) =>
+                    {
+                        continue;
+                    }
+                    Err(e) => {
+                        log::error!("network is stopped due to: {e}");
+                        return;
+                    }
+                };
+
+                if tx.send((buf, len, src)).is_err() {
+                    return;
+                }
             }
         });
+        self.keep_handler(handler_recv);
 
         let dev = Arc::clone(self);
-        let _handler_packet = std::thread::spawn(move || {
-            while let Ok((buf, len, src)) = rx.recv() {
-                let msg = PacketProcessor::to_rdma_message(&buf[..len]).unwrap();
-                log::debug!("receive data {msg:?} from {src:?}");
-
-                dev.handle_message(&msg, src)
-                    .unwrap_or_else(|_| panic!("handle message error: {msg:?}"));
+        let handler_packet = std::thread::spawn(move || {
+            let page_table_updated = dev.watch_page_table();
+            // in arrival order, so the first one expires first
+            let mut parked = VecDeque::<ParkedPacket>::new();
+            loop {
+                let packet = if let Some(first) = parked.front() {
+                    let deadline = first.deadline;
+                    flume::Selector::new()
+                        .recv(&rx, |packet| packet.map(Some))
+                        .recv(&page_table_updated, |_| Ok(None))
+                        .wait_deadline(deadline)
+                        .unwrap_or(Ok(None))
+                } else {
+                    // nothing waits for the updates received so far
+                    let _ = page_table_updated.drain();
+                    rx.recv().map(Some)
+                };
+                match packet {
+                    Ok(Some((buf, len, src))) => {
+                        let deadline = Instant::now() + PAGE_FAULT_TIMEOUT;
+                        dev.handle_packet(buf, len, src, deadline, &mut parked);
+                    }
+                    // the page table is updated or the first parked packet expires
+                    Ok(None) => {
+                        for ParkedPacket {
+                            buf,
+                            len,
+                            src,
+                            deadline,
+                            ..
+                        } in core::mem::take(&mut parked)
+                        {
+                            dev.handle_packet(buf, len, src, deadline, &mut parked);
+                        }
+                    }
+                    Err(_) => return,
+                }
             }
         });
+        self.keep_handler(handler_packet);
     }
 
     pub fn start_work_queue(self: &Arc<Self>) {
         let dev = Arc::clone(self);
-        // TODO(fh): Store this handler properly
-        let _handler_command_request = std::thread::spawn(move || {
+        let handler_command_request = std::thread::spawn(move || {
             // let _ = dev.queues_are_initialized.wait();
             dev.command_request_queue().run();
         });
+        self.keep_handler(handler_command_request);
         let dev = Arc::clone(self);
-        let _handler_send = std::thread::spawn(move || {
+        let handler_send = std::thread::spawn(move || {
             // let _ = dev.queues_are_initialized.wait();
             dev.send_queue().run();
         });
+        self.keep_handler(handler_send);
+    }
+}
+
+impl<UA: net::Agent, DC: dma::Client> DeviceInner<UA, DC> {
+    /// handle a packet, which is parked if it faults on a page, and is handled again once the page table is updated
+    ///
+    /// The packets of a QP are handled in order, so they are parked behind the parked one.
+    /// A bad packet is dropped like the network lost it, so that the peer may retry it.
+    fn handle_packet(
+        &self,
+        buf: Vec<u8>,
+        len: usize,
+        src: IpAddr,
+        deadline: Instant,
+        parked: &mut VecDeque<ParkedPacket>,
+    ) {
+        let msg = match PacketProcessor::to_rdma_message(&buf[..len]) {
+            Ok(msg) => msg,
+            Err(e) => {
+                log::error!("drop malformed packet from {src:?}: {e}");
+                return;
+            }
+        };
+        let qpn = msg.meta_data.common_meta().dqpn.get();
+        let packet = ParkedPacket {
+            qpn,
+            buf,
+            len,
+            src,
+            deadline,
+        };
+        if parked.iter().any(|parked| parked.qpn == qpn) {
+            parked.push_back(packet);
+            return;
+        }
+
+        log::debug!("receive data {msg:?} from {src:?}");
+        match self.handle_message(&msg, src) {
+            Ok(()) => {}
+            Err(Error::MemoryRegion(mr_table::Error::PageFault { .. })) if Instant::now() < deadline => {
+                parked.push_back(packet);
+            }
+            Err(e) => log::error!("drop packet failed to be handled: {e}, {msg:?}"),
+        }
     }
 }
 
 impl<UA: net::Agent, DC: dma::Client, MRT: MemoryRegionTable> Drop for DeviceInner<UA, DC, MRT> {
     fn drop(&mut self) {
-        self.stop.store(true, core::sync::atomic::Ordering::Relaxed);
+        self.stop.store(true, Ordering::Relaxed);
     }
 }
 
@@ -184,26 +371,191 @@ impl<UA: net::Agent, DC: dma::Client> RawDevice for DeviceInner<UA, DC> {
 }
 
 impl<UA: net::Agent, DC: dma::Client> DeviceInner<UA, DC> {
-    // TODO(fh): refactor to `copy_to_with_key(&self, src: &[u8], dst: &mut [u8], key: Key) -> super::Result`
-    // or `copy_to_with_key(&self, src: &[u8], dst: ScatterGatherElement) -> super::Result`
     pub(crate) fn copy_to_with_key(&self, msg: &RdmaMessage) -> Result<(), mr_table::Error> {
-        let data = &msg.payload.sg_list;
-        assert_eq!(data.len(), 1, "currently only consider one Sge");
-        let data = data[0];
-
         let Metadata::General(ref header) = msg.meta_data else {
             panic!("currently only consider write first and write last packet");
         };
         let key = header.reth.rkey.get().into();
-        let va = VirtualAddress(header.reth.va);
+        let mut va = VirtualAddress(header.reth.va);
         let access_flag = header.needed_permissions();
 
-        let dma_addr = self
-            .memory_region_table()
-            .query(key, va, access_flag, &self.page_table)?;
+        for &data in &msg.payload.sg_list {
+            self.copy_to(data, key, va, access_flag)?;
+            va = VirtualAddress(va.0 + data.len as u64);
+        }
+        Ok(())
+    }
+
+    /// copy read response payload into local buffers of the outstanding read
+    ///
+    /// Falls back to `copy_to_with_key` if the read has only one local buffer.
+    pub(crate) fn copy_read_response(&self, msg: &RdmaMessage, is_last: bool) -> Result<(), mr_table::Error> {
+        let common_meta = msg.meta_data.common_meta();
+        let qpn = common_meta.dqpn.get();
+        let msn = common_meta.pkey.get();
+
+        let guard = self.queue_pair_table().guard();
+        let qp_context = self.queue_pair_table().get(qpn, &guard);
+        let Some(buffers) = qp_context.and_then(|qp_context| qp_context.read_buffers(msn)) else {
+            return self.copy_to_with_key(msg);
+        };
 
-        let ptr = self.dma_client.with_dma_addr::<u8>(dma_addr);
-        unsafe { ptr.copy_from_nonoverlapping(data.data, data.len) };
+        let result = self.copy_to_read_buffers(msg, &buffers);
+        // a packet faulted on a page is handled again, which still needs the buffers
+        if is_last && !matches!(result, Err(mr_table::Error::PageFault { .. })) {
+            let _ = qp_context.and_then(|qp_context| qp_context.finish_read(msn));
+        }
+        result
+    }
+
+    fn copy_to_read_buffers(&self, msg: &RdmaMessage, buffers: &[ReadBuffer]) -> Result<(), mr_table::Error> {
+        let Metadata::General(ref header) = msg.meta_data else {
+            panic!("read response should be general message");
+        };
+        let access_flag = header.needed_permissions();
+        // reth va is the address of the first buffer plus the offset of the packet in message
+        let mut msg_offset = header.reth.va - buffers[0].addr.0;
+
+        for &data in &msg.payload.sg_list {
+            let mut offset = msg_offset;
+            let mut copied = 0;
+            for buffer in buffers {
+                if copied == data.len {
+                    break;
+                }
+                let buffer_len = u64::from(buffer.len);
+                if offset >= buffer_len {
+                    offset -= buffer_len;
+                    continue;
+                }
+                let chunk = (data.len - copied).min(usize::try_from(buffer_len - offset).unwrap());
+                let piece = SGListElement {
+                    // SAFETY: `copied + chunk` is not greater than `data.len`
+                    data: unsafe { data.data.add(copied) },
+                    len: chunk,
+                };
+                self.copy_to(piece, buffer.key, VirtualAddress(buffer.addr.0 + offset), access_flag)?;
+                copied += chunk;
+                offset = 0;
+            }
+            assert_eq!(copied, data.len, "read response is longer than local buffers");
+            msg_offset += data.len as u64;
+        }
         Ok(())
     }
+
+    /// translate `va` of the memory region identified by `key` into a dma address
+    ///
+    /// A page fault on an on-demand paging region is reported to the driver and returned, the caller parks the
+    /// request until the page table is updated, see `watch_page_table`.
+    pub(crate) fn translate(
+        &self,
+        key: MemoryRegionKey,
+        va: VirtualAddress,
+        access_flag: MemoryAccessFlag,
+    ) -> Result<Translation, mr_table::Error> {
+        let result = self.memory_region_table().query(key, va, access_flag, &self.page_table);
+        let Err(mr_table::Error::PageFault {
+            key: mr_key,
+            va: fault_va,
+        }) = result
+        else {
+            return result;
+        };
+
+        log::debug!("page fault: {fault_va:?} of {mr_key:?}");
+        if self.tx_page_fault.send((mr_key, fault_va)).is_ok() {
+            let _ = self.tx_command_request.send(());
+        }
+        result
+    }
+
+    /// translate `len` bytes starting at `va` piece by piece, each piece is physically continuous
+    ///
+    /// `f` is called with the offset of the piece from `va`, its dma address and its length.
+    pub(crate) fn for_each_dma_piece(
+        &self,
+        key: MemoryRegionKey,
+        va: VirtualAddress,
+        len: usize,
+        access_flag: MemoryAccessFlag,
+        mut f: impl FnMut(usize, DmaAddress, usize),
+    ) -> Result<(), mr_table::Error> {
+        let mut offset = 0;
+        while offset < len {
+            let translation = self.translate(key, VirtualAddress(va.0 + offset as u64), access_flag)?;
+            let piece = (len - offset).min(usize::try_from(translation.len).unwrap());
+            f(offset, translation.dma_addr, piece);
+            offset += piece;
+        }
+        Ok(())
+    }
+
+    /// same as `for_each_dma_piece`, but the calling thread is stalled on a page fault until the page table is
+    /// updated, or the fault is returned after `PAGE_FAULT_TIMEOUT`
+    pub(crate) fn for_each_dma_piece_blocking(
+        &self,
+        key: MemoryRegionKey,
+        va: VirtualAddress,
+        len: usize,
+        access_flag: MemoryAccessFlag,
+        mut f: impl FnMut(usize, DmaAddress, usize),
+    ) -> Result<(), mr_table::Error> {
+        let deadline = Instant::now() + PAGE_FAULT_TIMEOUT;
+        let mut page_table_updated: Option<Receiver<()>> = None;
+        loop {
+            match self.for_each_dma_piece(key, va, len, access_flag, &mut f) {
+                Err(mr_table::Error::PageFault { .. })
+                    if Instant::now() < deadline && !self.stop.load(Ordering::Relaxed) => {}
+                result => return result,
+            }
+            match page_table_updated {
+                Some(ref rx) => {
+                    let _ = rx.recv_deadline(deadline);
+                }
+                // the page table may be updated before watching it, so translate once more before waiting
+                None => page_table_updated = Some(self.watch_page_table()),
+            }
+        }
+    }
+
+    /// copy `data` into memory region identified by `key`, starting at `va`
+    pub(crate) fn copy_to(
+        &self,
+        data: SGListElement,
+        key: MemoryRegionKey,
+        va: VirtualAddress,
+        access_flag: MemoryAccessFlag,
+    ) -> Result<(), mr_table::Error> {
+        self.for_each_dma_piece(key, va, data.len, access_flag, |offset, dma_addr, piece| {
+            let ptr = self.dma_client.with_dma_addr::<u8>(dma_addr);
+            // SAFETY: the piece is within both the payload and a single page of the memory region
+            unsafe { ptr.copy_from_nonoverlapping(data.data.add(offset), piece) };
+        })
+    }
+
+    /// execute compare and swap or fetch and add on memory region, returns the original value
+    pub(crate) fn execute_atomic(&self, header: &AtomicEthHeader) -> Result<u64, mr_table::Error> {
+        let key = header.rkey.get().into();
+        let va = VirtualAddress(header.va);
+        // the operand is naturally aligned, so it never crosses a page
+        let dma_addr = self
+            .translate(key, va, MemoryAccessFlag::IbvAccessRemoteAtomic)?
+            .dma_addr;
+
+        let ptr = self.dma_client.with_dma_addr::<u64>(dma_addr);
+        // SAFETY: the address is aligned and in bound, which is checked by query.
+        // Packets are handled by a single thread, so no other request touches the value in between.
+        let orig = unsafe { ptr.read() };
+        let new = if header.is_compare_swap() {
+            (orig == header.compare).then_some(header.swap_add)
+        } else {
+            Some(orig.wrapping_add(header.swap_add))
+        };
+        if let Some(new) = new {
+            // SAFETY: same as above
+            unsafe { ptr.write(new) };
+        }
+        Ok(orig)
+    }
 }
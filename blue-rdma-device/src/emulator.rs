@@ -4,4 +4,5 @@ mod dma_client;
 mod net_agent;
 
 pub(crate) use dma_client::DmaClient;
+pub use net_agent::LocalNetwork;
 pub(crate) use net_agent::NetAgent;
@@ -1,7 +1,7 @@
 use core::net::IpAddr;
 use std::sync::Arc;
 
-use super::{DmaClient, NetAgent};
+use super::{DmaClient, LocalNetwork, NetAgent};
 use crate::Emulator;
 use crate::memory_region::Table;
 
@@ -17,4 +17,17 @@ impl Emulator {
 
         dev
     }
+
+    /// Create an emulator on a network in the process instead of a TUN device, see [`LocalNetwork`]
+    pub fn new_local(network: Arc<LocalNetwork>) -> Arc<Self> {
+        let dma_client = DmaClient;
+        let mr_table = Table::new();
+
+        let dev = Arc::new(Self::new(dma_client, mr_table));
+
+        dev.start_work_queue();
+        dev.start_net(move |para| NetAgent::new_local(para.ip.into(), network));
+
+        dev
+    }
 }
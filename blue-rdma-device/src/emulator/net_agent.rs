@@ -1,24 +1,47 @@
 use core::fmt;
 use core::net::IpAddr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use smoltcp::phy::ChecksumCapabilities;
 use smoltcp::wire::{IpProtocol, IpRepr, Ipv4Packet, Ipv6Packet, UdpPacket, UdpRepr};
 
 use crate::net::{self, RDMA_PORT};
 
+/// How long `recv_from` waits for a packet before returning `TimedOut`
+const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long `recv_from` sleeps when there is no packet
+const RECV_IDLE_INTERVAL: Duration = Duration::from_micros(50);
+
 pub struct NetAgent {
-    tun: tun::Device,
-    tun_ip: IpAddr,
+    link: Link,
 
     ip: IpAddr,
 }
 
+/// Where the IP packets of the emulator go
+enum Link {
+    /// A TUN device, whose peer address is the IP of the emulator
+    Tun {
+        tun: tun::Device,
+        tun_ip: IpAddr,
+    },
+    Local(Arc<LocalNetwork>),
+}
+
 impl fmt::Debug for NetAgent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("NetAgent")
-            .field("ip", &self.ip)
-            .field("tun", &self.tun_ip)
-            .finish_non_exhaustive()
+        let mut f = f.debug_struct("NetAgent");
+        let _ = f.field("ip", &self.ip);
+        match self.link {
+            Link::Tun { tun_ip, .. } => f.field("tun", &tun_ip),
+            Link::Local(ref network) => f.field("local", network),
+        }
+        .finish_non_exhaustive()
     }
 }
 
@@ -28,8 +51,21 @@ impl NetAgent {
         let mut config = tun::configure();
         let config = config.address(tun_ip).netmask(netmask).destination(ip).up();
         let tun = tun::create(config).unwrap();
+        // read without blocking, so that the receiving thread can be stopped
+        tun.set_nonblock().unwrap();
 
-        Self { tun, tun_ip, ip }
+        Self {
+            link: Link::Tun { tun, tun_ip },
+            ip,
+        }
+    }
+
+    pub fn new_local(ip: IpAddr, network: Arc<LocalNetwork>) -> Self {
+        log::info!("new local {ip}");
+        Self {
+            link: Link::Local(network),
+            ip,
+        }
     }
 
     fn parse_packet_and_extract_payload<'b>(&self, buffer: &'b [u8]) -> Result<(&'b [u8], IpAddr), net::Error> {
@@ -126,7 +162,7 @@ impl NetAgent {
 impl net::Agent for NetAgent {
     fn send_to(&self, buf: &[u8], addr: IpAddr) -> net::Result<usize> {
         let buffer = self.construct_frame(addr, buf);
-        let len = self.tun.send(&buffer)?;
+        let len = self.link.send(addr, buffer)?;
 
         // FIXME(fh): len is not send packet len
         Ok(len)
@@ -134,9 +170,20 @@ impl net::Agent for NetAgent {
 
     fn recv_from(&self, buf: &mut [u8]) -> net::Result<(usize, IpAddr)> {
         let mut buffer = vec![0u8; 8192];
+        let start = Instant::now();
         loop {
-            let len = self.tun.recv(&mut buffer)?;
-            log::trace!("tun recv {:?}", &buffer[..len]);
+            let len = match self.link.recv(self.ip, &mut buffer) {
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if start.elapsed() >= RECV_TIMEOUT {
+                        return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+                    }
+                    std::thread::sleep(RECV_IDLE_INTERVAL);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            log::trace!("recv {:?}", &buffer[..len]);
 
             let (payload, origin) = match self.parse_packet_and_extract_payload(&buffer[..len]) {
                 Ok(res) => res,
@@ -151,6 +198,62 @@ impl net::Agent for NetAgent {
     }
 }
 
+impl Link {
+    fn send(&self, dst: IpAddr, packet: Vec<u8>) -> io::Result<usize> {
+        match *self {
+            Self::Tun { ref tun, .. } => tun.send(&packet),
+            Self::Local(ref network) => {
+                let len = packet.len();
+                network
+                    .packets
+                    .lock()
+                    .unwrap()
+                    .entry(dst)
+                    .or_default()
+                    .push_back(packet);
+                Ok(len)
+            }
+        }
+    }
+
+    /// Returns `WouldBlock` if there is no packet to `ip`
+    fn recv(&self, ip: IpAddr, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Self::Tun { ref tun, .. } => tun.recv(buf),
+            Self::Local(ref network) => {
+                let packet = (!network.paused.load(Ordering::Acquire))
+                    .then(|| network.packets.lock().unwrap().get_mut(&ip)?.pop_front())
+                    .flatten()
+                    .ok_or(io::ErrorKind::WouldBlock)?;
+                let len = buf.len().min(packet.len());
+                buf[..len].copy_from_slice(&packet[..len]);
+                Ok(len)
+            }
+        }
+    }
+}
+
+/// Connects the emulators in the same process without TUN devices, each receives the packets sent to its IP in order.
+///
+/// The delivery can be paused to hold the packets in flight, such as to delay the response of a request.
+#[derive(Debug, Default)]
+pub struct LocalNetwork {
+    packets: Mutex<HashMap<IpAddr, VecDeque<Vec<u8>>>>,
+    paused: AtomicBool,
+}
+
+impl LocalNetwork {
+    /// Hold the packets not received yet, until `resume`
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Release);
+    }
+
+    /// Deliver the held packets, and the ones sent later
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Release);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::net::{Ipv4Addr, SocketAddr};
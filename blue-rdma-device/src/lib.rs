@@ -14,6 +14,7 @@ mod mr_table;
 mod net;
 mod queue_pair;
 mod queues;
+mod shared_receive_queue;
 mod types;
 
 pub use device_inner::DeviceInner;
@@ -1,18 +1,57 @@
-use papaya::HashMap;
+use core::hash::BuildHasher;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use papaya::{Guard, HashMap, HashMapRef};
 
 use super::address::{DmaAddress, VirtualAddress};
-use super::mr_table::MemoryRegionTable;
+use super::mr_table::{MemoryRegionTable, Translation};
 use super::types::{MemoryAccessFlag, MemoryRegionKey, ProtectDomainHandler};
 use crate::mr_table::Error;
 
+/// Size of the operand of remote atomic operations
+const ATOMIC_OPERAND_SIZE: u64 = 8;
+
+/// The sizes of the pages mapped by the page table in bits: 4KiB, 2MiB and 1GiB, each region picks one
+const PAGE_SIZE_BITS: [u32; 3] = [12, 21, 30];
+
+/// The number of key indexes, which are shared by the regions and the windows
+pub(crate) const TABLE_SIZE: u32 = 1 << 16;
+
+/// The number of page table entries, the offset of a region is a 17 bits field of the descriptor
+pub(crate) const PAGE_TABLE_SIZE: u32 = 1 << 17;
+
+/// The high bits of a key index the table, the rest is a secret checked on each access
+const DEFAULT_KEY_INDEX_BITS: u32 = 8;
+
+/// Memory windows only grant remote access
+const WINDOW_ACCESS_FLAG: MemoryAccessFlag = MemoryAccessFlag::IbvAccessRemoteRead
+    .union(MemoryAccessFlag::IbvAccessRemoteWrite)
+    .union(MemoryAccessFlag::IbvAccessRemoteAtomic);
+
+#[derive(Debug, PartialEq)]
+enum Kind {
+    Region {
+        page_table_offset: u32,
+        page_size_bits: u32,
+    },
+    /// A memory window bound to a sub-range of the parent memory region, which shares its page table
+    Window {
+        parent: MemoryRegionKey,
+        /// type 2 windows can be invalidated by the peer through SEND with invalidate
+        is_type2: bool,
+    },
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Context {
     addr: VirtualAddress,
     len: u32,
     key: MemoryRegionKey,
+    /// windows are checked against the protect domain of the parent by the driver, so it's zero for them
     protect_domain_handler: ProtectDomainHandler,
     access_flag: MemoryAccessFlag,
-    page_table_offset: u32,
+    kind: Kind,
 }
 
 impl Context {
@@ -23,6 +62,7 @@ impl Context {
         protect_domain_handler: u32,
         access_flag: MemoryAccessFlag,
         page_table_offset: u32,
+        page_size_bits: u32,
     ) -> Self {
         assert!(!addr.0.overflowing_add(len as u64).1);
         Self {
@@ -31,17 +71,133 @@ impl Context {
             key,
             protect_domain_handler,
             access_flag,
+            kind: Kind::Region {
+                page_table_offset,
+                page_size_bits,
+            },
+        }
+    }
+
+    pub(crate) const fn new_window(
+        addr: VirtualAddress,
+        len: u32,
+        key: MemoryRegionKey,
+        parent: MemoryRegionKey,
+        access_flag: MemoryAccessFlag,
+        is_type2: bool,
+    ) -> Self {
+        assert!(!addr.0.overflowing_add(len as u64).1);
+        Self {
+            addr,
+            len,
+            key,
+            protect_domain_handler: 0,
+            access_flag,
+            kind: Kind::Window { parent, is_type2 },
+        }
+    }
+
+    /// check that `va` is within the context, returns the offset of `va` from the start of the context
+    fn check_bound(&self, va: VirtualAddress, access_flag: MemoryAccessFlag) -> Result<u64, Error> {
+        let addr = self.addr;
+        let len = self.len;
+        // a zero based memory region is addressed by the offset from its start, both locally and remotely
+        let offset = if self.access_flag.contains(MemoryAccessFlag::IbvAccessZeroBased) {
+            Some(va.0)
+        } else {
+            va.0.checked_sub(addr.0)
+        };
+        let Some(offset) = offset.filter(|offset| *offset < u64::from(len)) else {
+            return Err(Error::OutOfBound { va, addr, len });
+        };
+
+        // remote atomic operates on a naturally aligned 8 bytes word, which must be within the memory region
+        if access_flag.contains(MemoryAccessFlag::IbvAccessRemoteAtomic) {
+            if !va.0.is_multiple_of(ATOMIC_OPERAND_SIZE) {
+                return Err(Error::UnalignedAtomic(va));
+            }
+            if u64::from(len) - offset < ATOMIC_OPERAND_SIZE {
+                return Err(Error::OutOfBound { va, addr, len });
+            }
+        }
+        Ok(offset)
+    }
+
+    /// The page table entries of a region, a window has none of its own
+    pub(crate) fn page_table(&self) -> Option<Range<u32>> {
+        let Kind::Region {
             page_table_offset,
+            page_size_bits,
+        } = self.kind
+        else {
+            return None;
+        };
+        let page_cnt = u32::try_from(u64::from(self.len).div_ceil(1 << page_size_bits)).unwrap();
+        Some(page_table_offset..page_table_offset.saturating_add(page_cnt))
+    }
+
+    /// The page table entries of an on-demand paging region, which are filled by the driver on page faults
+    pub(crate) fn on_demand_page_table(&self) -> Option<Range<u32>> {
+        if !self.access_flag.contains(MemoryAccessFlag::IbvAccessOnDemand) {
+            return None;
         }
+        self.page_table()
     }
 }
 
-#[derive(Debug, Default)]
-pub struct Table(HashMap<MemoryRegionKey, Context>);
+/// Indexed by the key index like the hardware, so updating an entry with a new key revokes the old key at once
+#[derive(Debug)]
+pub struct Table {
+    contexts: HashMap<u32, Context>,
+    /// set by the driver before it registers any region
+    key_index_bits: AtomicU32,
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Table {
     pub(crate) fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            contexts: HashMap::new(),
+            key_index_bits: AtomicU32::new(DEFAULT_KEY_INDEX_BITS),
+        }
+    }
+
+    pub(crate) fn key_index_bits(&self) -> u32 {
+        self.key_index_bits.load(Ordering::Relaxed)
+    }
+
+    /// the indexes of `bits` bits must fit in the table, otherwise the layout is not changed
+    pub(crate) fn set_key_index_bits(&self, bits: u32) {
+        if bits == 0 || bits > TABLE_SIZE.trailing_zeros() {
+            log::warn!("ignore {bits} key index bits, the table has {TABLE_SIZE} entries");
+            return;
+        }
+        self.key_index_bits.store(bits, Ordering::Relaxed);
+    }
+
+    fn key_index(&self, key: MemoryRegionKey) -> u32 {
+        key.get() >> (u32::BITS - self.key_index_bits())
+    }
+
+    /// find the context of `key`, whose secret must match as well
+    fn get<'table, S, G>(
+        &self,
+        mr_table: &'table HashMapRef<'_, u32, Context, S, G>,
+        key: MemoryRegionKey,
+    ) -> Result<&'table Context, Error>
+    where
+        S: BuildHasher,
+        G: Guard,
+    {
+        mr_table
+            .get(&self.key_index(key))
+            .filter(|mr_context| mr_context.key == key)
+            .ok_or(Error::KeyNotFound(key))
     }
 }
 
@@ -49,23 +205,58 @@ impl MemoryRegionTable for Table {
     fn update(&self, mr_context: Context) -> Result<(), Error> {
         log::debug!("update mr_table with {mr_context:?}");
 
-        let mr_table = self.0.pin();
-        let _ = mr_table.insert(mr_context.key, mr_context);
+        if let Kind::Region { page_size_bits, .. } = mr_context.kind
+            && !PAGE_SIZE_BITS.contains(&page_size_bits)
+        {
+            return Err(Error::UnsupportedPageSize(page_size_bits));
+        }
+        if let Some(range) = mr_context.page_table()
+            && range.end > PAGE_TABLE_SIZE
+        {
+            return Err(Error::PageTableOutOfBound(range));
+        }
+
+        let mr_table = self.contexts.pin();
+        let _ = mr_table.insert(self.key_index(mr_context.key), mr_context);
 
         log::trace!("after update {self:?}");
 
         Ok(())
     }
 
+    fn remove(&self, key: MemoryRegionKey) -> Result<(), Error> {
+        log::debug!("remove {key:?} from mr_table");
+
+        let mr_table = self.contexts.pin();
+        // a stale key must not remove the entry updated with a new key
+        let _ = self.get(&mr_table, key)?;
+        let _ = mr_table.remove(&self.key_index(key));
+
+        Ok(())
+    }
+
+    fn invalidate(&self, key: MemoryRegionKey) -> Result<(), Error> {
+        let mr_table = self.contexts.pin();
+        let mr_context = self.get(&mr_table, key)?;
+        // only the type 2 windows can be invalidated by the peer
+        if !matches!(mr_context.kind, Kind::Window { is_type2: true, .. }) {
+            return Err(Error::NotInvalidatable(key));
+        }
+        log::debug!("invalidate {key:?} on behalf of the peer");
+        let _ = mr_table.remove(&self.key_index(key));
+
+        Ok(())
+    }
+
     fn query(
         &self,
         key: MemoryRegionKey,
         va: VirtualAddress,
         access_flag: MemoryAccessFlag,
-        page_table: &HashMap<u32, Vec<DmaAddress>>,
-    ) -> Result<DmaAddress, Error> {
-        let mr_table = self.0.pin();
-        let mr_context = mr_table.get(&key).ok_or(Error::KeyNotFound(key))?;
+        page_table: &HashMap<u32, DmaAddress>,
+    ) -> Result<Translation, Error> {
+        let mr_table = self.contexts.pin();
+        let mr_context = self.get(&mr_table, key)?;
 
         let permit_access_flag = mr_context.access_flag;
         if !permit_access_flag.contains(access_flag) {
@@ -74,35 +265,361 @@ impl MemoryRegionTable for Table {
                 permit: permit_access_flag,
             });
         }
+        let mr_offset = mr_context.check_bound(va, access_flag)?;
 
-        let addr = mr_context.addr;
-        let len = mr_context.len;
-        // SAFETY: (addr + len) should be checked in `MemoryRegionContext`
-        let end = unsafe { addr.0.unchecked_add(u64::from(len)) };
-        if !(addr.0 <= va.0 && va.0 < end) {
-            return Err(Error::OutOfBound { va, addr, len });
-        }
+        // a window is resolved to the page table of its parent
+        let (region, offset) = match mr_context.kind {
+            Kind::Region { .. } => (mr_context, mr_offset),
+            Kind::Window { parent, .. } => {
+                if !WINDOW_ACCESS_FLAG.intersects(access_flag) {
+                    return Err(Error::PermissionDeny {
+                        give: access_flag,
+                        permit: permit_access_flag,
+                    });
+                }
+                let parent_context = self.get(&mr_table, parent)?;
+                (parent_context, parent_context.check_bound(va, access_flag)?)
+            }
+        };
+        let Kind::Region {
+            page_table_offset,
+            page_size_bits,
+        } = region.kind
+        else {
+            return Err(Error::KeyNotFound(region.key));
+        };
 
-        const PAGE_SIZE: u64 = 2 * 1024 * 1024; // 2MiB
-        const PAGE_SIZE_BITS: u64 = PAGE_SIZE.trailing_zeros() as _;
-        let idx = (va.0 - addr.0) >> PAGE_SIZE_BITS;
-        let addr = (va.0 - addr.0) & (PAGE_SIZE - 1);
+        let idx = u32::try_from(offset >> page_size_bits).unwrap();
+        let addr = offset & ((1 << page_size_bits) - 1);
 
-        let offset = mr_context.page_table_offset;
         let page_table = page_table.pin();
 
-        // check when construct `MemoryRegionContext`?
-        let dma_address = page_table
-            .get(&offset)
-            .expect("logic error: page table entry not found");
+        // the page table of an on-demand paging region is filled lazily, others are filled on registration
+        let Some(dma_address) = page_table.get(&page_table_offset.checked_add(idx).unwrap()) else {
+            if !region.access_flag.contains(MemoryAccessFlag::IbvAccessOnDemand) {
+                return Err(Error::PageNotFound { key: region.key, va });
+            }
+            return Err(Error::PageFault { key: region.key, va });
+        };
+
+        let dma_address = dma_address.0.checked_add(addr).unwrap();
+        // pages are not physically continuous, so the access must be split at the end of the page
+        let len = ((1 << page_size_bits) - addr)
+            .min(u64::from(mr_context.len) - mr_offset)
+            .min(u64::from(region.len) - offset);
+
+        Ok(Translation {
+            dma_addr: dma_address.into(),
+            len,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use papaya::HashMap;
+
+    use super::{Context, PAGE_TABLE_SIZE, Table};
+    use crate::address::{DmaAddress, VirtualAddress};
+    use crate::mr_table::{Error, MemoryRegionTable, Translation};
+    use crate::types::{MemoryAccessFlag, MemoryRegionKey};
+
+    #[test]
+    fn test_window_resolve_to_parent_page_table() {
+        const PAGE_SIZE: u64 = 2 * 1024 * 1024;
+        let mr_key = MemoryRegionKey::new(0x0100_0001);
+        let mw_key = MemoryRegionKey::new(0x8000_0001);
+        let table = Table::new();
+        let page_table = HashMap::new();
+        let _ = page_table.pin().insert(3, DmaAddress(0x10_0000_0000));
+        let _ = page_table.pin().insert(4, DmaAddress(0x20_0000_0000));
+        let mr_flag = MemoryAccessFlag::IbvAccessLocalWrite | MemoryAccessFlag::IbvAccessMwBind;
+        let mr = Context::new(
+            VirtualAddress(0x4000_0000),
+            2 * PAGE_SIZE as u32,
+            mr_key,
+            1,
+            mr_flag,
+            3,
+            21,
+        );
+        table.update(mr).unwrap();
+
+        // window over the head of the second page
+        let mw_addr = 0x4000_0000 + PAGE_SIZE;
+        let mw_flag = MemoryAccessFlag::IbvAccessRemoteWrite;
+        let mw = Context::new_window(VirtualAddress(mw_addr), 0x1000, mw_key, mr_key, mw_flag, true);
+        table.update(mw).unwrap();
+
+        let dma = table.query(mw_key, VirtualAddress(mw_addr + 0x10), mw_flag, &page_table);
+        assert_eq!(dma.unwrap().dma_addr.0, 0x20_0000_0010);
+        let out_of_window = table.query(mw_key, VirtualAddress(mw_addr + 0x1000), mw_flag, &page_table);
+        assert!(matches!(out_of_window, Err(Error::OutOfBound { .. })));
+        let read = table.query(
+            mw_key,
+            VirtualAddress(mw_addr),
+            MemoryAccessFlag::IbvAccessRemoteRead,
+            &page_table,
+        );
+        assert!(matches!(read, Err(Error::PermissionDeny { .. })));
+
+        // only the windows are invalidated by the peer
+        assert!(matches!(table.invalidate(mr_key), Err(Error::NotInvalidatable(_))));
+        table.invalidate(mw_key).unwrap();
+        let dma = table.query(mw_key, VirtualAddress(mw_addr), mw_flag, &page_table);
+        assert!(matches!(dma, Err(Error::KeyNotFound(_))));
+    }
+
+    #[test]
+    fn test_update_revokes_old_key() {
+        let old_key = MemoryRegionKey::new(0x0100_0001);
+        let new_key = MemoryRegionKey::new(0x0100_0002);
+        let table = Table::new();
+        let page_table = HashMap::new();
+        let _ = page_table.pin().insert(0, DmaAddress(0x10_0000_0000));
+        let _ = page_table.pin().insert(1, DmaAddress(0x20_0000_0000));
+        let flag = MemoryAccessFlag::IbvAccessRemoteRead;
+        table
+            .update(Context::new(
+                VirtualAddress(0x4000_0000),
+                0x1000,
+                old_key,
+                1,
+                flag,
+                0,
+                21,
+            ))
+            .unwrap();
 
-        let dma_address = dma_address
-            .get(usize::try_from(idx).unwrap())
-            .expect("logic error: page table offset out of bound")
-            .0
-            .checked_add(addr)
+        // the same index with a new secret, which moves to another page table as well
+        table
+            .update(Context::new(
+                VirtualAddress(0x4000_0000),
+                0x1000,
+                new_key,
+                1,
+                flag,
+                1,
+                21,
+            ))
             .unwrap();
+        let dma = table.query(old_key, VirtualAddress(0x4000_0000), flag, &page_table);
+        assert!(matches!(dma, Err(Error::KeyNotFound(_))));
+        let dma = table.query(new_key, VirtualAddress(0x4000_0000), flag, &page_table);
+        assert_eq!(dma.unwrap().dma_addr.0, 0x20_0000_0000);
+        assert!(matches!(table.remove(old_key), Err(Error::KeyNotFound(_))));
+    }
+
+    #[test]
+    fn test_zero_based_region() {
+        const PAGE_SIZE: u64 = 2 * 1024 * 1024;
+        let key = MemoryRegionKey::new(0x0200_0001);
+        let table = Table::new();
+        let page_table = HashMap::new();
+        let _ = page_table.pin().insert(0, DmaAddress(0x10_0000_0000));
+        let _ = page_table.pin().insert(1, DmaAddress(0x20_0000_0000));
+        let flag = MemoryAccessFlag::IbvAccessRemoteWrite | MemoryAccessFlag::IbvAccessZeroBased;
+        let mr = Context::new(VirtualAddress(0x4000_0000), 2 * PAGE_SIZE as u32, key, 1, flag, 0, 21);
+        table.update(mr).unwrap();
+
+        // the peer addresses the region by the offset from its start
+        let write = MemoryAccessFlag::IbvAccessRemoteWrite;
+        let dma = table.query(key, VirtualAddress(PAGE_SIZE + 0x10), write, &page_table);
+        assert_eq!(dma.unwrap().dma_addr.0, 0x20_0000_0010);
+        let dma = table.query(key, VirtualAddress(0x4000_0000), write, &page_table);
+        assert!(matches!(dma, Err(Error::OutOfBound { .. })));
+    }
+
+    #[test]
+    fn test_on_demand_page_fault() {
+        const PAGE_SIZE: u64 = 2 * 1024 * 1024;
+        let mr_key = MemoryRegionKey::new(0x0300_0001);
+        let mw_key = MemoryRegionKey::new(0x8100_0001);
+        let table = Table::new();
+        let page_table = HashMap::new();
+        // a sparse region of 1GiB, only the 100th page is mapped
+        let _ = page_table.pin().insert(8 + 100, DmaAddress(0x10_0000_0000));
+        let flag = MemoryAccessFlag::IbvAccessRemoteWrite | MemoryAccessFlag::IbvAccessOnDemand;
+        let mr = Context::new(
+            VirtualAddress(0x4000_0000),
+            512 * PAGE_SIZE as u32,
+            mr_key,
+            1,
+            flag,
+            8,
+            21,
+        );
+        assert_eq!(mr.on_demand_page_table(), Some(8..8 + 512));
+        table.update(mr).unwrap();
+
+        let write = MemoryAccessFlag::IbvAccessRemoteWrite;
+        let mapped = VirtualAddress(0x4000_0000 + 100 * PAGE_SIZE + 0x10);
+        let dma = table.query(mr_key, mapped, write, &page_table);
+        assert_eq!(dma.unwrap().dma_addr.0, 0x10_0000_0010);
+
+        // the fault of a window is reported on its parent region
+        let unmapped = VirtualAddress(0x4000_0000 + 300 * PAGE_SIZE);
+        let mw = Context::new_window(unmapped, 0x1000, mw_key, mr_key, write, true);
+        table.update(mw).unwrap();
+        let dma = table.query(mw_key, unmapped, write, &page_table);
+        assert!(matches!(dma, Err(Error::PageFault { key, va }) if key == mr_key && va == unmapped));
+
+        // the driver resolves the fault by filling just that page
+        let _ = page_table.pin().insert(8 + 300, DmaAddress(0x20_0000_0000));
+        let dma = table.query(mw_key, unmapped, write, &page_table);
+        assert_eq!(dma.unwrap().dma_addr.0, 0x20_0000_0000);
+    }
+
+    #[test]
+    fn test_missing_page_entry() {
+        const PAGE_SIZE: u64 = 2 * 1024 * 1024;
+        let mr_key = MemoryRegionKey::new(0x0300_0001);
+        let table = Table::new();
+        let page_table = HashMap::new();
+        // the page table of a region without on-demand paging is broken, the access is rejected instead of faulted
+        let flag = MemoryAccessFlag::IbvAccessRemoteWrite;
+        let mr = Context::new(VirtualAddress(0x4000_0000), PAGE_SIZE as u32, mr_key, 1, flag, 8, 21);
+        table.update(mr).unwrap();
+
+        let va = VirtualAddress(0x4000_0010);
+        let dma = table.query(mr_key, va, flag, &page_table);
+        assert!(matches!(dma, Err(Error::PageNotFound { key, va: fault_va }) if key == mr_key && fault_va == va));
+    }
+
+    #[test]
+    fn test_key_index_bits() {
+        const PAGE_SIZE: u64 = 2 * 1024 * 1024;
+        let table = Table::new();
+        let page_table = HashMap::new();
+        let _ = page_table.pin().insert(0, DmaAddress(0x10_0000_0000));
+        let flag = MemoryAccessFlag::IbvAccessRemoteRead;
+        let va = VirtualAddress(0x4000_0000);
+
+        // with 12 index bits, the keys differing in the 21st bit are different regions
+        table.set_key_index_bits(12);
+        let key_a = MemoryRegionKey::new(0x0010_0001);
+        let key_b = MemoryRegionKey::new(0x0000_0001);
+        table.update(Context::new(va, 0x1000, key_a, 1, flag, 0, 21)).unwrap();
+        table.update(Context::new(va, 0x1000, key_b, 1, flag, 0, 21)).unwrap();
+        assert!(table.query(key_a, va, flag, &page_table).is_ok());
+        assert!(table.query(key_b, va, flag, &page_table).is_ok());
+
+        // the indexes of 17 bits do not fit in the table
+        table.set_key_index_bits(17);
+        assert_eq!(table.key_index_bits(), 12);
+
+        // a region whose page table is beyond the device is rejected
+        let key_c = MemoryRegionKey::new(0x0020_0001);
+        let mr = Context::new(va, 2 * PAGE_SIZE as u32, key_c, 1, flag, PAGE_TABLE_SIZE - 1, 21);
+        assert!(matches!(table.update(mr), Err(Error::PageTableOutOfBound(_))));
+        assert!(matches!(
+            table.query(key_c, va, flag, &page_table),
+            Err(Error::KeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_page_size() {
+        let table = Table::new();
+        let page_table = HashMap::new();
+        let flag = MemoryAccessFlag::IbvAccessRemoteRead;
+        let va = VirtualAddress(0x7f00_1234_5000);
+
+        // the 4KiB pages of an ordinary heap buffer are not physically continuous
+        let key = MemoryRegionKey::new(0x0100_0001);
+        for (idx, dma) in [0x3000, 0x9000, 0x1000].into_iter().enumerate() {
+            let _ = page_table.pin().insert(4 + idx as u32, DmaAddress(dma));
+        }
+        let mr = Context::new(va, 3 * 0x1000, key, 1, flag, 4, 12);
+        assert_eq!(mr.page_table(), Some(4..7));
+        table.update(mr).unwrap();
+        let dma = table.query(key, VirtualAddress(va.0 + 0x1010), flag, &page_table);
+        assert_eq!(dma.unwrap().dma_addr.0, 0x9010);
+        let dma = table.query(key, VirtualAddress(va.0 + 0x2ff8), flag, &page_table);
+        assert_eq!(dma.unwrap().dma_addr.0, 0x1ff8);
+
+        // a 1GiB page takes one entry
+        let key = MemoryRegionKey::new(0x0200_0001);
+        let _ = page_table.pin().insert(8, DmaAddress(0x40_0000_0000));
+        let mr = Context::new(VirtualAddress(0x40_0000_0000), 0x4000_0000, key, 1, flag, 8, 30);
+        assert_eq!(mr.page_table(), Some(8..9));
+        table.update(mr).unwrap();
+        let dma = table.query(key, VirtualAddress(0x40_3000_0000), flag, &page_table);
+        assert_eq!(dma.unwrap().dma_addr.0, 0x40_3000_0000);
+
+        let key = MemoryRegionKey::new(0x0300_0001);
+        let mr = Context::new(va, 0x1000, key, 1, flag, 9, 16);
+        assert!(matches!(table.update(mr), Err(Error::UnsupportedPageSize(16))));
+    }
+
+    #[test]
+    fn test_fragmented_page_table() {
+        let mr_key = MemoryRegionKey::new(0x0100_0001);
+        let mw_key = MemoryRegionKey::new(0x8000_0001);
+        let table = Table::new();
+        let page_table = HashMap::new();
+        for (idx, dma) in [0x5000, 0x2000, 0x9000, 0x1000].into_iter().enumerate() {
+            let _ = page_table.pin().insert(idx as u32, DmaAddress(dma));
+        }
+        let flag = MemoryAccessFlag::IbvAccessRemoteWrite | MemoryAccessFlag::IbvAccessMwBind;
+        let va = 0x7f00_0000_0000;
+        // the region ends in the middle of the last page
+        let mr = Context::new(VirtualAddress(va), 0x3800, mr_key, 1, flag, 0, 12);
+        table.update(mr).unwrap();
+
+        // each translation stops at the end of the page
+        let write = MemoryAccessFlag::IbvAccessRemoteWrite;
+        let query = |key, offset| {
+            table
+                .query(key, VirtualAddress(va + offset), write, &page_table)
+                .unwrap()
+        };
+        assert_eq!(
+            query(mr_key, 0),
+            Translation {
+                dma_addr: DmaAddress(0x5000),
+                len: 0x1000,
+            }
+        );
+        assert_eq!(
+            query(mr_key, 0xfff),
+            Translation {
+                dma_addr: DmaAddress(0x5fff),
+                len: 1,
+            }
+        );
+        assert_eq!(
+            query(mr_key, 0x1000),
+            Translation {
+                dma_addr: DmaAddress(0x2000),
+                len: 0x1000,
+            }
+        );
+        assert_eq!(
+            query(mr_key, 0x2100),
+            Translation {
+                dma_addr: DmaAddress(0x9100),
+                len: 0xf00,
+            }
+        );
+        // or at the end of the region
+        assert_eq!(
+            query(mr_key, 0x3100),
+            Translation {
+                dma_addr: DmaAddress(0x1100),
+                len: 0x700,
+            }
+        );
 
-        Ok(dma_address.into())
+        // or at the end of the window
+        let mw = Context::new_window(VirtualAddress(va + 0x1800), 0x100, mw_key, mr_key, write, true);
+        table.update(mw).unwrap();
+        assert_eq!(
+            query(mw_key, 0x1810),
+            Translation {
+                dma_addr: DmaAddress(0x2810),
+                len: 0xf0,
+            }
+        );
     }
 }
@@ -6,18 +6,30 @@ pub trait MemoryRegionTable {
     fn update(&self, mr_context: Context) -> Result<(), Error>;
     // fn update(&self, key: MemoryRegionKey, mr_context: Context) -> Result<()>;
 
+    fn remove(&self, key: MemoryRegionKey) -> Result<(), Error>;
+
+    /// remove a type 2 memory window on behalf of the peer
+    fn invalidate(&self, key: MemoryRegionKey) -> Result<(), Error>;
+
     fn query(
         &self,
         key: MemoryRegionKey,
         va: VirtualAddress,
         access_flag: MemoryAccessFlag,
         // TODO(fh): Replace with generic args <PageTable>
-        page_table: &papaya::HashMap<u32, Vec<DmaAddress>>,
-    ) -> Result<DmaAddress, Error>;
+        page_table: &papaya::HashMap<u32, DmaAddress>,
+    ) -> Result<Translation, Error>;
+}
+
+/// The dma address of a virtual address, and how far the memory is continuous from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Translation {
+    pub dma_addr: DmaAddress,
+    /// bytes until the end of the page, or the end of the memory region or window if it comes first
+    pub len: u64,
 }
 
 // should move to memory_region module
-#[expect(variant_size_differences, reason = "TODO(fh): move into Box")]
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("key not found: {0:?}")]
@@ -35,4 +47,25 @@ pub enum Error {
         addr: VirtualAddress,
         len: u32,
     },
+
+    #[error("atomic operation on unaligned virtual address: {0:?}")]
+    UnalignedAtomic(VirtualAddress),
+
+    #[error("key can not be invalidated by the peer: {0:?}")]
+    NotInvalidatable(MemoryRegionKey),
+
+    /// the page is not in the page table of an on-demand paging memory region, `key` is the region
+    /// even if it's accessed through a window
+    #[error("page fault: {va:?} of {key:?} is not mapped")]
+    PageFault { key: MemoryRegionKey, va: VirtualAddress },
+
+    /// the page of a region which is not on-demand paging is missing, so the page table is broken
+    #[error("page table entry not found: {va:?} of {key:?}")]
+    PageNotFound { key: MemoryRegionKey, va: VirtualAddress },
+
+    #[error("unsupported page size: {} bytes", 1_u64 << .0)]
+    UnsupportedPageSize(u32),
+
+    #[error("page table entries out of bound: {0:?}")]
+    PageTableOutOfBound(core::ops::Range<u32>),
 }
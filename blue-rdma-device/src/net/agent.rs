@@ -16,5 +16,10 @@ pub trait Agent {
     ///
     /// The function must be called with valid byte array buf of sufficient size to hold the message bytes.
     /// If a message is too long to fit in the supplied buffer, excess bytes may be discarded.
+    ///
+    /// # Errors
+    ///
+    /// Like a socket with read timeout, this will return an error of kind `WouldBlock` or `TimedOut`
+    /// if there is no message for a while, so that the caller has a chance to stop receiving.
     fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, core::net::IpAddr)>;
 }
@@ -1,9 +1,12 @@
 mod acknowledge;
+mod atomic;
+mod atomic_acknowledge;
 mod read_request;
 mod read_response_first;
 mod read_response_last;
 mod read_response_middle;
 mod read_response_only;
+mod send;
 mod write_first;
 mod write_last;
 mod write_last_with_immediate;
@@ -12,11 +15,14 @@ mod write_only;
 mod write_only_with_immediate;
 
 pub use acknowledge::Acknowledge;
+pub use atomic::Atomic;
+pub use atomic_acknowledge::AtomicAcknowledge;
 pub use read_request::ReadRequest;
 pub use read_response_first::ReadResponseFirst;
 pub use read_response_last::ReadResponseLast;
 pub use read_response_middle::ReadResponseMiddle;
 pub use read_response_only::ReadResponseOnly;
+pub use send::Send;
 pub use write_first::WriteFirst;
 pub use write_last::WriteLast;
 pub use write_last_with_immediate::WriteLastWithImmediate;
@@ -32,6 +38,15 @@ pub trait Message<Dev> {
     fn handle(&self, dev: &Dev) -> super::super::Result;
 }
 
+/// whether the payload failed to be placed, a page fault is returned instead,
+/// so that the packet is parked and handled again once the driver resolves it
+fn placement_failed(result: Result<(), crate::mr_table::Error>) -> Result<bool, crate::mr_table::Error> {
+    match result {
+        Err(err @ crate::mr_table::Error::PageFault { .. }) => Err(err),
+        result => Ok(result.is_err()),
+    }
+}
+
 // which is better?
 pub trait HandleMessage<Msg> {
     fn handle(&self, msg: Msg, src: core::net::IpAddr) -> super::super::Result;
@@ -39,21 +54,33 @@ pub trait HandleMessage<Msg> {
 
 mod handler {
     use super::{
-        Acknowledge, HandleMessage, ReadRequest, ReadResponseFirst, ReadResponseLast, ReadResponseMiddle,
-        ReadResponseOnly, WriteFirst, WriteLast, WriteLastWithImmediate, WriteMiddle, WriteOnly,
-        WriteOnlyWithImmediate,
+        Acknowledge, Atomic, AtomicAcknowledge, HandleMessage, ReadRequest, ReadResponseFirst, ReadResponseLast,
+        ReadResponseMiddle, ReadResponseOnly, Send, WriteFirst, WriteLast, WriteLastWithImmediate, WriteMiddle,
+        WriteOnly, WriteOnlyWithImmediate,
     };
     use crate::DeviceInner;
     use crate::dma::Client;
     use crate::errors::Error;
     use crate::net::Agent;
     use crate::third_party::net::RdmaMessage;
-    use crate::third_party::queues::meta_report::ToHostWorkRbDescOpcode;
+    use crate::third_party::queues::meta_report::{ToHostWorkRbDescOpcode, ToHostWorkRbDescWriteType};
+    use crate::types::QueuePairType;
 
     impl<UA: Agent, DC: Client> DeviceInner<UA, DC> {
         pub(crate) fn handle_message(&self, msg: &RdmaMessage, src: core::net::IpAddr) -> Result<(), Error> {
             log::debug!("handle network message {msg:?}");
+            if self.in_error_state(msg) || !self.accept_unreliable(msg) {
+                return Ok(());
+            }
             match msg.meta_data.common_meta().opcode {
+                ToHostWorkRbDescOpcode::SendFirst
+                | ToHostWorkRbDescOpcode::SendMiddle
+                | ToHostWorkRbDescOpcode::SendLast
+                | ToHostWorkRbDescOpcode::SendLastWithImmediate
+                | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+                | ToHostWorkRbDescOpcode::SendOnly
+                | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate => self.handle(Send::parse(msg)?, src)?,
                 ToHostWorkRbDescOpcode::RdmaWriteFirst => self.handle(WriteFirst::parse(msg)?, src)?,
                 ToHostWorkRbDescOpcode::RdmaWriteMiddle => self.handle(WriteMiddle::parse(msg)?, src)?,
                 ToHostWorkRbDescOpcode::RdmaWriteLast => self.handle(WriteLast::parse(msg)?, src)?,
@@ -71,9 +98,45 @@ mod handler {
 
                 ToHostWorkRbDescOpcode::RdmaReadRequest => self.handle(ReadRequest::parse(msg)?, src)?,
                 ToHostWorkRbDescOpcode::Acknowledge => self.handle(Acknowledge::parse(msg)?, src)?,
+                ToHostWorkRbDescOpcode::CompareSwap | ToHostWorkRbDescOpcode::FetchAdd => {
+                    self.handle(Atomic::parse(msg)?, src)?;
+                }
+                ToHostWorkRbDescOpcode::AtomicAcknowledge => self.handle(AtomicAcknowledge::parse(msg)?, src)?,
             }
 
             Ok(())
         }
+
+        /// a queue pair in the error state drops all packets, whose requests are flushed by the driver
+        fn in_error_state(&self, msg: &RdmaMessage) -> bool {
+            let guard = self.queue_pair_table().guard();
+            let qp_context = self
+                .queue_pair_table()
+                .get(msg.meta_data.common_meta().dqpn.get(), &guard);
+            let in_error_state = qp_context.is_some_and(|qp_context| qp_context.in_error_state());
+            if in_error_state {
+                log::debug!("drop packet of queue pair in error state {msg:?}");
+            }
+            in_error_state
+        }
+
+        /// drop the packets of UC message which has lost some packets, other QP types always accept
+        fn accept_unreliable(&self, msg: &RdmaMessage) -> bool {
+            let common_meta = msg.meta_data.common_meta();
+            let guard = self.queue_pair_table().guard();
+            let Some(qp_context) = self.queue_pair_table().get(common_meta.dqpn.get(), &guard) else {
+                return true;
+            };
+            if qp_context.qp_type() != QueuePairType::Uc {
+                return true;
+            }
+            let is_first_packet = matches!(
+                common_meta.opcode.write_type(),
+                Some(ToHostWorkRbDescWriteType::First | ToHostWorkRbDescWriteType::Only)
+            );
+            let srq_guard = self.shared_receive_queue_table().guard();
+            let srq = self.shared_receive_queue_of(qp_context, &srq_guard);
+            qp_context.check_unreliable_psn(common_meta.psn.get(), is_first_packet, srq)
+        }
     }
 }
@@ -0,0 +1,63 @@
+use super::HandleMessage;
+use crate::dma::Client;
+use crate::net::util::generate_atomic_ack;
+use crate::net::{Agent, Error};
+use crate::queue_pair::next_psn;
+use crate::third_party::net::{Metadata, RdmaMessage};
+use crate::{DeviceInner, mr_table};
+
+/// Compare and swap, fetch and add, which are executed against the memory region without driver
+#[derive(Debug)]
+pub struct Atomic<'msg> {
+    // TODO(fh): replace with BaseTransportHeader
+    bth: &'msg RdmaMessage,
+}
+type Message<'msg> = Atomic<'msg>;
+
+impl<'msg> Message<'msg> {
+    pub const fn parse<'input>(msg: &'input RdmaMessage) -> Result<Self, Error>
+    where
+        'input: 'msg,
+    {
+        Ok(Self { bth: msg })
+    }
+}
+
+impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
+    fn handle(&self, msg: Message, src: core::net::IpAddr) -> crate::Result {
+        let msg = msg.bth;
+        let Metadata::Atomic(ref header) = msg.meta_data else {
+            unreachable!("logic error: atomic without atomic eth");
+        };
+
+        let qpn = header.common_meta.dqpn.get();
+        let psn = header.common_meta.psn.get();
+
+        let guard = self.queue_pair_table().guard();
+        let Some(qp_context) = self.queue_pair_table().get(qpn, &guard) else {
+            log::warn!("drop atomic packet to unknown QPN: {qpn}");
+            return Ok(());
+        };
+
+        // TODO(fh): keep responses of recent atomics, so that a duplicate request can be responded again
+        let expected_psn = qp_context.expected_psn();
+        if psn != expected_psn {
+            log::warn!("QPN: {qpn}: drop atomic packet {psn}, expected {expected_psn}");
+            return Ok(());
+        }
+        let result = match self.execute_atomic(header) {
+            // the packet is handled again once the page fault is resolved, so it's still expected
+            Err(err @ mr_table::Error::PageFault { .. }) => return Err(err.into()),
+            result => result,
+        };
+        qp_context.set_expect_psn(next_psn(psn));
+        if let Err(ref err) = result {
+            log::warn!("QPN: {qpn}: atomic failed: {err}");
+        }
+
+        let buf = generate_atomic_ack(msg, qp_context.peer_qpn(), psn, result.ok());
+        let _ = self.udp_agent.get().unwrap().send_to(&buf, src);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use super::HandleMessage;
+use crate::dma::Client;
+use crate::net::{Agent, Error, util};
+use crate::queues::complete_queue::CompleteQueue;
+use crate::third_party::net::{Metadata, RdmaMessage, SGListElement};
+use crate::types::MemoryAccessFlag;
+use crate::{DeviceInner, mr_table};
+
+/// Atomic acknowledge, which carries the original value of the remote address
+#[derive(Debug)]
+pub struct AtomicAcknowledge<'msg> {
+    // TODO(fh): replace with BaseTransportHeader
+    aeth: &'msg RdmaMessage,
+}
+type Message<'msg> = AtomicAcknowledge<'msg>;
+
+impl<'msg> Message<'msg> {
+    pub const fn parse<'input>(msg: &'input RdmaMessage) -> Result<Self, Error>
+    where
+        'input: 'msg,
+    {
+        Ok(Self { aeth: msg })
+    }
+}
+
+impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
+    fn handle(&self, msg: Message, _: core::net::IpAddr) -> crate::Result {
+        let msg = msg.aeth;
+        let Metadata::Acknowledge(ref header) = msg.meta_data else {
+            unreachable!("logic error: atomic acknowledge without aeth");
+        };
+
+        let qpn = header.common_meta.dqpn.get();
+        #[expect(clippy::cast_possible_truncation, reason = "msn is stored in the 16 bits pkey")]
+        let msn = header.msn as u16;
+        let orig = header.atomic_orig.expect("atomic acknowledge without original value");
+
+        let guard = self.queue_pair_table().guard();
+        let qp_context = self.queue_pair_table().get(qpn, &guard);
+        let Some((qp_context, buffer)) =
+            qp_context.and_then(|qp_context| qp_context.finish_atomic(msn).map(|buffer| (qp_context, buffer)))
+        else {
+            log::warn!("QPN: {qpn}: drop atomic acknowledge of unknown msn {msn}");
+            return Ok(());
+        };
+
+        // place the original value into local buffer before the driver is notified
+        let bytes = orig.to_ne_bytes();
+        let data = SGListElement {
+            data: bytes.as_ptr(),
+            len: bytes.len().min(buffer.len as usize),
+        };
+        match self.copy_to(data, buffer.key, buffer.addr, MemoryAccessFlag::IbvAccessLocalWrite) {
+            Ok(()) => {}
+            // the acknowledge is handled again once the page fault is resolved, which still needs the buffer
+            Err(err @ mr_table::Error::PageFault { .. }) => {
+                qp_context.start_atomic(msn, buffer);
+                return Err(err.into());
+            }
+            Err(err) => log::warn!("QPN: {qpn}: write back atomic result failed: {err}"),
+        }
+
+        let descriptor = util::message_to_bthaeth(msg);
+        log::debug!("push meta report: {descriptor:?}");
+        unsafe { self.meta_report_queue().push(descriptor) };
+
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
-use super::HandleMessage;
+use super::{HandleMessage, placement_failed};
 use crate::DeviceInner;
 use crate::dma::Client;
-use crate::net::util::{generate_ack, message_to_bthreth, message_to_secondary_reth};
+use crate::net::util::{message_to_bthreth, message_to_secondary_reth};
 use crate::net::{Agent, Error};
+use crate::queue_pair::{next_psn, psn_after, psn_after_or_eq};
 use crate::queues::complete_queue::CompleteQueue;
 use crate::third_party::net::RdmaMessage;
 use crate::third_party::queues::meta_report::ToHostWorkRbDescStatus;
@@ -31,13 +32,13 @@ impl<'msg> Message<'msg> {
 }
 
 impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
-    fn handle(&self, msg: Message, src: core::net::IpAddr) -> crate::Result {
+    fn handle(&self, msg: Message, _src: core::net::IpAddr) -> crate::Result {
         let msg = msg.bth;
 
         let qpn = msg.meta_data.common_meta().dqpn.get();
         let psn = msg.meta_data.common_meta().psn.get();
 
-        let mr_error = self.copy_to_with_key(msg).is_err();
+        let mr_error = placement_failed(self.copy_to_with_key(msg))?;
 
         let guard = self.queue_pair_table().guard();
         let qp_context = self.queue_pair_table().get(qpn, &guard);
@@ -68,8 +69,8 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
 
             expected_psn_option = Some(expected_psn);
 
-            let new_expected_psn = (psn >= expected_psn).then_some(psn + 1);
-            let new_error_psn = (psn > expected_psn).then_some(psn);
+            let new_expected_psn = psn_after_or_eq(psn, expected_psn).then(|| next_psn(psn));
+            let new_error_psn = psn_after(psn, expected_psn).then_some(psn);
 
             if let Some(new_expected_psn) = new_expected_psn {
                 qp_context.set_expect_psn(new_expected_psn);
@@ -80,11 +81,8 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
             }
         }
 
-        let need_ack = can_auto_ack && msg.meta_data.common_meta().ack_req;
-        if need_ack {
-            let buf = generate_ack(msg, qp_context.unwrap().peer_qpn(), expected_psn_option.unwrap());
-            let _ = self.udp_agent.get().unwrap().send_to(&buf, src);
-        }
+        // the read response acknowledges the request, a separate ack would complete the read before its data
+        // is placed
 
         let need_report_header = true;
 
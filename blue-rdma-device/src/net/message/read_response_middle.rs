@@ -1,8 +1,9 @@
-use super::HandleMessage;
+use super::{HandleMessage, placement_failed};
 use crate::DeviceInner;
 use crate::dma::Client;
 use crate::net::util::{generate_ack, message_to_bthreth};
 use crate::net::{Agent, Error};
+use crate::queue_pair::{next_psn, psn_after, psn_after_or_eq};
 use crate::queues::complete_queue::CompleteQueue;
 use crate::third_party::net::RdmaMessage;
 use crate::third_party::queues::meta_report::ToHostWorkRbDescStatus;
@@ -32,7 +33,7 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
         let qpn = msg.meta_data.common_meta().dqpn.get();
         let psn = msg.meta_data.common_meta().psn.get();
 
-        let mr_error = self.copy_to_with_key(msg).is_err();
+        let mr_error = placement_failed(self.copy_read_response(msg, false))?;
 
         let guard = self.queue_pair_table().guard();
         let qp_context = self.queue_pair_table().get(qpn, &guard);
@@ -63,8 +64,8 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
 
             expected_psn_option = Some(expected_psn);
 
-            let new_expected_psn = (psn >= expected_psn).then_some(psn + 1);
-            let new_error_psn = (psn > expected_psn).then_some(psn);
+            let new_expected_psn = psn_after_or_eq(psn, expected_psn).then(|| next_psn(psn));
+            let new_error_psn = psn_after(psn, expected_psn).then_some(psn);
 
             if let Some(new_expected_psn) = new_expected_psn {
                 qp_context.set_expect_psn(new_expected_psn);
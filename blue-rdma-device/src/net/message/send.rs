@@ -0,0 +1,239 @@
+use super::{HandleMessage, placement_failed};
+use crate::DeviceInner;
+use crate::address::VirtualAddress;
+use crate::dma::Client;
+use crate::mr_table::MemoryRegionTable;
+use crate::net::util::{
+    NAK_INVALID_REQUEST, NAK_REMOTE_ACCESS_ERROR, NAK_REMOTE_OPERATIONAL_ERROR, datagram_to_bthreth, generate_ack,
+    generate_nak, generate_rnr_nak, message_to_bthreth, message_to_imm_dt,
+};
+use crate::net::{Agent, Error};
+use crate::queue_pair::{next_psn, psn_after, psn_after_or_eq};
+use crate::queues::complete_queue::CompleteQueue;
+use crate::third_party::net::{DethHeader, Metadata, RdmaMessage};
+use crate::third_party::queues::meta_report::{ToHostWorkRbDescOpcode, ToHostWorkRbDescStatus};
+use crate::types::{MemoryAccessFlag, MemoryRegionKey, QueuePairType};
+
+/// All SEND packets, which place payload into the receive work request consumed by the message
+#[derive(Debug)]
+pub struct Send<'msg> {
+    // TODO(fh): replace with BaseTransportHeader
+    bth: &'msg RdmaMessage,
+}
+type Message<'msg> = Send<'msg>;
+
+impl<'msg> Message<'msg> {
+    pub const fn parse<'input>(msg: &'input RdmaMessage) -> Result<Self, Error>
+    where
+        'input: 'msg,
+    {
+        Ok(Self { bth: msg })
+    }
+}
+
+impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
+    fn handle(&self, msg: Message, src: core::net::IpAddr) -> crate::Result {
+        let msg = msg.bth;
+        let Metadata::General(ref header) = msg.meta_data else {
+            unreachable!("logic error: SEND without reth");
+        };
+        if let Some(ref deth) = header.deth {
+            return self.handle_datagram(msg, deth, src);
+        }
+
+        let opcode = &header.common_meta.opcode;
+        let qpn = header.common_meta.dqpn.get();
+        let psn = header.common_meta.psn.get();
+        let is_first = matches!(
+            opcode,
+            ToHostWorkRbDescOpcode::SendFirst
+                | ToHostWorkRbDescOpcode::SendOnly
+                | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+        );
+        let is_last = !matches!(
+            opcode,
+            ToHostWorkRbDescOpcode::SendFirst | ToHostWorkRbDescOpcode::SendMiddle
+        );
+        let with_immediate = matches!(
+            opcode,
+            ToHostWorkRbDescOpcode::SendLastWithImmediate | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+        );
+        let with_invalidate = matches!(
+            opcode,
+            ToHostWorkRbDescOpcode::SendLastWithInvalidate | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+        );
+
+        let guard = self.queue_pair_table().guard();
+        let Some(qp_context) = self.queue_pair_table().get(qpn, &guard) else {
+            log::warn!("drop SEND packet to unknown QPN: {qpn}");
+            return Ok(());
+        };
+
+        let expected_psn = qp_context.expected_psn();
+        if !psn_after_or_eq(psn, expected_psn) {
+            // duplicate packet, its data is already placed, ack it again so the sender can make progress
+            if header.common_meta.ack_req && !qp_context.is_error() {
+                let buf = generate_ack(msg, qp_context.peer_qpn(), expected_psn);
+                let _ = self.udp_agent.get().unwrap().send_to(&buf, src);
+            }
+            return Ok(());
+        }
+        if psn_after(psn, expected_psn) {
+            // a SEND message must be placed in order, drop it and wait for retransmission
+            log::warn!("QPN: {qpn}: drop out of order SEND packet {psn}, expected {expected_psn}");
+            return Ok(());
+        }
+
+        if is_first {
+            let srq_guard = self.shared_receive_queue_table().guard();
+            if qp_context
+                .start_recv(self.shared_receive_queue_of(qp_context, &srq_guard))
+                .is_none()
+            {
+                // the expected PSN is kept, so the sender retries the whole message after the RNR timer
+                log::warn!("QPN: {qpn}: no receive work request, reply RNR NAK to SEND packet {psn}");
+                let buf = generate_rnr_nak(msg, qp_context.peer_qpn(), psn, qp_context.min_rnr_timer());
+                let _ = self.udp_agent.get().unwrap().send_to(&buf, src);
+                return Ok(());
+            }
+        }
+        // SEND has no RETH, packets are placed in PSN order, right after the bytes placed by the former ones
+        let len = msg.payload.get_length() as u32;
+        let Some((wr, offset)) = qp_context.current_recv() else {
+            log::warn!("QPN: {qpn}: no receive work request, drop SEND packet {psn}");
+            return Ok(());
+        };
+
+        let message_len = offset.saturating_add(len);
+        let overflow = message_len > wr.len;
+        let mr_error = overflow || {
+            let data = &msg.payload.sg_list;
+            assert_eq!(data.len(), 1, "currently only consider one Sge");
+            let va = VirtualAddress(wr.addr.0 + u64::from(offset));
+            placement_failed(self.copy_to(data[0], wr.key, va, MemoryAccessFlag::IbvAccessLocalWrite))?
+        };
+        qp_context.place_recv(len);
+        // the IETH carries the remote key to invalidate, which is reported like the immediate data
+        let inv_error = is_last && with_invalidate && !mr_error && {
+            let key = MemoryRegionKey::new(header.imm.expect("SEND with invalidate without IETH"));
+            self.memory_region_table()
+                .invalidate(key)
+                .inspect_err(|error| log::warn!("QPN: {qpn}: failed to invalidate: {error}"))
+                .is_err()
+        };
+
+        let nak_code = if overflow {
+            Some(NAK_INVALID_REQUEST)
+        } else if mr_error {
+            Some(NAK_REMOTE_ACCESS_ERROR)
+        } else if inv_error {
+            Some(NAK_REMOTE_OPERATIONAL_ERROR)
+        } else {
+            None
+        };
+        // the device answers the request unless the QP is recovering from a PSN gap
+        let can_auto_ack = !qp_context.is_error();
+        if let Some(code) = nak_code {
+            // the expected PSN is kept, so a retransmission is rejected again instead of acknowledged as a duplicate,
+            // and the rest of the message is dropped as out of order
+            log::warn!("QPN: {qpn}: reject SEND packet {psn} with Nak code {code:#x}");
+            if can_auto_ack {
+                let buf = generate_nak(msg, qp_context.peer_qpn(), psn, code);
+                let _ = self.udp_agent.get().unwrap().send_to(&buf, src);
+            }
+        } else {
+            qp_context.set_expect_psn(next_psn(psn));
+            if can_auto_ack && header.common_meta.ack_req {
+                let buf = generate_ack(msg, qp_context.peer_qpn(), expected_psn);
+                let _ = self.udp_agent.get().unwrap().send_to(&buf, src);
+            }
+        }
+
+        // only report the SEND message once it is fully placed, or once it failed
+        if is_last || nak_code.is_some() {
+            let _ = qp_context.finish_recv();
+
+            let req_status = if mr_error {
+                ToHostWorkRbDescStatus::InvMrRegion
+            } else if inv_error {
+                ToHostWorkRbDescStatus::InvMrKey
+            } else {
+                ToHostWorkRbDescStatus::Normal
+            };
+            let descriptor0 = message_to_bthreth(msg, expected_psn, req_status.into(), can_auto_ack)
+                .with_recv_tag(wr.tag)
+                .with_message_len(message_len);
+            log::debug!("push meta report: {descriptor0:?}");
+            unsafe { self.meta_report_queue().push(descriptor0) };
+
+            if is_last && (with_immediate || with_invalidate) {
+                let descriptor1 = message_to_imm_dt(msg);
+                log::debug!("push meta report: {descriptor1:?}");
+                unsafe { self.meta_report_queue().push(descriptor1) };
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<UA: Agent, DC: Client> DeviceInner<UA, DC> {
+    /// UD SEND is never acknowledged, the datagram is silently dropped if it can not be received
+    fn handle_datagram(&self, msg: &RdmaMessage, deth: &DethHeader, src: core::net::IpAddr) -> crate::Result {
+        let Metadata::General(ref header) = msg.meta_data else {
+            unreachable!("logic error: SEND without reth");
+        };
+
+        let qpn = header.common_meta.dqpn.get();
+        let guard = self.queue_pair_table().guard();
+        let Some(qp_context) = self.queue_pair_table().get(qpn, &guard) else {
+            log::warn!("drop datagram to unknown QPN: {qpn}");
+            return Ok(());
+        };
+        if qp_context.qp_type() != QueuePairType::Ud {
+            log::warn!("QPN: {qpn}: drop datagram to {:?} queue pair", qp_context.qp_type());
+            return Ok(());
+        }
+        if deth.qkey != qp_context.qkey() {
+            log::warn!("QPN: {qpn}: drop datagram with mismatched Q_Key {:#x}", deth.qkey);
+            return Ok(());
+        }
+        let core::net::IpAddr::V4(src) = src else {
+            log::warn!("QPN: {qpn}: drop datagram from {src}");
+            return Ok(());
+        };
+        let srq_guard = self.shared_receive_queue_table().guard();
+        let srq = self.shared_receive_queue_of(qp_context, &srq_guard);
+        let Some(wr) = qp_context.take_recv(srq) else {
+            log::warn!("QPN: {qpn}: no receive work request, drop datagram");
+            return Ok(());
+        };
+
+        let overflow = header.reth.len > wr.len;
+        let mr_error = overflow || {
+            let data = &msg.payload.sg_list;
+            assert_eq!(data.len(), 1, "currently only consider one Sge");
+            // the datagram faulted on a page is received again by the same receive work request
+            placement_failed(self.copy_to(data[0], wr.key, wr.addr, MemoryAccessFlag::IbvAccessLocalWrite))
+                .inspect_err(|_| qp_context.untake_recv(wr, srq))?
+        };
+
+        let req_status = if mr_error {
+            ToHostWorkRbDescStatus::InvMrRegion
+        } else {
+            ToHostWorkRbDescStatus::Normal
+        };
+        let descriptor0 = datagram_to_bthreth(msg, src, req_status.into()).with_recv_tag(wr.tag);
+        log::debug!("push meta report: {descriptor0:?}");
+        unsafe { self.meta_report_queue().push(descriptor0) };
+
+        if header.imm.is_some() {
+            let descriptor1 = message_to_imm_dt(msg);
+            log::debug!("push meta report: {descriptor1:?}");
+            unsafe { self.meta_report_queue().push(descriptor1) };
+        }
+
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
-use super::HandleMessage;
+use super::{HandleMessage, placement_failed};
 use crate::DeviceInner;
 use crate::dma::Client;
 use crate::net::util::{generate_ack, message_to_bthreth, message_to_imm_dt};
 use crate::net::{Agent, Error};
+use crate::queue_pair::{next_psn, psn_after, psn_after_or_eq};
 use crate::queues::complete_queue::CompleteQueue;
 use crate::third_party::net::RdmaMessage;
 use crate::third_party::queues::meta_report::ToHostWorkRbDescStatus;
@@ -32,7 +33,7 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
         let qpn = msg.meta_data.common_meta().dqpn.get();
         let psn = msg.meta_data.common_meta().psn.get();
 
-        let mr_error = self.copy_to_with_key(msg).is_err();
+        let mr_error = placement_failed(self.copy_to_with_key(msg))?;
 
         let guard = self.queue_pair_table().guard();
         let qp_context = self.queue_pair_table().get(qpn, &guard);
@@ -46,6 +47,7 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
         let can_auto_ack;
         let _can_skip_report_header;
         let expected_psn_option;
+        let mut recv_tag = 0;
 
         if abnormal_packet {
             can_auto_ack = false;
@@ -63,8 +65,8 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
 
             expected_psn_option = Some(expected_psn);
 
-            let new_expected_psn = (psn >= expected_psn).then_some(psn + 1);
-            let new_error_psn = (psn > expected_psn).then_some(psn);
+            let new_expected_psn = psn_after_or_eq(psn, expected_psn).then(|| next_psn(psn));
+            let new_error_psn = psn_after(psn, expected_psn).then_some(psn);
 
             if let Some(new_expected_psn) = new_expected_psn {
                 qp_context.set_expect_psn(new_expected_psn);
@@ -73,6 +75,16 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
             if let Some(new_error_psn) = new_error_psn {
                 qp_context.set_error_psn(new_error_psn);
             }
+
+            // the driver completes the oldest receive work request of the queue pair itself,
+            // but it has to be told which one of a shared receive queue is consumed
+            if qp_context.srqn().is_some() && new_expected_psn.is_some() {
+                let srq_guard = self.shared_receive_queue_table().guard();
+                match qp_context.take_recv(self.shared_receive_queue_of(qp_context, &srq_guard)) {
+                    Some(wr) => recv_tag = wr.tag,
+                    None => log::warn!("QPN: {qpn}: no receive work request for RDMA write with immediate"),
+                }
+            }
         }
 
         let need_ack = can_auto_ack && msg.meta_data.common_meta().ack_req;
@@ -87,7 +99,7 @@ impl<UA: Agent, DC: Client> HandleMessage<Message<'_>> for DeviceInner<UA, DC> {
             log::debug!("push meta report: {descriptor0:?}");
             unsafe { self.meta_report_queue().push(descriptor0) };
 
-            let descriptor1 = message_to_imm_dt(msg);
+            let descriptor1 = message_to_imm_dt(msg).with_recv_tag(recv_tag);
             log::debug!("push meta report: {descriptor1:?}");
             unsafe { self.meta_report_queue().push(descriptor1) };
         }
@@ -7,10 +7,13 @@ use crate::queues::{
     AckExtendedTransportHeader, BaseTransportHeader, BthAeth, BthReth, ImmDt, RdmaExtendedTransportHeader,
     SecondaryReth,
 };
-use crate::third_party::net::{AETH, AethHeader, BTH, Metadata, PacketWriter, PayloadInfo, RdmaMessage};
+use crate::third_party::net::{
+    AETH, AethHeader, BTH, Metadata, PacketWriter, PayloadInfo, Qpn, RdmaMessage, RdmaMessageMetaCommon,
+};
 use crate::third_party::queues::meta_report::{
     ToHostWorkRbDescAethCode, ToHostWorkRbDescOpcode, ToHostWorkRbDescStatus, ToHostWorkRbDescTransType,
 };
+use crate::third_party::rdma::Psn;
 use crate::types::{PacketSequenceNumber, QueuePairNumber};
 
 pub(super) fn message_to_bthreth(
@@ -22,7 +25,15 @@ pub(super) fn message_to_bthreth(
     let meta = &msg.meta_data;
     match meta {
         Metadata::General(header) => match header.common_meta.opcode {
-            ToHostWorkRbDescOpcode::RdmaWriteFirst
+            ToHostWorkRbDescOpcode::SendFirst
+            | ToHostWorkRbDescOpcode::SendMiddle
+            | ToHostWorkRbDescOpcode::SendLast
+            | ToHostWorkRbDescOpcode::SendLastWithImmediate
+            | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+            | ToHostWorkRbDescOpcode::SendOnly
+            | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+            | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+            | ToHostWorkRbDescOpcode::RdmaWriteFirst
             | ToHostWorkRbDescOpcode::RdmaWriteMiddle
             | ToHostWorkRbDescOpcode::RdmaWriteLast
             | ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate
@@ -35,7 +46,7 @@ pub(super) fn message_to_bthreth(
             | ToHostWorkRbDescOpcode::RdmaReadResponseOnly => {
                 // TODO(fh): Add helper function to all operation
                 // Operation -> Descriptor
-                let trans_type = ToHostWorkRbDescTransType::Rc.into();
+                let trans_type = header.common_meta.tran_type.into();
                 let opcode = header.common_meta.opcode.clone().into();
                 let qpn = header.common_meta.dqpn.get();
                 let psn = header.common_meta.psn.get();
@@ -55,12 +66,45 @@ pub(super) fn message_to_bthreth(
 
                 BthReth::new(expected_psn, req_status, bth, reth, msn, can_auto_ack)
             }
-            ToHostWorkRbDescOpcode::Acknowledge => todo!(),
+            ToHostWorkRbDescOpcode::Acknowledge
+            | ToHostWorkRbDescOpcode::AtomicAcknowledge
+            | ToHostWorkRbDescOpcode::CompareSwap
+            | ToHostWorkRbDescOpcode::FetchAdd => {
+                unreachable!("logic error: general message with opcode of acknowledge or atomic operation")
+            }
         },
-        Metadata::Acknowledge(_header) => todo!(),
+        Metadata::Acknowledge(_) | Metadata::Atomic(_) => {
+            unreachable!("logic error: only SEND, RDMA write and read are reported by BTH and RETH")
+        }
     }
 }
 
+/// UD SEND has no RETH, so the reth of meta report carries the sender of the message instead,
+/// the va holds the source IP in the higher 32 bits and the source QPN in the lower 24 bits.
+pub(super) fn datagram_to_bthreth(msg: &RdmaMessage, src: Ipv4Addr, req_status: u8) -> BthReth {
+    let Metadata::General(ref header) = msg.meta_data else {
+        unreachable!("logic error");
+    };
+    let deth = header.deth.expect("datagram without deth");
+
+    let trans_type = ToHostWorkRbDescTransType::Ud.into();
+    let opcode = header.common_meta.opcode.clone().into();
+    let qpn = header.common_meta.dqpn.get();
+    let psn = header.common_meta.psn.get();
+    let pad_cnt = msg.payload.get_pad_cnt() as u8;
+    let solicited = false;
+    let is_ack_req = false;
+    let bth = BaseTransportHeader::new(trans_type, opcode, qpn, psn, solicited, is_ack_req, pad_cnt);
+
+    let source = u64::from(src.to_bits()) << 32 | u64::from(deth.src_qpn.get());
+    let reth = RdmaExtendedTransportHeader::new(source.into(), deth.qkey.into(), header.reth.len);
+
+    let msn = header.common_meta.pkey.get();
+
+    // UD message is never acknowledged, so the expected psn is meaningless
+    BthReth::new(psn, req_status, bth, reth, msn, true)
+}
+
 pub(super) fn message_to_secondary_reth(msg: &RdmaMessage) -> SecondaryReth {
     let Metadata::General(ref header) = msg.meta_data else {
         unreachable!("logic error");
@@ -90,9 +134,14 @@ pub(super) fn message_to_imm_dt(msg: &RdmaMessage) -> ImmDt {
     assert!(
         matches!(
             header.common_meta.opcode,
-            ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate | ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate
+            ToHostWorkRbDescOpcode::SendLastWithImmediate
+                | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+                | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+                | ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate
+                | ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate
         ),
-        "opcode mismatch, contains immediate data"
+        "opcode mismatch, contains immediate data or invalidated key"
     );
 
     let data = header.imm.expect("without immediate data");
@@ -105,14 +154,17 @@ pub(super) fn message_to_bthaeth(msg: &RdmaMessage) -> BthAeth {
         unreachable!();
     };
 
-    assert_eq!(
-        header.aeth_code,
-        ToHostWorkRbDescAethCode::Ack,
-        "currently only support normal Ack"
+    assert!(
+        matches!(
+            header.aeth_code,
+            ToHostWorkRbDescAethCode::Ack | ToHostWorkRbDescAethCode::Rnr | ToHostWorkRbDescAethCode::Nak
+        ),
+        "currently only support normal Ack, RNR Nak and Nak"
     );
 
     let trans_type = ToHostWorkRbDescTransType::Rc.into();
-    let opcode = header.common_meta.opcode.clone().into();
+    // atomic acknowledge is reported as normal Ack, the original value is already placed in local buffer
+    let opcode = ToHostWorkRbDescOpcode::Acknowledge.into();
     let qpn = header.common_meta.dqpn.get();
     let psn = header.common_meta.psn.get();
     let pad_cnt = msg.payload.get_pad_cnt() as u8;
@@ -149,7 +201,7 @@ pub(super) fn generate_ack(
         aeth.set_aeth_code_and_value(ToHostWorkRbDescAethCode::Ack.into(), 0x1f);
         aeth.set_msn(msg.meta_data.common_meta().pkey.get().into());
         RdmaMessage {
-            meta_data: Metadata::Acknowledge(AethHeader::new_from_packet(bth, aeth).unwrap()),
+            meta_data: Metadata::Acknowledge(AethHeader::new_from_packet(bth, aeth, None).unwrap()),
             payload: PayloadInfo::new(),
         }
     };
@@ -169,6 +221,107 @@ pub(super) fn generate_ack(
     udp_datagram.payload().to_vec()
 }
 
+/// Nak code of AETH value, the request is malformed or too long for the receive work request
+pub(super) const NAK_INVALID_REQUEST: u8 = 0b0_0001;
+/// Nak code of AETH value, the remote access is denied by memory region
+pub(super) const NAK_REMOTE_ACCESS_ERROR: u8 = 0b0_0010;
+/// Nak code of AETH value, the responder failed to complete a valid request
+pub(super) const NAK_REMOTE_OPERATIONAL_ERROR: u8 = 0b0_0011;
+
+/// Generate the response of an atomic request, which is an atomic acknowledge carrying the original value.
+/// If the atomic failed, a Nak with remote access error is generated instead.
+pub(super) fn generate_atomic_ack(
+    msg: &RdmaMessage,
+    peer_qpn: QueuePairNumber,
+    psn: PacketSequenceNumber,
+    orig: Option<u64>,
+) -> Vec<u8> {
+    let (opcode, aeth_code, aeth_value) = if orig.is_some() {
+        (
+            ToHostWorkRbDescOpcode::AtomicAcknowledge,
+            ToHostWorkRbDescAethCode::Ack,
+            0x1f,
+        )
+    } else {
+        (
+            ToHostWorkRbDescOpcode::Acknowledge,
+            ToHostWorkRbDescAethCode::Nak,
+            NAK_REMOTE_ACCESS_ERROR,
+        )
+    };
+    generate_acknowledge(msg, peer_qpn, psn, opcode, aeth_code, aeth_value, orig)
+}
+
+/// Generate the RNR Nak of a request which finds no receive work request, `timer` is the RNR timer code
+/// telling the sender how long to wait before retrying it.
+pub(super) fn generate_rnr_nak(
+    msg: &RdmaMessage,
+    peer_qpn: QueuePairNumber,
+    psn: PacketSequenceNumber,
+    timer: u8,
+) -> Vec<u8> {
+    generate_acknowledge(
+        msg,
+        peer_qpn,
+        psn,
+        ToHostWorkRbDescOpcode::Acknowledge,
+        ToHostWorkRbDescAethCode::Rnr,
+        timer,
+        None,
+    )
+}
+
+/// Generate the Nak of a request which the responder will never complete, `code` is the Nak code of AETH value.
+/// The requester fails the request instead of retrying it.
+pub(super) fn generate_nak(
+    msg: &RdmaMessage,
+    peer_qpn: QueuePairNumber,
+    psn: PacketSequenceNumber,
+    code: u8,
+) -> Vec<u8> {
+    generate_acknowledge(
+        msg,
+        peer_qpn,
+        psn,
+        ToHostWorkRbDescOpcode::Acknowledge,
+        ToHostWorkRbDescAethCode::Nak,
+        code,
+        None,
+    )
+}
+
+fn generate_acknowledge(
+    msg: &RdmaMessage,
+    peer_qpn: QueuePairNumber,
+    psn: PacketSequenceNumber,
+    opcode: ToHostWorkRbDescOpcode,
+    aeth_code: ToHostWorkRbDescAethCode,
+    aeth_value: u8,
+    orig: Option<u64>,
+) -> Vec<u8> {
+    let common_meta = msg.meta_data.common_meta();
+    let ack = RdmaMessage {
+        meta_data: Metadata::Acknowledge(AethHeader {
+            common_meta: RdmaMessageMetaCommon {
+                tran_type: ToHostWorkRbDescTransType::Rc,
+                opcode,
+                solicited: false,
+                pkey: common_meta.pkey,
+                dqpn: Qpn::new(peer_qpn),
+                ack_req: false,
+                psn: Psn::new(psn),
+            },
+            aeth_code,
+            aeth_value,
+            msn: common_meta.pkey.get().into(),
+            atomic_orig: orig,
+        }),
+        payload: PayloadInfo::new(),
+    };
+    // FIXME(fh): hardcode for calculate Invariant CRC, should remove
+    generate_payload_from_msg(&ack, Ipv4Addr::new(192, 168, 0, 3), Ipv4Addr::new(192, 168, 0, 2))
+}
+
 pub fn generate_payload_from_msg(msg: &RdmaMessage, src: Ipv4Addr, dst: Ipv4Addr) -> Vec<u8> {
     let mut buf = vec![0; 8192];
     let _len = PacketWriter::new(&mut buf)
@@ -190,7 +343,9 @@ mod tests {
     use smoltcp::wire::{EthernetFrame, Ipv4Packet, UdpPacket};
 
     use super::*;
-    use crate::third_party::net::PacketProcessor;
+    use crate::third_party::net::{
+        AtomicEthHeader, DethHeader, Key, PKey, PacketProcessor, RdmaGeneralMeta, RethHeader,
+    };
 
     fn write_first_message() -> RdmaMessage {
         let file = ".cache/captures/ethernet-frame-0.bin";
@@ -222,6 +377,161 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_atomic_round_trip() {
+        let header = AtomicEthHeader {
+            common_meta: RdmaMessageMetaCommon {
+                tran_type: ToHostWorkRbDescTransType::Rc,
+                opcode: ToHostWorkRbDescOpcode::CompareSwap,
+                solicited: false,
+                pkey: PKey::new(7),
+                dqpn: Qpn::new(3),
+                ack_req: true,
+                psn: Psn::new(0x10),
+            },
+            va: 0x1000_0008,
+            rkey: Key::new(0x1234_5678),
+            swap_add: 0x0102_0304_0506_0708,
+            compare: 0x1112_1314_1516_1718,
+        };
+        let msg = RdmaMessage {
+            meta_data: Metadata::Atomic(header),
+            payload: PayloadInfo::new(),
+        };
+        let payload = generate_payload_from_msg(&msg, Ipv4Addr::new(192, 168, 0, 2), Ipv4Addr::new(192, 168, 0, 3));
+        let parsed = PacketProcessor::to_rdma_message(&payload).unwrap();
+        let Metadata::Atomic(parsed_header) = parsed.meta_data else {
+            panic!("expect atomic message");
+        };
+        assert!(parsed_header.is_compare_swap());
+        assert_eq!(parsed_header.va, 0x1000_0008);
+        assert_eq!(parsed_header.rkey.get(), 0x1234_5678);
+        assert_eq!(parsed_header.swap_add, 0x0102_0304_0506_0708);
+        assert_eq!(parsed_header.compare, 0x1112_1314_1516_1718);
+        assert_eq!(parsed_header.common_meta.pkey.get(), 7);
+
+        let ack = generate_atomic_ack(&msg, 5, 0x10, Some(0xdead_beef));
+        let parsed = PacketProcessor::to_rdma_message(&ack).unwrap();
+        let Metadata::Acknowledge(aeth) = parsed.meta_data else {
+            panic!("expect acknowledge message");
+        };
+        assert_eq!(aeth.common_meta.opcode, ToHostWorkRbDescOpcode::AtomicAcknowledge);
+        assert_eq!(aeth.common_meta.dqpn.get(), 5);
+        assert_eq!(aeth.msn, 7);
+        assert_eq!(aeth.atomic_orig, Some(0xdead_beef));
+
+        let nak = generate_atomic_ack(&msg, 5, 0x10, None);
+        let parsed = PacketProcessor::to_rdma_message(&nak).unwrap();
+        let Metadata::Acknowledge(aeth) = parsed.meta_data else {
+            panic!("expect acknowledge message");
+        };
+        assert_eq!(aeth.aeth_code, ToHostWorkRbDescAethCode::Nak);
+        assert_eq!(aeth.aeth_value, NAK_REMOTE_ACCESS_ERROR);
+        assert_eq!(aeth.atomic_orig, None);
+
+        let rnr_nak = generate_rnr_nak(&msg, 5, 0x10, 14);
+        let parsed = PacketProcessor::to_rdma_message(&rnr_nak).unwrap();
+        let Metadata::Acknowledge(aeth) = parsed.meta_data else {
+            panic!("expect acknowledge message");
+        };
+        assert_eq!(aeth.common_meta.opcode, ToHostWorkRbDescOpcode::Acknowledge);
+        assert_eq!(aeth.common_meta.psn.get(), 0x10);
+        assert_eq!(aeth.aeth_code, ToHostWorkRbDescAethCode::Rnr);
+        assert_eq!(aeth.aeth_value, 14);
+        assert_eq!(aeth.msn, 7);
+
+        let nak = generate_nak(&msg, 5, 0x10, NAK_INVALID_REQUEST);
+        let parsed = PacketProcessor::to_rdma_message(&nak).unwrap();
+        let Metadata::Acknowledge(aeth) = parsed.meta_data else {
+            panic!("expect acknowledge message");
+        };
+        assert_eq!(aeth.common_meta.psn.get(), 0x10);
+        assert_eq!(aeth.aeth_code, ToHostWorkRbDescAethCode::Nak);
+        assert_eq!(aeth.aeth_value, NAK_INVALID_REQUEST);
+        assert_eq!(aeth.msn, 7);
+    }
+
+    #[test]
+    fn test_datagram_round_trip() {
+        let data = [0x5au8; 13];
+        let header = RdmaGeneralMeta {
+            common_meta: RdmaMessageMetaCommon {
+                tran_type: ToHostWorkRbDescTransType::Ud,
+                opcode: ToHostWorkRbDescOpcode::SendOnlyWithImmediate,
+                solicited: false,
+                pkey: PKey::new(9),
+                dqpn: Qpn::new(4),
+                ack_req: false,
+                psn: Psn::new(0x20),
+            },
+            reth: RethHeader::default(),
+            imm: Some(0xcafe_f00d),
+            secondary_reth: None,
+            deth: Some(DethHeader {
+                qkey: 0x1111_2222,
+                src_qpn: Qpn::new(6),
+            }),
+        };
+        let msg = RdmaMessage {
+            meta_data: Metadata::General(header),
+            payload: PayloadInfo::new_with_data(data.as_ptr(), data.len()),
+        };
+        let payload = generate_payload_from_msg(&msg, Ipv4Addr::new(192, 168, 0, 2), Ipv4Addr::new(192, 168, 0, 3));
+        let parsed = PacketProcessor::to_rdma_message(&payload).unwrap();
+        let Metadata::General(parsed_header) = parsed.meta_data else {
+            panic!("expect general message");
+        };
+        assert!(matches!(
+            parsed_header.common_meta.tran_type,
+            ToHostWorkRbDescTransType::Ud
+        ));
+        assert_eq!(parsed_header.common_meta.dqpn.get(), 4);
+        assert_eq!(parsed_header.imm, Some(0xcafe_f00d));
+        assert_eq!(parsed_header.reth.va, 0);
+        assert_eq!(parsed_header.reth.len, 13);
+        let deth = parsed_header.deth.unwrap();
+        assert_eq!(deth.qkey, 0x1111_2222);
+        assert_eq!(deth.src_qpn.get(), 6);
+        assert_eq!(parsed.payload.sg_list[0].len, 13);
+    }
+
+    #[test]
+    fn test_send_round_trip() {
+        let data = [0xa5u8; 13];
+        let header = RdmaGeneralMeta {
+            common_meta: RdmaMessageMetaCommon {
+                tran_type: ToHostWorkRbDescTransType::Rc,
+                opcode: ToHostWorkRbDescOpcode::SendOnlyWithImmediate,
+                solicited: false,
+                pkey: PKey::new(9),
+                dqpn: Qpn::new(4),
+                ack_req: true,
+                psn: Psn::new(0x20),
+            },
+            reth: RethHeader::default(),
+            imm: Some(0xcafe_f00d),
+            secondary_reth: None,
+            deth: None,
+        };
+        let msg = RdmaMessage {
+            meta_data: Metadata::General(header),
+            payload: PayloadInfo::new_with_data(data.as_ptr(), data.len()),
+        };
+        let payload = generate_payload_from_msg(&msg, Ipv4Addr::new(192, 168, 0, 2), Ipv4Addr::new(192, 168, 0, 3));
+        // BTH, immediate data, padded payload and ICRC, without RETH
+        assert_eq!(payload.len(), 12 + 4 + 16 + 4);
+        let parsed = PacketProcessor::to_rdma_message(&payload).unwrap();
+        let Metadata::General(parsed_header) = parsed.meta_data else {
+            panic!("expect general message");
+        };
+        assert_eq!(parsed_header.common_meta.dqpn.get(), 4);
+        assert_eq!(parsed_header.imm, Some(0xcafe_f00d));
+        assert_eq!(parsed_header.reth.len, 13);
+        assert_eq!(parsed.payload.sg_list[0].len, 13);
+        let placed = unsafe { core::slice::from_raw_parts(parsed.payload.sg_list[0].data, 13) };
+        assert_eq!(placed, &data);
+    }
+
     #[test]
     fn test_generate_ack() {
         let expected = &[
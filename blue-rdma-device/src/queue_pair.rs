@@ -1,10 +1,61 @@
 use core::sync::atomic::AtomicU32;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
 
 use papaya::HashMap;
 
+use super::address::VirtualAddress;
+use super::shared_receive_queue;
 use super::types::{
-    MemoryAccessFlag, PacketSequenceNumber, PathMtuKind, ProtectDomainHandler, QueuePairNumber, QueuePairType,
+    MemoryAccessFlag, MemoryRegionKey, MessageSequenceNumber, PacketSequenceNumber, PathMtuKind, ProtectDomainHandler,
+    QueuePairNumber, QueuePairType, SharedReceiveQueueNumber,
 };
+use crate::third_party::rdma::Psn;
+
+/// PSN is 24 bits and wraps around, `psn` is after `base` if it is in the half of the PSN space following `base`
+pub fn psn_after_or_eq(psn: PacketSequenceNumber, base: PacketSequenceNumber) -> bool {
+    Psn::new(psn).larger_in_psn(Psn::new(base))
+}
+
+pub fn psn_after(psn: PacketSequenceNumber, base: PacketSequenceNumber) -> bool {
+    psn != base && psn_after_or_eq(psn, base)
+}
+
+pub fn next_psn(psn: PacketSequenceNumber) -> PacketSequenceNumber {
+    Psn::new(psn).wrapping_add(1).get()
+}
+
+/// Receive buffer posted by driver, consumed by incoming SEND message
+#[derive(Debug, Clone, Copy)]
+pub struct ReceiveWorkRequest {
+    pub addr: VirtualAddress,
+    pub len: u32,
+    pub key: MemoryRegionKey,
+    /// Chosen by driver, reported back once the request is consumed, so that the driver knows which request
+    /// of a shared receive queue is consumed by which queue pair
+    pub tag: u32,
+}
+
+impl ReceiveWorkRequest {
+    pub const fn new(addr: VirtualAddress, len: u32, key: MemoryRegionKey, tag: u32) -> Self {
+        Self { addr, len, key, tag }
+    }
+}
+
+/// Local buffer of an outstanding RDMA READ, the read response is scattered into buffers in order.
+/// It is also the buffer receiving the original value of an outstanding atomic operation.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadBuffer {
+    pub addr: VirtualAddress,
+    pub len: u32,
+    pub key: MemoryRegionKey,
+}
+
+impl ReadBuffer {
+    pub const fn new(addr: VirtualAddress, len: u32, key: MemoryRegionKey) -> Self {
+        Self { addr, len, key }
+    }
+}
 
 #[derive(Debug)]
 pub struct Context {
@@ -12,17 +63,36 @@ pub struct Context {
     peer_queue_pair_number: QueuePairNumber,
     #[expect(unused, reason = "may use later")]
     protect_domain_handler: ProtectDomainHandler,
-    #[expect(unused, reason = "may use later")]
     queue_pair_type: QueuePairType,
     #[expect(unused, reason = "may use later")]
     access_flag: MemoryAccessFlag,
     #[expect(unused, reason = "may use later")]
     path_mtu_kind: PathMtuKind,
+    /// RNR timer code replied in RNR NAK, the time the sender should wait before retrying
+    min_rnr_timer: u8,
+    /// Q_Key of UD queue pair, incoming datagrams carrying other Q_Key are dropped
+    queue_key: u32,
+    /// Receive work requests are taken from the shared receive queue instead of `recv_queue` if it is set
+    shared_receive_queue_number: Option<SharedReceiveQueueNumber>,
+    /// The driver moved the queue pair to the error state, all incoming packets are dropped until it's reset.
+    /// Unlike `error_psn`, it's not recovered by the peer.
+    in_error_state: bool,
     error_psn: AtomicU32,
     expected_psn: AtomicU32,
+    recv_queue: Mutex<VecDeque<ReceiveWorkRequest>>,
+    /// Receive work request consumed by the SEND message in progress, and the bytes placed into it
+    current_recv: Mutex<Option<(ReceiveWorkRequest, u32)>>,
+    /// Outstanding RDMA READ with more than one local buffer, indexed by msn
+    pending_reads: Mutex<BTreeMap<MessageSequenceNumber, Vec<ReadBuffer>>>,
+    /// Outstanding atomic operations, indexed by msn
+    pending_atomics: Mutex<BTreeMap<MessageSequenceNumber, ReadBuffer>>,
 }
 
 impl Context {
+    #[expect(
+        clippy::too_many_arguments,
+        reason = "all attributes come from the queue pair management descriptor"
+    )]
     pub const fn new(
         queue_pair_number: QueuePairNumber,
         peer_queue_pair_number: QueuePairNumber,
@@ -30,6 +100,11 @@ impl Context {
         queue_pair_type: QueuePairType,
         access_flag: MemoryAccessFlag,
         path_mtu_kind: PathMtuKind,
+        min_rnr_timer: u8,
+        queue_key: u32,
+        receive_psn: PacketSequenceNumber,
+        shared_receive_queue_number: Option<SharedReceiveQueueNumber>,
+        in_error_state: bool,
     ) -> Self {
         Self {
             queue_pair_number,
@@ -38,8 +113,16 @@ impl Context {
             queue_pair_type,
             access_flag,
             path_mtu_kind,
+            min_rnr_timer,
+            queue_key,
+            shared_receive_queue_number,
+            in_error_state,
             error_psn: AtomicU32::new(u32::MAX),
-            expected_psn: AtomicU32::new(0),
+            expected_psn: AtomicU32::new(receive_psn),
+            recv_queue: Mutex::new(VecDeque::new()),
+            current_recv: Mutex::new(None),
+            pending_reads: Mutex::new(BTreeMap::new()),
+            pending_atomics: Mutex::new(BTreeMap::new()),
         }
     }
 
@@ -47,6 +130,32 @@ impl Context {
         self.peer_queue_pair_number
     }
 
+    pub const fn qp_type(&self) -> QueuePairType {
+        self.queue_pair_type
+    }
+
+    pub const fn min_rnr_timer(&self) -> u8 {
+        self.min_rnr_timer
+    }
+
+    pub const fn qkey(&self) -> u32 {
+        self.queue_key
+    }
+
+    pub const fn srqn(&self) -> Option<SharedReceiveQueueNumber> {
+        self.shared_receive_queue_number
+    }
+
+    pub const fn in_error_state(&self) -> bool {
+        self.in_error_state
+    }
+
+    /// keep the posted receive work requests of the queue pair whose attributes are modified
+    pub fn inherit_state(&self, old: &Self) {
+        let mut recv_queue = self.recv_queue.lock().unwrap();
+        recv_queue.extend(old.recv_queue.lock().unwrap().drain(..));
+    }
+
     /// try recover from error state, return true if current state is not error state
     pub fn try_recover(&self, psn: PacketSequenceNumber) -> bool {
         let error_psn = self.error_psn();
@@ -74,7 +183,7 @@ impl Context {
             i64::from(old_error_psn)
         );
         assert!(
-            old_error_psn < error_psn || old_error_psn == u32::MAX,
+            old_error_psn == u32::MAX || psn_after(error_psn, old_error_psn),
             "logic error, new error psn should greater than old, but {old_error_psn} < {error_psn}"
         );
     }
@@ -102,6 +211,121 @@ impl Context {
     pub fn set_expect_psn(&self, psn: PacketSequenceNumber) {
         self.expected_psn.store(psn, core::sync::atomic::Ordering::SeqCst);
     }
+
+    /// append receive work request into receive queue
+    pub fn post_recv(&self, wr: ReceiveWorkRequest) {
+        self.recv_queue.lock().unwrap().push_back(wr);
+    }
+
+    /// take the oldest receive work request for a new SEND message, return None if receive queue is empty
+    ///
+    /// `srq` is the shared receive queue the queue pair is attached to, if any.
+    /// The receive work request which has nothing placed is kept, as the first packet of the message is handled
+    /// again after a page fault.
+    pub fn start_recv(&self, srq: Option<&shared_receive_queue::Context>) -> Option<ReceiveWorkRequest> {
+        let mut current_recv = self.current_recv.lock().unwrap();
+        if let Some((wr, 0)) = *current_recv {
+            return Some(wr);
+        }
+        let wr = self.take_recv(srq)?;
+        if let Some((old, _)) = current_recv.replace((wr, 0)) {
+            log::warn!("QPN: {}: drop unfinished receive {old:?}", self.queue_pair_number);
+        }
+        Some(wr)
+    }
+
+    /// take the oldest receive work request for a message which always arrives in one packet,
+    /// such as a UD message or the last packet of a RDMA write with immediate
+    pub fn take_recv(&self, srq: Option<&shared_receive_queue::Context>) -> Option<ReceiveWorkRequest> {
+        if self.shared_receive_queue_number.is_some() {
+            // the shared receive queue is destroyed
+            srq?.take_recv()
+        } else {
+            self.recv_queue.lock().unwrap().pop_front()
+        }
+    }
+
+    /// put back the receive work request taken by `take_recv`, which is not consumed
+    pub fn untake_recv(&self, wr: ReceiveWorkRequest, srq: Option<&shared_receive_queue::Context>) {
+        match srq {
+            Some(srq) if self.shared_receive_queue_number.is_some() => srq.untake_recv(wr),
+            _ => self.recv_queue.lock().unwrap().push_front(wr),
+        }
+    }
+
+    /// UC has no retransmission, so a message losing any packet is dropped as a whole.
+    /// After a PSN gap, packets are discarded until the first packet of a new message arrives,
+    /// then the expected PSN is resynchronized to it.
+    ///
+    /// return false if the packet should be dropped
+    pub fn check_unreliable_psn(
+        &self,
+        psn: PacketSequenceNumber,
+        is_first_packet: bool,
+        srq: Option<&shared_receive_queue::Context>,
+    ) -> bool {
+        if psn == self.expected_psn() {
+            return true;
+        }
+        if !is_first_packet {
+            log::warn!("QPN: {}: drop packet {psn} of broken message", self.queue_pair_number);
+            return false;
+        }
+        // the receive work request of the broken SEND message is not consumed
+        if let Some(wr) = self.finish_recv() {
+            self.untake_recv(wr, srq);
+        }
+        self.set_expect_psn(psn);
+        true
+    }
+
+    /// the receive work request of the SEND message in progress, and the length of bytes placed into it
+    pub fn current_recv(&self) -> Option<(ReceiveWorkRequest, u32)> {
+        *self.current_recv.lock().unwrap()
+    }
+
+    /// place the next `len` bytes of the SEND message in progress
+    pub fn place_recv(&self, len: u32) {
+        if let Some((_, placed)) = self.current_recv.lock().unwrap().as_mut() {
+            *placed = placed.saturating_add(len);
+        }
+    }
+
+    /// finish the SEND message in progress
+    pub fn finish_recv(&self) -> Option<ReceiveWorkRequest> {
+        self.current_recv.lock().unwrap().take().map(|(wr, _)| wr)
+    }
+
+    /// record local buffers of an outstanding RDMA READ
+    pub fn start_read(&self, msn: MessageSequenceNumber, buffers: Vec<ReadBuffer>) {
+        let old = self.pending_reads.lock().unwrap().insert(msn, buffers);
+        if old.is_some() {
+            log::warn!("QPN: {}: drop unfinished read {msn}", self.queue_pair_number);
+        }
+    }
+
+    /// get local buffers of an outstanding RDMA READ
+    pub fn read_buffers(&self, msn: MessageSequenceNumber) -> Option<Vec<ReadBuffer>> {
+        self.pending_reads.lock().unwrap().get(&msn).cloned()
+    }
+
+    /// finish an outstanding RDMA READ
+    pub fn finish_read(&self, msn: MessageSequenceNumber) -> Option<Vec<ReadBuffer>> {
+        self.pending_reads.lock().unwrap().remove(&msn)
+    }
+
+    /// record local buffer of an outstanding atomic operation
+    pub fn start_atomic(&self, msn: MessageSequenceNumber, buffer: ReadBuffer) {
+        let old = self.pending_atomics.lock().unwrap().insert(msn, buffer);
+        if old.is_some() {
+            log::warn!("QPN: {}: drop unfinished atomic {msn}", self.queue_pair_number);
+        }
+    }
+
+    /// finish an outstanding atomic operation
+    pub fn finish_atomic(&self, msn: MessageSequenceNumber) -> Option<ReadBuffer> {
+        self.pending_atomics.lock().unwrap().remove(&msn)
+    }
 }
 
 #[derive(Debug, Default)]
@@ -141,3 +365,19 @@ impl Table {
         self.0.get(&qpn, guard)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{next_psn, psn_after, psn_after_or_eq};
+
+    #[test]
+    fn test_psn_wraparound() {
+        let max_psn = 0x00FF_FFFF;
+        assert_eq!(next_psn(max_psn), 0);
+        assert!(psn_after(0, max_psn));
+        assert!(psn_after(5, max_psn - 5));
+        assert!(!psn_after(max_psn, 0));
+        assert!(psn_after_or_eq(max_psn, max_psn));
+        assert!(!psn_after(max_psn, max_psn));
+    }
+}
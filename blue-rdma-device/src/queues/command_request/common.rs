@@ -24,6 +24,10 @@ impl CommonHeader {
         Self(header)
     }
 
+    pub(crate) const fn as_bytes(&self) -> &[u8; 8] {
+        &self.0.0
+    }
+
     pub fn valid(&self) -> bool {
         self.0.get_valid() as _
     }
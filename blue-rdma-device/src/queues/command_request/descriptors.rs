@@ -1,20 +1,27 @@
+mod post_recv;
 mod queue_pair_management;
 mod set_network_parameter;
 mod set_raw_packet_receive_meta;
+mod shared_receive_queue_management;
 mod update_error_psn_recover_point;
 mod update_mr_table;
+mod update_mw_table;
 mod update_page_table;
 
+use post_recv::PostReceive;
 use queue_pair_management::QueuePairManagement;
 use set_network_parameter::SetNetworkParameter;
 use set_raw_packet_receive_meta::SetRawPacketReceiveMeta;
+use shared_receive_queue_management::SharedReceiveQueueManagement;
 use update_error_psn_recover_point::UpdateErrorPacketSequenceNumberRecoverPoint;
 use update_mr_table::UpdateMemoryRegionTable;
+use update_mw_table::UpdateMemoryWindowTable;
 use update_page_table::UpdatePageTable;
 
 use super::common::{Opcode, Unknown};
 use crate::Result;
 use crate::queues::command_request::common::Header;
+use crate::queues::errors::ParseDescriptorError;
 
 #[non_exhaustive]
 #[derive(Debug)]
@@ -25,6 +32,9 @@ pub(super) enum DescriptorRef<'d> {
     SetNetworkParameter(&'d SetNetworkParameter),
     SetRawPacketReceiveMeta(&'d SetRawPacketReceiveMeta),
     UpdateErrorPacketSequenceNumberRecoverPoint(&'d UpdateErrorPacketSequenceNumberRecoverPoint),
+    PostReceive(&'d PostReceive),
+    SharedReceiveQueueManagement(&'d SharedReceiveQueueManagement),
+    UpdateMemoryWindowTable(&'d UpdateMemoryWindowTable),
     // Unknown(&'d Unknown),
 }
 
@@ -41,6 +51,11 @@ impl<'d> DescriptorRef<'d> {
             Opcode::SetNetworkParam => Self::SetNetworkParameter(raw.as_ref()),
             Opcode::SetRawPacketReceiveMeta => Self::SetRawPacketReceiveMeta(raw.as_ref()),
             Opcode::UpdateErrorPsnRecoverPoint => Self::UpdateErrorPacketSequenceNumberRecoverPoint(raw.as_ref()),
+            Opcode::PostRecv => Self::PostReceive(raw.as_ref()),
+            Opcode::SrqManagement => Self::SharedReceiveQueueManagement(raw.as_ref()),
+            Opcode::UpdateMwTable => Self::UpdateMemoryWindowTable(raw.as_ref()),
+            // page faults are only reported to the driver
+            Opcode::PageFault => return Err(ParseDescriptorError::CommandRequestUnknownOpcode(opcode.into()).into()),
         };
         Ok(descriptor)
     }
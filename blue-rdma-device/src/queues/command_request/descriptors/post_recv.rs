@@ -0,0 +1,110 @@
+use core::fmt;
+
+use super::Opcode;
+use crate::address::VirtualAddress;
+use crate::dma::Client;
+use crate::net::Agent;
+use crate::queue_pair::ReceiveWorkRequest;
+use crate::queues::command_request::common::{CommonHeader, DESCRIPTOR_ALIGN, DESCRIPTOR_SIZE, Header, Unknown};
+use crate::queues::complete_queue::CompleteQueue;
+use crate::queues::descriptor::HandleDescriptor;
+use crate::third_party::queues::command_request::descriptor::CmdQueueReqDescPostRecv;
+use crate::types::{MemoryRegionKey, QueuePairNumber};
+use crate::{DeviceInner, Result};
+
+#[repr(C, align(32))]
+pub struct PostReceive(CmdQueueReqDescPostRecv<[u8; DESCRIPTOR_SIZE]>);
+const _: () = assert!(size_of::<PostReceive>() == DESCRIPTOR_SIZE);
+const _: () = assert!(align_of::<PostReceive>() == DESCRIPTOR_ALIGN);
+
+impl PostReceive {
+    const OPCODE: Opcode = Opcode::PostRecv;
+}
+
+impl<UA: Agent, DC: Client> HandleDescriptor<PostReceive> for DeviceInner<UA, DC> {
+    type Context = ();
+    type Output = ();
+
+    fn handle(&self, req: &PostReceive, (): &mut ()) -> Result<Self::Output> {
+        log::debug!("handle {req:?}");
+
+        let qpn = req.queue_pair_number();
+        let wr = ReceiveWorkRequest::new(req.local_addr(), req.len(), req.local_key(), req.tag());
+
+        let success = if req.is_shared_receive_queue() {
+            // the queue pair number is the number of the shared receive queue
+            let guard = self.shared_receive_queue_table().guard();
+            self.shared_receive_queue_table()
+                .get(qpn, &guard)
+                .map(|srq_context| srq_context.post_recv(wr))
+                .is_some()
+        } else {
+            let guard = self.queue_pair_table().guard();
+            self.queue_pair_table()
+                .get(qpn, &guard)
+                .map(|qp_context| qp_context.post_recv(wr))
+                .is_some()
+        };
+
+        let response = CommonHeader::new(PostReceive::OPCODE, success, req.header().user_data());
+        unsafe { self.command_response_queue().push(response) };
+
+        Ok(())
+    }
+}
+
+impl PostReceive {
+    pub fn local_addr(&self) -> VirtualAddress {
+        self.0.get_laddr().into()
+    }
+
+    pub fn len(&self) -> u32 {
+        self.0.get_len().try_into().unwrap()
+    }
+
+    pub fn local_key(&self) -> MemoryRegionKey {
+        MemoryRegionKey::new(self.0.get_lkey().try_into().unwrap())
+    }
+
+    pub fn queue_pair_number(&self) -> QueuePairNumber {
+        self.0.get_qpn().try_into().unwrap()
+    }
+
+    pub fn is_shared_receive_queue(&self) -> bool {
+        self.0.get_is_srq()
+    }
+
+    pub fn tag(&self) -> u32 {
+        self.0.get_tag().try_into().unwrap()
+    }
+}
+
+impl fmt::Debug for PostReceive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandRequestPostReceive")
+            .field("header", self.header())
+            .field("queue_pair_number", &self.queue_pair_number())
+            .field("is_shared_receive_queue", &self.is_shared_receive_queue())
+            .field("tag", &self.tag())
+            .field("local_addr", &self.local_addr())
+            .field("len", &self.len())
+            .field("local_key", &self.local_key())
+            .finish()
+    }
+}
+
+impl AsRef<Unknown> for PostReceive {
+    fn as_ref(&self) -> &Unknown {
+        // SAFETY: const sound because we transmute two types with the same layout
+        unsafe { core::mem::transmute(self) }
+    }
+}
+
+impl AsRef<PostReceive> for Unknown {
+    fn as_ref(&self) -> &PostReceive {
+        assert_eq!(self.header().opcode().unwrap(), PostReceive::OPCODE);
+
+        // SAFETY: const sound because we transmute two types with the same layout
+        unsafe { core::mem::transmute(self) }
+    }
+}
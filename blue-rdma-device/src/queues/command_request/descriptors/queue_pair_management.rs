@@ -9,7 +9,10 @@ use crate::queues::complete_queue::CompleteQueue;
 use crate::queues::descriptor::HandleDescriptor;
 use crate::queues::errors::ParseDescriptorError;
 use crate::third_party::queues::command_request::descriptor::CmdQueueReqDescQpManagementSeg0;
-use crate::types::{MemoryAccessFlag, PathMtuKind, ProtectDomainHandler, QueuePairNumber, QueuePairType};
+use crate::types::{
+    MemoryAccessFlag, PacketSequenceNumber, PathMtuKind, ProtectDomainHandler, QueuePairNumber, QueuePairType,
+    SharedReceiveQueueNumber,
+};
 use crate::{DeviceInner, Result};
 
 #[repr(C, align(32))]
@@ -32,7 +35,12 @@ impl<UA: Agent, DC: Client> HandleDescriptor<QueuePairManagement> for DeviceInne
         let qp_context = Context::from_req(request)?;
 
         let success = if request.valid() {
-            // create
+            // create, or modify the attributes of an existing queue pair
+            let guard = self.queue_pair_table().guard();
+            if let Some(old) = self.queue_pair_table().get(qpn, &guard) {
+                qp_context.inherit_state(old);
+            }
+            drop(guard);
             let _ = self.queue_pair_table().insert(qp_context);
             true
         } else {
@@ -56,6 +64,11 @@ impl Context {
             req.queue_pair_type()?,
             req.remote_queue_access_flag(),
             req.path_mtu_kind()?,
+            req.min_rnr_timer(),
+            req.queue_key(),
+            req.receive_packet_sequence_number(),
+            req.shared_receive_queue_number(),
+            req.error(),
         ))
     }
 }
@@ -99,9 +112,26 @@ impl QueuePairManagement {
         Ok(path_mtu_kind)
     }
 
+    /// The 5 bits RNR timer code advertised in RNR NAK
+    pub fn min_rnr_timer(&self) -> u8 {
+        self.0.get_min_rnr_timer().try_into().unwrap()
+    }
+
     pub fn peer_queue_pair_number(&self) -> QueuePairNumber {
         self.0.get_peer_qpn().try_into().unwrap()
     }
+
+    pub fn queue_key(&self) -> u32 {
+        self.0.get_qkey().try_into().unwrap()
+    }
+
+    pub fn receive_packet_sequence_number(&self) -> PacketSequenceNumber {
+        self.0.get_rq_psn().try_into().unwrap()
+    }
+
+    pub fn shared_receive_queue_number(&self) -> Option<SharedReceiveQueueNumber> {
+        self.0.get_has_srq().then(|| self.0.get_srqn().try_into().unwrap())
+    }
 }
 
 impl fmt::Debug for QueuePairManagement {
@@ -115,7 +145,11 @@ impl fmt::Debug for QueuePairManagement {
             .field("queue_pair_type", &self.queue_pair_type().map_err(|_| fmt::Error))
             .field("remote_queue_access_flag", &self.remote_queue_access_flag())
             .field("path_mtu_kind", &self.path_mtu_kind().map_err(|_| fmt::Error))
+            .field("min_rnr_timer", &self.min_rnr_timer())
             .field("peer_queue_pair_number", &self.peer_queue_pair_number())
+            .field("queue_key", &self.queue_key())
+            .field("receive_packet_sequence_number", &self.receive_packet_sequence_number())
+            .field("shared_receive_queue_number", &self.shared_receive_queue_number())
             .finish()
     }
 }
@@ -0,0 +1,90 @@
+use core::fmt;
+
+use super::Opcode;
+use crate::dma::Client;
+use crate::net::Agent;
+use crate::queues::command_request::common::{CommonHeader, DESCRIPTOR_ALIGN, DESCRIPTOR_SIZE, Header, Unknown};
+use crate::queues::complete_queue::CompleteQueue;
+use crate::queues::descriptor::HandleDescriptor;
+use crate::shared_receive_queue::Context;
+use crate::third_party::queues::command_request::descriptor::CmdQueueReqDescSrqManagement;
+use crate::types::{ProtectDomainHandler, SharedReceiveQueueNumber};
+use crate::{DeviceInner, Result};
+
+#[repr(C, align(32))]
+pub struct SharedReceiveQueueManagement(CmdQueueReqDescSrqManagement<[u8; DESCRIPTOR_SIZE]>);
+const _: () = assert!(size_of::<SharedReceiveQueueManagement>() == DESCRIPTOR_SIZE);
+const _: () = assert!(align_of::<SharedReceiveQueueManagement>() == DESCRIPTOR_ALIGN);
+
+impl SharedReceiveQueueManagement {
+    const OPCODE: Opcode = Opcode::SrqManagement;
+}
+
+impl<UA: Agent, DC: Client> HandleDescriptor<SharedReceiveQueueManagement> for DeviceInner<UA, DC> {
+    type Context = ();
+    type Output = ();
+
+    fn handle(&self, request: &SharedReceiveQueueManagement, (): &mut ()) -> Result<Self::Output> {
+        log::debug!("handle {request:?}");
+
+        let srqn = request.shared_receive_queue_number();
+        let success = if request.valid() {
+            // create, the receive work requests are posted later
+            let srq_context = Context::new(srqn, request.protect_domain_handler());
+            !self.shared_receive_queue_table().insert(srq_context)
+        } else {
+            // delete, the posted receive work requests are dropped
+            self.shared_receive_queue_table().remove(srqn)
+        };
+
+        let response = CommonHeader::new(
+            SharedReceiveQueueManagement::OPCODE,
+            success,
+            request.header().user_data(),
+        );
+        unsafe { self.command_response_queue().push(response) };
+
+        Ok(())
+    }
+}
+
+impl SharedReceiveQueueManagement {
+    pub fn valid(&self) -> bool {
+        self.0.get_is_valid()
+    }
+
+    pub fn shared_receive_queue_number(&self) -> SharedReceiveQueueNumber {
+        self.0.get_srqn().try_into().unwrap()
+    }
+
+    pub fn protect_domain_handler(&self) -> ProtectDomainHandler {
+        self.0.get_pd_handler().try_into().unwrap()
+    }
+}
+
+impl fmt::Debug for SharedReceiveQueueManagement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandRequestSharedReceiveQueueManagement")
+            .field("header", self.header())
+            .field("valid", &self.valid())
+            .field("shared_receive_queue_number", &self.shared_receive_queue_number())
+            .field("protect_domain_handler", &self.protect_domain_handler())
+            .finish()
+    }
+}
+
+impl AsRef<Unknown> for SharedReceiveQueueManagement {
+    fn as_ref(&self) -> &Unknown {
+        // SAFETY: const sound because we transmute two types with the same layout
+        unsafe { core::mem::transmute(self) }
+    }
+}
+
+impl AsRef<SharedReceiveQueueManagement> for Unknown {
+    fn as_ref(&self) -> &SharedReceiveQueueManagement {
+        assert_eq!(self.header().opcode().unwrap(), SharedReceiveQueueManagement::OPCODE);
+
+        // SAFETY: const sound because we transmute two types with the same layout
+        unsafe { core::mem::transmute(self) }
+    }
+}
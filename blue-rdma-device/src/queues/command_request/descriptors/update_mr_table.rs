@@ -29,10 +29,30 @@ impl<UA: Agent, DC: Client> HandleDescriptor<UpdateMemoryRegionTable> for Device
     fn handle(&self, request: &UpdateMemoryRegionTable, (): &mut ()) -> Result<Self::Output> {
         log::debug!("handle {request:?}");
 
-        let mr_context = Context::from_req(request);
-        self.memory_region_table().update(mr_context)?;
-
-        let response = CommonHeader::new(UpdateMemoryRegionTable::OPCODE, true, request.header().user_data());
+        // the driver deregisters a memory region with an empty one, which carries no page size
+        let result = if request.mr_len() == 0 {
+            self.memory_region_table().remove(request.mr_key())
+        } else {
+            let mr_context = Context::from_req(request);
+            // an on-demand paging region starts with an empty page table, the entries left by the previous user
+            // of the range must not be reused
+            if let Some(range) = mr_context.on_demand_page_table() {
+                let page_table = self.page_table.pin();
+                for idx in range {
+                    let _ = page_table.remove(&idx);
+                }
+            }
+            self.memory_region_table().update(mr_context)
+        };
+        if let Err(error) = &result {
+            log::warn!("failed to update memory region: {error}");
+        }
+
+        let response = CommonHeader::new(
+            UpdateMemoryRegionTable::OPCODE,
+            result.is_ok(),
+            request.header().user_data(),
+        );
         unsafe { self.command_response_queue().push(response) };
 
         Ok(())
@@ -48,6 +68,7 @@ impl Context {
             req.pd_handler(),
             req.access_flag(),
             req.page_table_offset(),
+            req.page_size_bits(),
         )
     }
 }
@@ -76,6 +97,10 @@ impl UpdateMemoryRegionTable {
     pub fn page_table_offset(&self) -> u32 {
         self.0.get_pgt_offset().try_into().unwrap()
     }
+
+    pub fn page_size_bits(&self) -> u32 {
+        self.0.get_pg_size_log().try_into().unwrap()
+    }
 }
 
 impl fmt::Debug for UpdateMemoryRegionTable {
@@ -88,6 +113,7 @@ impl fmt::Debug for UpdateMemoryRegionTable {
             .field("pd_handler", &self.pd_handler())
             .field("access_flag", &self.access_flag())
             .field("page_table_offset", &self.page_table_offset())
+            .field("page_size_bits", &self.page_size_bits())
             .finish()
     }
 }
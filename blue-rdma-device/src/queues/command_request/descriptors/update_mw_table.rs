@@ -0,0 +1,126 @@
+use core::fmt;
+
+use super::Opcode;
+use crate::address::VirtualAddress;
+use crate::dma::Client;
+use crate::memory_region::Context;
+use crate::mr_table::MemoryRegionTable;
+use crate::net::Agent;
+use crate::queues::command_request::common::{CommonHeader, DESCRIPTOR_ALIGN, DESCRIPTOR_SIZE, Header, Unknown};
+use crate::queues::complete_queue::CompleteQueue;
+use crate::queues::descriptor::HandleDescriptor;
+use crate::third_party::queues::command_request::descriptor::CmdQueueReqDescUpdateMwTable;
+use crate::types::{MemoryAccessFlag, MemoryRegionKey};
+use crate::{DeviceInner, Result};
+
+#[repr(C, align(32))]
+pub struct UpdateMemoryWindowTable(CmdQueueReqDescUpdateMwTable<[u8; DESCRIPTOR_SIZE]>);
+const _: () = assert!(size_of::<UpdateMemoryWindowTable>() == DESCRIPTOR_SIZE);
+const _: () = assert!(align_of::<UpdateMemoryWindowTable>() == DESCRIPTOR_ALIGN);
+
+impl UpdateMemoryWindowTable {
+    const OPCODE: Opcode = Opcode::UpdateMwTable;
+}
+
+impl<UA: Agent, DC: Client> HandleDescriptor<UpdateMemoryWindowTable> for DeviceInner<UA, DC> {
+    type Context = ();
+    type Output = ();
+
+    fn handle(&self, request: &UpdateMemoryWindowTable, (): &mut ()) -> Result<Self::Output> {
+        log::debug!("handle {request:?}");
+
+        let result = if request.valid() {
+            // bind, the window shares the page table of the parent memory region
+            self.memory_region_table().update(Context::from_window_req(request))
+        } else {
+            // invalidate, the window may already be invalidated by the peer
+            self.memory_region_table().remove(request.mw_key())
+        };
+        if let Err(error) = &result {
+            log::warn!("failed to update memory window: {error}");
+        }
+
+        let response = CommonHeader::new(
+            UpdateMemoryWindowTable::OPCODE,
+            result.is_ok(),
+            request.header().user_data(),
+        );
+        unsafe { self.command_response_queue().push(response) };
+
+        Ok(())
+    }
+}
+
+impl Context {
+    pub(crate) fn from_window_req(req: &UpdateMemoryWindowTable) -> Self {
+        Self::new_window(
+            req.mw_base_va(),
+            req.mw_len(),
+            req.mw_key(),
+            req.mr_key(),
+            req.access_flag(),
+            req.is_type2(),
+        )
+    }
+}
+
+impl UpdateMemoryWindowTable {
+    pub fn valid(&self) -> bool {
+        self.0.get_is_valid()
+    }
+
+    pub fn is_type2(&self) -> bool {
+        self.0.get_is_type2()
+    }
+
+    pub fn mw_base_va(&self) -> VirtualAddress {
+        self.0.get_mw_base_va().into()
+    }
+
+    pub fn mw_len(&self) -> u32 {
+        self.0.get_mw_length().try_into().unwrap()
+    }
+
+    pub fn mw_key(&self) -> MemoryRegionKey {
+        MemoryRegionKey::new(self.0.get_mw_key().try_into().unwrap())
+    }
+
+    pub fn mr_key(&self) -> MemoryRegionKey {
+        MemoryRegionKey::new(self.0.get_mr_key().try_into().unwrap())
+    }
+
+    pub fn access_flag(&self) -> MemoryAccessFlag {
+        MemoryAccessFlag::from_bits(self.0.get_acc_flags().try_into().unwrap()).unwrap()
+    }
+}
+
+impl fmt::Debug for UpdateMemoryWindowTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandRequestUpdateMemoryWindowTable")
+            .field("header", self.header())
+            .field("valid", &self.valid())
+            .field("is_type2", &self.is_type2())
+            .field("mw_base_va", &self.mw_base_va())
+            .field("mw_len", &format_args!("{:#08X}", self.mw_len()))
+            .field("mw_key", &self.mw_key())
+            .field("mr_key", &self.mr_key())
+            .field("access_flag", &self.access_flag())
+            .finish()
+    }
+}
+
+impl AsRef<Unknown> for UpdateMemoryWindowTable {
+    fn as_ref(&self) -> &Unknown {
+        // SAFETY: const sound because we transmute two types with the same layout
+        unsafe { core::mem::transmute(self) }
+    }
+}
+
+impl AsRef<UpdateMemoryWindowTable> for Unknown {
+    fn as_ref(&self) -> &UpdateMemoryWindowTable {
+        assert_eq!(self.header().opcode().unwrap(), UpdateMemoryWindowTable::OPCODE);
+
+        // SAFETY: const sound because we transmute two types with the same layout
+        unsafe { core::mem::transmute(self) }
+    }
+}
@@ -3,6 +3,7 @@ use core::fmt;
 use super::Opcode;
 use crate::address::DmaAddress;
 use crate::dma::{Client, PointerMut};
+use crate::memory_region::PAGE_TABLE_SIZE;
 use crate::net::Agent;
 use crate::queues::command_request::common::{CommonHeader, DESCRIPTOR_ALIGN, DESCRIPTOR_SIZE, Header, Unknown};
 use crate::queues::complete_queue::CompleteQueue;
@@ -29,7 +30,15 @@ impl<UA: Agent, DC: Client> HandleDescriptor<UpdatePageTable> for DeviceInner<UA
         let dma_addr = request.dma_addr();
         let ptr = self.dma_client.with_dma_addr(dma_addr);
 
-        let len = usize::try_from(request.dma_read_length() / 8).unwrap();
+        let offset = request.start_index();
+        let len = request.dma_read_length() / 8;
+        if offset.checked_add(len).is_none_or(|end| end > PAGE_TABLE_SIZE) {
+            log::warn!("page table entries out of bound: {offset} + {len}");
+            let response = CommonHeader::new(UpdatePageTable::OPCODE, false, request.header().user_data());
+            unsafe { self.command_response_queue().push(response) };
+            return Ok(());
+        }
+        let len = usize::try_from(len).unwrap();
         let mut entries = Vec::with_capacity(len);
 
         let entries_uninit = entries.spare_capacity_mut();
@@ -39,8 +48,16 @@ impl<UA: Agent, DC: Client> HandleDescriptor<UpdatePageTable> for DeviceInner<UA
         unsafe { entries.set_len(len) };
 
         let page_table = self.page_table.pin();
-        let offset = request.start_index();
-        assert!(page_table.insert(offset, entries).is_none());
+        // the driver reuses the entries freed by deregistered or re-registered memory regions
+        let replaced = (offset..)
+            .zip(entries)
+            .filter(|&(idx, entry)| page_table.insert(idx, entry).is_some())
+            .count();
+        if replaced != 0 {
+            log::debug!("{replaced} page table entries from {offset} are replaced");
+        }
+        // the requests parked on page faults are handled again
+        self.notify_page_table_updated();
 
         let response = CommonHeader::new(UpdatePageTable::OPCODE, true, request.header().user_data());
         unsafe { self.command_response_queue().push(response) };
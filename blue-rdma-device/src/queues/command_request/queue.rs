@@ -1,10 +1,13 @@
 use core::marker::PhantomData;
+use core::sync::atomic::Ordering;
 
 use super::common::Unknown;
 use super::descriptors::DescriptorRef;
 use crate::DeviceInner;
 use crate::dma::{Client, PointerMut};
 use crate::net::Agent;
+use crate::queues::command_response::descriptors::PageFault;
+use crate::queues::complete_queue::CompleteQueue;
 use crate::queues::descriptor::HandleDescriptor;
 use crate::queues::work_queue::WorkQueue;
 
@@ -67,7 +70,14 @@ impl<UA: Agent, DC: Client> CommandRequestQueue<'_, UA, DC> {
     }
 
     pub(crate) fn run(&self) {
-        while self.dev.rx_command_request.recv().is_ok() {
+        while self.dev.rx_command_request.recv().is_ok() && !self.dev.stop.load(Ordering::Relaxed) {
+            // the other threads report page faults through this thread, so that it's the only producer of
+            // the command response queue
+            for (key, va) in self.dev.rx_page_fault.try_iter() {
+                let response = PageFault::new(key, va);
+                log::debug!("report {response:?}");
+                unsafe { self.dev.command_response_queue().push(response) };
+            }
             while let Some(raw) = unsafe { self.pop() } {
                 let descriptor_ref = DescriptorRef::parse(&raw).unwrap();
 
@@ -80,6 +90,9 @@ impl<UA: Agent, DC: Client> CommandRequestQueue<'_, UA, DC> {
                     DescriptorRef::UpdateErrorPacketSequenceNumberRecoverPoint(req) => {
                         self.dev.handle(req, &mut ()).unwrap();
                     }
+                    DescriptorRef::PostReceive(req) => self.dev.handle(req, &mut ()).unwrap(),
+                    DescriptorRef::SharedReceiveQueueManagement(req) => self.dev.handle(req, &mut ()).unwrap(),
+                    DescriptorRef::UpdateMemoryWindowTable(req) => self.dev.handle(req, &mut ()).unwrap(),
                 }
             }
         }
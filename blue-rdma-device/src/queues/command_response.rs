@@ -1,5 +1,4 @@
-mod descriptors;
+pub(super) mod descriptors;
 mod queue;
 
-#[expect(unused, reason = "only defined")]
 const DESCRIPTOR_SIZE: usize = 32;
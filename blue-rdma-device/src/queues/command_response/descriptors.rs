@@ -1 +1,43 @@
+use core::fmt;
 
+use super::DESCRIPTOR_SIZE;
+use crate::address::VirtualAddress;
+use crate::queues::command_request::common::CommonHeader;
+use crate::third_party::queues::command_request::CtrlRbDescOpcode;
+use crate::third_party::queues::command_request::descriptor::CmdQueueRespDescPageFault;
+use crate::types::MemoryRegionKey;
+
+/// Raised by the emulator when it touches a page of an on-demand paging memory region, which is not
+/// in the page table yet. It is not a response of any request, so the user data is zero.
+#[repr(C, align(32))]
+pub struct PageFault(CmdQueueRespDescPageFault<[u8; DESCRIPTOR_SIZE]>);
+const _: () = assert!(size_of::<PageFault>() == DESCRIPTOR_SIZE);
+
+impl PageFault {
+    pub fn new(key: MemoryRegionKey, va: VirtualAddress) -> Self {
+        let mut descriptor = CmdQueueRespDescPageFault([0; DESCRIPTOR_SIZE]);
+        let header = CommonHeader::new(CtrlRbDescOpcode::PageFault, true, 0);
+        descriptor.0[..8].copy_from_slice(header.as_bytes());
+        descriptor.set_va(va.0);
+        descriptor.set_mr_key(key.get().into());
+
+        Self(descriptor)
+    }
+
+    pub fn key(&self) -> MemoryRegionKey {
+        MemoryRegionKey::new(self.0.get_mr_key().try_into().unwrap())
+    }
+
+    pub fn va(&self) -> VirtualAddress {
+        self.0.get_va().into()
+    }
+}
+
+impl fmt::Debug for PageFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandResponsePageFault")
+            .field("key", &self.key())
+            .field("va", &self.va())
+            .finish()
+    }
+}
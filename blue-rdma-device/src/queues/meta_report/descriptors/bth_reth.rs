@@ -4,7 +4,7 @@ use super::common::{
     BaseTransportHeader, MessageSequenceNumberAndCanAutoAck, PsnAndReqStatus, RdmaExtendedTransportHeader,
 };
 use super::{DESCRIPTOR_ALIGN, DESCRIPTOR_SIZE};
-use crate::types::{MessageSequenceNumber, PacketSequenceNumber};
+use crate::types::{MemoryRegionKey, MessageSequenceNumber, PacketSequenceNumber};
 
 #[derive(Debug)]
 #[repr(C, align(32))]
@@ -44,6 +44,22 @@ impl BthReth {
             msn_and_can_auto_ack,
         }
     }
+
+    /// The key of RETH is meaningless for SEND, it carries the tag of the consumed receive work request instead
+    pub fn with_recv_tag(self, tag: u32) -> Self {
+        Self {
+            reth: self.reth.with_local_key(MemoryRegionKey::new(tag)),
+            ..self
+        }
+    }
+
+    /// SEND has no RETH, its length is counted by the receiver, so that it covers all packets of the message
+    pub const fn with_message_len(self, len: u32) -> Self {
+        Self {
+            reth: self.reth.with_len(len),
+            ..self
+        }
+    }
 }
 
 // impl fmt::Debug for BthReth {
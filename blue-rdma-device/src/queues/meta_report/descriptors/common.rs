@@ -98,6 +98,14 @@ impl RdmaExtendedTransportHeader {
     pub const fn local_va(&self) -> VirtualAddress {
         VirtualAddress(u64::from_ne_bytes(self.local_virtual_addr))
     }
+
+    pub const fn with_local_key(self, local_key: MemoryRegionKey) -> Self {
+        Self { local_key, ..self }
+    }
+
+    pub const fn with_len(self, len: u32) -> Self {
+        Self { len, ..self }
+    }
 }
 
 #[repr(transparent)]
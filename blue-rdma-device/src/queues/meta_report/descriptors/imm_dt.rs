@@ -7,7 +7,9 @@ use super::{DESCRIPTOR_ALIGN, DESCRIPTOR_SIZE};
 #[repr(C, align(32))]
 pub struct ImmDt {
     imm_dt: ImmediateExtendedTransportHeader,
-    _reserved: core::mem::MaybeUninit<[u8; 28]>,
+    /// The tag of the receive work request consumed by a RDMA write with immediate
+    recv_tag: u32,
+    _reserved: core::mem::MaybeUninit<[u8; 24]>,
 }
 
 #[expect(unused, reason = "for consistency")]
@@ -19,7 +21,12 @@ impl ImmDt {
     pub const fn new(data: u32) -> Self {
         Self {
             imm_dt: ImmediateExtendedTransportHeader::new(data),
+            recv_tag: 0,
             _reserved: core::mem::MaybeUninit::uninit(),
         }
     }
+
+    pub const fn with_recv_tag(self, recv_tag: u32) -> Self {
+        Self { recv_tag, ..self }
+    }
 }
@@ -1,9 +1,13 @@
+mod atomic_operands;
 mod common;
+mod inline_data;
 mod seg0;
 mod seg1;
 mod variable_len_sge;
 
+pub(super) use atomic_operands::AtomicOperands;
 pub(super) use common::ScatterGatherElement;
+pub(super) use inline_data::InlineData;
 pub(super) use seg0::Seg0;
 pub(super) use seg1::Seg1;
 pub(super) use variable_len_sge::VariableLengthSge;
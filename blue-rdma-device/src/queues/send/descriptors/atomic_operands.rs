@@ -0,0 +1,33 @@
+use core::fmt;
+
+use super::{DESCRIPTOR_ALIGN, DESCRIPTOR_SIZE};
+
+/// The last descriptor of atomic operations, which carries the operands
+#[repr(C, align(32))]
+pub struct AtomicOperands {
+    /// swap value of compare and swap, or add value of fetch and add
+    pub swap_add: u64,
+    /// compare value of compare and swap, ignored by fetch and add
+    pub compare: u64,
+    _reserved: core::mem::MaybeUninit<[u8; 16]>,
+}
+type Descriptor = AtomicOperands;
+const _: () = assert!(size_of::<Descriptor>() == DESCRIPTOR_SIZE);
+const _: () = assert!(align_of::<Descriptor>() == DESCRIPTOR_ALIGN);
+
+impl fmt::Debug for AtomicOperands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtomicOperands")
+            .field("swap_add", &self.swap_add)
+            .field("compare", &self.compare)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Descriptor {
+    pub fn from_bytes(raw: [u8; DESCRIPTOR_SIZE]) -> Self {
+        let descriptor = unsafe { core::mem::transmute::<[u8; 32], Self>(raw) };
+        assert!((&raw const descriptor).is_aligned());
+        descriptor
+    }
+}
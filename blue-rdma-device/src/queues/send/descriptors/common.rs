@@ -91,6 +91,7 @@ pub struct QueuePairNumber {
 }
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct ScatterGatherElement {
     pub local_key: MemoryRegionKey,
     pub len: u32,
@@ -0,0 +1,25 @@
+use core::fmt;
+
+use super::{DESCRIPTOR_ALIGN, DESCRIPTOR_SIZE};
+
+/// The payload copied into the descriptors by the driver, which takes the place of the sges when
+/// the request is posted with `IbvSendInline`
+#[repr(C, align(32))]
+pub struct InlineData {
+    pub data: [u8; DESCRIPTOR_SIZE],
+}
+type Descriptor = InlineData;
+const _: () = assert!(size_of::<Descriptor>() == DESCRIPTOR_SIZE);
+const _: () = assert!(align_of::<Descriptor>() == DESCRIPTOR_ALIGN);
+
+impl fmt::Debug for InlineData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InlineData").field("data", &self.data).finish()
+    }
+}
+
+impl Descriptor {
+    pub const fn from_bytes(raw: [u8; DESCRIPTOR_SIZE]) -> Self {
+        Self { data: raw }
+    }
+}
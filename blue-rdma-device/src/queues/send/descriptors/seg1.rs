@@ -15,7 +15,9 @@ pub struct Seg1 {
     _reserved0: core::mem::MaybeUninit<[u8; 2]>,
     dest_qpn_inner: common::QueuePairNumber,
     pub immediate_data: u32,
-    _reserved1: core::mem::MaybeUninit<[u8; 8]>,
+    /// Q_Key of the destination UD queue pair
+    pub queue_key: u32,
+    src_qpn_inner: common::QueuePairNumber,
 }
 type Descriptor = Seg1;
 const _: () = assert!(size_of::<Descriptor>() == DESCRIPTOR_SIZE);
@@ -68,6 +70,12 @@ impl Seg1 {
     pub fn dest_queue_pair_number(&self) -> QueuePairNumber {
         self.dest_qpn_inner.queue_pair_number().into()
     }
+
+    /// Local queue pair number, which is carried by DETH of UD messages
+    #[expect(clippy::useless_conversion, reason = "QueuePairNumber should change later")]
+    pub fn src_queue_pair_number(&self) -> QueuePairNumber {
+        self.src_qpn_inner.queue_pair_number().into()
+    }
 }
 
 impl fmt::Debug for Seg1 {
@@ -81,6 +89,8 @@ impl fmt::Debug for Seg1 {
             .field("mac", &self.mac)
             .field("queue_pair_number", &self.dest_qpn_inner)
             .field("immediate_data", &self.immediate_data)
+            .field("queue_key", &self.queue_key)
+            .field("src_queue_pair_number", &self.src_qpn_inner)
             .finish_non_exhaustive()
     }
 }
@@ -27,4 +27,9 @@ impl Descriptor {
         assert!((&raw const descriptor).is_aligned());
         descriptor
     }
+
+    /// Valid elements in order, an element with zero length is considered as absent
+    pub fn into_elements(self) -> impl Iterator<Item = ScatterGatherElement> {
+        [self.sge1, self.sge2].into_iter().filter(|sge| sge.len != 0)
+    }
 }
@@ -0,0 +1,128 @@
+use core::net::Ipv4Addr;
+
+use super::common::Common;
+use crate::dma::Client;
+use crate::net::Agent;
+use crate::net::util::generate_payload_from_msg;
+use crate::queue_pair::ReadBuffer;
+use crate::queues::descriptor::HandleDescriptor;
+use crate::queues::send::descriptors::{AtomicOperands, ScatterGatherElement, Seg0, Seg1, VariableLengthSge};
+use crate::queues::send::operations::Opcode;
+use crate::third_party::net::{
+    AtomicEthHeader, Key, Metadata, PKey, PayloadInfo, Qpn, RdmaMessage, RdmaMessageMetaCommon,
+};
+use crate::third_party::queues::meta_report::ToHostWorkRbDescOpcode;
+use crate::third_party::rdma::Psn;
+use crate::{DeviceInner, Result};
+
+/// Compare and swap, or fetch and add
+#[derive(Debug)]
+pub struct Atomic {
+    common: Common,
+    is_compare_swap: bool,
+    sge: Option<ScatterGatherElement>,
+    swap_add: u64,
+    compare: u64,
+}
+
+impl AsRef<Common> for Atomic {
+    fn as_ref(&self) -> &Common {
+        &self.common
+    }
+}
+
+impl<UA: Agent, DC: Client> HandleDescriptor<Atomic> for DeviceInner<UA, DC> {
+    type Context = ();
+    type Output = ();
+
+    fn handle(&self, req: &Atomic, &mut (): &mut Self::Context) -> Result<Self::Output> {
+        log::info!("handle atomic op: {req:?}");
+
+        let sge = req.sge.expect("atomic without local buffer");
+
+        // the original value is carried by the atomic acknowledge, which only contains the msn
+        let guard = self.queue_pair_table().guard();
+        let qp_context = self.queue_pair_table().get(req.common.dest_qpn, &guard);
+        let qp_context = qp_context.expect("atomic on unknown queue pair");
+        qp_context.start_atomic(req.common.msn, ReadBuffer::new(sge.local_addr, sge.len, sge.local_key));
+
+        let opcode = if req.is_compare_swap {
+            ToHostWorkRbDescOpcode::CompareSwap
+        } else {
+            ToHostWorkRbDescOpcode::FetchAdd
+        };
+        let atomic_msg = RdmaMessage {
+            meta_data: Metadata::Atomic(AtomicEthHeader {
+                common_meta: RdmaMessageMetaCommon {
+                    tran_type: req.common.qp_type.into(),
+                    opcode,
+                    solicited: false,
+                    // We use the pkey to store msn
+                    pkey: PKey::new(req.common.msn),
+                    dqpn: Qpn::new(req.common.dest_qpn),
+                    // atomic is always responded
+                    ack_req: true,
+                    psn: Psn::new(req.common.psn),
+                },
+                va: req.common.remote_addr.0,
+                rkey: Key::new(req.common.remote_key.get()),
+                swap_add: req.swap_add,
+                compare: req.compare,
+            }),
+            payload: PayloadInfo::new(),
+        };
+
+        // FIXME(fh): hardcode for calculate Invariant CRC, should remove
+        let src = Ipv4Addr::new(192, 168, 0, 2);
+        let dst = req.common.dest_ip;
+        let payload = generate_payload_from_msg(&atomic_msg, src, dst);
+        let _ = self
+            .udp_agent
+            .get()
+            .unwrap()
+            .send_to(&payload, dst.into())
+            .expect("send error");
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+/// Atomic Builder
+pub struct Builder(Atomic);
+
+impl Builder {
+    /// Initialize builder from valid seg0
+    pub fn from_seg0(seg0: Seg0) -> Self {
+        let is_compare_swap = seg0.header.opcode().is_ok_and(|opcode| opcode == Opcode::CompareSwap);
+        Self(Atomic {
+            common: Common::from_seg0(seg0),
+            is_compare_swap,
+            sge: None,
+            swap_add: 0,
+            compare: 0,
+        })
+    }
+
+    /// Update valid seg1, assuming only seg0 is processed
+    pub fn with_seg1(mut self, seg1: Seg1) -> Self {
+        self.0.common.with_seg1(seg1);
+
+        self
+    }
+
+    /// Update sge, assuming seg0 and seg1 are processed
+    pub fn with_sge(mut self, sge: VariableLengthSge) -> Self {
+        self.0.sge = sge.into_elements().next();
+
+        self
+    }
+
+    /// Update operands, assuming seg0, seg1 and sge are processed
+    pub fn with_operands(mut self, operands: AtomicOperands) -> Atomic {
+        self.0.swap_add = operands.swap_add;
+        self.0.compare = operands.compare;
+
+        self.0
+    }
+}
@@ -4,11 +4,10 @@ use eui48::MacAddress;
 
 use crate::address::VirtualAddress;
 use crate::dma::PointerMut;
-use crate::mr_table::MemoryRegionTable;
 use crate::net::util::generate_payload_from_msg;
-use crate::queues::send::descriptors::{Seg0, Seg1};
+use crate::queues::send::descriptors::{ScatterGatherElement, Seg0, Seg1, VariableLengthSge};
 use crate::third_party::net::{
-    Key, Metadata, PKey, PayloadInfo, Qpn, RdmaGeneralMeta, RdmaMessage, RdmaMessageMetaCommon, RethHeader,
+    DethHeader, Key, Metadata, PKey, PayloadInfo, Qpn, RdmaGeneralMeta, RdmaMessage, RdmaMessageMetaCommon, RethHeader,
 };
 use crate::third_party::queues::meta_report::ToHostWorkRbDescOpcode;
 use crate::third_party::rdma::{MemAccessTypeFlag, Psn};
@@ -30,6 +29,8 @@ pub(super) struct Common {
     pub qp_type: QueuePairType,
     pub psn: PacketSequenceNumber,
     pub msn: MessageSequenceNumber,
+    pub queue_key: u32,
+    pub src_qpn: QueuePairNumber,
 }
 
 impl Common {
@@ -54,6 +55,8 @@ impl Common {
             qp_type: QueuePairType::Rc,
             psn: 0,
             msn: message_sequence_number,
+            queue_key: 0,
+            src_qpn: 0,
         }
     }
 
@@ -65,6 +68,27 @@ impl Common {
         self.send_flag = seg1.send_flag();
         self.qp_type = seg1.queue_pair_type().unwrap();
         self.psn = seg1.packet_sequence_number();
+        self.queue_key = seg1.queue_key;
+        self.src_qpn = seg1.src_queue_pair_number();
+    }
+}
+
+/// Where the payload of a message is taken from
+#[derive(Debug)]
+pub(super) enum Payload {
+    /// gathered from the memory regions by DMA
+    Sgl(Vec<ScatterGatherElement>),
+    /// copied into the descriptors by the driver, no memory region is involved
+    Inline(Vec<u8>),
+}
+
+impl Payload {
+    /// Total length of the payload
+    pub(super) fn len(&self) -> u32 {
+        match *self {
+            Self::Sgl(ref sgl) => sgl_len(sgl),
+            Self::Inline(ref data) => u32::try_from(data.len()).unwrap(),
+        }
     }
 }
 
@@ -76,13 +100,19 @@ impl<UA: net::Agent, DC: dma::Client> DeviceInner<UA, DC> {
         opcode: ToHostWorkRbDescOpcode,
         psn: PacketSequenceNumber,
         ack_req: bool,
-        key: crate::types::MemoryRegionKey,
+        payload: &Payload,
         remote_va: u64,
         segment: &Segment,
+        imm: Option<u32>,
     ) {
         let common = req.as_ref();
         let rkey = Key::new(common.remote_key.get());
         let dst = common.dest_ip;
+        // UD message identifies its sender by DETH
+        let deth = matches!(common.qp_type, QueuePairType::Ud).then(|| DethHeader {
+            qkey: common.queue_key,
+            src_qpn: Qpn::new(common.src_qpn),
+        });
 
         let meta = RdmaMessageMetaCommon {
             tran_type: common.qp_type.into(),
@@ -91,19 +121,21 @@ impl<UA: net::Agent, DC: dma::Client> DeviceInner<UA, DC> {
             // We use the pkey to store msn
             pkey: PKey::new(common.msn),
             dqpn: Qpn::new(common.dest_qpn),
-            ack_req,
+            // only RC is acknowledged, UC and UD messages are never retransmitted
+            ack_req: ack_req && common.qp_type == QueuePairType::Rc,
             psn: Psn::new(psn),
         };
 
-        let dma_addr = self
-            .mr_table
-            .query(key, segment.va, MemAccessTypeFlag::empty(), &self.page_table)
-            .expect("verify key failed");
-        let ptr = self.dma_client.with_dma_addr::<u8>(dma_addr);
-        let len = segment.len as usize;
-        let mut data = vec![0u8; len];
-        // SAFETY: caller should guarantee ptr is valid dma ptr
-        unsafe { ptr.copy_to_nonoverlapping(data.as_mut_ptr(), len) };
+        // segments are generated from the remote address, so the distance is the offset in the message
+        let offset = segment.va.0 - common.remote_addr.0;
+        let data = match *payload {
+            Payload::Sgl(ref sgl) => self.gather_from_sgl(sgl, offset, segment.len),
+            Payload::Inline(ref data) => {
+                let start = usize::try_from(offset).unwrap();
+                data[start..start + segment.len as usize].to_vec()
+            }
+        };
+        let len = data.len();
 
         let payload = PayloadInfo::new_with_data(data.as_ptr(), len);
 
@@ -115,8 +147,9 @@ impl<UA: net::Agent, DC: dma::Client> DeviceInner<UA, DC> {
                     rkey,
                     len: common.total_len,
                 },
-                imm: None,
+                imm,
                 secondary_reth: None,
+                deth,
             }),
             payload,
         };
@@ -130,6 +163,55 @@ impl<UA: net::Agent, DC: dma::Client> DeviceInner<UA, DC> {
             .send_to(&payload, dst.into())
             .expect("send error");
     }
+
+    /// Gather `len` bytes of the message starting at `offset` from the scatter gather list
+    fn gather_from_sgl(&self, sgl: &[ScatterGatherElement], mut offset: u64, len: u32) -> Vec<u8> {
+        let mut data = Vec::with_capacity(len as usize);
+        let mut remainder = len;
+        for sge in sgl {
+            if remainder == 0 {
+                break;
+            }
+            let sge_len = u64::from(sge.len);
+            if offset >= sge_len {
+                offset -= sge_len;
+                continue;
+            }
+            let chunk = remainder.min(u32::try_from(sge_len - offset).unwrap());
+            let va = VirtualAddress(sge.local_addr.0 + offset);
+            let start = data.len();
+            data.resize(start + chunk as usize, 0);
+            self.for_each_dma_piece_blocking(
+                sge.local_key,
+                va,
+                chunk as usize,
+                MemAccessTypeFlag::empty(),
+                |piece_offset, dma_addr, piece| {
+                    let ptr = self.dma_client.with_dma_addr::<u8>(dma_addr);
+                    // SAFETY: caller should guarantee ptr is valid dma ptr
+                    unsafe { ptr.copy_to_nonoverlapping(data[start + piece_offset..].as_mut_ptr(), piece) };
+                },
+            )
+            .expect("verify key failed");
+
+            offset = 0;
+            remainder -= chunk;
+        }
+        assert_eq!(remainder, 0, "scatter gather list is shorter than the message");
+        data
+    }
+}
+
+/// Collect the scatter gather list, `extra_sge` only exists when there are more than two elements
+pub(super) fn collect_sgl(sge: VariableLengthSge, extra_sge: Option<VariableLengthSge>) -> Vec<ScatterGatherElement> {
+    sge.into_elements()
+        .chain(extra_sge.into_iter().flat_map(VariableLengthSge::into_elements))
+        .collect()
+}
+
+/// Total length of the scatter gather list
+pub(super) fn sgl_len(sgl: &[ScatterGatherElement]) -> u32 {
+    sgl.iter().map(|sge| sge.len).sum()
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -184,7 +266,13 @@ mod tests {
     use smoltcp::wire::{EthernetFrame, Ipv4Packet, UdpPacket};
 
     use super::*;
+    use crate::address::DmaAddress;
+    use crate::emulator::{DmaClient, NetAgent};
+    use crate::memory_region;
+    use crate::mr_table::MemoryRegionTable;
+    use crate::third_party::net::SGListElement;
     use crate::third_party::queues::meta_report::ToHostWorkRbDescTransType;
+    use crate::types::MemoryAccessFlag;
 
     #[test]
     fn test_generate_segments_from_request() {
@@ -250,6 +338,7 @@ mod tests {
                 },
                 imm: None,
                 secondary_reth: None,
+                deth: None,
             }),
             payload,
         };
@@ -269,6 +358,7 @@ mod tests {
                 },
                 imm: None,
                 secondary_reth: None,
+                deth: None,
             }),
             payload,
         };
@@ -299,4 +389,57 @@ mod tests {
         let expected = udp_packet.payload();
         assert_eq!(expected, payload_last);
     }
+
+    #[test]
+    fn test_dma_across_fragmented_pages() {
+        const PAGE_SIZE: usize = 0x1000;
+        let key = MemoryRegionKey::new(0x0100_0001);
+        let va = 0x7f00_0000_0000;
+        let device = DeviceInner::<NetAgent, DmaClient>::new(DmaClient, memory_region::Table::new());
+
+        // the second page of the backing memory is never mapped, the others are mapped out of order
+        let mut backing = vec![0u8; 4 * PAGE_SIZE];
+        let base = backing.as_mut_ptr() as u64;
+        for (idx, page) in [2, 0, 3].into_iter().enumerate() {
+            let dma = DmaAddress(base + (page * PAGE_SIZE) as u64);
+            let _ = device.page_table.pin().insert(idx as u32, dma);
+        }
+        let flag = MemoryAccessFlag::IbvAccessLocalWrite;
+        let mr = memory_region::Context::new(VirtualAddress(va), 3 * PAGE_SIZE as u32, key, 1, flag, 0, 12);
+        device.memory_region_table().update(mr).unwrap();
+
+        // the payload straddles all three pages
+        let payload = (0..PAGE_SIZE + 0x200).map(|i| i as u8).collect::<Vec<_>>();
+        let start = PAGE_SIZE - 0x100;
+        let data = SGListElement {
+            data: payload.as_ptr(),
+            len: payload.len(),
+        };
+        device
+            .copy_to(data, key, VirtualAddress(va + start as u64), flag)
+            .unwrap();
+        assert_eq!(backing[3 * PAGE_SIZE - 0x100..3 * PAGE_SIZE], payload[..0x100]);
+        assert_eq!(backing[..PAGE_SIZE], payload[0x100..PAGE_SIZE + 0x100]);
+        assert_eq!(
+            backing[3 * PAGE_SIZE..3 * PAGE_SIZE + 0x100],
+            payload[PAGE_SIZE + 0x100..]
+        );
+        assert!(backing[PAGE_SIZE..2 * PAGE_SIZE].iter().all(|&b| b == 0));
+
+        // gathered back through two elements, the first ends in the middle of a page
+        let sgl = [
+            ScatterGatherElement {
+                local_key: key,
+                len: 0x80,
+                local_addr: VirtualAddress(va + start as u64),
+            },
+            ScatterGatherElement {
+                local_key: key,
+                len: PAGE_SIZE as u32 + 0x180,
+                local_addr: VirtualAddress(va + start as u64 + 0x80),
+            },
+        ];
+        assert_eq!(device.gather_from_sgl(&sgl, 0, payload.len() as u32), payload);
+        assert_eq!(device.gather_from_sgl(&sgl, 0x100, 0x10), payload[0x100..0x110]);
+    }
 }
@@ -1,9 +1,10 @@
 use core::net::Ipv4Addr;
 
-use super::common::Common;
+use super::common::{Common, collect_sgl, sgl_len};
 use crate::dma::Client;
 use crate::net::Agent;
 use crate::net::util::generate_payload_from_msg;
+use crate::queue_pair::ReadBuffer;
 use crate::queues::descriptor::HandleDescriptor;
 use crate::queues::send::descriptors::{ScatterGatherElement, Seg0, Seg1, VariableLengthSge};
 use crate::third_party::net::{
@@ -17,7 +18,7 @@ use crate::{DeviceInner, Result};
 #[derive(Debug)]
 pub struct Read {
     common: Common,
-    sge: ScatterGatherElement,
+    sgl: Vec<ScatterGatherElement>,
 }
 
 impl AsRef<Common> for Read {
@@ -54,6 +55,20 @@ impl<UA: Agent, DC: Client> HandleDescriptor<Read> for DeviceInner<UA, DC> {
             }
         };
 
+        let first_sge = req.sgl.first().expect("read without local buffer");
+        if req.sgl.len() > 1 {
+            // the response only carries the address of the first buffer, so we scatter it ourselves
+            let guard = self.queue_pair_table().guard();
+            let qp_context = self.queue_pair_table().get(req.common.dest_qpn, &guard);
+            let qp_context = qp_context.expect("read on unknown queue pair");
+            let buffers = req
+                .sgl
+                .iter()
+                .map(|sge| ReadBuffer::new(sge.local_addr, sge.len, sge.local_key))
+                .collect();
+            qp_context.start_read(req.common.msn, buffers);
+        }
+
         let remote_va = req.common.remote_addr.0;
         let psn = req.common.psn;
         let read_msg = RdmaMessage {
@@ -66,10 +81,11 @@ impl<UA: Agent, DC: Client> HandleDescriptor<Read> for DeviceInner<UA, DC> {
                 },
                 imm: None,
                 secondary_reth: Some(RethHeader {
-                    va: req.sge.local_addr.into(),
-                    rkey: Key::new(req.sge.local_key.get()),
-                    len: req.sge.len,
+                    va: first_sge.local_addr.into(),
+                    rkey: Key::new(first_sge.local_key.get()),
+                    len: sgl_len(&req.sgl),
                 }),
+                deth: None,
             }),
             payload: PayloadInfo::new(),
         };
@@ -99,11 +115,7 @@ impl Builder {
     pub fn from_seg0(seg0: Seg0) -> Self {
         Self(Read {
             common: Common::from_seg0(seg0),
-            sge: ScatterGatherElement {
-                local_key: 0.into(),
-                len: 0,
-                local_addr: 0.into(),
-            },
+            sgl: Vec::new(),
         })
     }
 
@@ -115,15 +127,8 @@ impl Builder {
     }
 
     /// Update sge, assuming seg0 and seg1 are processed
-    pub fn with_sge(mut self, sge: VariableLengthSge) -> Read {
-        let sge0 = sge.sge1;
-        let sge1 = sge.sge2;
-        assert!(
-            sge1.local_addr == 0.into() && sge1.len == 0 && sge1.local_key == 0.into(),
-            "support only one sge for now"
-        );
-
-        self.0.sge = sge0;
+    pub fn with_sge(mut self, sge: VariableLengthSge, extra_sge: Option<VariableLengthSge>) -> Read {
+        self.0.sgl = collect_sgl(sge, extra_sge);
 
         self.0
     }
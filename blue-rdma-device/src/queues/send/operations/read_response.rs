@@ -2,8 +2,8 @@ use super::common::Common;
 use crate::dma::Client;
 use crate::net::Agent;
 use crate::queues::descriptor::HandleDescriptor;
-use crate::queues::send::descriptors::{ScatterGatherElement, Seg0, Seg1, VariableLengthSge};
-use crate::queues::send::operations::common::generate_segments_from_request;
+use crate::queues::send::descriptors::{Seg0, Seg1, VariableLengthSge};
+use crate::queues::send::operations::common::{Payload, collect_sgl, generate_segments_from_request};
 use crate::third_party::queues::meta_report::ToHostWorkRbDescOpcode;
 use crate::{DeviceInner, Result};
 
@@ -14,7 +14,7 @@ pub struct ReadResponse {
     last: bool,
     #[expect(dead_code, reason = "todo")]
     first: bool,
-    sge: ScatterGatherElement,
+    payload: Payload,
 }
 
 impl AsRef<Common> for ReadResponse {
@@ -31,8 +31,7 @@ impl<UA: Agent, DC: Client> HandleDescriptor<ReadResponse> for DeviceInner<UA, D
         log::info!("handle read response op: {req:?}");
 
         let path_mtu = u32::from(&req.common.path_mtu_kind);
-        let segments = generate_segments_from_request(req.sge.local_addr.0, req.sge.len, path_mtu);
-        let key = req.sge.local_key;
+        let segments = generate_segments_from_request(req.common.remote_addr.0, req.payload.len(), path_mtu);
 
         let mut remote_va = req.common.remote_addr.0;
         let mut psn = req.common.psn;
@@ -43,9 +42,10 @@ impl<UA: Agent, DC: Client> HandleDescriptor<ReadResponse> for DeviceInner<UA, D
                     ToHostWorkRbDescOpcode::RdmaReadResponseOnly,
                     psn,
                     true,
-                    key,
+                    &req.payload,
                     remote_va,
                     only,
+                    None,
                 );
             }
             [ref first, ref middles @ .., ref last] => {
@@ -54,9 +54,10 @@ impl<UA: Agent, DC: Client> HandleDescriptor<ReadResponse> for DeviceInner<UA, D
                     ToHostWorkRbDescOpcode::RdmaReadResponseFirst,
                     psn,
                     false,
-                    key,
+                    &req.payload,
                     remote_va,
                     first,
+                    None,
                 );
 
                 remote_va += u64::from(first.len());
@@ -68,9 +69,10 @@ impl<UA: Agent, DC: Client> HandleDescriptor<ReadResponse> for DeviceInner<UA, D
                         ToHostWorkRbDescOpcode::RdmaReadResponseMiddle,
                         psn,
                         false,
-                        key,
+                        &req.payload,
                         remote_va,
                         middle,
+                        None,
                     );
 
                     remote_va += u64::from(middle.len());
@@ -82,9 +84,10 @@ impl<UA: Agent, DC: Client> HandleDescriptor<ReadResponse> for DeviceInner<UA, D
                     ToHostWorkRbDescOpcode::RdmaReadResponseLast,
                     psn,
                     true,
-                    key,
+                    &req.payload,
                     remote_va,
                     last,
+                    None,
                 );
             }
             [] => todo!(),
@@ -108,11 +111,7 @@ impl Builder {
             common: Common::from_seg0(seg0),
             last,
             first,
-            sge: ScatterGatherElement {
-                local_key: 0.into(),
-                len: 0,
-                local_addr: 0.into(),
-            },
+            payload: Payload::Sgl(Vec::new()),
         })
     }
 
@@ -124,15 +123,8 @@ impl Builder {
     }
 
     /// Update sge, assuming seg0 and seg1 are processed
-    pub fn with_sge(mut self, sge: VariableLengthSge) -> ReadResponse {
-        let sge0 = sge.sge1;
-        let sge1 = sge.sge2;
-        assert!(
-            sge1.local_addr == 0.into() && sge1.len == 0 && sge1.local_key == 0.into(),
-            "support only one sge for now"
-        );
-
-        self.0.sge = sge0;
+    pub fn with_sge(mut self, sge: VariableLengthSge, extra_sge: Option<VariableLengthSge>) -> ReadResponse {
+        self.0.payload = Payload::Sgl(collect_sgl(sge, extra_sge));
 
         self.0
     }
@@ -0,0 +1,163 @@
+use super::common::Common;
+use crate::dma::Client;
+use crate::net::Agent;
+use crate::queues::descriptor::HandleDescriptor;
+use crate::queues::send::descriptors::{Seg0, Seg1, VariableLengthSge};
+use crate::queues::send::operations::Opcode;
+use crate::queues::send::operations::common::{Payload, collect_sgl, generate_segments_from_request};
+use crate::third_party::queues::meta_report::ToHostWorkRbDescOpcode;
+use crate::types::QueuePairType;
+use crate::{DeviceInner, Result};
+
+#[derive(Debug)]
+pub struct Send {
+    common: Common,
+    #[expect(dead_code, reason = "unknown usage yet")]
+    last: bool,
+    #[expect(dead_code, reason = "unknown usage yet")]
+    first: bool,
+    with_immediate: bool,
+    immediate_data: Option<u32>,
+    with_invalidate: bool,
+    /// remote key invalidated by the peer, carried by IETH
+    invalidate_key: Option<u32>,
+    payload: Payload,
+}
+
+impl AsRef<Common> for Send {
+    fn as_ref(&self) -> &Common {
+        &self.common
+    }
+}
+
+impl<UA: Agent, DC: Client> HandleDescriptor<Send> for DeviceInner<UA, DC> {
+    type Context = ();
+    type Output = ();
+
+    fn handle(&self, req: &Send, &mut (): &mut ()) -> Result<Self::Output> {
+        log::info!("handle send op: {req:?}");
+
+        let path_mtu = u32::from(&req.common.path_mtu_kind);
+        let segments = generate_segments_from_request(0, req.payload.len(), path_mtu);
+        let imm = req.immediate_data;
+        let ieth = req.invalidate_key;
+        if req.common.qp_type == QueuePairType::Ud && segments.len() > 1 {
+            log::error!("drop UD SEND larger than path mtu: {req:?}");
+            return Ok(());
+        }
+        if req.common.qp_type == QueuePairType::Ud && ieth.is_some() {
+            log::error!("drop UD SEND with invalidate: {req:?}");
+            return Ok(());
+        }
+
+        // SEND has no RETH, the receiver places the packets in PSN order, so no remote address is carried
+        let remote_va = 0;
+        let mut psn = req.common.psn;
+        match *segments.as_slice() {
+            [ref only] => {
+                let opcode = if imm.is_some() {
+                    ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                } else if ieth.is_some() {
+                    ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+                } else {
+                    ToHostWorkRbDescOpcode::SendOnly
+                };
+                self.send_write_message(req, opcode, psn, true, &req.payload, remote_va, only, imm.or(ieth));
+            }
+            [ref first, ref middles @ .., ref last] => {
+                self.send_write_message(
+                    req,
+                    ToHostWorkRbDescOpcode::SendFirst,
+                    psn,
+                    false,
+                    &req.payload,
+                    remote_va,
+                    first,
+                    None,
+                );
+
+                psn = psn.wrapping_add(1);
+
+                for middle in middles {
+                    self.send_write_message(
+                        req,
+                        ToHostWorkRbDescOpcode::SendMiddle,
+                        psn,
+                        false,
+                        &req.payload,
+                        remote_va,
+                        middle,
+                        None,
+                    );
+
+                    psn = psn.wrapping_add(1);
+                }
+
+                let opcode = if imm.is_some() {
+                    ToHostWorkRbDescOpcode::SendLastWithImmediate
+                } else if ieth.is_some() {
+                    ToHostWorkRbDescOpcode::SendLastWithInvalidate
+                } else {
+                    ToHostWorkRbDescOpcode::SendLast
+                };
+                self.send_write_message(req, opcode, psn, true, &req.payload, remote_va, last, imm.or(ieth));
+            }
+            [] => unreachable!("logic error: a message has at least one segment"),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+/// Send Builder
+pub struct Builder(Send);
+
+impl Builder {
+    /// Initialize builder from valid seg0
+    pub fn from_seg0(seg0: Seg0) -> Self {
+        let first = seg0.header.first();
+        let last = seg0.header.last();
+        let with_immediate = seg0.header.opcode().is_ok_and(|opcode| opcode == Opcode::SendWithImm);
+        let with_invalidate = seg0.header.opcode().is_ok_and(|opcode| opcode == Opcode::SendWithInv);
+        Self(Send {
+            common: Common::from_seg0(seg0),
+            last,
+            first,
+            with_immediate,
+            immediate_data: None,
+            with_invalidate,
+            invalidate_key: None,
+            payload: Payload::Sgl(Vec::new()),
+        })
+    }
+
+    /// Update valid seg1, assuming only seg0 is processed
+    pub fn with_seg1(mut self, seg1: Seg1) -> Self {
+        if self.0.with_immediate {
+            self.0.immediate_data = Some(seg1.immediate_data);
+        }
+        // the key to invalidate shares the immediate data field of seg1
+        if self.0.with_invalidate {
+            self.0.invalidate_key = Some(seg1.immediate_data);
+        }
+
+        self.0.common.with_seg1(seg1);
+
+        self
+    }
+
+    /// Update sge, assuming seg0 and seg1 are processed
+    pub fn with_sge(mut self, sge: VariableLengthSge, extra_sge: Option<VariableLengthSge>) -> Send {
+        self.0.payload = Payload::Sgl(collect_sgl(sge, extra_sge));
+
+        self.0
+    }
+
+    /// Update inline data, assuming seg0 and seg1 are processed
+    pub fn with_inline_data(mut self, data: Vec<u8>) -> Send {
+        self.0.payload = Payload::Inline(data);
+
+        self.0
+    }
+}
@@ -1,10 +1,9 @@
-
 use super::common::Common;
 use crate::dma::Client;
 use crate::net::Agent;
 use crate::queues::descriptor::HandleDescriptor;
-use crate::queues::send::descriptors::{ScatterGatherElement, Seg0, Seg1, VariableLengthSge};
-use crate::queues::send::operations::common::generate_segments_from_request;
+use crate::queues::send::descriptors::{Seg0, Seg1, VariableLengthSge};
+use crate::queues::send::operations::common::{Payload, collect_sgl, generate_segments_from_request};
 use crate::third_party::queues::meta_report::ToHostWorkRbDescOpcode;
 use crate::{DeviceInner, Result};
 
@@ -15,7 +14,7 @@ pub struct Write {
     last: bool,
     #[expect(dead_code, reason = "unknown usage yet")]
     first: bool,
-    sge: ScatterGatherElement,
+    payload: Payload,
 }
 
 impl AsRef<Common> for Write {
@@ -32,8 +31,7 @@ impl<UA: Agent, DC: Client> HandleDescriptor<Write> for DeviceInner<UA, DC> {
         log::info!("handle write op: {req:?}");
 
         let path_mtu = u32::from(&req.common.path_mtu_kind);
-        let segments = generate_segments_from_request(req.sge.local_addr.0, req.sge.len, path_mtu);
-        let key = req.sge.local_key;
+        let segments = generate_segments_from_request(req.common.remote_addr.0, req.payload.len(), path_mtu);
 
         let mut remote_va = req.common.remote_addr.0;
         let mut psn = req.common.psn;
@@ -44,9 +42,10 @@ impl<UA: Agent, DC: Client> HandleDescriptor<Write> for DeviceInner<UA, DC> {
                     ToHostWorkRbDescOpcode::RdmaWriteOnly,
                     psn,
                     true,
-                    key,
+                    &req.payload,
                     remote_va,
                     only,
+                    None,
                 );
             }
             [ref first, ref middles @ .., ref last] => {
@@ -55,9 +54,10 @@ impl<UA: Agent, DC: Client> HandleDescriptor<Write> for DeviceInner<UA, DC> {
                     ToHostWorkRbDescOpcode::RdmaWriteFirst,
                     psn,
                     false,
-                    key,
+                    &req.payload,
                     remote_va,
                     first,
+                    None,
                 );
 
                 remote_va += u64::from(first.len());
@@ -69,9 +69,10 @@ impl<UA: Agent, DC: Client> HandleDescriptor<Write> for DeviceInner<UA, DC> {
                         ToHostWorkRbDescOpcode::RdmaWriteMiddle,
                         psn,
                         false,
-                        key,
+                        &req.payload,
                         remote_va,
                         middle,
+                        None,
                     );
 
                     remote_va += u64::from(middle.len());
@@ -83,9 +84,10 @@ impl<UA: Agent, DC: Client> HandleDescriptor<Write> for DeviceInner<UA, DC> {
                     ToHostWorkRbDescOpcode::RdmaWriteLast,
                     psn,
                     true,
-                    key,
+                    &req.payload,
                     remote_va,
                     last,
+                    None,
                 );
             }
             [] => todo!(),
@@ -109,11 +111,7 @@ impl Builder {
             common: Common::from_seg0(seg0),
             last,
             first,
-            sge: ScatterGatherElement {
-                local_key: 0.into(),
-                len: 0,
-                local_addr: 0.into(),
-            },
+            payload: Payload::Sgl(Vec::new()),
         })
     }
 
@@ -125,15 +123,15 @@ impl Builder {
     }
 
     /// Update sge, assuming seg0 and seg1 are processed
-    pub fn with_sge(mut self, sge: VariableLengthSge) -> Write {
-        let sge0 = sge.sge1;
-        let sge1 = sge.sge2;
-        assert!(
-            sge1.local_addr == 0.into() && sge1.len == 0 && sge1.local_key == 0.into(),
-            "support only one sge for now"
-        );
-
-        self.0.sge = sge0;
+    pub fn with_sge(mut self, sge: VariableLengthSge, extra_sge: Option<VariableLengthSge>) -> Write {
+        self.0.payload = Payload::Sgl(collect_sgl(sge, extra_sge));
+
+        self.0
+    }
+
+    /// Update inline data, assuming seg0 and seg1 are processed
+    pub fn with_inline_data(mut self, data: Vec<u8>) -> Write {
+        self.0.payload = Payload::Inline(data);
 
         self.0
     }
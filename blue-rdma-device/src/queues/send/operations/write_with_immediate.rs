@@ -1,19 +1,19 @@
-use super::common::Common;
+use super::common::{Common, Payload, collect_sgl, generate_segments_from_request};
 use crate::dma::Client;
 use crate::net::Agent;
 use crate::queues::descriptor::HandleDescriptor;
-use crate::queues::send::descriptors::{ScatterGatherElement, Seg0, Seg1, VariableLengthSge};
+use crate::queues::send::descriptors::{Seg0, Seg1, VariableLengthSge};
+use crate::third_party::queues::meta_report::ToHostWorkRbDescOpcode;
 use crate::{DeviceInner, Result};
 
 #[derive(Debug)]
 pub struct WriteWithImmediate {
     common: Common,
-    #[expect(dead_code, reason = "todo")]
     last: bool,
-    #[expect(dead_code, reason = "todo")]
+    #[expect(dead_code, reason = "unknown usage yet")]
     first: bool,
     immediate_data: u32,
-    sge: ScatterGatherElement,
+    payload: Payload,
 }
 
 impl AsRef<Common> for WriteWithImmediate {
@@ -26,9 +26,71 @@ impl<UA: Agent, DC: Client> HandleDescriptor<WriteWithImmediate> for DeviceInner
     type Context = ();
     type Output = ();
 
-    #[expect(unused, reason = "todo")]
-    fn handle(&self, request: &WriteWithImmediate, cx: &mut Self::Context) -> Result<Self::Output> {
-        todo!()
+    fn handle(&self, req: &WriteWithImmediate, &mut (): &mut ()) -> Result<Self::Output> {
+        log::info!("handle write with immediate op: {req:?}");
+
+        let path_mtu = u32::from(&req.common.path_mtu_kind);
+        let segments = generate_segments_from_request(req.common.remote_addr.0, req.payload.len(), path_mtu);
+
+        // The driver may split a large request into multiple descriptors,
+        // only the last packet of the last descriptor carries the immediate data.
+        let (only_opcode, last_opcode, imm) = if req.last {
+            (
+                ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate,
+                ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate,
+                Some(req.immediate_data),
+            )
+        } else {
+            (
+                ToHostWorkRbDescOpcode::RdmaWriteOnly,
+                ToHostWorkRbDescOpcode::RdmaWriteLast,
+                None,
+            )
+        };
+
+        let mut remote_va = req.common.remote_addr.0;
+        let mut psn = req.common.psn;
+        match *segments.as_slice() {
+            [ref only] => {
+                self.send_write_message(req, only_opcode, psn, true, &req.payload, remote_va, only, imm);
+            }
+            [ref first, ref middles @ .., ref last] => {
+                self.send_write_message(
+                    req,
+                    ToHostWorkRbDescOpcode::RdmaWriteFirst,
+                    psn,
+                    false,
+                    &req.payload,
+                    remote_va,
+                    first,
+                    None,
+                );
+
+                remote_va += u64::from(first.len());
+                psn = psn.wrapping_add(1);
+
+                for middle in middles {
+                    self.send_write_message(
+                        req,
+                        ToHostWorkRbDescOpcode::RdmaWriteMiddle,
+                        psn,
+                        false,
+                        &req.payload,
+                        remote_va,
+                        middle,
+                        None,
+                    );
+
+                    remote_va += u64::from(middle.len());
+                    psn = psn.wrapping_add(1);
+                }
+
+                self.send_write_message(req, last_opcode, psn, true, &req.payload, remote_va, last, imm);
+            }
+            [] => unreachable!("logic error: a message has at least one segment"),
+        }
+
+        Ok(())
     }
 }
 
@@ -47,11 +109,7 @@ impl Builder {
             last,
             first,
             immediate_data: 0,
-            sge: ScatterGatherElement {
-                local_key: 0.into(),
-                len: 0,
-                local_addr: 0.into(),
-            },
+            payload: Payload::Sgl(Vec::new()),
         })
     }
 
@@ -65,15 +123,15 @@ impl Builder {
     }
 
     /// Update sge, assuming seg0 and seg1 are processed
-    pub fn with_sge(mut self, sge: VariableLengthSge) -> WriteWithImmediate {
-        let sge0 = sge.sge1;
-        let sge1 = sge.sge2;
-        assert!(
-            sge1.local_addr == 0.into() && sge1.len == 0 && sge1.local_key == 0.into(),
-            "support only one sge for now"
-        );
-
-        self.0.sge = sge0;
+    pub fn with_sge(mut self, sge: VariableLengthSge, extra_sge: Option<VariableLengthSge>) -> WriteWithImmediate {
+        self.0.payload = Payload::Sgl(collect_sgl(sge, extra_sge));
+
+        self.0
+    }
+
+    /// Update inline data, assuming seg0 and seg1 are processed
+    pub fn with_inline_data(mut self, data: Vec<u8>) -> WriteWithImmediate {
+        self.0.payload = Payload::Inline(data);
 
         self.0
     }
@@ -1,13 +1,17 @@
 use core::marker::PhantomData;
+use core::sync::atomic::Ordering;
 
-use super::descriptors::{DESCRIPTOR_SIZE, Seg0, Seg1, VariableLengthSge};
+use super::descriptors::{AtomicOperands, DESCRIPTOR_SIZE, InlineData, Seg0, Seg1, VariableLengthSge};
 use super::operations::WriteBuilder;
 use crate::DeviceInner;
 use crate::dma::{Client, PointerMut};
 use crate::net::Agent;
 use crate::queues::descriptor::HandleDescriptor;
-use crate::queues::send::operations::{Opcode, ReadBuilder, ReadResponseBuilder, WriteWithImmediateBuilder};
+use crate::queues::send::operations::{
+    AtomicBuilder, Opcode, ReadBuilder, ReadResponseBuilder, SendBuilder, WriteWithImmediateBuilder,
+};
 use crate::queues::work_queue::WorkQueue;
+use crate::types::SendFlag;
 
 // SendQueue is same type as RegistersSendHandle
 #[derive(Debug)]
@@ -69,7 +73,7 @@ impl<UA: Agent, DC: Client> SendQueue<'_, UA, DC> {
     }
 
     pub(crate) fn run(&self) {
-        while self.dev.rx_send.recv() == Ok(()) {
+        while self.dev.rx_send.recv() == Ok(()) && !self.dev.stop.load(Ordering::Relaxed) {
             // SAFETY: caller should guarantee queue is valid
             while let Some(raw0) = unsafe { self.pop() } {
                 let seg0 = Seg0::from_bytes(raw0);
@@ -77,48 +81,103 @@ impl<UA: Agent, DC: Client> SendQueue<'_, UA, DC> {
                 assert!(seg0.header.valid(), "invalid seg0 header");
                 log::info!("recv send seg0: {seg0:?}");
                 let opcode = seg0.header.opcode().expect("send opcode parse failed");
+                // the fourth descriptor only exists when there are more than two sges
+                let has_extra_sge = seg0.header.extra_segment_cnt() == 3;
+                let extra_segment_cnt = seg0.header.extra_segment_cnt();
+                let total_len = seg0.header.total_len();
 
                 match opcode {
                     Opcode::Write => {
-                        // write use 3 descriptors
+                        // write use 3 or 4 descriptors
                         let builder = WriteBuilder::from_seg0(seg0);
 
                         // SAFETY: caller should guarantee queue is valid
                         let raw1 = unsafe { self.pop() }.expect("partial write operator");
                         let seg1 = Seg1::from_bytes(raw1);
+                        let is_inline = seg1.send_flag().contains(SendFlag::IbvSendInline);
 
                         let builder = builder.with_seg1(seg1);
 
-                        // SAFETY: caller should guarantee queue is valid
-                        let raw2 = unsafe { self.pop() }.expect("partial write operator");
-                        let sge = VariableLengthSge::from_bytes(raw2);
+                        let write = if is_inline {
+                            let data = self.pop_inline_data(extra_segment_cnt, total_len, "write");
+                            builder.with_inline_data(data)
+                        } else {
+                            // SAFETY: caller should guarantee queue is valid
+                            let raw2 = unsafe { self.pop() }.expect("partial write operator");
+                            let sge = VariableLengthSge::from_bytes(raw2);
+
+                            // SAFETY: caller should guarantee queue is valid
+                            let extra_sge = has_extra_sge
+                                .then(|| unsafe { self.pop() }.expect("partial write operator"))
+                                .map(VariableLengthSge::from_bytes);
 
-                        let write = builder.with_sge(sge);
+                            builder.with_sge(sge, extra_sge)
+                        };
 
                         self.dev.handle(&write, &mut ()).unwrap();
                     }
                     Opcode::WriteWithImm => {
-                        // WriteWithImm use 3 descriptors
+                        // WriteWithImm use 3 or 4 descriptors
                         let builder = WriteWithImmediateBuilder::from_seg0(seg0);
 
                         // SAFETY: caller should guarantee queue is valid
                         let raw1 = unsafe { self.pop() }.expect("partial write_with_immediate operator");
                         let seg1 = Seg1::from_bytes(raw1);
+                        let is_inline = seg1.send_flag().contains(SendFlag::IbvSendInline);
 
                         let builder = builder.with_seg1(seg1);
 
-                        // SAFETY: caller should guarantee queue is valid
-                        let raw2 = unsafe { self.pop() }.expect("partial write_with_immediate operator");
-                        let sge = VariableLengthSge::from_bytes(raw2);
+                        let write_with_immediate = if is_inline {
+                            let data = self.pop_inline_data(extra_segment_cnt, total_len, "write_with_immediate");
+                            builder.with_inline_data(data)
+                        } else {
+                            // SAFETY: caller should guarantee queue is valid
+                            let raw2 = unsafe { self.pop() }.expect("partial write_with_immediate operator");
+                            let sge = VariableLengthSge::from_bytes(raw2);
 
-                        let write_with_immediate = builder.with_sge(sge);
+                            // SAFETY: caller should guarantee queue is valid
+                            let extra_sge = has_extra_sge
+                                .then(|| unsafe { self.pop() }.expect("partial write_with_immediate operator"))
+                                .map(VariableLengthSge::from_bytes);
+
+                            builder.with_sge(sge, extra_sge)
+                        };
 
                         self.dev
                             .handle(&write_with_immediate, &mut ())
                             .expect("handle WriteWithImm error");
                     }
+                    Opcode::Send | Opcode::SendWithImm | Opcode::SendWithInv => {
+                        // Send and SendWithImm use 3 or 4 descriptors
+                        let builder = SendBuilder::from_seg0(seg0);
+
+                        // SAFETY: caller should guarantee queue is valid
+                        let raw1 = unsafe { self.pop() }.expect("partial send operator");
+                        let seg1 = Seg1::from_bytes(raw1);
+                        let is_inline = seg1.send_flag().contains(SendFlag::IbvSendInline);
+
+                        let builder = builder.with_seg1(seg1);
+
+                        let send = if is_inline {
+                            let data = self.pop_inline_data(extra_segment_cnt, total_len, "send");
+                            builder.with_inline_data(data)
+                        } else {
+                            // SAFETY: caller should guarantee queue is valid
+                            let raw2 = unsafe { self.pop() }.expect("partial send operator");
+                            let sge = VariableLengthSge::from_bytes(raw2);
+
+                            // SAFETY: caller should guarantee queue is valid
+                            let extra_sge = has_extra_sge
+                                .then(|| unsafe { self.pop() }.expect("partial send operator"))
+                                .map(VariableLengthSge::from_bytes);
+
+                            builder.with_sge(sge, extra_sge)
+                        };
+
+                        self.dev.handle(&send, &mut ()).expect("handle Send error");
+                    }
                     Opcode::Read => {
-                        // Read use 3 descriptors
+                        // Read use 3 or 4 descriptors
                         let builder = ReadBuilder::from_seg0(seg0);
 
                         // SAFETY: caller should guarantee queue is valid
@@ -131,12 +190,41 @@ impl<UA: Agent, DC: Client> SendQueue<'_, UA, DC> {
                         let raw2 = unsafe { self.pop() }.expect("partial read operator");
                         let sge = VariableLengthSge::from_bytes(raw2);
 
-                        let read = builder.with_sge(sge);
+                        // SAFETY: caller should guarantee queue is valid
+                        let extra_sge = has_extra_sge
+                            .then(|| unsafe { self.pop() }.expect("partial read operator"))
+                            .map(VariableLengthSge::from_bytes);
+
+                        let read = builder.with_sge(sge, extra_sge);
 
                         self.dev.handle(&read, &mut ()).expect("handle Read error");
                     }
+                    Opcode::CompareSwap | Opcode::FetchAdd => {
+                        // atomic use 4 descriptors, the last one carries the operands
+                        let builder = AtomicBuilder::from_seg0(seg0);
+
+                        // SAFETY: caller should guarantee queue is valid
+                        let raw1 = unsafe { self.pop() }.expect("partial atomic operator");
+                        let seg1 = Seg1::from_bytes(raw1);
+
+                        let builder = builder.with_seg1(seg1);
+
+                        // SAFETY: caller should guarantee queue is valid
+                        let raw2 = unsafe { self.pop() }.expect("partial atomic operator");
+                        let sge = VariableLengthSge::from_bytes(raw2);
+
+                        let builder = builder.with_sge(sge);
+
+                        // SAFETY: caller should guarantee queue is valid
+                        let raw3 = unsafe { self.pop() }.expect("partial atomic operator");
+                        let operands = AtomicOperands::from_bytes(raw3);
+
+                        let atomic = builder.with_operands(operands);
+
+                        self.dev.handle(&atomic, &mut ()).expect("handle Atomic error");
+                    }
                     Opcode::ReadResp => {
-                        // ReadResp use 3 descriptors
+                        // ReadResp use 3 or 4 descriptors
                         let builder = ReadResponseBuilder::from_seg0(seg0);
 
                         // SAFETY: caller should guarantee queue is valid
@@ -149,7 +237,12 @@ impl<UA: Agent, DC: Client> SendQueue<'_, UA, DC> {
                         let raw2 = unsafe { self.pop() }.expect("partial read_response operator");
                         let sge = VariableLengthSge::from_bytes(raw2);
 
-                        let read_response = builder.with_sge(sge);
+                        // SAFETY: caller should guarantee queue is valid
+                        let extra_sge = has_extra_sge
+                            .then(|| unsafe { self.pop() }.expect("partial read_response operator"))
+                            .map(VariableLengthSge::from_bytes);
+
+                        let read_response = builder.with_sge(sge, extra_sge);
 
                         self.dev.handle(&read_response, &mut ()).expect("handle ReadResp error");
                     }
@@ -157,6 +250,23 @@ impl<UA: Agent, DC: Client> SendQueue<'_, UA, DC> {
             }
         }
     }
+
+    /// Pop the descriptors following seg1, which carry `total_len` bytes of inline data
+    fn pop_inline_data(&self, extra_segment_cnt: u8, total_len: u32, operator: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        // seg1 is the first extra segment
+        for _ in 1..extra_segment_cnt {
+            // SAFETY: caller should guarantee queue is valid
+            let raw = unsafe { self.pop() }.unwrap_or_else(|| panic!("partial {operator} operator"));
+            data.extend_from_slice(&InlineData::from_bytes(raw).data);
+        }
+        assert!(
+            data.len() >= total_len as usize,
+            "inline data is shorter than the message"
+        );
+        data.truncate(total_len as usize);
+        data
+    }
 }
 
 impl<UA: Agent, DC: Client> DeviceInner<UA, DC> {
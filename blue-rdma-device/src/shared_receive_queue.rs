@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use papaya::HashMap;
+
+use super::queue_pair::ReceiveWorkRequest;
+use super::types::{ProtectDomainHandler, SharedReceiveQueueNumber};
+
+/// Receive work requests shared by all queue pairs attached to the queue
+#[derive(Debug)]
+pub struct Context {
+    shared_receive_queue_number: SharedReceiveQueueNumber,
+    #[expect(unused, reason = "may use later")]
+    protect_domain_handler: ProtectDomainHandler,
+    recv_queue: Mutex<VecDeque<ReceiveWorkRequest>>,
+}
+
+impl Context {
+    pub const fn new(
+        shared_receive_queue_number: SharedReceiveQueueNumber,
+        protect_domain_handler: ProtectDomainHandler,
+    ) -> Self {
+        Self {
+            shared_receive_queue_number,
+            protect_domain_handler,
+            recv_queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// append receive work request into the shared receive queue
+    pub fn post_recv(&self, wr: ReceiveWorkRequest) {
+        self.recv_queue.lock().unwrap().push_back(wr);
+    }
+
+    /// take the oldest receive work request, return None if the queue is empty
+    pub fn take_recv(&self) -> Option<ReceiveWorkRequest> {
+        self.recv_queue.lock().unwrap().pop_front()
+    }
+
+    /// put back a receive work request which is taken but not consumed
+    pub fn untake_recv(&self, wr: ReceiveWorkRequest) {
+        self.recv_queue.lock().unwrap().push_front(wr);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Table(HashMap<SharedReceiveQueueNumber, Context>);
+
+impl Table {
+    pub fn insert(&self, srq_context: Context) -> bool {
+        log::debug!("insert srq_table with {srq_context:?}");
+
+        let srq_table = self.0.pin();
+        srq_table
+            .insert(srq_context.shared_receive_queue_number, srq_context)
+            .is_some()
+    }
+
+    pub fn remove(&self, srqn: SharedReceiveQueueNumber) -> bool {
+        log::debug!("remove srq_table with {srqn:?}");
+
+        let srq_table = self.0.pin();
+        srq_table.remove(&srqn).is_some()
+    }
+
+    pub fn guard(&self) -> impl papaya::Guard + '_ {
+        self.0.guard()
+    }
+
+    pub fn get<'guard>(
+        &self,
+        srqn: SharedReceiveQueueNumber,
+        guard: &'guard impl papaya::Guard,
+    ) -> Option<&'guard Context> {
+        self.0.get(&srqn, guard)
+    }
+}
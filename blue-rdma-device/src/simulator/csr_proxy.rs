@@ -114,6 +114,9 @@ trait ControlStatusRegistersExt: ControlStatusRegisters {
 mod csr {
     use super::super::super::csr::command_request::REGISTERS_COMMAND_REQUEST_BASE_ADDR;
     use super::super::super::csr::command_response::REGISTERS_COMMAND_RESPONSE_BASE_ADDR;
+    use super::super::super::csr::memory_region::{
+        REGISTERS_MR_KEY_INDEX_BITS, REGISTERS_MR_TABLE_SIZE, REGISTERS_PAGE_TABLE_SIZE,
+    };
     use super::super::super::csr::meta_report::REGISTERS_META_REPORT_BASE_ADDR;
     use super::super::super::csr::reset::REGISTERS_SOFT_RESET;
     use super::super::super::csr::send::REGISTERS_SEND_BASE_ADDR;
@@ -121,6 +124,7 @@ mod csr {
     use super::device_api::ControlStatusRegisters;
     use super::device_api::csr::{RegisterOperation, RegistersQueue, RegistersQueueAddress};
     use crate::csr::reset::{HARDWARE_VERSION, REGISTERS_HW_VERSION};
+    use crate::memory_region::{PAGE_TABLE_SIZE, TABLE_SIZE};
 
     const REGISTERS_SEND_BASE_ADDR_END: u64 = REGISTERS_SEND_BASE_ADDR + 16;
     const REGISTERS_META_REPORT_BASE_ADDR_END: u64 = REGISTERS_META_REPORT_BASE_ADDR + 16;
@@ -175,6 +179,9 @@ mod csr {
                     csr.read()
                 }
                 REGISTERS_HW_VERSION => HARDWARE_VERSION,
+                REGISTERS_MR_TABLE_SIZE => TABLE_SIZE,
+                REGISTERS_PAGE_TABLE_SIZE => PAGE_TABLE_SIZE,
+                REGISTERS_MR_KEY_INDEX_BITS => self.mr_key_index_bits().read(),
                 _ => unimplemented!("read at addr {addr:#018x}"),
             }
         }
@@ -225,6 +232,7 @@ mod csr {
                     let csr = self.reset();
                     csr.write(val);
                 }
+                REGISTERS_MR_KEY_INDEX_BITS => self.mr_key_index_bits().write(val),
                 _ => unimplemented!("write at addr {addr:#018x}, val: {val}"),
             };
         }
@@ -53,8 +53,8 @@ impl<R: Client> UdpAgent<R> {
     // So I can convert between `Vec<RpcNetIfcRxTxPayload>` and `payload: Vec<u8>`
     // Currently I convert between `eth_frame: Vec<u8>` and `payload: Vec<u8>`
 
-    /// Receive a single ethernet frame buffer from NIC
-    fn receive_ethernet_frame_buffer(&self) -> Vec<u8> {
+    /// Receive a single ethernet frame buffer from NIC, returns `None` if there is no frame
+    fn receive_ethernet_frame_buffer(&self) -> Option<Vec<u8>> {
         static FRAGMENT: AtomicU32 = AtomicU32::new(0);
         static FRAME: AtomicU32 = AtomicU32::new(0);
 
@@ -67,6 +67,9 @@ impl<R: Client> UdpAgent<R> {
             }
             let invalid_fragment = request.is_valid == 0;
             if invalid_fragment {
+                if buffer.is_empty() {
+                    return None;
+                }
                 continue;
             }
             generate_frame_fragment_file(&request, &FRAME, &FRAGMENT);
@@ -84,7 +87,7 @@ impl<R: Client> UdpAgent<R> {
             let last_fragment = request.is_last == 1;
             if last_fragment {
                 generate_frame_file(&buffer, &FRAME, &FRAGMENT);
-                return buffer;
+                return Some(buffer);
             }
         }
     }
@@ -265,7 +268,9 @@ impl<R: Client> Agent for UdpAgent<R> {
     }
 
     fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpAddr)> {
-        let buffer = self.receive_ethernet_frame_buffer();
+        let buffer = self
+            .receive_ethernet_frame_buffer()
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::WouldBlock))?;
 
         let (payload, origin) = self.parse_frame_and_extract_payload(&buffer)?;
         let len = buf.len().min(payload.len());
@@ -5,5 +5,6 @@ mod types;
 pub(crate) use packet::{AETH, BTH};
 pub(crate) use packet_processor::{PacketProcessor, PacketWriter};
 pub(crate) use types::{
-    AethHeader, Key, Metadata, PKey, PayloadInfo, Qpn, RdmaGeneralMeta, RdmaMessage, RdmaMessageMetaCommon, RethHeader,
+    AethHeader, AtomicEthHeader, DethHeader, Key, Metadata, PKey, PayloadInfo, Qpn, RdmaGeneralMeta, RdmaMessage,
+    RdmaMessageMetaCommon, RethHeader, SGListElement,
 };
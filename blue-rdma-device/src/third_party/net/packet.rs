@@ -6,7 +6,8 @@ use std::net::Ipv4Addr;
 use thiserror::Error;
 
 use super::types::{
-    AethHeader, Metadata, PayloadInfo, RdmaGeneralMeta, RdmaMessage, RdmaMessageMetaCommon, RethHeader,
+    AethHeader, AtomicEthHeader, DethHeader, Metadata, PayloadInfo, RdmaGeneralMeta, RdmaMessage,
+    RdmaMessageMetaCommon, RethHeader,
 };
 use crate::third_party::queues::meta_report::{
     ToHostWorkRbDescAethCode, ToHostWorkRbDescOpcode, ToHostWorkRbDescTransType,
@@ -34,6 +35,7 @@ const AETH_CODE_MASK: u8 = 0x60;
 const AETH_CODE_SHIFT: usize = 5;
 const AETH_VALUE_MASK: u8 = 0x1F;
 const AETH_MSN_MASK: u32 = 0x00FF_FFFF;
+const DETH_SOURCE_QPN_MASK: u32 = 0x00FF_FFFF;
 
 /// Base Transport Header of RDMA over Ethernet
 #[derive(Clone, Copy)]
@@ -255,6 +257,81 @@ impl Immediate {
     }
 }
 
+/// Atomic Extended Transport Header
+#[repr(C, packed)]
+#[allow(clippy::upper_case_acronyms)]
+pub(crate) struct AtomicETH {
+    va: [u8; 8],
+    rkey: [u8; 4],
+    swap_add: [u8; 8],
+    compare: [u8; 8],
+}
+
+impl AtomicETH {
+    pub(crate) fn get_va(&self) -> u64 {
+        u64::from_be_bytes(self.va)
+    }
+
+    pub(crate) fn get_rkey(&self) -> u32 {
+        u32::from_be_bytes(self.rkey)
+    }
+
+    pub(crate) fn get_swap_add(&self) -> u64 {
+        u64::from_be_bytes(self.swap_add)
+    }
+
+    pub(crate) fn get_compare(&self) -> u64 {
+        u64::from_be_bytes(self.compare)
+    }
+
+    pub(crate) fn set_from_atomic_header(&mut self, header: &AtomicEthHeader) {
+        self.va = header.va.to_be_bytes();
+        self.rkey = header.rkey.get().to_be_bytes();
+        self.swap_add = header.swap_add.to_be_bytes();
+        self.compare = header.compare.to_be_bytes();
+    }
+}
+
+/// Atomic ACK Extended Transport Header, which carries the original value of the remote address
+#[repr(C, packed)]
+#[allow(clippy::upper_case_acronyms)]
+pub(crate) struct AtomicAckETH {
+    orig: [u8; 8],
+}
+
+impl AtomicAckETH {
+    pub(crate) fn get_orig(&self) -> u64 {
+        u64::from_be_bytes(self.orig)
+    }
+
+    pub(crate) fn set_orig(&mut self, orig: u64) {
+        self.orig = orig.to_be_bytes();
+    }
+}
+
+/// Datagram Extended Transport Header, which is carried by every UD packet
+#[repr(C, packed)]
+#[allow(clippy::upper_case_acronyms)]
+pub(crate) struct DETH {
+    qkey: [u8; 4],
+    source_qpn: [u8; 4], // The higher 1 byte is not used.
+}
+
+impl DETH {
+    pub(crate) fn get_qkey(&self) -> u32 {
+        u32::from_be_bytes(self.qkey)
+    }
+
+    pub(crate) fn get_source_qpn(&self) -> u32 {
+        u32::from_be_bytes([0, self.source_qpn[1], self.source_qpn[2], self.source_qpn[3]])
+    }
+
+    pub(crate) fn set_from_deth_header(&mut self, deth: &DethHeader) {
+        self.qkey = deth.qkey.to_be_bytes();
+        self.source_qpn = (deth.src_qpn.get() & DETH_SOURCE_QPN_MASK).to_be_bytes();
+    }
+}
+
 /// Rdma packet header trait.
 ///
 /// We use trait instead of enum because the `enum` requires additional space to store the variant.
@@ -292,9 +369,10 @@ pub(crate) struct RdmaHeaderReqBthReth {
 
 impl RdmaPacketHeader for RdmaHeaderReqBthReth {
     fn to_rdma_message(&self, buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        // the receiver places the payload by its length, so the icrc must be excluded
         let payload_length = self
             .bth
-            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()));
+            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()).wrapping_sub(ICRC_SIZE));
         Ok(RdmaMessage {
             meta_data: Metadata::General(RdmaGeneralMeta::new_from_packet(&self.bth, &self.reth, None, None)?),
             payload: PayloadInfo::new_with_data(self.get_data_ptr(), payload_length),
@@ -309,7 +387,7 @@ impl RdmaPacketHeader for RdmaHeaderReqBthReth {
                 self.reth.set_from_reth_header(&header.reth);
                 Ok(size_of::<Self>())
             }
-            Metadata::Acknowledge(_) => Err(PacketError::InvalidMetadataType),
+            Metadata::Acknowledge(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
         }
     }
 }
@@ -324,9 +402,10 @@ pub(crate) struct RdmaHeaderReqBthDoubleReth {
 
 impl RdmaPacketHeader for RdmaHeaderReqBthDoubleReth {
     fn to_rdma_message(&self, buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        // the receiver places the payload by its length, so the icrc must be excluded
         let payload_length = self
             .bth
-            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()));
+            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()).wrapping_sub(ICRC_SIZE));
         Ok(RdmaMessage {
             meta_data: Metadata::General(RdmaGeneralMeta::new_from_packet(
                 &self.bth,
@@ -348,7 +427,7 @@ impl RdmaPacketHeader for RdmaHeaderReqBthDoubleReth {
                 self.secondary_reth.set_from_reth_header(sec_reth);
                 Ok(size_of::<Self>())
             }
-            Metadata::Acknowledge(_) => Err(PacketError::InvalidMetadataType),
+            Metadata::Acknowledge(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
         }
     }
 }
@@ -363,9 +442,10 @@ pub(crate) struct RdmaHeaderReqBthRethImm {
 
 impl RdmaPacketHeader for RdmaHeaderReqBthRethImm {
     fn to_rdma_message(&self, buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        // the receiver places the payload by its length, so the icrc must be excluded
         let payload_length = self
             .bth
-            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()));
+            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()).wrapping_sub(ICRC_SIZE));
         Ok(RdmaMessage {
             meta_data: Metadata::General(RdmaGeneralMeta::new_from_packet(
                 &self.bth,
@@ -386,7 +466,7 @@ impl RdmaPacketHeader for RdmaHeaderReqBthRethImm {
                 self.imm.set(header.imm.ok_or(PacketError::InvalidMetadataType)?);
                 Ok(size_of::<Self>())
             }
-            Metadata::Acknowledge(_) => Err(PacketError::InvalidMetadataType),
+            Metadata::Acknowledge(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
         }
     }
 }
@@ -400,11 +480,12 @@ pub(crate) struct RdmaHeaderRespBthAeth {
 
 impl RdmaPacketHeader for RdmaHeaderRespBthAeth {
     fn to_rdma_message(&self, buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        // the receiver places the payload by its length, so the icrc must be excluded
         let payload_length = self
             .bth
-            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()));
+            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()).wrapping_sub(ICRC_SIZE));
         Ok(RdmaMessage {
-            meta_data: Metadata::Acknowledge(AethHeader::new_from_packet(&self.bth, &self.aeth)?),
+            meta_data: Metadata::Acknowledge(AethHeader::new_from_packet(&self.bth, &self.aeth, None)?),
             payload: PayloadInfo::new_with_data(self.get_data_ptr(), payload_length),
         })
     }
@@ -419,11 +500,229 @@ impl RdmaPacketHeader for RdmaHeaderRespBthAeth {
                 self.aeth.set_msn(header.msn);
                 Ok(size_of::<Self>())
             }
-            Metadata::General(_) => Err(PacketError::InvalidMetadataType),
+            Metadata::General(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
+        }
+    }
+}
+
+/// A composite packet header layout that contains the BTH and the AtomicETH.
+#[repr(C, packed)]
+pub(crate) struct RdmaHeaderReqBthAtomicEth {
+    pub(crate) bth: BTH,
+    pub(crate) atomic_eth: AtomicETH,
+}
+
+impl RdmaPacketHeader for RdmaHeaderReqBthAtomicEth {
+    fn to_rdma_message(&self, _buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        Ok(RdmaMessage {
+            meta_data: Metadata::Atomic(AtomicEthHeader::new_from_packet(&self.bth, &self.atomic_eth)?),
+            payload: PayloadInfo::new(),
+        })
+    }
+
+    fn set_from_rdma_message(&mut self, message: &RdmaMessage) -> Result<usize, PacketError> {
+        match &message.meta_data {
+            Metadata::Atomic(header) => {
+                self.bth.set_from_common_meta(&header.common_meta, 0);
+                self.atomic_eth.set_from_atomic_header(header);
+                Ok(size_of::<Self>())
+            }
+            Metadata::General(_) | Metadata::Acknowledge(_) => Err(PacketError::InvalidMetadataType),
+        }
+    }
+}
+
+/// A composite packet header layout that contains the BTH, the AETH and the AtomicAckETH.
+#[repr(C, packed)]
+pub(crate) struct RdmaHeaderRespBthAethAtomicAckEth {
+    pub(crate) bth: BTH,
+    pub(crate) aeth: AETH,
+    pub(crate) atomic_ack_eth: AtomicAckETH,
+}
+
+impl RdmaPacketHeader for RdmaHeaderRespBthAethAtomicAckEth {
+    fn to_rdma_message(&self, _buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        Ok(RdmaMessage {
+            meta_data: Metadata::Acknowledge(AethHeader::new_from_packet(
+                &self.bth,
+                &self.aeth,
+                Some(&self.atomic_ack_eth),
+            )?),
+            payload: PayloadInfo::new(),
+        })
+    }
+
+    fn set_from_rdma_message(&mut self, message: &RdmaMessage) -> Result<usize, PacketError> {
+        match &message.meta_data {
+            Metadata::Acknowledge(header) => {
+                self.bth.set_from_common_meta(&header.common_meta, 0);
+                self.aeth
+                    .set_aeth_code_and_value(header.aeth_code.clone() as u8, header.aeth_value);
+                self.aeth.set_msn(header.msn);
+                self.atomic_ack_eth
+                    .set_orig(header.atomic_orig.ok_or(PacketError::InvalidMetadataType)?);
+                Ok(size_of::<Self>())
+            }
+            Metadata::General(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
+        }
+    }
+}
+
+/// A composite packet header layout that only contains the BTH, which is used by SEND of connected QPs.
+#[repr(C, packed)]
+pub(crate) struct RdmaHeaderReqBth {
+    pub(crate) bth: BTH,
+}
+
+impl RdmaPacketHeader for RdmaHeaderReqBth {
+    fn to_rdma_message(&self, buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        // the receiver places the payload by its length, so the icrc must be excluded
+        let payload_length = self
+            .bth
+            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()).wrapping_sub(ICRC_SIZE));
+        Ok(RdmaMessage {
+            meta_data: Metadata::General(RdmaGeneralMeta::new_from_send(&self.bth, None, payload_length)?),
+            payload: PayloadInfo::new_with_data(self.get_data_ptr(), payload_length),
+        })
+    }
+
+    fn set_from_rdma_message(&mut self, message: &RdmaMessage) -> Result<usize, PacketError> {
+        match &message.meta_data {
+            Metadata::General(header) => {
+                self.bth
+                    .set_from_common_meta(&header.common_meta, message.payload.get_pad_cnt());
+                Ok(size_of::<Self>())
+            }
+            Metadata::Acknowledge(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
+        }
+    }
+}
+
+/// A composite packet header layout that contains the BTH and the Immediate, which is used by SEND of connected QPs.
+#[repr(C, packed)]
+pub(crate) struct RdmaHeaderReqBthImm {
+    pub(crate) bth: BTH,
+    pub(crate) imm: Immediate,
+}
+
+impl RdmaPacketHeader for RdmaHeaderReqBthImm {
+    fn to_rdma_message(&self, buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        // the receiver places the payload by its length, so the icrc must be excluded
+        let payload_length = self
+            .bth
+            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()).wrapping_sub(ICRC_SIZE));
+        Ok(RdmaMessage {
+            meta_data: Metadata::General(RdmaGeneralMeta::new_from_send(
+                &self.bth,
+                Some(&self.imm),
+                payload_length,
+            )?),
+            payload: PayloadInfo::new_with_data(self.get_data_ptr(), payload_length),
+        })
+    }
+
+    fn set_from_rdma_message(&mut self, message: &RdmaMessage) -> Result<usize, PacketError> {
+        match &message.meta_data {
+            Metadata::General(header) => {
+                self.bth
+                    .set_from_common_meta(&header.common_meta, message.payload.get_pad_cnt());
+                self.imm.set(header.imm.ok_or(PacketError::InvalidMetadataType)?);
+                Ok(size_of::<Self>())
+            }
+            Metadata::Acknowledge(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
+        }
+    }
+}
+
+/// A composite packet header layout that contains the BTH and the DETH.
+#[repr(C, packed)]
+pub(crate) struct RdmaHeaderReqBthDeth {
+    pub(crate) bth: BTH,
+    pub(crate) deth: DETH,
+}
+
+impl RdmaPacketHeader for RdmaHeaderReqBthDeth {
+    fn to_rdma_message(&self, buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        // the payload length is the message length of UD, so the icrc must be excluded
+        let payload_length = self
+            .bth
+            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()).wrapping_sub(ICRC_SIZE));
+        Ok(RdmaMessage {
+            meta_data: Metadata::General(RdmaGeneralMeta::new_from_datagram(
+                &self.bth,
+                &self.deth,
+                None,
+                payload_length,
+            )?),
+            payload: PayloadInfo::new_with_data(self.get_data_ptr(), payload_length),
+        })
+    }
+
+    fn set_from_rdma_message(&mut self, message: &RdmaMessage) -> Result<usize, PacketError> {
+        match &message.meta_data {
+            Metadata::General(header) => {
+                self.bth
+                    .set_from_common_meta(&header.common_meta, message.payload.get_pad_cnt());
+                self.deth
+                    .set_from_deth_header(header.deth.as_ref().ok_or(PacketError::InvalidMetadataType)?);
+                Ok(size_of::<Self>())
+            }
+            Metadata::Acknowledge(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
+        }
+    }
+}
+
+/// A composite packet header layout that contains the BTH, the DETH and the Immediate.
+#[repr(C, packed)]
+pub(crate) struct RdmaHeaderReqBthDethImm {
+    pub(crate) bth: BTH,
+    pub(crate) deth: DETH,
+    pub(crate) imm: Immediate,
+}
+
+impl RdmaPacketHeader for RdmaHeaderReqBthDethImm {
+    fn to_rdma_message(&self, buf_size: usize) -> Result<RdmaMessage, PacketError> {
+        // the payload length is the message length of UD, so the icrc must be excluded
+        let payload_length = self
+            .bth
+            .get_packet_real_length(buf_size.wrapping_sub(size_of::<Self>()).wrapping_sub(ICRC_SIZE));
+        Ok(RdmaMessage {
+            meta_data: Metadata::General(RdmaGeneralMeta::new_from_datagram(
+                &self.bth,
+                &self.deth,
+                Some(&self.imm),
+                payload_length,
+            )?),
+            payload: PayloadInfo::new_with_data(self.get_data_ptr(), payload_length),
+        })
+    }
+
+    fn set_from_rdma_message(&mut self, message: &RdmaMessage) -> Result<usize, PacketError> {
+        match &message.meta_data {
+            Metadata::General(header) => {
+                self.bth
+                    .set_from_common_meta(&header.common_meta, message.payload.get_pad_cnt());
+                self.deth
+                    .set_from_deth_header(header.deth.as_ref().ok_or(PacketError::InvalidMetadataType)?);
+                self.imm.set(header.imm.ok_or(PacketError::InvalidMetadataType)?);
+                Ok(size_of::<Self>())
+            }
+            Metadata::Acknowledge(_) | Metadata::Atomic(_) => Err(PacketError::InvalidMetadataType),
         }
     }
 }
 
+pub(crate) type RdmaSendFirstHeader = RdmaHeaderReqBth;
+pub(crate) type RdmaSendMiddleHeader = RdmaHeaderReqBth;
+pub(crate) type RdmaSendLastHeader = RdmaHeaderReqBth;
+pub(crate) type RdmaSendLastWithImmediateHeader = RdmaHeaderReqBthImm;
+// IETH has the same layout as the immediate data
+pub(crate) type RdmaSendLastWithInvalidateHeader = RdmaHeaderReqBthImm;
+pub(crate) type RdmaSendOnlyHeader = RdmaHeaderReqBth;
+pub(crate) type RdmaSendOnlyWithImmediateHeader = RdmaHeaderReqBthImm;
+pub(crate) type RdmaSendOnlyWithInvalidateHeader = RdmaHeaderReqBthImm;
+pub(crate) type RdmaUdSendOnlyHeader = RdmaHeaderReqBthDeth;
+pub(crate) type RdmaUdSendOnlyWithImmediateHeader = RdmaHeaderReqBthDethImm;
 pub(crate) type RdmaWriteFirstHeader = RdmaHeaderReqBthReth;
 pub(crate) type RdmaWriteMiddleHeader = RdmaHeaderReqBthReth;
 pub(crate) type RdmaWriteLastHeader = RdmaHeaderReqBthReth;
@@ -436,6 +735,9 @@ pub(crate) type RdmaReadResponseMiddleHeader = RdmaHeaderReqBthReth;
 pub(crate) type RdmaReadResponseLastHeader = RdmaHeaderReqBthReth;
 pub(crate) type RdmaReadResponseOnlyHeader = RdmaHeaderReqBthReth;
 pub(crate) type RdmaAcknowledgeHeader = RdmaHeaderRespBthAeth;
+pub(crate) type RdmaAtomicAcknowledgeHeader = RdmaHeaderRespBthAethAtomicAckEth;
+pub(crate) type RdmaCompareSwapHeader = RdmaHeaderReqBthAtomicEth;
+pub(crate) type RdmaFetchAddHeader = RdmaHeaderReqBthAtomicEth;
 
 /// The IPv4 header
 #[derive(Clone, Copy)]
@@ -4,20 +4,59 @@ use std::net::Ipv4Addr;
 use thiserror::Error;
 
 use super::packet::{
-    BTH, CommonPacketHeader, ICRC_SIZE, IpUdpHeaders, Ipv4Header, PacketError, RdmaAcknowledgeHeader, RdmaPacketHeader,
-    RdmaReadRequestHeader, RdmaReadResponseFirstHeader, RdmaReadResponseLastHeader, RdmaReadResponseMiddleHeader,
-    RdmaReadResponseOnlyHeader, RdmaWriteFirstHeader, RdmaWriteLastHeader, RdmaWriteLastWithImmediateHeader,
-    RdmaWriteMiddleHeader, RdmaWriteOnlyHeader, RdmaWriteOnlyWithImmediateHeader,
+    BTH, CommonPacketHeader, ICRC_SIZE, IpUdpHeaders, Ipv4Header, PacketError, RdmaAcknowledgeHeader,
+    RdmaAtomicAcknowledgeHeader, RdmaCompareSwapHeader, RdmaFetchAddHeader, RdmaPacketHeader, RdmaReadRequestHeader,
+    RdmaReadResponseFirstHeader, RdmaReadResponseLastHeader, RdmaReadResponseMiddleHeader, RdmaReadResponseOnlyHeader,
+    RdmaSendFirstHeader, RdmaSendLastHeader, RdmaSendLastWithImmediateHeader, RdmaSendLastWithInvalidateHeader,
+    RdmaSendMiddleHeader, RdmaSendOnlyHeader, RdmaSendOnlyWithImmediateHeader, RdmaSendOnlyWithInvalidateHeader,
+    RdmaUdSendOnlyHeader, RdmaUdSendOnlyWithImmediateHeader, RdmaWriteFirstHeader, RdmaWriteLastHeader,
+    RdmaWriteLastWithImmediateHeader, RdmaWriteMiddleHeader, RdmaWriteOnlyHeader, RdmaWriteOnlyWithImmediateHeader,
 };
 use super::types::RdmaMessage;
-use crate::third_party::queues::meta_report::ToHostWorkRbDescOpcode;
+use crate::third_party::queues::meta_report::{ToHostWorkRbDescOpcode, ToHostWorkRbDescTransType};
 
 pub(crate) struct PacketProcessor;
 
 impl PacketProcessor {
     pub(crate) fn to_rdma_message(buf: &[u8]) -> Result<RdmaMessage, PacketError> {
-        let opcode = ToHostWorkRbDescOpcode::try_from(BTH::from_bytes(buf).get_opcode());
+        let bth = BTH::from_bytes(buf);
+        let opcode = ToHostWorkRbDescOpcode::try_from(bth.get_opcode());
+        if bth.get_transaction_type() == u8::from(ToHostWorkRbDescTransType::Ud) {
+            return Self::datagram_to_rdma_message(buf, opcode.map_err(|_| PacketError::InvalidOpcode)?);
+        }
         match opcode {
+            Ok(ToHostWorkRbDescOpcode::SendFirst) => {
+                let header = RdmaSendFirstHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::SendMiddle) => {
+                let header = RdmaSendMiddleHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::SendLast) => {
+                let header = RdmaSendLastHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::SendLastWithImmediate) => {
+                let header = RdmaSendLastWithImmediateHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::SendLastWithInvalidate) => {
+                let header = RdmaSendLastWithInvalidateHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::SendOnly) => {
+                let header = RdmaSendOnlyHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::SendOnlyWithImmediate) => {
+                let header = RdmaSendOnlyWithImmediateHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::SendOnlyWithInvalidate) => {
+                let header = RdmaSendOnlyWithInvalidateHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
             Ok(ToHostWorkRbDescOpcode::RdmaWriteFirst) => {
                 let header = RdmaWriteFirstHeader::from_bytes(buf);
                 Ok(header.to_rdma_message(buf.len())?)
@@ -66,12 +105,74 @@ impl PacketProcessor {
                 let header = RdmaAcknowledgeHeader::from_bytes(buf);
                 Ok(header.to_rdma_message(buf.len())?)
             }
+            Ok(ToHostWorkRbDescOpcode::AtomicAcknowledge) => {
+                let header = RdmaAtomicAcknowledgeHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::CompareSwap) => {
+                let header = RdmaCompareSwapHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            Ok(ToHostWorkRbDescOpcode::FetchAdd) => {
+                let header = RdmaFetchAddHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
             Err(_) => Err(PacketError::InvalidOpcode),
         }
     }
 
+    /// UD only supports SEND, and the message always fits in one packet
+    fn datagram_to_rdma_message(buf: &[u8], opcode: ToHostWorkRbDescOpcode) -> Result<RdmaMessage, PacketError> {
+        match opcode {
+            ToHostWorkRbDescOpcode::SendOnly => {
+                let header = RdmaUdSendOnlyHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            ToHostWorkRbDescOpcode::SendOnlyWithImmediate => {
+                let header = RdmaUdSendOnlyWithImmediateHeader::from_bytes(buf);
+                Ok(header.to_rdma_message(buf.len())?)
+            }
+            _ => Err(PacketError::InvalidOpcode),
+        }
+    }
+
     pub(crate) fn set_from_rdma_message(buf: &mut [u8], message: &RdmaMessage) -> Result<usize, PacketError> {
+        if matches!(message.meta_data.common_meta().tran_type, ToHostWorkRbDescTransType::Ud) {
+            return Self::set_from_datagram(buf, message);
+        }
         match message.meta_data.get_opcode() {
+            ToHostWorkRbDescOpcode::SendFirst => {
+                let header = RdmaSendFirstHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::SendMiddle => {
+                let header = RdmaSendMiddleHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::SendLast => {
+                let header = RdmaSendLastHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::SendLastWithImmediate => {
+                let header = RdmaSendLastWithImmediateHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::SendLastWithInvalidate => {
+                let header = RdmaSendLastWithInvalidateHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::SendOnly => {
+                let header = RdmaSendOnlyHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::SendOnlyWithImmediate => {
+                let header = RdmaSendOnlyWithImmediateHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::SendOnlyWithInvalidate => {
+                let header = RdmaSendOnlyWithInvalidateHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
             ToHostWorkRbDescOpcode::RdmaWriteFirst => {
                 let header = RdmaWriteFirstHeader::from_bytes(buf);
                 Ok(header.set_from_rdma_message(message)?)
@@ -120,6 +221,32 @@ impl PacketProcessor {
                 let header = RdmaAcknowledgeHeader::from_bytes(buf);
                 Ok(header.set_from_rdma_message(message)?)
             }
+            ToHostWorkRbDescOpcode::AtomicAcknowledge => {
+                let header = RdmaAtomicAcknowledgeHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::CompareSwap => {
+                let header = RdmaCompareSwapHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::FetchAdd => {
+                let header = RdmaFetchAddHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+        }
+    }
+
+    fn set_from_datagram(buf: &mut [u8], message: &RdmaMessage) -> Result<usize, PacketError> {
+        match message.meta_data.get_opcode() {
+            ToHostWorkRbDescOpcode::SendOnly => {
+                let header = RdmaUdSendOnlyHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            ToHostWorkRbDescOpcode::SendOnlyWithImmediate => {
+                let header = RdmaUdSendOnlyWithImmediateHeader::from_bytes(buf);
+                Ok(header.set_from_rdma_message(message)?)
+            }
+            _ => Err(PacketError::InvalidOpcode),
         }
     }
 }
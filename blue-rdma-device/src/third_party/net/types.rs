@@ -1,5 +1,5 @@
 // use super::logic::BlueRdmaLogicError;
-use super::packet::{AETH, BTH, Immediate, PacketError, RDMA_PAYLOAD_ALIGNMENT, RETH};
+use super::packet::{AETH, AtomicAckETH, AtomicETH, BTH, DETH, Immediate, PacketError, RDMA_PAYLOAD_ALIGNMENT, RETH};
 use crate::third_party::queues::meta_report::{
     ToHostWorkRbDescAethCode, ToHostWorkRbDescOpcode, ToHostWorkRbDescTransType,
 };
@@ -98,8 +98,11 @@ pub(crate) enum Metadata {
     /// RDMA write, read request and response
     General(RdmaGeneralMeta),
 
-    /// Acknowledge message
+    /// Acknowledge message, including the atomic acknowledge
     Acknowledge(AethHeader),
+
+    /// Atomic compare and swap, fetch and add
+    Atomic(AtomicEthHeader),
 }
 
 impl Metadata {
@@ -107,6 +110,7 @@ impl Metadata {
         match self {
             Metadata::General(header) => header.common_meta.opcode.clone(),
             Metadata::Acknowledge(header) => header.common_meta.opcode.clone(),
+            Metadata::Atomic(header) => header.common_meta.opcode.clone(),
         }
     }
 
@@ -114,6 +118,7 @@ impl Metadata {
         match self {
             Metadata::General(header) => &header.common_meta,
             Metadata::Acknowledge(header) => &header.common_meta,
+            Metadata::Atomic(header) => &header.common_meta,
         }
     }
 }
@@ -147,7 +152,6 @@ impl PayloadInfo {
         }
     }
 
-    #[cfg(test)]
     pub(crate) fn get_length(&self) -> usize {
         self.total_len
     }
@@ -225,6 +229,22 @@ impl From<&RETH> for RethHeader {
     }
 }
 
+/// Datagram extended transport header, which identifies the sender of a UD message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DethHeader {
+    pub(crate) qkey: u32,
+    pub(crate) src_qpn: Qpn,
+}
+
+impl From<&DETH> for DethHeader {
+    fn from(deth: &DETH) -> Self {
+        DethHeader {
+            qkey: deth.get_qkey(),
+            src_qpn: Qpn::new(deth.get_source_qpn()),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct RdmaMessageMetaCommon {
     pub(crate) tran_type: ToHostWorkRbDescTransType,
@@ -259,6 +279,7 @@ pub(crate) struct RdmaGeneralMeta {
     pub(crate) reth: RethHeader,
     pub(crate) imm: Option<u32>,
     pub(crate) secondary_reth: Option<RethHeader>,
+    pub(crate) deth: Option<DethHeader>,
 }
 
 impl RdmaGeneralMeta {
@@ -273,6 +294,50 @@ impl RdmaGeneralMeta {
             reth: RethHeader::from(reth),
             imm: imm.map(Immediate::get),
             secondary_reth: secondary_reth.map(RethHeader::from),
+            deth: None,
+        })
+    }
+
+    /// A SEND packet has no RETH, the receiver places the payload at the offset it tracks for the message,
+    /// so the reth carries a zero offset and the payload length of the packet.
+    #[allow(clippy::cast_possible_truncation)] // payload length is limited by pmtu
+    pub(crate) fn new_from_send(
+        bth: &BTH,
+        imm: Option<&Immediate>,
+        payload_length: usize,
+    ) -> Result<Self, PacketError> {
+        Ok(RdmaGeneralMeta {
+            common_meta: RdmaMessageMetaCommon::try_from(bth)?,
+            reth: RethHeader {
+                va: 0,
+                rkey: Key::default(),
+                len: payload_length as u32,
+            },
+            imm: imm.map(Immediate::get),
+            secondary_reth: None,
+            deth: None,
+        })
+    }
+
+    /// A UD message has no RETH and always fits in one packet,
+    /// so the reth carries a zero offset and the payload length as the message length.
+    #[allow(clippy::cast_possible_truncation)] // payload length is limited by pmtu
+    pub(crate) fn new_from_datagram(
+        bth: &BTH,
+        deth: &DETH,
+        imm: Option<&Immediate>,
+        payload_length: usize,
+    ) -> Result<Self, PacketError> {
+        Ok(RdmaGeneralMeta {
+            common_meta: RdmaMessageMetaCommon::try_from(bth)?,
+            reth: RethHeader {
+                va: 0,
+                rkey: Key::default(),
+                len: payload_length as u32,
+            },
+            imm: imm.map(Immediate::get),
+            secondary_reth: None,
+            deth: Some(DethHeader::from(deth)),
         })
     }
 
@@ -280,24 +345,42 @@ impl RdmaGeneralMeta {
         matches!(self.common_meta.opcode, ToHostWorkRbDescOpcode::RdmaReadRequest)
     }
 
-    pub(crate) fn has_payload(&self) -> bool {
+    pub(crate) fn is_send(&self) -> bool {
         matches!(
             self.common_meta.opcode,
-            ToHostWorkRbDescOpcode::RdmaWriteFirst
-                | ToHostWorkRbDescOpcode::RdmaWriteMiddle
-                | ToHostWorkRbDescOpcode::RdmaWriteLast
-                | ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate
-                | ToHostWorkRbDescOpcode::RdmaWriteOnly
-                | ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate
-                | ToHostWorkRbDescOpcode::RdmaReadResponseFirst
-                | ToHostWorkRbDescOpcode::RdmaReadResponseMiddle
-                | ToHostWorkRbDescOpcode::RdmaReadResponseLast
-                | ToHostWorkRbDescOpcode::RdmaReadResponseOnly
+            ToHostWorkRbDescOpcode::SendFirst
+                | ToHostWorkRbDescOpcode::SendMiddle
+                | ToHostWorkRbDescOpcode::SendLast
+                | ToHostWorkRbDescOpcode::SendLastWithImmediate
+                | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+                | ToHostWorkRbDescOpcode::SendOnly
+                | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
         )
     }
 
+    pub(crate) fn has_payload(&self) -> bool {
+        self.is_send()
+            || matches!(
+                self.common_meta.opcode,
+                ToHostWorkRbDescOpcode::RdmaWriteFirst
+                    | ToHostWorkRbDescOpcode::RdmaWriteMiddle
+                    | ToHostWorkRbDescOpcode::RdmaWriteLast
+                    | ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate
+                    | ToHostWorkRbDescOpcode::RdmaWriteOnly
+                    | ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate
+                    | ToHostWorkRbDescOpcode::RdmaReadResponseFirst
+                    | ToHostWorkRbDescOpcode::RdmaReadResponseMiddle
+                    | ToHostWorkRbDescOpcode::RdmaReadResponseLast
+                    | ToHostWorkRbDescOpcode::RdmaReadResponseOnly
+            )
+    }
+
     pub(crate) fn needed_permissions(&self) -> MemAccessTypeFlag {
-        if self.has_payload() {
+        // SEND places data into a local receive buffer instead of a remote memory region
+        if self.is_send() {
+            MemAccessTypeFlag::IbvAccessLocalWrite
+        } else if self.has_payload() {
             MemAccessTypeFlag::IbvAccessRemoteWrite
         } else if self.is_read_request() {
             MemAccessTypeFlag::IbvAccessRemoteRead
@@ -312,10 +395,16 @@ pub(crate) struct AethHeader {
     pub(crate) aeth_code: ToHostWorkRbDescAethCode,
     pub(crate) aeth_value: u8,
     pub(crate) msn: u32,
+    /// The original value of the remote address, only exists in atomic acknowledge
+    pub(crate) atomic_orig: Option<u64>,
 }
 
 impl AethHeader {
-    pub(crate) fn new_from_packet(bth: &BTH, aeth: &AETH) -> Result<Self, PacketError> {
+    pub(crate) fn new_from_packet(
+        bth: &BTH,
+        aeth: &AETH,
+        atomic_ack_eth: Option<&AtomicAckETH>,
+    ) -> Result<Self, PacketError> {
         let aeth_code = ToHostWorkRbDescAethCode::try_from(aeth.get_aeth_code())?;
         let aeth_value = aeth.get_aeth_value();
         let msn = aeth.get_msn();
@@ -325,8 +414,34 @@ impl AethHeader {
             aeth_code,
             aeth_value,
             msn,
+            atomic_orig: atomic_ack_eth.map(AtomicAckETH::get_orig),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AtomicEthHeader {
+    pub(crate) common_meta: RdmaMessageMetaCommon,
+    pub(crate) va: u64,
+    pub(crate) rkey: Key,
+    pub(crate) swap_add: u64,
+    pub(crate) compare: u64,
+}
+
+impl AtomicEthHeader {
+    pub(crate) fn new_from_packet(bth: &BTH, atomic_eth: &AtomicETH) -> Result<Self, PacketError> {
+        Ok(AtomicEthHeader {
+            common_meta: RdmaMessageMetaCommon::try_from(bth)?,
+            va: atomic_eth.get_va(),
+            rkey: Key::new(atomic_eth.get_rkey()),
+            swap_add: atomic_eth.get_swap_add(),
+            compare: atomic_eth.get_compare(),
         })
     }
+
+    pub(crate) fn is_compare_swap(&self) -> bool {
+        matches!(self.common_meta.opcode, ToHostWorkRbDescOpcode::CompareSwap)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -655,6 +770,9 @@ impl From<Box<ToCardWorkRbDesc>> for ToCardDescriptor {
                 imm: None,
                 sg_list: SGList::new_with_sge_list(desc.sge0, desc.sge1, desc.sge2, desc.sge3),
             }),
+            ToCardWorkRbDesc::Send(_) | ToCardWorkRbDesc::SendWithImm(_) => {
+                unimplemented!("SEND is handled by `queues::send::operations`")
+            }
         }
     }
 }
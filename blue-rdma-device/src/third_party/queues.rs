@@ -15,6 +15,10 @@ pub mod command_request {
         SetNetworkParam = 0x03,
         SetRawPacketReceiveMeta = 0x04,
         UpdateErrorPsnRecoverPoint = 0x05,
+        PostRecv = 0x06,
+        SrqManagement = 0x07,
+        UpdateMwTable = 0x08,
+        PageFault = 0x09,
     }
 
     #[derive(Debug)]
@@ -25,6 +29,9 @@ pub mod command_request {
         SetNetworkParam(ToCardCtrlRbDescSetNetworkParam),
         SetRawPacketReceiveMeta(ToCardCtrlRbDescSetRawPacketReceiveMeta),
         UpdateErrorPsnRecoverPoint(ToCardCtrlRbDescUpdateErrPsnRecoverPoint),
+        PostRecv(ToCardCtrlRbDescPostRecv),
+        SrqManagement(ToCardCtrlRbDescSrqManagement),
+        UpdateMwTable(ToCardCtrlRbDescUpdateMwTable),
     }
 
     #[derive(Debug, Default)]
@@ -86,6 +93,35 @@ pub mod command_request {
         pub(crate) recover_psn: Psn,
     }
 
+    #[derive(Debug)]
+    pub(crate) struct ToCardCtrlRbDescPostRecv {
+        pub(crate) common: ToCardCtrlRbDescCommon,
+        pub(crate) qpn: Qpn,
+        pub(crate) laddr: u64,
+        pub(crate) len: u32,
+        pub(crate) lkey: Key,
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct ToCardCtrlRbDescSrqManagement {
+        pub(crate) common: ToCardCtrlRbDescCommon,
+        pub(crate) is_valid: bool,
+        pub(crate) srqn: u32,
+        pub(crate) pd_hdl: u32,
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct ToCardCtrlRbDescUpdateMwTable {
+        pub(crate) common: ToCardCtrlRbDescCommon,
+        pub(crate) is_valid: bool,
+        pub(crate) is_type2: bool,
+        pub(crate) addr: u64,
+        pub(crate) len: u32,
+        pub(crate) mw_key: Key,
+        pub(crate) mr_key: Key,
+        pub(crate) acc_flags: MemAccessTypeFlag,
+    }
+
     impl ToCardCtrlRbDesc {
         pub(crate) fn set_id(&mut self, id: u32) {
             match self {
@@ -95,6 +131,9 @@ pub mod command_request {
                 ToCardCtrlRbDesc::SetNetworkParam(desc) => desc.common.op_id = id,
                 ToCardCtrlRbDesc::SetRawPacketReceiveMeta(desc) => desc.common.op_id = id,
                 ToCardCtrlRbDesc::UpdateErrorPsnRecoverPoint(desc) => desc.common.op_id = id,
+                ToCardCtrlRbDesc::PostRecv(desc) => desc.common.op_id = id,
+                ToCardCtrlRbDesc::SrqManagement(desc) => desc.common.op_id = id,
+                ToCardCtrlRbDesc::UpdateMwTable(desc) => desc.common.op_id = id,
             }
         }
     }
@@ -123,7 +162,8 @@ pub mod command_request {
             pub get_pd_handler, set_pd_handler: 223, 192;  // 32bits
             pub get_acc_flags, set_acc_flags: 231, 224;    // 8bits
             pub get_pgt_offset, set_pgt_offset: 248, 232;  // 17bits
-            _reserved0, _: 255, 249;                   // 7bits
+            pub get_pg_size_log, set_pg_size_log: 253, 249; // 5bits
+            _reserved0, _: 255, 254;                   // 2bits
         }
 
         bitfield! {
@@ -142,16 +182,19 @@ pub mod command_request {
             _cmd_queue_desc_common_head,_: 63, 0;                                       // 64bits
             pub get_is_valid, set_is_valid: 64;                                             // 1bit
             pub get_is_error, set_is_error: 65;                                             // 1bit
-            _reserverd4, _: 71, 66;                                                     // 6bits
+            pub get_has_srq, set_has_srq: 66;                                               // 1bit
+            _reserverd4, _: 71, 67;                                                     // 5bits
             pub get_qpn, set_qpn: 95, 72;                                                   // 24bits
             pub get_pd_handler, set_pd_handler: 127, 96;                                    // 32bits
             pub get_qp_type, set_qp_type: 131, 128;                                         // 4bits
             _reserverd3, _: 135, 132;                                                   // 4bits
             pub get_rq_access_flags, set_rq_access_flags: 143, 136;                         // 8bits
             pub get_pmtu, set_pmtu: 146, 144;                                               // 3bits
-            _reserverd2, _: 151, 147;                                                   // 5bits
+            pub get_min_rnr_timer, set_min_rnr_timer: 151, 147;                             // 5bits
             pub get_peer_qpn, set_peer_qpn: 175, 152;                                      // 24bits
-            _reserverd1, _: 255, 176;                                                   // 80bits
+            pub get_qkey, set_qkey: 207, 176;                                               // 32bits
+            pub get_rq_psn, set_rq_psn: 231, 208;                                           // 24bits
+            pub get_srqn, set_srqn: 255, 232;                                               // 24bits
         }
 
         bitfield! {
@@ -192,6 +235,88 @@ pub mod command_request {
             pub get_qpn, set_qpn:                       119,  96;  // 24bits
             _reserverd2, _:                             255, 120;  // 64bits
         }
+
+        // typedef struct {
+        //     Bit#(32)                        tag;            // 32  bits
+        //     ReservedZero#(7)                reserved1;      // 7   bits
+        //     Bool                            isSrq;          // 1   bit
+        //     QPN                             qpn;            // 24  bits
+        //     RKEY                            lkey;           // 32  bits
+        //     Length                          len;            // 32  bits
+        //     ADDR                            laddr;          // 64  bits
+        //     CmdQueueDescCommonHead          commonHeader;   // 64  bits
+        // } CmdQueueReqDescPostRecv deriving(Bits, FShow);
+        bitfield! {
+            pub struct CmdQueueReqDescPostRecv([u8]);
+            u64;
+            _cmd_queue_desc_common_head,_:              63 ,   0;  // 64bits
+            pub get_laddr, set_laddr:                   127,  64;  // 64bits
+            pub get_len, set_len:                       159, 128;  // 32bits
+            pub get_lkey, set_lkey:                     191, 160;  // 32bits
+            pub get_qpn, set_qpn:                       215, 192;  // 24bits
+            pub get_is_srq, set_is_srq:                 216;       // 1bit
+            _reserverd1, _:                             223, 217;  // 7bits
+            pub get_tag, set_tag:                       255, 224;  // 32bits
+        }
+
+        // typedef struct {
+        //     ReservedZero#(128)              reserved1;      // 128 bits
+        //     HandlerPD                       pdHandler;      // 32  bits
+        //     SRQN                            srqn;           // 24  bits
+        //     ReservedZero#(7)                reserved2;      // 7   bits
+        //     Bool                            isValid;        // 1   bit
+        //     CmdQueueDescCommonHead          commonHeader;   // 64  bits
+        // } CmdQueueReqDescSrqManagement deriving(Bits, FShow);
+        bitfield! {
+            pub struct CmdQueueReqDescSrqManagement([u8]);
+            u64;
+            _cmd_queue_desc_common_head,_:              63 ,   0;  // 64bits
+            pub get_is_valid, set_is_valid:             64;        // 1bit
+            _reserverd2, _:                             71 ,  65;  // 7bits
+            pub get_srqn, set_srqn:                     95 ,  72;  // 24bits
+            pub get_pd_handler, set_pd_handler:         127,  96;  // 32bits
+            _reserverd1, _:                             255, 128;  // 128bits
+        }
+
+        // typedef struct {
+        //     ReservedZero#(22)               reserved1;      // 22  bits
+        //     Bool                            isType2;        // 1   bit
+        //     Bool                            isValid;        // 1   bit
+        //     FlagsType#(MemAccessTypeFlag)   accFlags;       // 8   bits
+        //     RKEY                            mrKey;          // 32  bits
+        //     RKEY                            mwKey;          // 32  bits
+        //     Length                          mwLength;       // 32  bits
+        //     ADDR                            mwBaseVA;       // 64  bits
+        //     CmdQueueDescCommonHead          commonHeader;   // 64  bits
+        // } CmdQueueReqDescUpdateMwTable deriving(Bits, FShow);
+        bitfield! {
+            pub struct CmdQueueReqDescUpdateMwTable([u8]);
+            u64;
+            _cmd_queue_desc_common_head,_: 63, 0;      // 64bits
+            pub get_mw_base_va, set_mw_base_va: 127, 64;   // 64bits
+            pub get_mw_length, set_mw_length: 159, 128;    // 32bits
+            pub get_mw_key, set_mw_key: 191, 160;          // 32bits
+            pub get_mr_key, set_mr_key: 223, 192;          // 32bits
+            pub get_acc_flags, set_acc_flags: 231, 224;    // 8bits
+            pub get_is_valid, set_is_valid: 232;           // 1bit
+            pub get_is_type2, set_is_type2: 233;           // 1bit
+            _reserved0, _: 255, 234;                   // 22bits
+        }
+
+        // typedef struct {
+        //     ReservedZero#(96)               reserved1;      // 96  bits
+        //     RKEY                            mrKey;          // 32  bits
+        //     ADDR                            va;             // 64  bits
+        //     CmdQueueDescCommonHead          commonHeader;   // 64  bits
+        // } CmdQueueRespDescPageFault deriving(Bits, FShow);
+        bitfield! {
+            pub struct CmdQueueRespDescPageFault([u8]);
+            u64;
+            _cmd_queue_desc_common_head,_:              63 ,   0;  // 64bits
+            pub get_va, set_va:                         127,  64;  // 64bits
+            pub get_mr_key, set_mr_key:                 159, 128;  // 32bits
+            _reserverd1, _:                             255, 160;  // 96bits
+        }
     }
 }
 
@@ -223,7 +348,12 @@ pub mod send {
         // IBV_WR_ATOMIC_WRITE         = 15
         Write = 0,
         WriteWithImm = 1,
+        Send = 2,
+        SendWithImm = 3,
         Read = 4,
+        CompareSwap = 5,
+        FetchAdd = 6,
+        SendWithInv = 9,
         ReadResp = 12, // Not defined in rdma-core
     }
 
@@ -282,6 +412,8 @@ pub mod send {
         Read(ToCardWorkRbDescRead),
         Write(ToCardWorkRbDescWrite),
         WriteWithImm(ToCardWorkRbDescWriteWithImm),
+        Send(ToCardWorkRbDescWrite),
+        SendWithImm(ToCardWorkRbDescWriteWithImm),
         ReadResp(ToCardWorkRbDescWrite),
     }
 
@@ -411,6 +543,12 @@ pub mod meta_report {
         // Resync = 0x15,
         // SendLastWithInvalidate = 0x16,
         // SendOnlyWithInvalidate = 0x17,
+        SendFirst = 0x00,
+        SendMiddle = 0x01,
+        SendLast = 0x02,
+        SendLastWithImmediate = 0x03,
+        SendOnly = 0x04,
+        SendOnlyWithImmediate = 0x05,
         RdmaWriteFirst = 0x06,
         RdmaWriteMiddle = 0x07,
         RdmaWriteLast = 0x08,
@@ -423,13 +561,27 @@ pub mod meta_report {
         RdmaReadResponseOnly = 0x10,
         RdmaReadRequest = 0x0c,
         Acknowledge = 0x11,
+        AtomicAcknowledge = 0x12,
+        CompareSwap = 0x13,
+        FetchAdd = 0x14,
+        SendLastWithInvalidate = 0x16,
+        SendOnlyWithInvalidate = 0x17,
     }
 
     impl ToHostWorkRbDescOpcode {
         pub(crate) fn is_first(&self) -> bool {
             match self {
-                ToHostWorkRbDescOpcode::RdmaWriteFirst | ToHostWorkRbDescOpcode::RdmaReadResponseFirst => true,
-                ToHostWorkRbDescOpcode::RdmaWriteMiddle
+                ToHostWorkRbDescOpcode::SendFirst
+                | ToHostWorkRbDescOpcode::RdmaWriteFirst
+                | ToHostWorkRbDescOpcode::RdmaReadResponseFirst => true,
+                ToHostWorkRbDescOpcode::SendMiddle
+                | ToHostWorkRbDescOpcode::SendLast
+                | ToHostWorkRbDescOpcode::SendLastWithImmediate
+                | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+                | ToHostWorkRbDescOpcode::SendOnly
+                | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+                | ToHostWorkRbDescOpcode::RdmaWriteMiddle
                 | ToHostWorkRbDescOpcode::RdmaWriteLast
                 | ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate
                 | ToHostWorkRbDescOpcode::RdmaWriteOnly
@@ -438,7 +590,10 @@ pub mod meta_report {
                 | ToHostWorkRbDescOpcode::RdmaReadResponseLast
                 | ToHostWorkRbDescOpcode::RdmaReadResponseOnly
                 | ToHostWorkRbDescOpcode::RdmaReadRequest
-                | ToHostWorkRbDescOpcode::Acknowledge => false,
+                | ToHostWorkRbDescOpcode::Acknowledge
+                | ToHostWorkRbDescOpcode::AtomicAcknowledge
+                | ToHostWorkRbDescOpcode::CompareSwap
+                | ToHostWorkRbDescOpcode::FetchAdd => false,
             }
         }
 
@@ -464,19 +619,29 @@ pub mod meta_report {
 
         pub(crate) fn write_type(&self) -> Option<ToHostWorkRbDescWriteType> {
             match self {
-                ToHostWorkRbDescOpcode::RdmaWriteFirst | ToHostWorkRbDescOpcode::RdmaReadResponseFirst => {
-                    Some(ToHostWorkRbDescWriteType::First)
-                }
-                ToHostWorkRbDescOpcode::RdmaWriteMiddle | ToHostWorkRbDescOpcode::RdmaReadResponseMiddle => {
-                    Some(ToHostWorkRbDescWriteType::Middle)
-                }
-                ToHostWorkRbDescOpcode::RdmaWriteLast
+                ToHostWorkRbDescOpcode::SendFirst
+                | ToHostWorkRbDescOpcode::RdmaWriteFirst
+                | ToHostWorkRbDescOpcode::RdmaReadResponseFirst => Some(ToHostWorkRbDescWriteType::First),
+                ToHostWorkRbDescOpcode::SendMiddle
+                | ToHostWorkRbDescOpcode::RdmaWriteMiddle
+                | ToHostWorkRbDescOpcode::RdmaReadResponseMiddle => Some(ToHostWorkRbDescWriteType::Middle),
+                ToHostWorkRbDescOpcode::SendLast
+                | ToHostWorkRbDescOpcode::SendLastWithImmediate
+                | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+                | ToHostWorkRbDescOpcode::RdmaWriteLast
                 | ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate
                 | ToHostWorkRbDescOpcode::RdmaReadResponseLast => Some(ToHostWorkRbDescWriteType::Last),
-                ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate
+                ToHostWorkRbDescOpcode::SendOnly
+                | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+                | ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate
                 | ToHostWorkRbDescOpcode::RdmaWriteOnly
                 | ToHostWorkRbDescOpcode::RdmaReadResponseOnly => Some(ToHostWorkRbDescWriteType::Only),
-                ToHostWorkRbDescOpcode::RdmaReadRequest | ToHostWorkRbDescOpcode::Acknowledge => None,
+                ToHostWorkRbDescOpcode::RdmaReadRequest
+                | ToHostWorkRbDescOpcode::Acknowledge
+                | ToHostWorkRbDescOpcode::AtomicAcknowledge
+                | ToHostWorkRbDescOpcode::CompareSwap
+                | ToHostWorkRbDescOpcode::FetchAdd => None,
             }
         }
     }
@@ -12,5 +12,8 @@ pub type MemoryRegionKey = crate::third_party::rdma::Key;
 pub type QueuePairType = crate::third_party::rdma::QpType;
 pub type QueuePairNumber = u32;
 
+// Shared Receive Queue
+pub type SharedReceiveQueueNumber = u32;
+
 // Send
 pub type SendFlag = crate::third_party::rdma::WorkReqSendFlag;
@@ -190,11 +190,12 @@ fn main() {
 
     let ctx1 = dev_a
         .write(
+            1,
             dpqn,
             mr_buffer_b.as_ref().as_ptr() as usize as u64,
             mr_b.get_key(),
             WorkReqSendFlag::IbvSendSignaled,
-            sge0,
+            &[sge0],
         )
         .unwrap();
 
@@ -226,11 +226,12 @@ fn main() {
         );
         let ctx1 = dev_a
             .write(
+                1,
                 dpqn,
                 mr_buffer_b.as_ref().as_ptr() as usize as u64,
                 mr_b.get_key(),
                 WorkReqSendFlag::IbvSendSignaled,
-                sge0,
+                &[sge0],
             )
             .unwrap();
 
@@ -256,11 +257,12 @@ fn main() {
         );
         let ctx1 = dev_a
             .read(
+                2,
                 dpqn,
                 mr_buffer_b.as_ref().as_ptr() as usize as u64,
                 mr_b.get_key(),
                 WorkReqSendFlag::IbvSendSignaled,
-                sge0,
+                &[sge0],
             )
             .unwrap();
 
@@ -285,11 +287,12 @@ fn main() {
         );
         let ctx1 = dev_b
             .write(
+                3,
                 dpqn,
                 mr_buffer_a.as_ref().as_ptr() as usize as u64,
                 mr_a.get_key(),
                 WorkReqSendFlag::IbvSendSignaled,
-                sge0,
+                &[sge0],
             )
             .unwrap();
 
@@ -314,11 +317,12 @@ fn main() {
         );
         let ctx1 = dev_b
             .read(
+                4,
                 dpqn,
                 mr_buffer_a.as_ref().as_ptr() as usize as u64,
                 mr_a.get_key(),
                 WorkReqSendFlag::IbvSendSignaled,
-                sge0,
+                &[sge0],
             )
             .unwrap();
 
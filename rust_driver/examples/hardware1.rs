@@ -143,7 +143,7 @@ fn main() {
     // for i in 0..10 {
     let write_start = Instant::now();
     let ctx1 = dev_a
-        .write(dpqn, raddr, rkey, WorkReqSendFlag::IbvSendSignaled, sge0)
+        .write(1, dpqn, raddr, rkey, WorkReqSendFlag::IbvSendSignaled, &[sge0])
         .unwrap();
     ctx1.wait_result().unwrap();
     // let _ = ctx1.wait();
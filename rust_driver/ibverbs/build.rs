@@ -0,0 +1,4 @@
+fn main() {
+    // programs linked against rdma-core look for `libibverbs.so.1`
+    println!("cargo:rustc-cdylib-link-arg=-Wl,-soname,libibverbs.so.1");
+}
@@ -0,0 +1,597 @@
+//! The verbs objects laid out the same as `infiniband/verbs.h` of rdma-core
+//!
+//! Only the fields read or written by the provider are documented in detail,
+//! the others are kept to make the offsets match.
+
+use std::ffi::{c_char, c_int, c_uint, c_void};
+
+/// The length of `name` and `dev_name` of `struct ibv_device`
+pub const IBV_SYSFS_NAME_MAX: usize = 64;
+/// The length of `dev_path` and `ibdev_path` of `struct ibv_device`
+pub const IBV_SYSFS_PATH_MAX: usize = 256;
+
+/// `IBV_NODE_CA` of `enum ibv_node_type`
+pub const IBV_NODE_CA: c_int = 1;
+/// `IBV_TRANSPORT_IB` of `enum ibv_transport_type`
+pub const IBV_TRANSPORT_IB: c_int = 0;
+
+/// `enum ibv_qp_type`
+pub const IBV_QPT_RC: c_uint = 2;
+/// `enum ibv_qp_type`
+pub const IBV_QPT_UC: c_uint = 3;
+
+/// `enum ibv_qp_state`
+pub const IBV_QPS_RESET: c_uint = 0;
+/// `enum ibv_qp_state`
+pub const IBV_QPS_INIT: c_uint = 1;
+/// `enum ibv_qp_state`
+pub const IBV_QPS_RTR: c_uint = 2;
+/// `enum ibv_qp_state`
+pub const IBV_QPS_RTS: c_uint = 3;
+/// `enum ibv_qp_state`
+pub const IBV_QPS_SQD: c_uint = 4;
+/// `enum ibv_qp_state`
+pub const IBV_QPS_ERR: c_uint = 6;
+
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_STATE: c_uint = 1 << 0;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_AV: c_uint = 1 << 7;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_PATH_MTU: c_uint = 1 << 8;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_TIMEOUT: c_uint = 1 << 9;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_RETRY_CNT: c_uint = 1 << 10;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_RNR_RETRY: c_uint = 1 << 11;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_RQ_PSN: c_uint = 1 << 12;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_ALT_PATH: c_uint = 1 << 14;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_MIN_RNR_TIMER: c_uint = 1 << 15;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_SQ_PSN: c_uint = 1 << 16;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_PATH_MIG_STATE: c_uint = 1 << 18;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_CAP: c_uint = 1 << 19;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_DEST_QPN: c_uint = 1 << 20;
+/// `enum ibv_qp_attr_mask`
+pub const IBV_QP_RATE_LIMIT: c_uint = 1 << 25;
+
+/// `enum ibv_wr_opcode`
+pub const IBV_WR_RDMA_WRITE: c_uint = 0;
+/// `enum ibv_wr_opcode`
+pub const IBV_WR_RDMA_WRITE_WITH_IMM: c_uint = 1;
+/// `enum ibv_wr_opcode`
+pub const IBV_WR_SEND: c_uint = 2;
+/// `enum ibv_wr_opcode`
+pub const IBV_WR_SEND_WITH_IMM: c_uint = 3;
+/// `enum ibv_wr_opcode`
+pub const IBV_WR_RDMA_READ: c_uint = 4;
+/// `enum ibv_wr_opcode`
+pub const IBV_WR_ATOMIC_CMP_AND_SWP: c_uint = 5;
+/// `enum ibv_wr_opcode`
+pub const IBV_WR_ATOMIC_FETCH_AND_ADD: c_uint = 6;
+/// `enum ibv_wr_opcode`
+pub const IBV_WR_SEND_WITH_INV: c_uint = 9;
+
+/// `enum ibv_rereg_mr_flags`
+pub const IBV_REREG_MR_FLAGS_SUPPORTED: c_int = 1 << 4;
+/// `enum ibv_rereg_mr_err_code`, the input is invalid
+pub const IBV_REREG_MR_ERR_INPUT: c_int = -1;
+/// `enum ibv_rereg_mr_err_code`, the device failed to re-register the MR
+pub const IBV_REREG_MR_ERR_CMD: c_int = -4;
+
+/// `enum ibv_wc_status`
+pub const IBV_WC_SUCCESS: c_uint = 0;
+/// `enum ibv_wc_status`
+pub const IBV_WC_LOC_QP_OP_ERR: c_uint = 2;
+/// `enum ibv_wc_status`
+pub const IBV_WC_LOC_PROT_ERR: c_uint = 4;
+/// `enum ibv_wc_status`
+pub const IBV_WC_WR_FLUSH_ERR: c_uint = 5;
+/// `enum ibv_wc_status`
+pub const IBV_WC_REM_INV_REQ_ERR: c_uint = 9;
+/// `enum ibv_wc_status`
+pub const IBV_WC_REM_ACCESS_ERR: c_uint = 10;
+/// `enum ibv_wc_status`
+pub const IBV_WC_REM_OP_ERR: c_uint = 11;
+/// `enum ibv_wc_status`
+pub const IBV_WC_RETRY_EXC_ERR: c_uint = 12;
+/// `enum ibv_wc_status`
+pub const IBV_WC_RNR_RETRY_EXC_ERR: c_uint = 13;
+/// `enum ibv_wc_status`
+pub const IBV_WC_GENERAL_ERR: c_uint = 21;
+
+/// `enum ibv_wc_opcode`
+pub const IBV_WC_SEND: c_uint = 0;
+/// `enum ibv_wc_opcode`
+pub const IBV_WC_RDMA_WRITE: c_uint = 1;
+/// `enum ibv_wc_opcode`
+pub const IBV_WC_RDMA_READ: c_uint = 2;
+/// `enum ibv_wc_opcode`
+pub const IBV_WC_COMP_SWAP: c_uint = 3;
+/// `enum ibv_wc_opcode`
+pub const IBV_WC_FETCH_ADD: c_uint = 4;
+/// `enum ibv_wc_opcode`
+pub const IBV_WC_RECV: c_uint = 128;
+/// `enum ibv_wc_opcode`
+pub const IBV_WC_RECV_RDMA_WITH_IMM: c_uint = 129;
+
+/// `enum ibv_wc_flags`
+pub const IBV_WC_WITH_IMM: c_uint = 1 << 1;
+/// `enum ibv_wc_flags`
+pub const IBV_WC_WITH_INV: c_uint = 1 << 3;
+
+/// An entry of the ops table that the provider does not implement
+pub type UnimplementedOp = Option<unsafe extern "C" fn()>;
+
+/// `struct ibv_device`
+#[repr(C)]
+pub struct IbvDevice {
+    /// `struct _ibv_device_ops`, unused since rdma-core v17
+    pub _ops: [UnimplementedOp; 2],
+    /// `enum ibv_node_type`
+    pub node_type: c_int,
+    /// `enum ibv_transport_type`
+    pub transport_type: c_int,
+    /// The name returned by `ibv_get_device_name`
+    pub name: [c_char; IBV_SYSFS_NAME_MAX],
+    /// The name of the uverbs device
+    pub dev_name: [c_char; IBV_SYSFS_NAME_MAX],
+    /// The sysfs path of the uverbs device
+    pub dev_path: [c_char; IBV_SYSFS_PATH_MAX],
+    /// The sysfs path of the device
+    pub ibdev_path: [c_char; IBV_SYSFS_PATH_MAX],
+}
+
+impl IbvDevice {
+    /// A CA named `name`, which has no sysfs entry
+    #[must_use]
+    pub const fn new(name: &[u8]) -> Self {
+        let mut name_buf = [0; IBV_SYSFS_NAME_MAX];
+        let mut idx = 0;
+        // keep the trailing NUL
+        while idx < name.len() && idx < IBV_SYSFS_NAME_MAX - 1 {
+            #[allow(clippy::indexing_slicing, clippy::cast_possible_wrap)]
+            {
+                name_buf[idx] = name[idx] as c_char;
+            }
+            idx += 1;
+        }
+        Self {
+            _ops: [None; 2],
+            node_type: IBV_NODE_CA,
+            transport_type: IBV_TRANSPORT_IB,
+            name: name_buf,
+            dev_name: name_buf,
+            dev_path: [0; IBV_SYSFS_PATH_MAX],
+            ibdev_path: [0; IBV_SYSFS_PATH_MAX],
+        }
+    }
+}
+
+/// `struct ibv_context_ops`
+///
+/// `ibv_poll_cq`, `ibv_req_notify_cq`, `ibv_post_send` and `ibv_post_recv` are inline functions of
+/// `verbs.h`, which call through this table.
+#[repr(C)]
+pub struct IbvContextOps {
+    /// From `_compat_query_device` to `_compat_create_cq`
+    pub _head: [UnimplementedOp; 11],
+    /// `poll_cq`
+    pub poll_cq: Option<unsafe extern "C" fn(*mut IbvCq, c_int, *mut IbvWc) -> c_int>,
+    /// `req_notify_cq`
+    pub req_notify_cq: Option<unsafe extern "C" fn(*mut IbvCq, c_int) -> c_int>,
+    /// From `_compat_cq_event` to `_compat_destroy_qp`
+    pub _mid: [UnimplementedOp; 12],
+    /// `post_send`
+    pub post_send: Option<unsafe extern "C" fn(*mut IbvQp, *mut IbvSendWr, *mut *mut IbvSendWr) -> c_int>,
+    /// `post_recv`
+    pub post_recv: Option<unsafe extern "C" fn(*mut IbvQp, *mut IbvRecvWr, *mut *mut IbvRecvWr) -> c_int>,
+    /// From `_compat_create_ah` to `_compat_async_event`
+    pub _tail: [UnimplementedOp; 5],
+}
+
+/// `struct ibv_context`
+#[repr(C)]
+pub struct IbvContext {
+    /// The device opened
+    pub device: *mut IbvDevice,
+    /// The ops table
+    pub ops: IbvContextOps,
+    /// The command fd of the uverbs device, -1 since there is none
+    pub cmd_fd: c_int,
+    /// The async event fd, -1 since there is none
+    pub async_fd: c_int,
+    /// The number of completion vectors
+    pub num_comp_vectors: c_int,
+    /// Used by libibverbs
+    pub mutex: libc::pthread_mutex_t,
+    /// Null, so that the extended verbs of `verbs.h` fall back to the legacy ones
+    pub abi_compat: *mut c_void,
+}
+
+/// `struct ibv_pd`
+#[repr(C)]
+pub struct IbvPd {
+    /// The context owning the PD
+    pub context: *mut IbvContext,
+    /// The handle of the PD
+    pub handle: u32,
+}
+
+/// `struct ibv_mr`
+#[repr(C)]
+pub struct IbvMr {
+    /// The context owning the MR
+    pub context: *mut IbvContext,
+    /// The PD of the MR
+    pub pd: *mut IbvPd,
+    /// The start address
+    pub addr: *mut c_void,
+    /// The length in bytes
+    pub length: usize,
+    /// The handle of the MR
+    pub handle: u32,
+    /// The local key
+    pub lkey: u32,
+    /// The remote key
+    pub rkey: u32,
+}
+
+/// `struct ibv_cq`
+#[repr(C)]
+pub struct IbvCq {
+    /// The context owning the CQ
+    pub context: *mut IbvContext,
+    /// `struct ibv_comp_channel *`, always null
+    pub channel: *mut c_void,
+    /// The user context given to `ibv_create_cq`
+    pub cq_context: *mut c_void,
+    /// The handle of the CQ
+    pub handle: u32,
+    /// The number of entries
+    pub cqe: c_int,
+    /// Used by libibverbs
+    pub mutex: libc::pthread_mutex_t,
+    /// Used by libibverbs
+    pub cond: libc::pthread_cond_t,
+    /// Used by `ibv_ack_cq_events`
+    pub comp_events_completed: u32,
+    /// Used by `ibv_ack_async_event`
+    pub async_events_completed: u32,
+}
+
+/// `struct ibv_qp`
+#[repr(C)]
+pub struct IbvQp {
+    /// The context owning the QP
+    pub context: *mut IbvContext,
+    /// The user context given by `struct ibv_qp_init_attr`
+    pub qp_context: *mut c_void,
+    /// The PD of the QP
+    pub pd: *mut IbvPd,
+    /// The CQ of the send queue
+    pub send_cq: *mut IbvCq,
+    /// The CQ of the receive queue
+    pub recv_cq: *mut IbvCq,
+    /// `struct ibv_srq *`, always null
+    pub srq: *mut c_void,
+    /// The handle of the QP
+    pub handle: u32,
+    /// The QPN
+    pub qp_num: u32,
+    /// `enum ibv_qp_state`
+    pub state: c_uint,
+    /// `enum ibv_qp_type`
+    pub qp_type: c_uint,
+    /// Used by libibverbs
+    pub mutex: libc::pthread_mutex_t,
+    /// Used by libibverbs
+    pub cond: libc::pthread_cond_t,
+    /// Used by `ibv_ack_async_event`
+    pub events_completed: u32,
+}
+
+/// `struct ibv_qp_cap`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IbvQpCap {
+    /// Max outstanding send requests
+    pub max_send_wr: u32,
+    /// Max outstanding receive requests
+    pub max_recv_wr: u32,
+    /// Max SGEs of a send request
+    pub max_send_sge: u32,
+    /// Max SGEs of a receive request
+    pub max_recv_sge: u32,
+    /// Max inline data of a send request
+    pub max_inline_data: u32,
+}
+
+/// `struct ibv_qp_init_attr`
+#[repr(C)]
+pub struct IbvQpInitAttr {
+    /// The user context of the QP
+    pub qp_context: *mut c_void,
+    /// The CQ of the send queue
+    pub send_cq: *mut IbvCq,
+    /// The CQ of the receive queue
+    pub recv_cq: *mut IbvCq,
+    /// `struct ibv_srq *`
+    pub srq: *mut c_void,
+    /// The capabilities of the QP
+    pub cap: IbvQpCap,
+    /// `enum ibv_qp_type`
+    pub qp_type: c_uint,
+    /// Whether every send request generates a completion
+    pub sq_sig_all: c_int,
+}
+
+/// `union ibv_gid`
+#[repr(C, align(8))]
+#[derive(Clone, Copy)]
+pub struct IbvGid {
+    /// The GID in network order
+    pub raw: [u8; 16],
+}
+
+/// `struct ibv_global_route`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IbvGlobalRoute {
+    /// The GID of the peer, which is an IPv4-mapped IPv6 address for RoCEv2
+    pub dgid: IbvGid,
+    /// Flow label
+    pub flow_label: u32,
+    /// Source GID index
+    pub sgid_index: u8,
+    /// Hop limit
+    pub hop_limit: u8,
+    /// Traffic class
+    pub traffic_class: u8,
+}
+
+/// `struct ibv_ah_attr`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IbvAhAttr {
+    /// The global route header, valid if `is_global` is set
+    pub grh: IbvGlobalRoute,
+    /// The LID of the peer
+    pub dlid: u16,
+    /// Service level
+    pub sl: u8,
+    /// Source path bits
+    pub src_path_bits: u8,
+    /// Static rate
+    pub static_rate: u8,
+    /// Whether `grh` is valid
+    pub is_global: u8,
+    /// Local port
+    pub port_num: u8,
+}
+
+/// `struct ibv_qp_attr`
+#[repr(C)]
+pub struct IbvQpAttr {
+    /// `enum ibv_qp_state`
+    pub qp_state: c_uint,
+    /// `enum ibv_qp_state`
+    pub cur_qp_state: c_uint,
+    /// `enum ibv_mtu`
+    pub path_mtu: c_uint,
+    /// `enum ibv_mig_state`
+    pub path_mig_state: c_uint,
+    /// Q_Key
+    pub qkey: u32,
+    /// The PSN of the first packet expected to be received
+    pub rq_psn: u32,
+    /// The PSN of the first packet to be sent
+    pub sq_psn: u32,
+    /// The QPN of the peer
+    pub dest_qp_num: u32,
+    /// `enum ibv_access_flags`
+    pub qp_access_flags: c_uint,
+    /// The capabilities of the QP
+    pub cap: IbvQpCap,
+    /// The address vector of the peer
+    pub ah_attr: IbvAhAttr,
+    /// Alternate path
+    pub alt_ah_attr: IbvAhAttr,
+    /// P_Key index
+    pub pkey_index: u16,
+    /// Alternate P_Key index
+    pub alt_pkey_index: u16,
+    /// Enable SQD async notification
+    pub en_sqd_async_notify: u8,
+    /// Whether the send queue is draining
+    pub sq_draining: u8,
+    /// Max outstanding RDMA read and atomic as initiator
+    pub max_rd_atomic: u8,
+    /// Max outstanding RDMA read and atomic as responder
+    pub max_dest_rd_atomic: u8,
+    /// Minimum RNR NAK timer
+    pub min_rnr_timer: u8,
+    /// Local port
+    pub port_num: u8,
+    /// Local ACK timeout
+    pub timeout: u8,
+    /// Retry count
+    pub retry_cnt: u8,
+    /// RNR retry count
+    pub rnr_retry: u8,
+    /// Alternate port
+    pub alt_port_num: u8,
+    /// Alternate local ACK timeout
+    pub alt_timeout: u8,
+    /// Rate limit in kbps
+    pub rate_limit: u32,
+}
+
+/// `struct ibv_sge`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IbvSge {
+    /// Start address
+    pub addr: u64,
+    /// Length in bytes
+    pub length: u32,
+    /// Local key
+    pub lkey: u32,
+}
+
+/// `wr.rdma` of `struct ibv_send_wr`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IbvRdmaWr {
+    /// Remote address
+    pub remote_addr: u64,
+    /// Remote key
+    pub rkey: u32,
+}
+
+/// `wr.atomic` of `struct ibv_send_wr`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IbvAtomicWr {
+    /// Remote address
+    pub remote_addr: u64,
+    /// The value to compare with, or to add
+    pub compare_add: u64,
+    /// The value to swap in
+    pub swap: u64,
+    /// Remote key
+    pub rkey: u32,
+}
+
+/// `wr.ud` of `struct ibv_send_wr`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IbvUdWr {
+    /// `struct ibv_ah *`
+    pub ah: *mut c_void,
+    /// Remote QPN
+    pub remote_qpn: u32,
+    /// Remote Q_Key
+    pub remote_qkey: u32,
+}
+
+/// `wr` of `struct ibv_send_wr`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union IbvSendWrWr {
+    /// RDMA write and read
+    pub rdma: IbvRdmaWr,
+    /// Atomic operations
+    pub atomic: IbvAtomicWr,
+    /// UD send
+    pub ud: IbvUdWr,
+}
+
+/// `struct ibv_send_wr`
+#[repr(C)]
+pub struct IbvSendWr {
+    /// The id reported by the completion
+    pub wr_id: u64,
+    /// The next request of the list
+    pub next: *mut IbvSendWr,
+    /// Scatter gather list
+    pub sg_list: *mut IbvSge,
+    /// The length of `sg_list`
+    pub num_sge: c_int,
+    /// `enum ibv_wr_opcode`
+    pub opcode: c_uint,
+    /// `enum ibv_send_flags`
+    pub send_flags: c_uint,
+    /// Immediate data in network order, or the rkey to invalidate
+    pub imm_data: u32,
+    /// The opcode specific fields
+    pub wr: IbvSendWrWr,
+    /// `qp_type.xrc.remote_srqn`
+    pub remote_srqn: u32,
+    /// The `bind_mw` and `tso` union
+    pub _bind_mw_tso: [u64; 6],
+}
+
+/// `struct ibv_recv_wr`
+#[repr(C)]
+pub struct IbvRecvWr {
+    /// The id reported by the completion
+    pub wr_id: u64,
+    /// The next request of the list
+    pub next: *mut IbvRecvWr,
+    /// Scatter gather list
+    pub sg_list: *mut IbvSge,
+    /// The length of `sg_list`
+    pub num_sge: c_int,
+}
+
+/// `struct ibv_wc`
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IbvWc {
+    /// The id of the completed request
+    pub wr_id: u64,
+    /// `enum ibv_wc_status`
+    pub status: c_uint,
+    /// `enum ibv_wc_opcode`
+    pub opcode: c_uint,
+    /// Vendor error
+    pub vendor_err: u32,
+    /// The number of bytes transferred
+    pub byte_len: u32,
+    /// Immediate data in network order, or the invalidated rkey
+    pub imm_data: u32,
+    /// The local QPN
+    pub qp_num: u32,
+    /// The QPN of the sender of UD QPs
+    pub src_qp: u32,
+    /// `enum ibv_wc_flags`
+    pub wc_flags: c_uint,
+    /// P_Key index
+    pub pkey_index: u16,
+    /// Source LID
+    pub slid: u16,
+    /// Service level
+    pub sl: u8,
+    /// Destination LID path bits
+    pub dlid_path_bits: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{offset_of, size_of};
+
+    use super::{
+        IbvContext, IbvContextOps, IbvCq, IbvDevice, IbvMr, IbvQp, IbvQpAttr, IbvQpInitAttr, IbvRecvWr, IbvSendWr,
+        IbvWc,
+    };
+
+    /// The sizes and offsets of rdma-core on x86_64 Linux
+    #[test]
+    fn test_layout() {
+        assert_eq!(size_of::<IbvDevice>(), 664);
+        assert_eq!(size_of::<IbvContextOps>(), 256);
+        assert_eq!(offset_of!(IbvContextOps, poll_cq), 88);
+        assert_eq!(offset_of!(IbvContextOps, post_send), 200);
+        assert_eq!(size_of::<IbvContext>(), 328);
+        assert_eq!(offset_of!(IbvContext, abi_compat), 320);
+        assert_eq!(size_of::<IbvMr>(), 48);
+        assert_eq!(size_of::<IbvCq>(), 128);
+        assert_eq!(size_of::<IbvQp>(), 160);
+        assert_eq!(size_of::<IbvQpInitAttr>(), 64);
+        assert_eq!(size_of::<IbvQpAttr>(), 144);
+        assert_eq!(offset_of!(IbvQpAttr, ah_attr), 56);
+        assert_eq!(offset_of!(IbvQpAttr, timeout), 130);
+        assert_eq!(size_of::<IbvSendWr>(), 128);
+        assert_eq!(offset_of!(IbvSendWr, wr), 40);
+        assert_eq!(size_of::<IbvRecvWr>(), 32);
+        assert_eq!(size_of::<IbvWc>(), 48);
+    }
+}
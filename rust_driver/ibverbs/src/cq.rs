@@ -0,0 +1,175 @@
+//! Completion queue
+
+use std::ffi::{c_int, c_void};
+use std::{ptr, slice};
+
+use open_rdma_driver::cq::{WorkCompletion, WorkCompletionOpcode, WorkCompletionStatus};
+use open_rdma_driver::Cq;
+
+use crate::abi::{
+    IbvContext, IbvCq, IbvWc, IBV_WC_COMP_SWAP, IBV_WC_FETCH_ADD, IBV_WC_GENERAL_ERR, IBV_WC_LOC_PROT_ERR,
+    IBV_WC_LOC_QP_OP_ERR, IBV_WC_RDMA_READ, IBV_WC_RDMA_WRITE, IBV_WC_RECV, IBV_WC_RECV_RDMA_WITH_IMM,
+    IBV_WC_REM_ACCESS_ERR, IBV_WC_REM_INV_REQ_ERR, IBV_WC_REM_OP_ERR, IBV_WC_RETRY_EXC_ERR, IBV_WC_RNR_RETRY_EXC_ERR,
+    IBV_WC_SEND, IBV_WC_SUCCESS, IBV_WC_WITH_IMM, IBV_WC_WITH_INV, IBV_WC_WR_FLUSH_ERR,
+};
+use crate::device::Context;
+use crate::{errno_of, null_with_errno, Object};
+
+pub(crate) type CompletionQueue = Object<IbvCq, Cq>;
+
+impl From<&WorkCompletion> for IbvWc {
+    fn from(wc: &WorkCompletion) -> Self {
+        let status = match wc.status {
+            WorkCompletionStatus::Success => IBV_WC_SUCCESS,
+            WorkCompletionStatus::LocalProtectionError => IBV_WC_LOC_PROT_ERR,
+            WorkCompletionStatus::LocalQpOperationError => IBV_WC_LOC_QP_OP_ERR,
+            WorkCompletionStatus::RemoteInvalidRequestError => IBV_WC_REM_INV_REQ_ERR,
+            WorkCompletionStatus::RemoteAccessError => IBV_WC_REM_ACCESS_ERR,
+            WorkCompletionStatus::RemoteOperationError => IBV_WC_REM_OP_ERR,
+            WorkCompletionStatus::RetryExceeded => IBV_WC_RETRY_EXC_ERR,
+            WorkCompletionStatus::RnrRetryExceeded => IBV_WC_RNR_RETRY_EXC_ERR,
+            WorkCompletionStatus::WrFlushError => IBV_WC_WR_FLUSH_ERR,
+            _ => IBV_WC_GENERAL_ERR,
+        };
+        let opcode = match wc.opcode {
+            WorkCompletionOpcode::RdmaWrite => IBV_WC_RDMA_WRITE,
+            WorkCompletionOpcode::RdmaRead => IBV_WC_RDMA_READ,
+            WorkCompletionOpcode::Send => IBV_WC_SEND,
+            WorkCompletionOpcode::RecvRdmaWithImm => IBV_WC_RECV_RDMA_WITH_IMM,
+            WorkCompletionOpcode::CompareSwap => IBV_WC_COMP_SWAP,
+            WorkCompletionOpcode::FetchAdd => IBV_WC_FETCH_ADD,
+            _ => IBV_WC_RECV,
+        };
+        // the invalidated rkey shares the field of the immediate data
+        let (imm_data, wc_flags) = match (wc.imm, wc.invalidated_rkey) {
+            (Some(imm), _) => (imm.get(), IBV_WC_WITH_IMM),
+            (None, Some(rkey)) => (rkey.get(), IBV_WC_WITH_INV),
+            (None, None) => (0, 0),
+        };
+        Self {
+            wr_id: wc.wr_id,
+            status,
+            opcode,
+            byte_len: wc.byte_len,
+            // the immediate data is passed through as is, which is in network order on both sides
+            imm_data,
+            qp_num: wc.qpn.get(),
+            src_qp: wc.src_qpn.map_or(0, |qpn| qpn.get()),
+            wc_flags,
+            ..Self::default()
+        }
+    }
+}
+
+/// Create a CQ
+///
+/// Completion channels are not supported, so `channel` must be null.
+///
+/// # Safety
+///
+/// `context` must be null or returned by `ibv_open_device`
+#[no_mangle]
+pub unsafe extern "C" fn ibv_create_cq(
+    context: *mut IbvContext,
+    cqe: c_int,
+    cq_context: *mut c_void,
+    channel: *mut c_void,
+    _comp_vector: c_int,
+) -> *mut IbvCq {
+    // SAFETY: the caller guarantees `context` is null or opened
+    let Some(ctx) = (unsafe { Context::from_raw(context) }) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    if !channel.is_null() {
+        return null_with_errno(libc::EOPNOTSUPP);
+    }
+    let Ok(depth) = usize::try_from(cqe) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    match ctx.inner.dev.create_cq(depth, false) {
+        Ok(cq) => {
+            let ibv = IbvCq {
+                context,
+                channel: ptr::null_mut(),
+                cq_context,
+                handle: ctx.inner.next_handle(),
+                cqe,
+                mutex: libc::PTHREAD_MUTEX_INITIALIZER,
+                cond: libc::PTHREAD_COND_INITIALIZER,
+                comp_events_completed: 0,
+                async_events_completed: 0,
+            };
+            CompletionQueue::into_raw(ibv, cq)
+        }
+        Err(e) => null_with_errno(errno_of(&e)),
+    }
+}
+
+/// Destroy a CQ, which fails if the CQ is still bound to a QP
+///
+/// Returns 0 on success, or errno on failure.
+///
+/// # Safety
+///
+/// `cq` must be null or returned by `ibv_create_cq` and not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn ibv_destroy_cq(cq: *mut IbvCq) -> c_int {
+    // SAFETY: the caller guarantees `cq` is null or created
+    let Some(obj) = (unsafe { CompletionQueue::from_raw(cq) }) else {
+        return libc::EINVAL;
+    };
+    // SAFETY: the context outlives its CQs
+    let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+        return libc::EINVAL;
+    };
+    if let Err(e) = ctx.inner.dev.destroy_cq(obj.inner) {
+        return errno_of(&e);
+    }
+    // SAFETY: `obj` is not used anymore
+    unsafe { CompletionQueue::free(cq) };
+    0
+}
+
+/// Poll at most `num_entries` completions into `wc`, which never blocks
+///
+/// Returns the number of completions polled, or a negative errno on failure.
+/// This is also the `poll_cq` op called by the inline `ibv_poll_cq` of `verbs.h`.
+///
+/// # Safety
+///
+/// `cq` must be null or returned by `ibv_create_cq`, and `wc` must be valid for `num_entries` writes
+#[no_mangle]
+pub unsafe extern "C" fn ibv_poll_cq(cq: *mut IbvCq, num_entries: c_int, wc: *mut IbvWc) -> c_int {
+    // SAFETY: the caller guarantees `cq` is null or created
+    let Some(obj) = (unsafe { CompletionQueue::from_raw(cq) }) else {
+        return -libc::EINVAL;
+    };
+    // SAFETY: the context outlives its CQs
+    let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+        return -libc::EINVAL;
+    };
+    let max_entries = match usize::try_from(num_entries) {
+        Ok(0) => return 0,
+        Ok(n) if !wc.is_null() => n,
+        _ => return -libc::EINVAL,
+    };
+    let completions = match ctx.inner.dev.poll_cq(obj.inner, max_entries) {
+        Ok(completions) => completions,
+        Err(e) => return -errno_of(&e),
+    };
+    // SAFETY: the caller guarantees `wc` holds `num_entries` entries, which is no less than `completions`
+    let entries = unsafe { slice::from_raw_parts_mut(wc, completions.len()) };
+    for (entry, completion) in entries.iter_mut().zip(&completions) {
+        *entry = completion.into();
+    }
+    // the length is no more than `num_entries`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    {
+        completions.len() as c_int
+    }
+}
+
+/// The `req_notify_cq` op, which is not supported without completion channels
+pub(crate) unsafe extern "C" fn req_notify_cq(_cq: *mut IbvCq, _solicited_only: c_int) -> c_int {
+    libc::EOPNOTSUPP
+}
@@ -0,0 +1,224 @@
+//! Device list, device context and GID
+
+use std::ffi::{c_char, c_int};
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use std::{env, ptr};
+
+use eui48::MacAddress;
+use open_rdma_driver::qp::QpManager;
+use open_rdma_driver::types::{RdmaDeviceNetworkParam, RdmaDeviceNetworkParamBuilder};
+use open_rdma_driver::{Device, DeviceConfigBuilder, DeviceType, RetryConfig, RoundRobinStrategy};
+
+use crate::abi::{IbvContext, IbvContextOps, IbvDevice, IbvGid};
+use crate::{cq, errno_of, null_with_errno, qp, set_errno, Object};
+
+const ENV_IPADDR: &str = "OPEN_RDMA_IPADDR";
+const ENV_NETMASK: &str = "OPEN_RDMA_NETMASK";
+const ENV_GATEWAY: &str = "OPEN_RDMA_GATEWAY";
+const ENV_MACADDR: &str = "OPEN_RDMA_MACADDR";
+const DEFAULT_NETMASK: Ipv4Addr = Ipv4Addr::new(255, 255, 255, 0);
+
+const MAX_RETRY: u32 = 7;
+const RETRY_TIMEOUT: Duration = Duration::from_secs(1);
+const RETRY_CHECKING_INTERVAL: Duration = Duration::from_millis(10);
+const SCHEDULER_SIZE: u32 = 1024 * 32;
+
+/// The only port of the device
+const PORT_NUM: u8 = 1;
+
+/// The device backed by the software emulator
+static DEVICE: IbvDevice = IbvDevice::new(b"blue_rdma0");
+
+const OPS: IbvContextOps = IbvContextOps {
+    _head: [None; 11],
+    poll_cq: Some(cq::ibv_poll_cq),
+    req_notify_cq: Some(cq::req_notify_cq),
+    _mid: [None; 12],
+    post_send: Some(qp::ibv_post_send),
+    post_recv: Some(qp::ibv_post_recv),
+    _tail: [None; 5],
+};
+
+/// The provider side of `struct ibv_context`
+pub(crate) struct Provider {
+    pub(crate) dev: Device,
+    pub(crate) qp_manager: QpManager,
+    ipaddr: Ipv4Addr,
+    next_handle: AtomicU32,
+}
+
+impl Provider {
+    /// Allocate the handle of a verbs object
+    pub(crate) fn next_handle(&self) -> u32 {
+        self.next_handle.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+pub(crate) type Context = Object<IbvContext, Provider>;
+
+fn env_var<T: FromStr>(key: &str) -> Result<Option<T>, c_int> {
+    env::var(key).map_or(Ok(None), |val| val.parse().map(Some).map_err(|_| libc::EINVAL))
+}
+
+fn network_param_from_env() -> Result<RdmaDeviceNetworkParam, c_int> {
+    let ipaddr: Ipv4Addr = env_var(ENV_IPADDR)?.ok_or(libc::EINVAL)?;
+    let netmask = env_var(ENV_NETMASK)?.unwrap_or(DEFAULT_NETMASK);
+    let gateway = env_var(ENV_GATEWAY)?.unwrap_or_else(|| Ipv4Addr::from((u32::from(ipaddr) & u32::from(netmask)) | 1));
+    let macaddr: MacAddress = env_var(ENV_MACADDR)?.unwrap_or_default();
+    RdmaDeviceNetworkParamBuilder::default()
+        .ipaddr(ipaddr)
+        .netmask(netmask)
+        .gateway(gateway)
+        .macaddr(macaddr)
+        .build()
+        .map_err(|_| libc::EINVAL)
+}
+
+fn open_device() -> Result<Provider, c_int> {
+    let network = network_param_from_env()?;
+    let config = DeviceConfigBuilder::default()
+        .network_config(network)
+        .device_type(DeviceType::Software)
+        .strategy(RoundRobinStrategy::new())
+        .retry_config(RetryConfig::new(
+            true,
+            MAX_RETRY,
+            RETRY_TIMEOUT,
+            RETRY_CHECKING_INTERVAL,
+        ))
+        .scheduler_size(SCHEDULER_SIZE)
+        .build()
+        .map_err(|_| libc::EINVAL)?;
+    let dev = Device::new(config).map_err(|e| errno_of(&e))?;
+    Ok(Provider {
+        dev,
+        qp_manager: QpManager::new(),
+        ipaddr: network.ipaddr,
+        next_handle: AtomicU32::new(0),
+    })
+}
+
+/// Get the list of devices, which is terminated by null
+///
+/// # Safety
+///
+/// `num_devices` must be null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn ibv_get_device_list(num_devices: *mut c_int) -> *mut *mut IbvDevice {
+    // SAFETY: the caller guarantees `num_devices` is null or valid
+    if let Some(num) = unsafe { num_devices.as_mut() } {
+        *num = 1;
+    }
+    let list = Box::new([ptr::addr_of!(DEVICE).cast_mut(), ptr::null_mut()]);
+    Box::into_raw(list).cast()
+}
+
+/// Free the list returned by `ibv_get_device_list`
+///
+/// # Safety
+///
+/// `list` must be null or returned by `ibv_get_device_list` and not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn ibv_free_device_list(list: *mut *mut IbvDevice) {
+    if !list.is_null() {
+        // SAFETY: the list is allocated by `ibv_get_device_list`
+        drop(unsafe { Box::from_raw(list.cast::<[*mut IbvDevice; 2]>()) });
+    }
+}
+
+/// Get the name of a device
+///
+/// # Safety
+///
+/// `device` must be null or returned by `ibv_get_device_list`
+#[no_mangle]
+pub unsafe extern "C" fn ibv_get_device_name(device: *mut IbvDevice) -> *const c_char {
+    // SAFETY: the caller guarantees `device` is null or valid
+    unsafe { device.as_ref() }.map_or(ptr::null(), |device| device.name.as_ptr())
+}
+
+/// Open a device, which brings up the emulator configured by the environment variables
+///
+/// # Safety
+///
+/// `device` must be null or returned by `ibv_get_device_list`
+#[no_mangle]
+pub unsafe extern "C" fn ibv_open_device(device: *mut IbvDevice) -> *mut IbvContext {
+    if device.is_null() {
+        return null_with_errno(libc::EINVAL);
+    }
+    let provider = match open_device() {
+        Ok(provider) => provider,
+        Err(errno) => return null_with_errno(errno),
+    };
+    let ibv = IbvContext {
+        device,
+        ops: OPS,
+        cmd_fd: -1,
+        async_fd: -1,
+        num_comp_vectors: 1,
+        mutex: libc::PTHREAD_MUTEX_INITIALIZER,
+        abi_compat: ptr::null_mut(),
+    };
+    Context::into_raw(ibv, provider)
+}
+
+/// Close a device, all the resources of it are released
+///
+/// Returns 0 on success, or -1 with errno set.
+///
+/// # Safety
+///
+/// `context` must be returned by `ibv_open_device` and not closed yet,
+/// and the verbs objects created from it must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ibv_close_device(context: *mut IbvContext) -> c_int {
+    // SAFETY: the caller guarantees `context` is null or opened
+    let Some(ctx) = (unsafe { Context::from_raw(context) }) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    let res = ctx.inner.dev.shutdown();
+    // SAFETY: `ctx` is not used anymore
+    unsafe { Context::free(context) };
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            set_errno(errno_of(&e));
+            -1
+        }
+    }
+}
+
+/// Query the GID, which is the IPv4-mapped IPv6 address of the device
+///
+/// Only index 0 of port 1 exists. Returns 0 on success, or -1 with errno set.
+///
+/// # Safety
+///
+/// `context` must be null or returned by `ibv_open_device`, and `gid` must be null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn ibv_query_gid(
+    context: *mut IbvContext,
+    port_num: u8,
+    index: c_int,
+    gid: *mut IbvGid,
+) -> c_int {
+    // SAFETY: the caller guarantees `context` is null or opened
+    let ctx = unsafe { Context::from_raw(context) };
+    // SAFETY: the caller guarantees `gid` is null or valid
+    let gid = unsafe { gid.as_mut() };
+    match (ctx, gid) {
+        (Some(ctx), Some(gid)) if port_num == PORT_NUM && index == 0 => {
+            gid.raw = ctx.inner.ipaddr.to_ipv6_mapped().octets();
+            0
+        }
+        _ => {
+            set_errno(libc::EINVAL);
+            -1
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! A libibverbs compatible provider over open-rdma-driver
+//!
+//! The library exports the `ibv_*` entry points and lays out the verbs objects the same as
+//! `infiniband/verbs.h` of rdma-core, so C programs built against libibverbs run against the
+//! software emulator by loading this library instead, e.g. through `LD_LIBRARY_PATH`.
+//!
+//! The device is configured by environment variables when it is opened:
+//! * `OPEN_RDMA_IPADDR`: the IP address of the device, required
+//! * `OPEN_RDMA_NETMASK`: `255.255.255.0` if not set
+//! * `OPEN_RDMA_GATEWAY`: the first address of the subnet if not set
+//! * `OPEN_RDMA_MACADDR`: all zero if not set
+//!
+//! Limitations:
+//! * Only RC and UC QPs are supported, there is no SRQ, address handle or completion channel.
+//! * A receive request, SEND or atomic operation takes exactly one SGE, unless the SEND is posted with
+//!   `IBV_SEND_INLINE`.
+//! * The buffer of a MR must be aligned to 4 KiB, it's mapped in hugepages of `PAGE_SIZE` of the driver if it's aligned
+//!   to them.
+//! * `ibv_modify_qp` ignores the attributes the device has no counterpart of, such as the P_Key index and the port. The
+//!   MAC of the peer is not resolved since the emulator routes by IP.
+#![deny(
+    missing_docs,
+    unreachable_pub,
+    unsafe_op_in_unsafe_fn,
+    unused_qualifications,
+    unused_results,
+    clippy::all,
+    clippy::undocumented_unsafe_blocks,
+    clippy::unwrap_used,
+    clippy::expect_used
+)]
+
+use std::ffi::c_int;
+
+use open_rdma_driver::Error;
+
+pub mod abi;
+pub mod cq;
+pub mod device;
+pub mod mr;
+pub mod pd;
+pub mod qp;
+
+/// A verbs object, which is the `ibv` struct seen by C followed by the driver object
+///
+/// The object is boxed and handed out as a pointer to `ibv`, which is also a pointer to the object.
+#[repr(C)]
+pub(crate) struct Object<I, T> {
+    pub(crate) ibv: I,
+    pub(crate) inner: T,
+}
+
+impl<I, T> Object<I, T> {
+    /// Box the object and return the pointer to `ibv`
+    pub(crate) fn into_raw(ibv: I, inner: T) -> *mut I {
+        Box::into_raw(Box::new(Self { ibv, inner })).cast()
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be null or returned by `into_raw` and not freed yet
+    pub(crate) unsafe fn from_raw<'a>(ptr: *mut I) -> Option<&'a Self> {
+        // SAFETY: the caller guarantees `ptr` points to a live `Self` if not null
+        unsafe { ptr.cast::<Self>().as_ref() }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be null or returned by `into_raw` and not freed yet, and no other reference to it is alive
+    pub(crate) unsafe fn from_raw_mut<'a>(ptr: *mut I) -> Option<&'a mut Self> {
+        // SAFETY: the caller guarantees `ptr` points to a live `Self` if not null
+        unsafe { ptr.cast::<Self>().as_mut() }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be returned by `into_raw` and not freed yet, and no reference to it is alive
+    pub(crate) unsafe fn free(ptr: *mut I) {
+        // SAFETY: the caller guarantees `ptr` is returned by `into_raw`
+        drop(unsafe { Box::from_raw(ptr.cast::<Self>()) });
+    }
+}
+
+/// Map a driver error to errno
+fn errno_of(err: &Error) -> c_int {
+    match *err {
+        Error::Invalid(_) | Error::AddressNotAlign(..) => libc::EINVAL,
+        Error::ResourceNoAvailable(_) | Error::SendQueueFull => libc::ENOMEM,
+        Error::PdInUse(_) => libc::EBUSY,
+        Error::NotSupport(_) => libc::EOPNOTSUPP,
+        Error::DeviceShutdown => libc::ENODEV,
+        _ => libc::EIO,
+    }
+}
+
+fn set_errno(errno: c_int) {
+    // SAFETY: `__errno_location` always returns a valid pointer to the errno of the current thread
+    unsafe { *libc::__errno_location() = errno };
+}
+
+/// Set errno and return null, for the verbs returning a pointer
+fn null_with_errno<T>(errno: c_int) -> *mut T {
+    set_errno(errno);
+    std::ptr::null_mut()
+}
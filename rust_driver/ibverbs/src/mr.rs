@@ -0,0 +1,215 @@
+//! Memory region
+
+use std::ffi::{c_int, c_uint, c_void};
+
+use open_rdma_driver::mr::{ReregMrAttr, ReregMrFlags};
+use open_rdma_driver::types::{MemAccessTypeFlag, PAGE_SIZE};
+use open_rdma_driver::Mr;
+
+use crate::abi::{IbvMr, IbvPd, IBV_REREG_MR_ERR_CMD, IBV_REREG_MR_ERR_INPUT, IBV_REREG_MR_FLAGS_SUPPORTED};
+use crate::device::Context;
+use crate::pd::ProtectionDomain;
+use crate::{errno_of, null_with_errno, set_errno, Object};
+
+type MemoryRegion = Object<IbvMr, Mr>;
+
+/// The access flags beyond `IBV_ACCESS_OPTIONAL_FIRST` can be ignored by providers
+const ACCESS_OPTIONAL_FIRST: c_uint = 1 << 20;
+
+/// The page size of the ordinary heap memory
+const BASE_PAGE_SIZE: u32 = 4096;
+
+/// The page size to map a buffer from `addr`, the hugepages are only used if the buffer is aligned to them
+#[allow(clippy::cast_possible_truncation)]
+fn pg_size_of(addr: *mut c_void) -> u32 {
+    if (addr as usize) % PAGE_SIZE == 0 {
+        PAGE_SIZE as u32
+    } else {
+        BASE_PAGE_SIZE
+    }
+}
+
+/// Register a MR
+///
+/// # Safety
+///
+/// `pd` must be null or returned by `ibv_alloc_pd`, and `[addr, addr + length)` must stay valid
+/// until the MR is deregistered
+#[no_mangle]
+pub unsafe extern "C" fn ibv_reg_mr(pd: *mut IbvPd, addr: *mut c_void, length: usize, access: c_int) -> *mut IbvMr {
+    // SAFETY: the caller guarantees the same as `ibv_reg_mr_iova2`
+    #[allow(clippy::cast_sign_loss)]
+    unsafe {
+        ibv_reg_mr_iova2(pd, addr, length, addr as u64, access as c_uint)
+    }
+}
+
+/// Register a MR, whose remote address starts at `iova`
+///
+/// `iova` must be either `addr`, or 0 for a zero based MR.
+///
+/// # Safety
+///
+/// `pd` must be null or returned by `ibv_alloc_pd`, and `[addr, addr + length)` must stay valid
+/// until the MR is deregistered
+#[no_mangle]
+pub unsafe extern "C" fn ibv_reg_mr_iova2(
+    pd: *mut IbvPd,
+    addr: *mut c_void,
+    length: usize,
+    iova: u64,
+    access: c_uint,
+) -> *mut IbvMr {
+    // SAFETY: the caller guarantees `pd` is null or allocated
+    let Some(pd_obj) = (unsafe { ProtectionDomain::from_raw(pd) }) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    // SAFETY: the context outlives its PDs
+    let Some(ctx) = (unsafe { Context::from_raw(pd_obj.ibv.context) }) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    let Ok(len) = u32::try_from(length) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    let Some(mut acc_flags) = acc_flags_of(access) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    if iova == 0 {
+        acc_flags |= MemAccessTypeFlag::IbvAccessZeroBased;
+    } else if iova != addr as u64 {
+        return null_with_errno(libc::EINVAL);
+    }
+
+    match ctx
+        .inner
+        .dev
+        .reg_mr(pd_obj.inner, addr as u64, len, pg_size_of(addr), acc_flags)
+    {
+        Ok(mr) => {
+            let key = mr.get_key().get();
+            let ibv = IbvMr {
+                context: pd_obj.ibv.context,
+                pd,
+                addr,
+                length,
+                handle: ctx.inner.next_handle(),
+                lkey: key,
+                rkey: key,
+            };
+            MemoryRegion::into_raw(ibv, mr)
+        }
+        Err(e) => null_with_errno(errno_of(&e)),
+    }
+}
+
+/// Re-register a MR, which rotates its keys and changes what `flags` asks in place
+///
+/// Returns 0 on success, or a negative `enum ibv_rereg_mr_err_code` with errno set on failure.
+/// The MR is still registered with its old attributes on failure.
+///
+/// # Safety
+///
+/// `mr` must be null or returned by `ibv_reg_mr` and not deregistered yet, `pd` must be null or
+/// returned by `ibv_alloc_pd` if the PD is changed, and `[addr, addr + length)` must stay valid until
+/// the MR is deregistered if the translation is changed
+#[no_mangle]
+pub unsafe extern "C" fn ibv_rereg_mr(
+    mr: *mut IbvMr,
+    flags: c_int,
+    pd: *mut IbvPd,
+    addr: *mut c_void,
+    length: usize,
+    access: c_int,
+) -> c_int {
+    let input_err = || {
+        set_errno(libc::EINVAL);
+        IBV_REREG_MR_ERR_INPUT
+    };
+    // SAFETY: the caller guarantees `mr` is null or registered
+    let Some(obj) = (unsafe { MemoryRegion::from_raw_mut(mr) }) else {
+        return input_err();
+    };
+    // SAFETY: the context outlives its MRs
+    let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+        return input_err();
+    };
+    if !(0..IBV_REREG_MR_FLAGS_SUPPORTED).contains(&flags) {
+        return input_err();
+    }
+    #[allow(clippy::cast_sign_loss)]
+    let flags = ReregMrFlags::from_bits_truncate(flags as u32);
+
+    let mut attr = ReregMrAttr::new();
+    if flags.contains(ReregMrFlags::IbvReregMrChangePd) {
+        // SAFETY: the caller guarantees `pd` is null or allocated
+        let Some(pd_obj) = (unsafe { ProtectionDomain::from_raw(pd) }) else {
+            return input_err();
+        };
+        attr = attr.with_pd(pd_obj.inner);
+    }
+    if flags.contains(ReregMrFlags::IbvReregMrChangeTranslation) {
+        let Ok(len) = u32::try_from(length) else {
+            return input_err();
+        };
+        attr = attr.with_translation(addr as u64, len, pg_size_of(addr));
+    }
+    if flags.contains(ReregMrFlags::IbvReregMrChangeAccess) {
+        #[allow(clippy::cast_sign_loss)]
+        let Some(acc_flags) = acc_flags_of(access as c_uint) else {
+            return input_err();
+        };
+        attr = attr.with_access(acc_flags);
+    }
+
+    match ctx.inner.dev.rereg_mr(obj.inner, &attr) {
+        Ok(new_mr) => {
+            let key = new_mr.get_key().get();
+            obj.inner = new_mr;
+            obj.ibv.lkey = key;
+            obj.ibv.rkey = key;
+            if flags.contains(ReregMrFlags::IbvReregMrChangePd) {
+                obj.ibv.pd = pd;
+            }
+            if flags.contains(ReregMrFlags::IbvReregMrChangeTranslation) {
+                obj.ibv.addr = addr;
+                obj.ibv.length = length;
+            }
+            0
+        }
+        Err(e) => {
+            set_errno(errno_of(&e));
+            IBV_REREG_MR_ERR_CMD
+        }
+    }
+}
+
+/// Deregister a MR
+///
+/// Returns 0 on success, or errno on failure.
+///
+/// # Safety
+///
+/// `mr` must be null or returned by `ibv_reg_mr` and not deregistered yet
+#[no_mangle]
+pub unsafe extern "C" fn ibv_dereg_mr(mr: *mut IbvMr) -> c_int {
+    // SAFETY: the caller guarantees `mr` is null or registered
+    let Some(obj) = (unsafe { MemoryRegion::from_raw(mr) }) else {
+        return libc::EINVAL;
+    };
+    // SAFETY: the context outlives its MRs
+    let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+        return libc::EINVAL;
+    };
+    if let Err(e) = ctx.inner.dev.dereg_mr(obj.inner) {
+        return errno_of(&e);
+    }
+    // SAFETY: `obj` is not used anymore
+    unsafe { MemoryRegion::free(mr) };
+    0
+}
+
+/// The access flags of the driver, the optional flags are ignored
+fn acc_flags_of(access: c_uint) -> Option<MemAccessTypeFlag> {
+    let access = u8::try_from(access % ACCESS_OPTIONAL_FIRST).ok()?;
+    MemAccessTypeFlag::from_bits(access)
+}
@@ -0,0 +1,59 @@
+//! Protection domain
+
+use std::ffi::c_int;
+
+use open_rdma_driver::Pd;
+
+use crate::abi::{IbvContext, IbvPd};
+use crate::device::Context;
+use crate::{errno_of, null_with_errno, Object};
+
+pub(crate) type ProtectionDomain = Object<IbvPd, Pd>;
+
+/// Allocate a PD
+///
+/// # Safety
+///
+/// `context` must be null or returned by `ibv_open_device`
+#[no_mangle]
+pub unsafe extern "C" fn ibv_alloc_pd(context: *mut IbvContext) -> *mut IbvPd {
+    // SAFETY: the caller guarantees `context` is null or opened
+    let Some(ctx) = (unsafe { Context::from_raw(context) }) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    match ctx.inner.dev.alloc_pd() {
+        Ok(pd) => {
+            let ibv = IbvPd {
+                context,
+                handle: ctx.inner.next_handle(),
+            };
+            ProtectionDomain::into_raw(ibv, pd)
+        }
+        Err(e) => null_with_errno(errno_of(&e)),
+    }
+}
+
+/// Deallocate a PD, which fails if the PD is still used by a MR or QP
+///
+/// Returns 0 on success, or errno on failure.
+///
+/// # Safety
+///
+/// `pd` must be null or returned by `ibv_alloc_pd` and not deallocated yet
+#[no_mangle]
+pub unsafe extern "C" fn ibv_dealloc_pd(pd: *mut IbvPd) -> c_int {
+    // SAFETY: the caller guarantees `pd` is null or allocated
+    let Some(obj) = (unsafe { ProtectionDomain::from_raw(pd) }) else {
+        return libc::EINVAL;
+    };
+    // SAFETY: the context outlives its PDs
+    let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+        return libc::EINVAL;
+    };
+    if let Err(e) = ctx.inner.dev.dealloc_pd(obj.inner) {
+        return errno_of(&e);
+    }
+    // SAFETY: `obj` is not used anymore
+    unsafe { ProtectionDomain::free(pd) };
+    0
+}
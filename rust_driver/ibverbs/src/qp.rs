@@ -0,0 +1,450 @@
+//! Queue pair and work requests
+
+use std::ffi::{c_int, c_uint};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::{ptr, slice};
+
+use eui48::MacAddress;
+use open_rdma_driver::qp::{QpAttr, QpState};
+use open_rdma_driver::types::{
+    Imm, Key, MemAccessTypeFlag, Pmtu, Psn, QpBuilder, QpType, Qpn, Sge, WorkReqSendFlag, MAX_INLINE_DATA,
+};
+use open_rdma_driver::Device;
+
+use crate::abi::{
+    IbvAhAttr, IbvPd, IbvQp, IbvQpAttr, IbvQpInitAttr, IbvRecvWr, IbvSendWr, IbvSge, IBV_QPS_ERR, IBV_QPS_INIT,
+    IBV_QPS_RESET, IBV_QPS_RTR, IBV_QPS_RTS, IBV_QPS_SQD, IBV_QPT_RC, IBV_QPT_UC, IBV_QP_ALT_PATH, IBV_QP_AV,
+    IBV_QP_CAP, IBV_QP_DEST_QPN, IBV_QP_MIN_RNR_TIMER, IBV_QP_PATH_MIG_STATE, IBV_QP_PATH_MTU, IBV_QP_RATE_LIMIT,
+    IBV_QP_RETRY_CNT, IBV_QP_RNR_RETRY, IBV_QP_RQ_PSN, IBV_QP_SQ_PSN, IBV_QP_STATE, IBV_QP_TIMEOUT,
+    IBV_WR_ATOMIC_CMP_AND_SWP, IBV_WR_ATOMIC_FETCH_AND_ADD, IBV_WR_RDMA_READ, IBV_WR_RDMA_WRITE,
+    IBV_WR_RDMA_WRITE_WITH_IMM, IBV_WR_SEND, IBV_WR_SEND_WITH_IMM, IBV_WR_SEND_WITH_INV,
+};
+use crate::cq::CompletionQueue;
+use crate::device::Context;
+use crate::pd::ProtectionDomain;
+use crate::{errno_of, null_with_errno, Object};
+
+type QueuePair = Object<IbvQp, Qpn>;
+
+/// The attributes of `ibv_modify_qp` the device cannot honor
+const UNSUPPORTED_ATTR_MASK: c_uint = IBV_QP_ALT_PATH | IBV_QP_PATH_MIG_STATE | IBV_QP_CAP | IBV_QP_RATE_LIMIT;
+
+/// The QP access flags are not modifiable, so every remote access is allowed by the QP
+/// and is checked against the access flags of the MR
+const RQ_ACC_FLAGS: MemAccessTypeFlag = MemAccessTypeFlag::IbvAccessLocalWrite
+    .union(MemAccessTypeFlag::IbvAccessRemoteWrite)
+    .union(MemAccessTypeFlag::IbvAccessRemoteRead)
+    .union(MemAccessTypeFlag::IbvAccessRemoteAtomic);
+
+fn qp_state_from(state: c_uint) -> Result<QpState, c_int> {
+    match state {
+        IBV_QPS_RESET => Ok(QpState::Reset),
+        IBV_QPS_INIT => Ok(QpState::Init),
+        IBV_QPS_RTR => Ok(QpState::Rtr),
+        IBV_QPS_RTS => Ok(QpState::Rts),
+        IBV_QPS_SQD => Ok(QpState::Sqd),
+        IBV_QPS_ERR => Ok(QpState::Err),
+        _ => Err(libc::EINVAL),
+    }
+}
+
+/// The peer IP of RoCEv2 is carried by the GID as an IPv4-mapped IPv6 address
+fn peer_ip_of(ah_attr: &IbvAhAttr) -> Result<Ipv4Addr, c_int> {
+    if ah_attr.is_global == 0 {
+        return Err(libc::EINVAL);
+    }
+    Ipv6Addr::from(ah_attr.grh.dgid.raw)
+        .to_ipv4_mapped()
+        .ok_or(libc::EINVAL)
+}
+
+fn qp_attr_from(attr: &IbvQpAttr, attr_mask: c_uint, cur_state: c_uint) -> Result<QpAttr, c_int> {
+    if attr_mask & UNSUPPORTED_ATTR_MASK != 0 {
+        return Err(libc::EOPNOTSUPP);
+    }
+    let next_state = if attr_mask & IBV_QP_STATE == 0 {
+        cur_state
+    } else {
+        attr.qp_state
+    };
+    let mut qp_attr = QpAttr::new(qp_state_from(next_state)?);
+    if attr_mask & IBV_QP_AV != 0 {
+        qp_attr = qp_attr.with_av(peer_ip_of(&attr.ah_attr)?, MacAddress::default());
+    }
+    if attr_mask & IBV_QP_PATH_MTU != 0 {
+        let pmtu = u8::try_from(attr.path_mtu)
+            .ok()
+            .and_then(|mtu| Pmtu::try_from(mtu).ok())
+            .ok_or(libc::EINVAL)?;
+        qp_attr = qp_attr.with_pmtu(pmtu);
+    }
+    if attr_mask & IBV_QP_DEST_QPN != 0 {
+        qp_attr = qp_attr.with_peer_qpn(Qpn::new(attr.dest_qp_num));
+    }
+    if attr_mask & IBV_QP_RQ_PSN != 0 {
+        qp_attr = qp_attr.with_rq_psn(Psn::new(attr.rq_psn));
+    }
+    if attr_mask & IBV_QP_SQ_PSN != 0 {
+        qp_attr = qp_attr.with_sq_psn(Psn::new(attr.sq_psn));
+    }
+    if attr_mask & IBV_QP_TIMEOUT != 0 {
+        qp_attr = qp_attr.with_timeout(attr.timeout);
+    }
+    if attr_mask & IBV_QP_RETRY_CNT != 0 {
+        qp_attr = qp_attr.with_retry_cnt(attr.retry_cnt);
+    }
+    if attr_mask & IBV_QP_RNR_RETRY != 0 {
+        qp_attr = qp_attr.with_rnr_retry(attr.rnr_retry);
+    }
+    if attr_mask & IBV_QP_MIN_RNR_TIMER != 0 {
+        qp_attr = qp_attr.with_min_rnr_timer(attr.min_rnr_timer);
+    }
+    Ok(qp_attr)
+}
+
+/// Create a QP in the `RESET` state
+///
+/// Only RC and UC QPs are supported, and `srq` must be null.
+///
+/// # Safety
+///
+/// `pd` must be null or returned by `ibv_alloc_pd`, the CQs of `qp_init_attr` must be null
+/// or returned by `ibv_create_cq`
+#[no_mangle]
+pub unsafe extern "C" fn ibv_create_qp(pd: *mut IbvPd, qp_init_attr: *mut IbvQpInitAttr) -> *mut IbvQp {
+    // SAFETY: the caller guarantees `pd` is null or allocated
+    let Some(pd_obj) = (unsafe { ProtectionDomain::from_raw(pd) }) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    // SAFETY: the caller guarantees `qp_init_attr` is null or valid
+    let Some(init_attr) = (unsafe { qp_init_attr.as_ref() }) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    // SAFETY: the context outlives its PDs
+    let Some(ctx) = (unsafe { Context::from_raw(pd_obj.ibv.context) }) else {
+        return null_with_errno(libc::EINVAL);
+    };
+    let qp_type = match init_attr.qp_type {
+        IBV_QPT_RC => QpType::Rc,
+        IBV_QPT_UC => QpType::Uc,
+        _ => return null_with_errno(libc::EOPNOTSUPP),
+    };
+    if !init_attr.srq.is_null() {
+        return null_with_errno(libc::EOPNOTSUPP);
+    }
+    // SAFETY: the caller guarantees the CQs are null or created
+    let send_cq = unsafe { CompletionQueue::from_raw(init_attr.send_cq) }.map(|cq| cq.inner);
+    // SAFETY: the caller guarantees the CQs are null or created
+    let recv_cq = unsafe { CompletionQueue::from_raw(init_attr.recv_cq) }.map(|cq| cq.inner);
+
+    let qpn = match ctx.inner.qp_manager.alloc() {
+        Ok(qpn) => qpn,
+        Err(e) => return null_with_errno(errno_of(&e)),
+    };
+    // the peer is given by `ibv_modify_qp` when moving to RTR
+    let qp = QpBuilder::default()
+        .pd(pd_obj.inner)
+        .qpn(qpn)
+        .peer_qpn(Qpn::default())
+        .qp_type(qp_type)
+        .rq_acc_flags(RQ_ACC_FLAGS)
+        .pmtu(Pmtu::default())
+        .dqp_ip(Ipv4Addr::UNSPECIFIED)
+        .dqp_mac(MacAddress::default())
+        .send_cq(send_cq)
+        .recv_cq(recv_cq)
+        .sq_sig_all(init_attr.sq_sig_all != 0)
+        .max_send_wr(init_attr.cap.max_send_wr)
+        .ready(false)
+        .build();
+    let res = match qp {
+        Ok(qp) => ctx.inner.dev.create_qp(&qp).map_err(|e| errno_of(&e)),
+        Err(_) => Err(libc::EINVAL),
+    };
+    if let Err(errno) = res {
+        ctx.inner.qp_manager.free(qpn);
+        return null_with_errno(errno);
+    }
+
+    let ibv = IbvQp {
+        context: pd_obj.ibv.context,
+        qp_context: init_attr.qp_context,
+        pd,
+        send_cq: init_attr.send_cq,
+        recv_cq: init_attr.recv_cq,
+        srq: ptr::null_mut(),
+        handle: ctx.inner.next_handle(),
+        qp_num: qpn.get(),
+        state: IBV_QPS_RESET,
+        qp_type: init_attr.qp_type,
+        mutex: libc::PTHREAD_MUTEX_INITIALIZER,
+        cond: libc::PTHREAD_COND_INITIALIZER,
+        events_completed: 0,
+    };
+    QueuePair::into_raw(ibv, qpn)
+}
+
+/// Modify the state and attributes of a QP
+///
+/// The peer is given by `IBV_QP_AV` and `IBV_QP_DEST_QPN`, and the attributes the device
+/// has no counterpart of are ignored.
+/// Returns 0 on success, or errno on failure.
+///
+/// # Safety
+///
+/// `qp` must be null or returned by `ibv_create_qp`, and `attr` must be null or valid
+#[no_mangle]
+pub unsafe extern "C" fn ibv_modify_qp(qp: *mut IbvQp, attr: *mut IbvQpAttr, attr_mask: c_int) -> c_int {
+    // SAFETY: the caller guarantees `attr` is null or valid
+    let Some(attr) = (unsafe { attr.as_ref() }) else {
+        return libc::EINVAL;
+    };
+    let (ctx, qpn, cur_state) = {
+        // SAFETY: the caller guarantees `qp` is null or created
+        let Some(obj) = (unsafe { QueuePair::from_raw(qp) }) else {
+            return libc::EINVAL;
+        };
+        // SAFETY: the context outlives its QPs
+        let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+            return libc::EINVAL;
+        };
+        (ctx, obj.inner, obj.ibv.state)
+    };
+    #[allow(clippy::cast_sign_loss)]
+    let attr_mask = attr_mask as c_uint;
+    let qp_attr = match qp_attr_from(attr, attr_mask, cur_state) {
+        Ok(qp_attr) => qp_attr,
+        Err(errno) => return errno,
+    };
+    if let Err(e) = ctx.inner.dev.modify_qp(qpn, &qp_attr) {
+        return errno_of(&e);
+    }
+    if attr_mask & IBV_QP_STATE != 0 {
+        // SAFETY: `qp` is valid, and no reference to it is alive
+        unsafe { (*qp).state = attr.qp_state };
+    }
+    0
+}
+
+/// Destroy a QP
+///
+/// Returns 0 on success, or errno on failure.
+///
+/// # Safety
+///
+/// `qp` must be null or returned by `ibv_create_qp` and not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn ibv_destroy_qp(qp: *mut IbvQp) -> c_int {
+    // SAFETY: the caller guarantees `qp` is null or created
+    let Some(obj) = (unsafe { QueuePair::from_raw(qp) }) else {
+        return libc::EINVAL;
+    };
+    // SAFETY: the context outlives its QPs
+    let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+        return libc::EINVAL;
+    };
+    let qpn = obj.inner;
+    if let Err(e) = ctx.inner.dev.destroy_qp(qpn) {
+        return errno_of(&e);
+    }
+    ctx.inner.qp_manager.free(qpn);
+    // SAFETY: `obj` is not used anymore
+    unsafe { QueuePair::free(qp) };
+    0
+}
+
+/// # Safety
+///
+/// `sg_list` must be valid for `num_sge` reads if `num_sge` is positive
+unsafe fn sgl_from(sg_list: *const IbvSge, num_sge: c_int) -> Result<Vec<Sge>, c_int> {
+    let len = usize::try_from(num_sge).map_err(|_| libc::EINVAL)?;
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    if sg_list.is_null() {
+        return Err(libc::EINVAL);
+    }
+    // SAFETY: the caller guarantees `sg_list` holds `num_sge` entries
+    let sges = unsafe { slice::from_raw_parts(sg_list, len) };
+    Ok(sges
+        .iter()
+        .map(|sge| Sge::new(sge.addr, sge.length, Key::new(sge.lkey)))
+        .collect())
+}
+
+/// The request takes exactly one SGE
+fn single_sge(sgl: &[Sge]) -> Result<Sge, c_int> {
+    match *sgl {
+        [sge] => Ok(sge),
+        _ => Err(libc::EINVAL),
+    }
+}
+
+/// Copy the data of the SGEs, which is carried by the descriptor of a request with `IBV_SEND_INLINE`
+///
+/// # Safety
+///
+/// Each SGE must point to `length` readable bytes
+unsafe fn inline_data_from(sgl: &[Sge]) -> Result<Vec<u8>, c_int> {
+    let total_len = sgl.iter().try_fold(0u32, |acc, sge| acc.checked_add(sge.len));
+    if total_len.map_or(true, |len| len > MAX_INLINE_DATA) {
+        return Err(libc::EINVAL);
+    }
+    let mut data = Vec::new();
+    for sge in sgl {
+        // SAFETY: the caller guarantees the SGE is readable
+        data.extend_from_slice(unsafe { slice::from_raw_parts(sge.addr as *const u8, sge.len as usize) });
+    }
+    Ok(data)
+}
+
+/// # Safety
+///
+/// The `sg_list` of `wr` must be valid
+unsafe fn post_one_send(dev: &Device, qpn: Qpn, wr: &IbvSendWr) -> Result<(), c_int> {
+    // SAFETY: the caller guarantees `sg_list` is valid
+    let sgl = unsafe { sgl_from(wr.sg_list, wr.num_sge) }?;
+    #[allow(clippy::cast_possible_truncation)]
+    let flags = WorkReqSendFlag::from_bits_truncate(wr.send_flags as u8);
+    let imm = Imm::new(wr.imm_data);
+    // SAFETY: all the variants of the union are plain integers or pointers
+    let (rdma, atomic) = unsafe { (wr.wr.rdma, wr.wr.atomic) };
+    // the data is copied here, for the driver only takes inline data as bytes
+    let inline = if flags.contains(WorkReqSendFlag::IbvSendInline) {
+        // SAFETY: the caller guarantees the SGEs are valid
+        Some(unsafe { inline_data_from(&sgl) }?)
+    } else {
+        None
+    };
+    let res = match (wr.opcode, inline.as_deref()) {
+        (IBV_WR_RDMA_WRITE, Some(data)) => {
+            dev.write_inline(wr.wr_id, qpn, rdma.remote_addr, Key::new(rdma.rkey), flags, data, None)
+        }
+        (IBV_WR_RDMA_WRITE_WITH_IMM, Some(data)) => dev.write_inline(
+            wr.wr_id,
+            qpn,
+            rdma.remote_addr,
+            Key::new(rdma.rkey),
+            flags,
+            data,
+            Some(imm),
+        ),
+        (IBV_WR_SEND, Some(data)) => dev.post_send_inline(wr.wr_id, qpn, flags, data, None),
+        (IBV_WR_SEND_WITH_IMM, Some(data)) => dev.post_send_inline(wr.wr_id, qpn, flags, data, Some(imm)),
+        (IBV_WR_SEND_WITH_INV, Some(data)) => {
+            dev.post_send_with_invalidate_inline(wr.wr_id, qpn, flags, data, Key::new(wr.imm_data))
+        }
+        (IBV_WR_RDMA_WRITE, None) => dev.write(wr.wr_id, qpn, rdma.remote_addr, Key::new(rdma.rkey), flags, &sgl),
+        (IBV_WR_RDMA_WRITE_WITH_IMM, None) => {
+            dev.write_with_imm(wr.wr_id, qpn, rdma.remote_addr, Key::new(rdma.rkey), flags, &sgl, imm)
+        }
+        (IBV_WR_SEND, None) => dev.post_send(wr.wr_id, qpn, flags, single_sge(&sgl)?, None),
+        (IBV_WR_SEND_WITH_IMM, None) => dev.post_send(wr.wr_id, qpn, flags, single_sge(&sgl)?, Some(imm)),
+        // `invalidate_rkey` shares the field of `imm_data`
+        (IBV_WR_SEND_WITH_INV, None) => {
+            dev.post_send_with_invalidate(wr.wr_id, qpn, flags, single_sge(&sgl)?, Key::new(wr.imm_data))
+        }
+        (IBV_WR_RDMA_READ, _) => dev.read(wr.wr_id, qpn, rdma.remote_addr, Key::new(rdma.rkey), flags, &sgl),
+        (IBV_WR_ATOMIC_CMP_AND_SWP, _) => dev.atomic_cas(
+            wr.wr_id,
+            qpn,
+            atomic.remote_addr,
+            Key::new(atomic.rkey),
+            flags,
+            single_sge(&sgl)?,
+            atomic.compare_add,
+            atomic.swap,
+        ),
+        (IBV_WR_ATOMIC_FETCH_AND_ADD, _) => dev.atomic_fetch_add(
+            wr.wr_id,
+            qpn,
+            atomic.remote_addr,
+            Key::new(atomic.rkey),
+            flags,
+            single_sge(&sgl)?,
+            atomic.compare_add,
+        ),
+        _ => return Err(libc::EOPNOTSUPP),
+    };
+    // the request is completed through the CQ, so the operation context is not kept
+    res.map(drop).map_err(|e| errno_of(&e))
+}
+
+/// Post a list of send requests
+///
+/// Returns 0 on success, or errno on failure with `bad_wr` set to the first request not posted.
+/// This is also the `post_send` op called by the inline `ibv_post_send` of `verbs.h`.
+///
+/// # Safety
+///
+/// `qp` must be null or returned by `ibv_create_qp`, `wr` must be a valid list of requests,
+/// and `bad_wr` must be null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn ibv_post_send(qp: *mut IbvQp, wr: *mut IbvSendWr, bad_wr: *mut *mut IbvSendWr) -> c_int {
+    // SAFETY: the caller guarantees `qp` is null or created
+    let Some(obj) = (unsafe { QueuePair::from_raw(qp) }) else {
+        return libc::EINVAL;
+    };
+    // SAFETY: the context outlives its QPs
+    let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+        return libc::EINVAL;
+    };
+    let mut cur = wr;
+    // SAFETY: the caller guarantees `wr` is a valid list
+    while let Some(req) = unsafe { cur.as_ref() } {
+        // SAFETY: the caller guarantees the requests are valid
+        if let Err(errno) = unsafe { post_one_send(&ctx.inner.dev, obj.inner, req) } {
+            // SAFETY: the caller guarantees `bad_wr` is null or valid
+            if let Some(bad_wr) = unsafe { bad_wr.as_mut() } {
+                *bad_wr = cur;
+            }
+            return errno;
+        }
+        cur = req.next;
+    }
+    0
+}
+
+/// Post a list of receive requests
+///
+/// Returns 0 on success, or errno on failure with `bad_wr` set to the first request not posted.
+/// This is also the `post_recv` op called by the inline `ibv_post_recv` of `verbs.h`.
+///
+/// # Safety
+///
+/// `qp` must be null or returned by `ibv_create_qp`, `wr` must be a valid list of requests,
+/// and `bad_wr` must be null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn ibv_post_recv(qp: *mut IbvQp, wr: *mut IbvRecvWr, bad_wr: *mut *mut IbvRecvWr) -> c_int {
+    // SAFETY: the caller guarantees `qp` is null or created
+    let Some(obj) = (unsafe { QueuePair::from_raw(qp) }) else {
+        return libc::EINVAL;
+    };
+    // SAFETY: the context outlives its QPs
+    let Some(ctx) = (unsafe { Context::from_raw(obj.ibv.context) }) else {
+        return libc::EINVAL;
+    };
+    let mut cur = wr;
+    // SAFETY: the caller guarantees `wr` is a valid list
+    while let Some(req) = unsafe { cur.as_ref() } {
+        // SAFETY: the caller guarantees the requests are valid
+        let res = unsafe { sgl_from(req.sg_list, req.num_sge) }
+            .and_then(|sgl| single_sge(&sgl))
+            .and_then(|sge| {
+                ctx.inner
+                    .dev
+                    .post_recv(req.wr_id, obj.inner, sge)
+                    .map(drop)
+                    .map_err(|e| errno_of(&e))
+            });
+        if let Err(errno) = res {
+            // SAFETY: the caller guarantees `bad_wr` is null or valid
+            if let Some(bad_wr) = unsafe { bad_wr.as_mut() } {
+                *bad_wr = cur;
+            }
+            return errno;
+        }
+        cur = req.next;
+    }
+    0
+}
@@ -9,17 +9,24 @@ use log::{error, info};
 use parking_lot::RwLock;
 
 use crate::buf::{PacketBuf, RDMA_ACK_BUFFER_SLOT_SIZE};
+use crate::cq::{WorkCompletionOpcode, WorkCompletionStatus};
 use crate::device::{
     ToCardCtrlRbDesc, ToCardCtrlRbDescCommon, ToCardCtrlRbDescUpdateErrPsnRecoverPoint, ToHostWorkRbDescAck,
-    ToHostWorkRbDescAethCode, ToHostWorkRbDescRead, ToHostWorkRbDescWriteOrReadResp, ToHostWorkRbDescWriteType,
+    ToHostWorkRbDescAethCode, ToHostWorkRbDescRead, ToHostWorkRbDescSend, ToHostWorkRbDescStatus,
+    ToHostWorkRbDescTransType, ToHostWorkRbDescWriteOrReadResp, ToHostWorkRbDescWriteType,
+    ToHostWorkRbDescWriteWithImm,
 };
+use crate::mw::{invalidate_by_peer, MwTable};
 use crate::op_ctx::OpCtx;
 use crate::qp::QpContext;
-use crate::responser::{make_ack, make_nack, make_read_resp};
-use crate::retry::RetryMap;
-use crate::types::{Msn, Pmtu, Psn, Qpn, PSN_MAX_WINDOW_SIZE};
+use crate::responser::{
+    make_ack, make_nack, make_nack_with_code, make_read_resp, NAK_INVALID_REQUEST, NAK_REMOTE_ACCESS_ERROR,
+    NAK_REMOTE_OPERATIONAL_ERROR,
+};
+use crate::retry::{rnr_timer_duration, RetryMap};
+use crate::types::{Imm, Msn, Pmtu, Psn, QpType, Qpn, RecvCompletion, PSN_MAX_WINDOW_SIZE};
 use crate::utils::calculate_packet_cnt;
-use crate::{CtrlDescriptorSender, ThreadSafeHashmap, WorkDescriptorSender};
+use crate::{CtrlDescriptorSender, MrConfig, ThreadSafeHashmap, WorkDescriptorSender};
 
 const MAX_MSN_WINDOW_PER_QP: usize = 16;
 
@@ -38,6 +45,8 @@ pub(crate) struct PacketCheckerContext {
     pub(crate) work_desc_sender: Arc<dyn WorkDescriptorSender>,
     pub(crate) ack_buffers: PacketBuf<RDMA_ACK_BUFFER_SLOT_SIZE>,
     pub(crate) retry_map: RetryMap,
+    pub(crate) mw_table: MwTable,
+    pub(crate) mr_config: MrConfig,
 }
 
 impl PacketChecker {
@@ -85,33 +94,15 @@ fn working_thread(ctx: &mut PacketCheckerContext, stop_flag: &AtomicBool) {
 
 impl PacketCheckerContext {
     pub(crate) fn handle_check_event(&self, event: PacketCheckEvent) {
-        match event {
-            PacketCheckEvent::Write(event) => {
-                let qpn = event.common.dqpn;
-                let expected_psn = event.common.expected_psn;
-                let psn = event.psn;
-                let enter_error = expected_psn != psn;
-                let (mut is_normal, pmtu) = if let Some(qp) = self.qp_table.read().get(&qpn) {
-                    (qp.status.load(Ordering::Acquire).is_normal(), qp.pmtu)
-                } else {
-                    return;
-                };
-                if is_normal && enter_error {
-                    // ensure only enter error status once
-                    self.enter_qp_error_status(qpn, pmtu, expected_psn, psn);
-                    is_normal = false;
-                }
-                // log::info!(
-                //     "psn={},expect={}",
-                //     event.psn.get(),
-                //     event.common.expected_psn.get()
-                // );
-                if is_normal {
-                    self.handle_qp_normal(&event);
-                } else {
-                    self.handle_qp_ooo(&event, pmtu);
-                }
+        if let Some(qpn) = event.recv_qpn() {
+            if !self.can_receive(qpn) {
+                log::warn!("drop request to {:?}, which is not ready to receive", qpn);
+                return;
             }
+        }
+        match event {
+            PacketCheckEvent::Write(event) => self.handle_write(&event),
+            PacketCheckEvent::WriteWithImm(event) => self.handle_write_with_imm(&event),
             PacketCheckEvent::ReadReq(event) => {
                 // convert read req directly
                 self.recv_ctx_map.set_recent_msn_status(
@@ -125,6 +116,7 @@ impl PacketCheckerContext {
                     }
                 }
             }
+            PacketCheckEvent::Send(event) => self.handle_send(&event),
             PacketCheckEvent::Ack(event) => {
                 log::info!("{:?}", event);
                 let code = event.code;
@@ -135,17 +127,36 @@ impl PacketCheckerContext {
                         wakeup_user_op_ctx(&self.user_op_ctx_map, qpn, msn);
                     }
                     ToHostWorkRbDescAethCode::Nak => {
-                        if let Ok(Some(desc)) = self
-                            .retry_map
-                            .get_descritpor((qpn, msn), Some((event.psn.get(), event.common.expected_psn.get())))
-                        {
-                            if let Err(e) = self.work_desc_sender.send_work_desc(desc) {
-                                error!("Failed to send retry {:?}", e);
+                        if let Some((cause, status)) = nak_status(event.value) {
+                            // the request will never succeed, so it is not retried
+                            if let Some(ctx) = self.user_op_ctx_map.read().get(&(qpn, msn)) {
+                                ctx.set_error(cause, status);
                             }
+                            let _ignore = self.retry_map.cancel((qpn, msn));
+                            log::info!("receive nak with code {:#x}", event.value);
+                        } else {
+                            if let Ok(Some(desc)) = self
+                                .retry_map
+                                .get_descritpor((qpn, msn), Some((event.psn.get(), event.common.expected_psn.get())))
+                            {
+                                if let Err(e) = self.work_desc_sender.send_work_desc(desc) {
+                                    error!("Failed to send retry {:?}", e);
+                                }
+                            }
+                            log::info!("receive nak");
                         }
-                        log::info!("receive nak");
                     }
-                    ToHostWorkRbDescAethCode::Rnr | ToHostWorkRbDescAethCode::Rsvd => {
+                    ToHostWorkRbDescAethCode::Rnr => {
+                        // the responder has no receive request, retry the request after the advertised time
+                        if !self.retry_map.wait_rnr((qpn, msn), rnr_timer_duration(event.value)) {
+                            if let Some(ctx) = self.user_op_ctx_map.read().get(&(qpn, msn)) {
+                                ctx.set_error("exceed rnr retry count", WorkCompletionStatus::RnrRetryExceeded);
+                            }
+                            let _ignore = self.retry_map.cancel((qpn, msn));
+                        }
+                        log::info!("receive rnr nak");
+                    }
+                    ToHostWorkRbDescAethCode::Rsvd => {
                         // reserved
                     }
                 }
@@ -153,6 +164,110 @@ impl PacketCheckerContext {
         }
     }
 
+    fn can_receive(&self, qpn: Qpn) -> bool {
+        self.qp_table
+            .read()
+            .get(&qpn)
+            .is_some_and(|qp| qp.state().can_receive())
+    }
+
+    fn handle_write(&self, event: &ToHostWorkRbDescWriteOrReadResp) {
+        let qpn = event.common.dqpn;
+        let expected_psn = event.common.expected_psn;
+        let psn = event.psn;
+        let enter_error = expected_psn != psn;
+        let (mut is_normal, pmtu) = if let Some(qp) = self.qp_table.read().get(&qpn) {
+            // the device drops broken UC messages, and nothing is acknowledged or recovered
+            if matches!(qp.qp_type, QpType::Uc) {
+                return;
+            }
+            (qp.status.load(Ordering::Acquire).is_normal(), qp.pmtu)
+        } else {
+            return;
+        };
+        if is_normal && enter_error {
+            // ensure only enter error status once
+            self.enter_qp_error_status(qpn, pmtu, expected_psn, psn);
+            is_normal = false;
+        }
+        // log::info!(
+        //     "psn={},expect={}",
+        //     event.psn.get(),
+        //     event.common.expected_psn.get()
+        // );
+        if is_normal {
+            self.handle_qp_normal(event);
+        } else {
+            self.handle_qp_ooo(event, pmtu);
+        }
+    }
+
+    /// The last packet of a RDMA write with immediate carries the immediate data.
+    /// Once the whole message is received, the oldest receive request of the QP is completed with it.
+    fn handle_write_with_imm(&self, event: &ToHostWorkRbDescWriteWithImm) {
+        let qpn = event.common.dqpn;
+        let msn = event.common.msn;
+        // a retransmitted packet of a finished message should not notify the user again
+        let is_duplicated = matches!(
+            self.recv_ctx_map.query_recent_msn_status(qpn, msn),
+            RecentQpMsnStatus::Finished
+        );
+        self.handle_write(&ToHostWorkRbDescWriteOrReadResp::from(event));
+        if is_duplicated {
+            return;
+        }
+        if let Some(mut ctx) = self.recv_ctx_map.get_ctx_mut(qpn, msn) {
+            // some packets are still missing, notify the user once they are recovered
+            ctx.imm = Some((event.imm, event.recv_tag));
+            return;
+        }
+        // `WriteOnly` doesn't create the per QP context, so the status should be recorded here
+        self.recv_ctx_map
+            .get_or_create_per_qp_ctx_mut(qpn, self.initial_recv_psn(qpn))
+            .set_recent_msn_status(msn, RecentQpMsnStatus::Finished);
+        self.complete_write_with_imm(qpn, event.imm, event.recv_tag, event.len);
+    }
+
+    fn complete_write_with_imm(&self, qpn: Qpn, imm: u32, recv_tag: u32, byte_len: u32) {
+        let Some(recv_ctx) = self.pop_recv_request(qpn, recv_tag) else {
+            error!("No receive request found for {:?}", qpn);
+            return;
+        };
+        let completion = RecvCompletion {
+            byte_len,
+            imm: Some(Imm::new(imm)),
+            src_qpn: None,
+            src_ip: None,
+            invalidated_rkey: None,
+        };
+        recv_ctx.update_completion(|wc| {
+            wc.opcode = WorkCompletionOpcode::RecvRdmaWithImm;
+            wc.byte_len = byte_len;
+            wc.imm = completion.imm;
+        });
+        if let Err(e) = recv_ctx.set_result(completion) {
+            error!("Set result failed {:?}", e);
+        }
+    }
+
+    /// Take the receive request consumed by the message.
+    ///
+    /// The requests of a QP are consumed in order, while the requests of a SRQ are shared by QPs,
+    /// so they are identified by the tag reported by the device.
+    fn pop_recv_request(&self, qpn: Qpn, recv_tag: u32) -> Option<OpCtx<RecvCompletion>> {
+        let qp_table = self.qp_table.read();
+        let qp = qp_table.get(&qpn)?;
+        match &qp.srq {
+            Some(srq) => srq.take_recv(recv_tag, qpn, qp.recv_cq.as_deref()),
+            None => qp.recv_queue.lock().pop_front(),
+        }
+    }
+
+    /// The initial receive PSN of the QP, which is used to create the per qp context
+    fn initial_recv_psn(&self, qpn: Qpn) -> Psn {
+        self.qp_table.read().get(&qpn).map_or_else(Psn::default, |qp| qp.rq_psn)
+    }
+
     fn handle_qp_normal(&self, event: &ToHostWorkRbDescWriteOrReadResp) {
         let qpn = event.common.dqpn;
         let msn = event.common.msn;
@@ -162,7 +277,7 @@ impl PacketCheckerContext {
                 let status = self.recv_ctx_map.query_recent_msn_status(qpn, msn);
                 if matches!(status, RecentQpMsnStatus::NoExist) {
                     let ctx = RecvContext::from(event);
-                    self.recv_ctx_map.insert_ctx(qpn, msn, ctx);
+                    self.recv_ctx_map.insert_ctx(qpn, msn, ctx, self.initial_recv_psn(qpn));
                 }
             }
             ToHostWorkRbDescWriteType::Last => {
@@ -190,6 +305,60 @@ impl PacketCheckerContext {
         };
     }
 
+    /// The device only reports a SEND message once it is fully placed in the receive buffer,
+    /// so we complete the oldest receive request of the QP here.
+    ///
+    /// A datagram (UD) SEND carries its source instead of a connection state, so it is not tracked by msn.
+    /// Only RC SEND is acknowledged.
+    fn handle_send(&self, event: &ToHostWorkRbDescSend) {
+        let qpn = event.common.dqpn;
+        let msn = event.common.msn;
+        let is_datagram = event.src.is_some();
+        if !is_datagram {
+            self.recv_ctx_map
+                .set_recent_msn_status(qpn, msn, RecentQpMsnStatus::Finished);
+        }
+
+        let Some(recv_ctx) = self.pop_recv_request(qpn, event.recv_tag) else {
+            error!("No receive request found for {:?}", qpn);
+            return;
+        };
+
+        if !event.common.status.is_ok() {
+            // the requester is told by a NAK, which the device sends unless the QP is recovering from a PSN gap
+            if matches!(event.common.trans, ToHostWorkRbDescTransType::Rc) && !event.can_auto_ack {
+                self.send_nack_with_code(qpn, msn, event.psn, nak_code_of(&event.common.status));
+            }
+            recv_ctx.set_error("receive request failed", event.common.status.clone().into());
+            return;
+        }
+        if matches!(event.common.trans, ToHostWorkRbDescTransType::Rc) && !event.can_auto_ack {
+            self.send_ack(qpn, msn, event.psn);
+        }
+        let completion = RecvCompletion {
+            byte_len: event.len,
+            imm: event.imm.map(Imm::new),
+            src_qpn: event.src.map(|(src_qpn, _)| src_qpn),
+            src_ip: event.src.map(|(_, src_ip)| src_ip),
+            invalidated_rkey: event.invalidated_rkey,
+        };
+        if let Some(rkey) = completion.invalidated_rkey {
+            invalidate_by_peer(&self.mw_table, &self.mr_config, rkey);
+        }
+        recv_ctx.update_completion(|wc| {
+            wc.byte_len = completion.byte_len;
+            wc.imm = completion.imm;
+            wc.src_qpn = completion.src_qpn;
+            wc.invalidated_rkey = completion.invalidated_rkey;
+            if completion.imm.is_some() {
+                wc.opcode = WorkCompletionOpcode::RecvWithImm;
+            }
+        });
+        if let Err(e) = recv_ctx.set_result(completion) {
+            error!("Set result failed {:?}", e);
+        }
+    }
+
     fn send_ack(&self, qpn: Qpn, msn: Msn, psn: Psn) {
         let slot = self.ack_buffers.recycle_buf();
         if let Ok(desc) = make_ack(slot, &self.qp_table, qpn, msn, psn) {
@@ -201,6 +370,17 @@ impl PacketCheckerContext {
         }
     }
 
+    fn send_nack_with_code(&self, qpn: Qpn, msn: Msn, psn: Psn, code: u8) {
+        let slot = self.ack_buffers.recycle_buf();
+        if let Ok(desc) = make_nack_with_code(slot, &self.qp_table, qpn, msn, psn, code) {
+            if let Err(e) = self.work_desc_sender.send_work_desc(desc) {
+                error!("Send nack failed {:?}", e);
+            }
+        } else {
+            error!("create nack failed");
+        }
+    }
+
     fn send_nack(&self, qpn: Qpn, msn: Msn, start_psn: Psn, end_psn: Psn) {
         let slot = self.ack_buffers.recycle_buf();
         if let Ok(desc) = make_nack(slot, &self.qp_table, qpn, msn, start_psn, end_psn) {
@@ -230,7 +410,7 @@ impl PacketCheckerContext {
                 let status = self.recv_ctx_map.query_recent_msn_status(qpn, msn);
                 if matches!(status, RecentQpMsnStatus::NoExist) {
                     let ctx = RecvContext::new_with_recvmap(event, pmtu);
-                    self.recv_ctx_map.insert_ctx(qpn, msn, ctx);
+                    self.recv_ctx_map.insert_ctx(qpn, msn, ctx, self.initial_recv_psn(qpn));
                 }
             }
             ToHostWorkRbDescWriteType::Middle | ToHostWorkRbDescWriteType::Last => {
@@ -274,16 +454,17 @@ impl PacketCheckerContext {
     #[allow(clippy::unwrap_used)]
     fn check_completed_and_try_recover(&self, qpn: Qpn, msn: Msn) {
         let mut perqp_map = self.recv_ctx_map.get_per_qp_ctx_mut(qpn).unwrap();
-        let (is_read_resp, is_completed, last_psn, recover_psn) = if let Some(ctx) = perqp_map.map.get_mut(&msn) {
+        let (is_read_resp, is_completed, last_psn, recover_psn, imm) = if let Some(ctx) = perqp_map.map.get_mut(&msn) {
             let recv_map = ctx.recv_map.as_ref().unwrap();
             (
                 ctx.is_read_resp,
                 recv_map.is_complete(),
                 recv_map.last_psn(),
                 recv_map.try_get_recover_psn(),
+                ctx.imm.map(|imm| (imm, ctx.len_in_bytes)),
             )
         } else {
-            (false, false, Psn::default(), None)
+            (false, false, Psn::default(), None, None)
         };
 
         // decrease borrow
@@ -297,6 +478,9 @@ impl PacketCheckerContext {
                 // we should manually send ack the packet
                 self.send_ack(qpn, msn, last_psn);
                 let _ignore = self.retry_map.cancel((qpn, msn));
+                if let Some(((imm, recv_tag), byte_len)) = imm {
+                    self.complete_write_with_imm(qpn, imm, recv_tag, byte_len);
+                }
             }
         }
 
@@ -334,7 +518,10 @@ impl PacketCheckerContext {
         };
 
         // create context for all msn
-        let mut per_qp_map = self.recv_ctx_map.get_or_create_per_qp_ctx_mut(qpn, recved_psn);
+        let mut per_qp_map = self
+            .recv_ctx_map
+            .get_or_create_per_qp_ctx_mut(qpn, self.initial_recv_psn(qpn));
+        per_qp_map.update_largest_psn_recved(recved_psn);
         // we know that if we are previous in the normal status,
         // we should have only one or not recv context left.
         debug_assert!(per_qp_map.map.len() <= 1, "Not in normal status");
@@ -347,6 +534,30 @@ impl PacketCheckerContext {
     }
 }
 
+/// The failure of a request rejected by a NAK with `code`, or `None` if the request should be retried
+fn nak_status(code: u8) -> Option<(&'static str, WorkCompletionStatus)> {
+    match code {
+        NAK_INVALID_REQUEST => Some((
+            "remote invalid request",
+            WorkCompletionStatus::RemoteInvalidRequestError,
+        )),
+        NAK_REMOTE_ACCESS_ERROR => Some(("remote access error", WorkCompletionStatus::RemoteAccessError)),
+        NAK_REMOTE_OPERATIONAL_ERROR => Some(("remote operation error", WorkCompletionStatus::RemoteOperationError)),
+        _ => None,
+    }
+}
+
+/// The NAK code which tells the requester why a received request failed with `status`
+fn nak_code_of(status: &ToHostWorkRbDescStatus) -> u8 {
+    match *status {
+        ToHostWorkRbDescStatus::InvAccFlag | ToHostWorkRbDescStatus::InvMrRegion => NAK_REMOTE_ACCESS_ERROR,
+        ToHostWorkRbDescStatus::InvOpcode => NAK_INVALID_REQUEST,
+        ToHostWorkRbDescStatus::Normal | ToHostWorkRbDescStatus::InvMrKey | ToHostWorkRbDescStatus::Unknown => {
+            NAK_REMOTE_OPERATIONAL_ERROR
+        }
+    }
+}
+
 fn wakeup_user_op_ctx(user_op_ctx_map: &RwLock<HashMap<(Qpn, Msn), OpCtx<()>>>, qpn: Qpn, msn: Msn) {
     if let Some(ctx) = user_op_ctx_map.read().get(&(qpn, msn)) {
         if let Err(e) = ctx.set_result(()) {
@@ -401,10 +612,11 @@ pub(crate) struct PerQpContextMap {
 }
 
 impl PerQpContextMap {
-    fn new(largest_psn_recved: Psn) -> Self {
+    /// create the context of a QP which has received nothing, `rq_psn` is the first PSN expected by the QP
+    fn new(rq_psn: Psn) -> Self {
         Self {
             map: BTreeMap::new(),
-            largest_psn_recved,
+            largest_psn_recved: rq_psn.wrapping_sub(1),
             recent_msn_finished: [(Msn::default(), RecentQpMsnStatus::default()); MAX_MSN_WINDOW_PER_QP],
         }
     }
@@ -448,10 +660,10 @@ impl RecvContextMap {
         Self(HashMap::new().into())
     }
 
-    fn insert_ctx(&self, qpn: Qpn, msn: Msn, ctx: RecvContext) {
+    /// insert a receive context, `rq_psn` is the first PSN expected by the QP
+    fn insert_ctx(&self, qpn: Qpn, msn: Msn, ctx: RecvContext, rq_psn: Psn) {
         // the `per qp context` might leak here?
-        let mut inner = self.0.borrow_mut();
-        let per_qp_map = inner.entry(qpn).or_insert(PerQpContextMap::new(Psn::default()));
+        let mut per_qp_map = self.get_or_create_per_qp_ctx_mut(qpn, rq_psn);
         if per_qp_map.map.insert(msn, ctx).is_some() {
             log::error!("create duplicate msn({:?}) record for qpn={:?}", msn, qpn);
         } else {
@@ -493,9 +705,10 @@ impl RecvContextMap {
         }
     }
 
-    pub(crate) fn get_or_create_per_qp_ctx_mut(&self, qpn: Qpn, psn: Psn) -> RefMut<PerQpContextMap> {
+    /// `rq_psn` is the first PSN expected by the QP, which seeds the context if it is created
+    pub(crate) fn get_or_create_per_qp_ctx_mut(&self, qpn: Qpn, rq_psn: Psn) -> RefMut<PerQpContextMap> {
         let mut inner = self.0.borrow_mut();
-        let _per_qp_map = inner.entry(qpn).or_insert(PerQpContextMap::new(psn));
+        let _per_qp_map = inner.entry(qpn).or_insert_with(|| PerQpContextMap::new(rq_psn));
         drop(inner);
         #[allow(clippy::unwrap_used)] // value is create above.
         RefMut::map(self.0.borrow_mut(), |inner_mut| inner_mut.get_mut(&qpn).unwrap())
@@ -538,6 +751,9 @@ pub(crate) struct RecvContext {
     len_in_bytes: u32,
     start_psn: Psn,
     recv_map: Option<Box<SlidingWindow>>,
+    /// the immediate data and the receive tag of a RDMA write with immediate,
+    /// which are received before the message is completed
+    imm: Option<(u32, u32)>,
 }
 
 impl RecvContext {
@@ -551,6 +767,7 @@ impl RecvContext {
             len_in_bytes: event.len,
             start_psn: event.psn,
             recv_map: Some(map),
+            imm: None,
         }
     }
 
@@ -576,6 +793,7 @@ impl From<&ToHostWorkRbDescWriteOrReadResp> for RecvContext {
             len_in_bytes: event.len,
             start_psn: event.psn,
             recv_map: None,
+            imm: None,
         }
     }
 }
@@ -725,6 +943,21 @@ pub(crate) enum PacketCheckEvent {
     Write(ToHostWorkRbDescWriteOrReadResp),
     Ack(ToHostWorkRbDescAck),
     ReadReq(ToHostWorkRbDescRead),
+    Send(ToHostWorkRbDescSend),
+    WriteWithImm(ToHostWorkRbDescWriteWithImm),
+}
+
+impl PacketCheckEvent {
+    /// The QP receiving the message, `None` for an acknowledge which only completes the sent requests
+    fn recv_qpn(&self) -> Option<Qpn> {
+        match self {
+            PacketCheckEvent::Write(desc) => Some(desc.common.dqpn),
+            PacketCheckEvent::ReadReq(desc) => Some(desc.common.dqpn),
+            PacketCheckEvent::Send(desc) => Some(desc.common.dqpn),
+            PacketCheckEvent::WriteWithImm(desc) => Some(desc.common.dqpn),
+            PacketCheckEvent::Ack(_) => None,
+        }
+    }
 }
 
 impl From<ToHostWorkRbDescWriteOrReadResp> for PacketCheckEvent {
@@ -745,6 +978,18 @@ impl From<ToHostWorkRbDescRead> for PacketCheckEvent {
     }
 }
 
+impl From<ToHostWorkRbDescSend> for PacketCheckEvent {
+    fn from(desc: ToHostWorkRbDescSend) -> Self {
+        Self::Send(desc)
+    }
+}
+
+impl From<ToHostWorkRbDescWriteWithImm> for PacketCheckEvent {
+    fn from(desc: ToHostWorkRbDescWriteWithImm) -> Self {
+        Self::WriteWithImm(desc)
+    }
+}
+
 impl Default for PacketCheckEvent {
     fn default() -> Self {
         Self::Write(ToHostWorkRbDescWriteOrReadResp::default())
@@ -956,4 +1201,35 @@ mod tests {
         let r = get_continous_range(Psn::new(10), Psn::new(9));
         assert_eq!(r, None);
     }
+
+    #[test]
+    fn test_recv_ctx_initial_psn() {
+        let recv_ctx_map = super::RecvContextMap::new();
+        let qpn = Qpn::new(3);
+        let ctx = super::RecvContext {
+            is_read_resp: false,
+            start_addr: 0,
+            len_in_bytes: 0,
+            start_psn: Psn::new(0xff_fffe),
+            recv_map: None,
+            imm: None,
+        };
+        recv_ctx_map.insert_ctx(qpn, Msn::new(0), ctx, Psn::new(0xff_fffe));
+        let mut per_qp_map = recv_ctx_map.get_per_qp_ctx_mut(qpn).unwrap();
+        assert_eq!(per_qp_map.largest_psn_recved(), Psn::new(0xff_fffd));
+        per_qp_map.update_largest_psn_recved(Psn::new(0xff_fffe));
+        assert_eq!(per_qp_map.largest_psn_recved(), Psn::new(0xff_fffe));
+        // wrap around at 2^24
+        per_qp_map.update_largest_psn_recved(Psn::new(1));
+        assert_eq!(per_qp_map.largest_psn_recved(), Psn::new(1));
+        drop(per_qp_map);
+        assert_eq!(
+            get_continous_range(Psn::new(0xff_fffe), Psn::new(2)),
+            Some((Psn::new(0xff_ffff), Psn::new(1)))
+        );
+
+        // a context created without a receive context, e.g. by a write with immediate, is seeded the same way
+        let per_qp_map = recv_ctx_map.get_or_create_per_qp_ctx_mut(Qpn::new(4), Psn::new(0));
+        assert_eq!(per_qp_map.largest_psn_recved(), Psn::new(0xff_ffff));
+    }
 }
@@ -0,0 +1,410 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use rand::RngCore as _;
+
+use crate::device::ToHostWorkRbDescStatus;
+use crate::types::{Imm, Key, Qpn};
+use crate::utils::{clear_eventfd, new_eventfd, notify_eventfd};
+use crate::{Device, Error};
+
+/// Completion Queue
+///
+/// A handle of a completion queue created by `Device::create_cq`.
+/// QPs are bound to completion queues through `Qp::send_cq` and `Qp::recv_cq`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cq {
+    pub(crate) handle: u32,
+}
+
+impl Hash for Cq {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.handle.hash(state);
+    }
+}
+
+impl PartialEq for Cq {
+    fn eq(&self, other: &Self) -> bool {
+        self.handle == other.handle
+    }
+}
+
+impl Eq for Cq {}
+
+/// The status of a work completion
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkCompletionStatus {
+    /// The work request is completed successfully
+    Success,
+    /// The local memory region of the work request is invalid
+    LocalProtectionError,
+    /// The opcode is not supported by the QP
+    LocalQpOperationError,
+    /// The responder rejected the request as invalid, such as a SEND longer than its receive request
+    RemoteInvalidRequestError,
+    /// The remote memory region denied the access, such as an invalid rkey or an unaligned atomic address
+    RemoteAccessError,
+    /// The responder failed to complete a valid request, such as invalidating a key on a SEND with invalidate
+    RemoteOperationError,
+    /// The request is still not acknowledged after the max retry count
+    RetryExceeded,
+    /// The responder is still not ready to receive after the max RNR retry count
+    RnrRetryExceeded,
+    /// The request is flushed since the QP entered the error state
+    WrFlushError,
+    /// Other errors
+    GeneralError,
+}
+
+impl From<ToHostWorkRbDescStatus> for WorkCompletionStatus {
+    fn from(status: ToHostWorkRbDescStatus) -> Self {
+        match status {
+            ToHostWorkRbDescStatus::Normal => Self::Success,
+            ToHostWorkRbDescStatus::InvAccFlag
+            | ToHostWorkRbDescStatus::InvMrKey
+            | ToHostWorkRbDescStatus::InvMrRegion => Self::LocalProtectionError,
+            ToHostWorkRbDescStatus::InvOpcode => Self::LocalQpOperationError,
+            ToHostWorkRbDescStatus::Unknown => Self::GeneralError,
+        }
+    }
+}
+
+/// The opcode of a work completion
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkCompletionOpcode {
+    /// RDMA write
+    RdmaWrite,
+    /// RDMA read
+    RdmaRead,
+    /// SEND, with or without immediate data
+    Send,
+    /// A receive request consumed by a SEND
+    Recv,
+    /// A receive request consumed by a SEND with immediate data
+    RecvWithImm,
+    /// A receive request consumed by a RDMA write with immediate data
+    RecvRdmaWithImm,
+    /// Atomic compare and swap
+    CompareSwap,
+    /// Atomic fetch and add
+    FetchAdd,
+}
+
+/// A completion entry polled from a completion queue
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy)]
+pub struct WorkCompletion {
+    /// The work request id given when posting the request
+    pub wr_id: u64,
+    /// The status of the work request
+    pub status: WorkCompletionStatus,
+    /// The opcode of the work request
+    pub opcode: WorkCompletionOpcode,
+    /// The local QPN of the work request
+    pub qpn: Qpn,
+    /// The number of bytes transferred
+    pub byte_len: u32,
+    /// The immediate data, only valid for `RecvWithImm` and `RecvRdmaWithImm`
+    pub imm: Option<Imm>,
+    /// The QPN of the sender, only valid for receive completions of UD QPs
+    pub src_qpn: Option<Qpn>,
+    /// The memory window key invalidated by the peer, only valid for `Recv`
+    pub invalidated_rkey: Option<Key>,
+}
+
+impl WorkCompletion {
+    pub(crate) fn new(wr_id: u64, opcode: WorkCompletionOpcode, qpn: Qpn, byte_len: u32) -> Self {
+        Self {
+            wr_id,
+            status: WorkCompletionStatus::Success,
+            opcode,
+            qpn,
+            byte_len,
+            imm: None,
+            src_qpn: None,
+            invalidated_rkey: None,
+        }
+    }
+}
+
+/// CQ context
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug)]
+pub(crate) struct CqContext {
+    depth: usize,
+    entries: Mutex<VecDeque<WorkCompletion>>,
+    /// more than `depth` entries were pushed since the last poll, which is reported by the next poll
+    overrun: AtomicBool,
+    /// an eventfd which is readable as long as the queue is not empty
+    channel: Option<OwnedFd>,
+    /// the QPs bound to the queue, which must be destroyed before the queue
+    bound_qps: AtomicUsize,
+}
+
+impl CqContext {
+    pub(crate) fn new(depth: usize, with_channel: bool) -> Result<Self, Error> {
+        let channel = with_channel.then(|| new_eventfd("completion channel")).transpose()?;
+        Ok(Self {
+            depth,
+            entries: Mutex::new(VecDeque::with_capacity(depth)),
+            overrun: AtomicBool::new(false),
+            channel,
+            bound_qps: AtomicUsize::new(0),
+        })
+    }
+
+    /// push a completion entry, and notify the completion channel
+    ///
+    /// The entry is kept even if the queue is full, the overrun is reported by the next poll instead.
+    pub(crate) fn push(&self, wc: WorkCompletion) {
+        let mut entries = self.entries.lock();
+        if entries.len() >= self.depth {
+            log::error!("CQ overrun at completion {:?}", wc);
+            self.overrun.store(true, Ordering::Relaxed);
+        }
+        entries.push_back(wc);
+        if let Some(channel) = &self.channel {
+            notify_eventfd(channel);
+        }
+    }
+
+    /// pop at most `max_entries` completion entries, or `Error::CqOverrun` once after an overrun
+    pub(crate) fn poll(&self, max_entries: usize) -> Result<Vec<WorkCompletion>, Error> {
+        let mut entries = self.entries.lock();
+        if self.overrun.swap(false, Ordering::Relaxed) {
+            return Err(Error::CqOverrun);
+        }
+        let cnt = max_entries.min(entries.len());
+        let polled: Vec<WorkCompletion> = entries.drain(..cnt).collect();
+        if entries.is_empty() {
+            if let Some(channel) = &self.channel {
+                clear_eventfd(channel);
+            }
+        }
+        Ok(polled)
+    }
+
+    fn channel_fd(&self) -> Option<RawFd> {
+        self.channel.as_ref().map(AsRawFd::as_raw_fd)
+    }
+}
+
+pub(crate) type CqTable = Mutex<HashMap<Cq, Arc<CqContext>>>;
+
+/// A CQ bound to a QP, which is unbound once dropped with the QP
+#[derive(Debug)]
+pub(crate) struct BoundCq(Arc<CqContext>);
+
+impl Deref for BoundCq {
+    type Target = Arc<CqContext>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for BoundCq {
+    fn drop(&mut self) {
+        let _: usize = self.0.bound_qps.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Device {
+    /// create a completion queue
+    ///
+    /// `depth` is the max number of completion entries the queue should hold. Completions are never dropped,
+    /// but once the queue holds more than `depth` entries, the next `poll_cq` reports `Error::CqOverrun`.
+    /// If `with_channel` is set, the queue has a completion channel, see `Device::cq_channel_fd`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * `depth` is zero
+    /// * failed to create the completion channel
+    pub fn create_cq(&self, depth: usize, with_channel: bool) -> Result<Cq, Error> {
+        self.check_running()?;
+        if depth == 0 {
+            return Err(Error::Invalid("CQ depth: 0".to_owned()));
+        }
+        let ctx = CqContext::new(depth, with_channel)?;
+
+        let mut pool = self.0.cq_table.lock();
+        // the handle is drawn again on collision, the table lock keeps it unused until inserted
+        let cq = loop {
+            let cq = Cq {
+                handle: rand::thread_rng().next_u32(),
+            };
+            if !pool.contains_key(&cq) {
+                break cq;
+            }
+        };
+        let _: Option<Arc<CqContext>> = pool.insert(cq, Arc::new(ctx));
+        Ok(cq)
+    }
+
+    /// destroy a completion queue
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Cq
+    /// * the CQ is still bound to a QP
+    pub fn destroy_cq(&self, cq: Cq) -> Result<(), Error> {
+        let mut pool = self.0.cq_table.lock();
+        let ctx = pool.get(&cq).ok_or(Error::Invalid(format!("CQ :{cq:?}")))?;
+        // the completions held by outstanding requests are dropped with the queue, only bound QPs keep it
+        if ctx.bound_qps.load(Ordering::Acquire) != 0 {
+            return Err(Error::Invalid(format!("CQ in use :{cq:?}")));
+        }
+        let _: Option<Arc<CqContext>> = pool.remove(&cq);
+        Ok(())
+    }
+
+    /// poll at most `max_entries` completion entries from a completion queue
+    ///
+    /// This never blocks, an empty `Vec` is returned if there is no completion.
+    /// After an overrun is reported, the entries including the ones beyond the depth are polled as usual.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Cq
+    /// * the queue held more entries than its depth since the last poll, see `create_cq`
+    pub fn poll_cq(&self, cq: Cq, max_entries: usize) -> Result<Vec<WorkCompletion>, Error> {
+        let ctx = self.get_cq_ctx(cq)?;
+        ctx.poll(max_entries)
+    }
+
+    /// get the completion channel of a completion queue
+    ///
+    /// The returned fd can be registered to `epoll`. It is readable as long as the queue is not empty,
+    /// and the readiness is cleared by `Device::poll_cq` once the queue is drained.
+    /// The fd is owned by the device and is closed when the CQ is destroyed.
+    ///
+    /// Returns `None` if the queue is created without a completion channel.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Cq
+    pub fn cq_channel_fd(&self, cq: Cq) -> Result<Option<RawFd>, Error> {
+        let ctx = self.get_cq_ctx(cq)?;
+        Ok(ctx.channel_fd())
+    }
+
+    pub(crate) fn get_cq_ctx(&self, cq: Cq) -> Result<Arc<CqContext>, Error> {
+        self.0
+            .cq_table
+            .lock()
+            .get(&cq)
+            .map(Arc::clone)
+            .ok_or(Error::Invalid(format!("CQ :{cq:?}")))
+    }
+
+    /// bind a QP to a CQ, which can't be destroyed until the returned `BoundCq` is dropped
+    pub(crate) fn bind_cq_ctx(&self, cq: Cq) -> Result<BoundCq, Error> {
+        // bound with the table locked, so that the CQ is not destroyed meanwhile
+        let pool = self.0.cq_table.lock();
+        let ctx = pool.get(&cq).ok_or(Error::Invalid(format!("CQ :{cq:?}")))?;
+        let _: usize = ctx.bound_qps.fetch_add(1, Ordering::AcqRel);
+        Ok(BoundCq(Arc::clone(ctx)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use blue_rdma_device::emulator::LocalNetwork;
+
+    use super::{CqContext, WorkCompletion, WorkCompletionOpcode};
+    use crate::tests::test_fence::network_param;
+    use crate::types::{Error, MemAccessTypeFlag, Pmtu, QpBuilder, QpType, Qpn};
+    use crate::{Device, DeviceConfigBuilder, DeviceType, RetryConfig, RoundRobinStrategy};
+
+    #[test]
+    fn test_cq_poll_and_channel() {
+        let cq = CqContext::new(2, true).unwrap();
+        let fd = cq.channel_fd().unwrap();
+        let is_readable = || {
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            unsafe { libc::poll(&mut pollfd, 1, 0) == 1 }
+        };
+        assert!(!is_readable());
+
+        for wr_id in 0..3 {
+            cq.push(WorkCompletion::new(
+                wr_id,
+                WorkCompletionOpcode::RdmaWrite,
+                Qpn::new(3),
+                0x10,
+            ));
+        }
+        assert!(is_readable());
+
+        // the third entry overruns the depth of 2, which is reported once and kept
+        assert!(matches!(cq.poll(1), Err(Error::CqOverrun)));
+        let polled = cq.poll(1).unwrap();
+        assert_eq!(polled.len(), 1);
+        assert_eq!(polled[0].wr_id, 0);
+        assert!(is_readable());
+        let polled = cq.poll(16).unwrap();
+        assert_eq!(polled.len(), 2);
+        assert_eq!(polled[1].wr_id, 2);
+        assert!(!is_readable());
+        assert!(cq.poll(16).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_destroy_cq_bound_to_qp() {
+        let config = DeviceConfigBuilder::default()
+            .network_config(network_param(2))
+            .device_type(DeviceType::Local(Arc::new(LocalNetwork::default())))
+            .strategy(RoundRobinStrategy::new())
+            .retry_config(RetryConfig::new(
+                false,
+                1,
+                Duration::from_secs(100),
+                Duration::from_millis(10),
+            ))
+            .scheduler_size(1024 * 32)
+            .build()
+            .unwrap();
+        let dev = Device::new(config).unwrap();
+        let pd = dev.alloc_pd().unwrap();
+        let cq = dev.create_cq(4, false).unwrap();
+        let qp = QpBuilder::default()
+            .pd(pd)
+            .qpn(Qpn::new(2))
+            .peer_qpn(Qpn::new(2))
+            .qp_type(QpType::Rc)
+            .rq_acc_flags(MemAccessTypeFlag::IbvAccessLocalWrite)
+            .pmtu(Pmtu::Mtu1024)
+            .dqp_ip(network_param(3).ipaddr)
+            .dqp_mac(network_param(3).macaddr)
+            .send_cq(Some(cq))
+            .recv_cq(Some(cq))
+            .build()
+            .unwrap();
+        dev.create_qp(&qp).unwrap();
+        assert!(matches!(dev.destroy_cq(cq), Err(Error::Invalid(_))));
+
+        // a completion waiting to be pushed holds the queue, but doesn't keep it from being destroyed
+        let _held = dev.get_cq_ctx(cq).unwrap();
+        dev.destroy_qp(qp.qpn).unwrap();
+        dev.destroy_cq(cq).unwrap();
+        dev.shutdown().unwrap();
+    }
+}
@@ -4,9 +4,9 @@ use std::sync::Arc;
 use core_affinity::CoreId;
 use log::{error, info};
 
-use crate::device::{CtrlRbDescOpcode, ToHostCtrlRbDesc, ToHostRb};
+use crate::device::{CtrlRbDescOpcode, DeviceError, ToHostCtrlRbDesc, ToHostRb};
 use crate::op_ctx::CtrlOpCtx;
-use crate::ThreadSafeHashmap;
+use crate::{PageFaultHandler, ThreadSafeHashmap};
 
 #[derive(Debug)]
 pub(crate) struct ControlPoller {
@@ -17,6 +17,7 @@ pub(crate) struct ControlPoller {
 pub(crate) struct ControlPollerContext {
     pub(crate) to_host_ctrl_rb: Arc<dyn ToHostRb<ToHostCtrlRbDesc>>,
     pub(crate) ctrl_op_ctx_map: ThreadSafeHashmap<u32, CtrlOpCtx>,
+    pub(crate) page_fault_handler: Arc<dyn PageFaultHandler>,
 }
 
 unsafe impl Send for ControlPollerContext {}
@@ -47,12 +48,16 @@ impl ControlPollerContext {
         while !stop_flag.load(Ordering::Relaxed) {
             let desc = match ctx.to_host_ctrl_rb.pop() {
                 Ok(desc) => desc,
+                Err(DeviceError::Timeout) => continue,
                 Err(e) => {
                     error!("failed to fetch descriptor from ctrl rb : {:?}", e);
                     return;
                 }
             };
             if matches!(desc.common.opcode, CtrlRbDescOpcode::UpdateErrorPsnRecoverPoint) {
+            } else if let Some(ref page_fault) = desc.page_fault {
+                // the page is pushed without waiting for the response, which is polled by this thread
+                ctx.page_fault_handler.handle_page_fault(page_fault.key, page_fault.va);
             } else {
                 ctx.handle_ctrl_desc_resp(&desc);
             }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 enum CsrIndex {
     BaseAddrLow = 0x0,
     BaseAddrHigh = 0x1,
@@ -32,6 +34,15 @@ pub(super) const CSR_ADDR_META_REPORT_QUEUE_TAIL: usize = generate_csr_addr(fals
 pub(super) const CSR_ADDR_META_REPORT_QUEUE_ADDR_LOW: usize = generate_csr_addr(false, 1, CsrIndex::BaseAddrLow);
 pub(super) const CSR_ADDR_META_REPORT_QUEUE_ADDR_HIGH: usize = generate_csr_addr(false, 1, CsrIndex::BaseAddrHigh);
 
+/// Read only, the number of key indexes of the MR table, which are shared by the MRs and the MWs
+pub(super) const CSR_ADDR_MR_TABLE_SIZE: usize = 0x2_0004;
+/// Read only, the number of entries of the page table
+pub(super) const CSR_ADDR_MR_PGT_LENGTH: usize = 0x2_0008;
+/// The number of the high bits of a key, which index the MR table
+pub(super) const CSR_ADDR_MR_KEY_IDX_BIT_CNT: usize = 0x2_000C;
+
 pub(super) const RINGBUF_DEPTH: usize = 128;
 pub(super) const RINGBUF_ELEM_SIZE: usize = 32;
 pub(super) const RINGBUF_PAGE_SIZE: usize = 4096;
+/// How long a to-host ring buffer waits for a descriptor before returning `DeviceError::Timeout`
+pub(super) const RINGBUF_POLL_TIMEOUT: Duration = Duration::from_millis(100);
@@ -15,7 +15,7 @@ use super::{
     ToHostWorkRbDesc, ToHostWorkRbDescError,
 };
 use crate::utils::Buffer;
-use crate::SchedulerStrategy;
+use crate::{MrConfig, SchedulerStrategy};
 
 mod rpc_cli;
 
@@ -174,6 +174,27 @@ impl<Strat: SchedulerStrategy> EmulatedDevice<Strat> {
 
         Ok(dev)
     }
+
+    /// Check `mr_config` against the MR table and the page table of the emulator, and set the key layout
+    pub(crate) fn apply_mr_config(&self, mr_config: &MrConfig) -> Result<(), DeviceError> {
+        let mr_table_size = self.rpc_cli.read_csr(constants::CSR_ADDR_MR_TABLE_SIZE)?;
+        let pgt_length = self.rpc_cli.read_csr(constants::CSR_ADDR_MR_PGT_LENGTH)?;
+        if mr_config.key_idx_cnt() > mr_table_size || mr_config.pgt_length > pgt_length {
+            return Err(DeviceError::Device(format!(
+                "{mr_config:?} exceeds the MR table of {mr_table_size} entries and the page table of {pgt_length} entries"
+            )));
+        }
+        self.rpc_cli
+            .write_csr(constants::CSR_ADDR_MR_KEY_IDX_BIT_CNT, mr_config.key_idx_bit_cnt)?;
+        let key_idx_bit_cnt = self.rpc_cli.read_csr(constants::CSR_ADDR_MR_KEY_IDX_BIT_CNT)?;
+        if key_idx_bit_cnt != mr_config.key_idx_bit_cnt {
+            return Err(DeviceError::Device(format!(
+                "emulator rejected {} key index bits, it uses {key_idx_bit_cnt}",
+                mr_config.key_idx_bit_cnt
+            )));
+        }
+        Ok(())
+    }
 }
 
 impl<Strat: SchedulerStrategy> DeviceAdaptor for Arc<EmulatedDevice<Strat>> {
@@ -236,7 +257,7 @@ impl<Strat: SchedulerStrategy> ToHostRb<ToHostCtrlRbDesc> for EmulatedDevice<Str
     fn pop(&self) -> Result<ToHostCtrlRbDesc, DeviceError> {
         let mut guard = self.to_host_ctrl_rb.lock();
         let mut reader = guard.read();
-        let mem = reader.next()?;
+        let mem = reader.next_timeout(Some(constants::RINGBUF_POLL_TIMEOUT))?;
         let desc = ToHostCtrlRbDesc::read(mem)?;
         debug!("{:?}", &desc);
         Ok(desc)
@@ -270,7 +291,7 @@ impl<Strat: SchedulerStrategy> ToHostRb<ToHostWorkRbDesc> for EmulatedDevice<Str
         let mut guard = self.to_host_work_rb.lock();
         let mut reader = guard.read();
 
-        let mem = reader.next()?;
+        let mem = reader.next_timeout(Some(constants::RINGBUF_POLL_TIMEOUT))?;
         let mut read_res = ToHostWorkRbDesc::read(mem);
 
         loop {
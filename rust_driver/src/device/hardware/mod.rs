@@ -226,7 +226,7 @@ impl ToHostRb<ToHostCtrlRbDesc> for Mutex<ToHostCtrlRb> {
     fn pop(&self) -> Result<ToHostCtrlRbDesc, DeviceError> {
         let mut guard = self.lock();
         let mut reader = guard.read();
-        let mem = reader.next()?;
+        let mem = reader.next_timeout(Some(constants::RINGBUF_POLL_TIMEOUT))?;
         let desc = ToHostCtrlRbDesc::read(mem)?;
         debug!("{:?}", &desc);
         Ok(desc)
@@ -238,7 +238,7 @@ impl ToHostRb<ToHostWorkRbDesc> for Mutex<ToHostWorkRb> {
         let mut guard = self.lock();
         let mut reader = guard.read();
 
-        let mem = reader.next()?;
+        let mem = reader.next_timeout(Some(constants::RINGBUF_POLL_TIMEOUT))?;
         let mut read_res = ToHostWorkRbDesc::read(mem);
 
         loop {
@@ -26,7 +26,22 @@ bitfield! {
     pub get_pd_handler, set_pd_handler: 223, 192;  // 32bits
     pub get_acc_flags, set_acc_flags: 231, 224;    // 8bits
     pub get_pgt_offset, set_pgt_offset: 248, 232;  // 17bits
-    _reserved0, _: 255, 249;                   // 7bits
+    pub get_pg_size_log, set_pg_size_log: 253, 249; // 5bits
+    _reserved0, _: 255, 254;                   // 2bits
+}
+
+bitfield! {
+    pub struct CmdQueueReqDescUpdateMwTable([u8]);
+    u64;
+    _cmd_queue_desc_common_head,_: 63, 0;      // 64bits
+    pub get_mw_base_va, set_mw_base_va: 127, 64;   // 64bits
+    pub get_mw_length, set_mw_length: 159, 128;    // 32bits
+    pub get_mw_key, set_mw_key: 191, 160;          // 32bits
+    pub get_mr_key, set_mr_key: 223, 192;          // 32bits
+    pub get_acc_flags, set_acc_flags: 231, 224;    // 8bits
+    pub get_is_valid, set_is_valid: 232;           // 1bit
+    pub get_is_type2, set_is_type2: 233;           // 1bit
+    _reserved0, _: 255, 234;                   // 22bits
 }
 
 bitfield! {
@@ -45,16 +60,19 @@ bitfield! {
     _cmd_queue_desc_common_head,_: 63, 0;                                       // 64bits
     pub get_is_valid, set_is_valid: 64;                                             // 1bit
     pub get_is_error, set_is_error: 65;                                             // 1bit
-    _reserverd4, _: 71, 66;                                                     // 6bits
+    pub get_has_srq, set_has_srq: 66;                                               // 1bit
+    _reserverd4, _: 71, 67;                                                     // 5bits
     pub get_qpn, set_qpn: 95, 72;                                                   // 24bits
     pub get_pd_handler, set_pd_handler: 127, 96;                                    // 32bits
     pub get_qp_type, set_qp_type: 131, 128;                                         // 4bits
     _reserverd3, _: 135, 132;                                                   // 4bits
     pub get_rq_access_flags, set_rq_access_flags: 143, 136;                         // 8bits
     pub get_pmtu, set_pmtu: 146, 144;                                               // 3bits
-    _reserverd2, _: 151, 147;                                                   // 5bits
+    pub get_min_rnr_timer, set_min_rnr_timer: 151, 147;                             // 5bits
     pub get_peer_qpn, set_peer_qpn: 175, 152;                                      // 24bits
-    _reserverd1, _: 255, 176;                                                   // 80bits
+    pub get_qkey, set_qkey: 207, 176;                                               // 32bits
+    pub get_rq_psn, set_rq_psn: 231, 208;                                           // 24bits
+    pub get_srqn, set_srqn: 255, 232;                                               // 24bits
 }
 
 bitfield! {
@@ -96,6 +114,63 @@ bitfield! {
     _reserverd2, _:                             255, 120;  // 64bits
 }
 
+// typedef struct {
+//     Bit#(32)                        tag;            // 32  bits
+//     ReservedZero#(7)                reserved1;      // 7   bits
+//     Bool                            isSrq;          // 1   bit
+//     QPN                             qpn;            // 24  bits
+//     LKEY                            lkey;           // 32  bits
+//     Length                          len;            // 32  bits
+//     ADDR                            laddr;          // 64  bits
+//     CmdQueueDescCommonHead          commonHeader;   // 64  bits
+// } CmdQueueReqDescPostRecv deriving(Bits, FShow);
+bitfield! {
+    pub struct CmdQueueReqDescPostRecv([u8]);
+    u64;
+    _cmd_queue_desc_common_head,_:              63 ,   0;  // 64bits
+    pub get_laddr, set_laddr:                   127,  64;  // 64bits
+    pub get_len, set_len:                       159, 128;  // 32bits
+    pub get_lkey, set_lkey:                     191, 160;  // 32bits
+    pub get_qpn, set_qpn:                       215, 192;  // 24bits
+    pub get_is_srq, set_is_srq:                 216;       // 1bit
+    _reserverd1, _:                             223, 217;  // 7bits
+    pub get_tag, set_tag:                       255, 224;  // 32bits
+}
+
+// typedef struct {
+//     ReservedZero#(128)              reserved1;      // 128 bits
+//     HandlerPD                       pdHandler;      // 32  bits
+//     SRQN                            srqn;           // 24  bits
+//     ReservedZero#(7)                reserved2;      // 7   bits
+//     Bool                            isValid;        // 1   bit
+//     CmdQueueDescCommonHead          commonHeader;   // 64  bits
+// } CmdQueueReqDescSrqManagement deriving(Bits, FShow);
+bitfield! {
+    pub struct CmdQueueReqDescSrqManagement([u8]);
+    u64;
+    _cmd_queue_desc_common_head,_:              63 ,   0;  // 64bits
+    pub get_is_valid, set_is_valid:             64;        // 1bit
+    _reserverd2, _:                             71 ,  65;  // 7bits
+    pub get_srqn, set_srqn:                     95 ,  72;  // 24bits
+    pub get_pd_handler, set_pd_handler:         127,  96;  // 32bits
+    _reserverd1, _:                             255, 128;  // 128bits
+}
+
+// typedef struct {
+//     ReservedZero#(96)               reserved1;      // 96  bits
+//     RKEY                            mrKey;          // 32  bits
+//     ADDR                            va;             // 64  bits
+//     CmdQueueDescCommonHead          commonHeader;   // 64  bits
+// } CmdQueueRespDescPageFault deriving(Bits, FShow);
+bitfield! {
+    pub struct CmdQueueRespDescPageFault([u8]);
+    u64;
+    _cmd_queue_desc_common_head,_:              63 ,   0;  // 64bits
+    pub get_va, set_va:                         127,  64;  // 64bits
+    pub get_mr_key, set_mr_key:                 159, 128;  // 32bits
+    _reserverd1, _:                             255, 160;  // 96bits
+}
+
 bitfield! {
     pub struct SendQueueDescCommonHead([u8]);
     u32;
@@ -138,7 +213,9 @@ bitfield! {
     pub get_dqpn, set_dqpn: 151, 128;         // 24bits
     _reserved2 , _: 159, 152;             // 8bits
     pub get_imm, set_imm: 191, 160;           // 32bits
-    _reserved1 , _: 255, 192;             // 64bits
+    pub get_qkey, set_qkey: 223, 192;         // 32bits
+    pub get_sqpn, set_sqpn: 247, 224;         // 24bits
+    _reserved1 , _: 255, 248;             // 8bits
 }
 
 bitfield! {
@@ -149,6 +226,22 @@ bitfield! {
     pub get_laddr, set_laddr: 127, 64; // 64bits
 }
 
+// typedef struct {
+//     Bit#(64)                data3;      // 64 bits
+//     Bit#(64)                data2;      // 64 bits
+//     Bit#(64)                data1;      // 64 bits
+//     Bit#(64)                data0;      // 64 bits
+// } SendQueueReqDescInlineData deriving(Bits, FShow);
+// The data takes the place of the SGEs when `IbvSendInline` is set in the flags of seg1, in byte order.
+bitfield! {
+    pub struct SendQueueReqDescInlineData([u8]);
+    u64;
+    pub get_data0, set_data0: 63, 0;      // 64bits
+    pub get_data1, set_data1: 127, 64;    // 64bits
+    pub get_data2, set_data2: 191, 128;   // 64bits
+    pub get_data3, set_data3: 255, 192;   // 64bits
+}
+
 bitfield! {
     pub struct MetaReportQueueDescFragRETH([u8]);
     no default BitRange;
@@ -189,7 +282,8 @@ impl<T: AsMut<[u8]>> BitRangeMut<u64> for MetaReportQueueDescFragRETH<T> {
 bitfield! {
     pub struct MetaReportQueueDescFragImmDT([u8]);
     u32;
-    pub get_imm, set_imm: 32, 0;          // 32bits
+    pub get_imm, set_imm: 31, 0;              // 32bits
+    pub get_recv_tag, set_recv_tag: 63, 32;   // 32bits
 }
 
 bitfield! {
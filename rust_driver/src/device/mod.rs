@@ -4,6 +4,8 @@ use std::sync::Arc;
 
 use thiserror::Error;
 
+use crate::types::WorkReqSendFlag;
+
 mod constants;
 mod emulated;
 mod hardware;
@@ -34,6 +36,21 @@ pub(crate) trait DeviceAdaptor: Send + Sync {
     fn get_phys_addr(&self, virt_addr: usize) -> Result<usize, DeviceError>;
 
     fn use_hugepage(&self) -> bool;
+
+    /// Check that the adaptor is able to execute a work request, before any resource is allocated for it.
+    ///
+    /// Returns the name of the unsupported feature otherwise.
+    fn check_work_req(
+        &self,
+        _opcode: &ToCardWorkRbDescOpcode,
+        _flags: WorkReqSendFlag,
+        _sge_cnt: usize,
+    ) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    /// Stop the threads owned by the adaptor, the adaptor must not be used after that.
+    fn shutdown(&self) {}
 }
 
 /// Generic interface for a to-card ring buffer.
@@ -43,6 +60,8 @@ pub(crate) trait ToCardRb<D> {
 
 /// Generic interface for a to-host ring buffer.
 pub(crate) trait ToHostRb<D> {
+    /// Returns `DeviceError::Timeout` if there is no descriptor for a while,
+    /// so that the polling thread has a chance to stop.
     fn pop(&self) -> Result<D, DeviceError>;
 }
 
@@ -13,10 +13,12 @@ use parking_lot::Mutex;
 use super::ringbuf::{CsrWriterAdaptor, Ringbuf};
 use super::software::BlueRDMALogic;
 use super::{DescSge, DeviceError, ToCardRb, ToCardWorkRbDesc, ToCardWorkRbDescCommon};
+use crate::op_ctx::CtxStatus;
 use crate::types::{Msn, Pmtu, Psn, Qpn};
 use crate::utils::{calculate_packet_cnt, get_first_packet_max_length, Buffer};
 
-const MAX_SGL_LENGTH: usize = 1;
+/// The max number of sges in a work request
+pub(crate) const MAX_SGL_LENGTH: usize = 4;
 
 pub(crate) mod round_robin;
 pub(crate) mod testing;
@@ -130,25 +132,42 @@ pub trait SchedulerStrategy: Send + Sync + Clone + 'static {
         I: Iterator<Item = SealedDesc>;
 
     /// Pop a batch of descriptors from the scheduler.
+    ///
+    /// A descriptor is not popped while `SealedDesc::is_fenced`, nor are the descriptors behind it.
     fn pop_batch(&self) -> Result<(BatchDescs, u32), Box<dyn Error>>;
 }
 
-struct SGList {
+pub(crate) struct SGList {
     pub(crate) data: [DescSge; MAX_SGL_LENGTH],
     pub(crate) cur_level: u32,
     pub(crate) len: u32,
 }
 
 impl SGList {
-    pub(crate) fn new_from_sge(sge: DescSge) -> Self {
-        let mut sge_list = Self {
-            data: [DescSge::default(); MAX_SGL_LENGTH],
-            cur_level: 0,
-            len: 1,
-        };
-        sge_list.data[0] = sge;
+    pub(crate) fn new_from_sges(
+        sge0: DescSge,
+        sge1: Option<DescSge>,
+        sge2: Option<DescSge>,
+        sge3: Option<DescSge>,
+    ) -> Self {
+        let mut sge_list = Self::default();
+        for sge in [Some(sge0), sge1, sge2, sge3].into_iter().flatten() {
+            // at most `MAX_SGL_LENGTH` sges, so the index is always valid
+            #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
+            {
+                sge_list.data[sge_list.len as usize] = sge;
+                sge_list.len += 1;
+            }
+        }
         sge_list
     }
+
+    /// Convert into the `sge0..sge3` fields of a descriptor
+    #[allow(clippy::indexing_slicing)] // `data` always has `MAX_SGL_LENGTH` elements
+    pub(crate) fn into_sges(self) -> (DescSge, Option<DescSge>, Option<DescSge>, Option<DescSge>) {
+        let get = |level: u32| (level < self.len).then_some(self.data[level as usize]);
+        (self.data[0], get(1), get(2), get(3))
+    }
 }
 
 impl Default for SGList {
@@ -308,17 +327,37 @@ impl SealedDesc {
     pub fn get_dqpn(&self) -> Qpn {
         match &*self.0 {
             ToCardWorkRbDesc::Read(desc) => desc.common.dqpn,
-            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) => desc.common.dqpn,
-            ToCardWorkRbDesc::WriteWithImm(desc) => desc.common.dqpn,
+            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) | ToCardWorkRbDesc::Send(desc) => {
+                desc.common.dqpn
+            }
+            ToCardWorkRbDesc::WriteWithImm(desc)
+            | ToCardWorkRbDesc::SendWithImm(desc)
+            | ToCardWorkRbDesc::SendWithInv(desc) => desc.common.dqpn,
+            ToCardWorkRbDesc::CompareSwap(desc) | ToCardWorkRbDesc::FetchAdd(desc) => desc.common.dqpn,
+            ToCardWorkRbDesc::Inline(desc) => desc.common.dqpn,
         }
     }
 
+    /// Returns `true` if the descriptor is held by a fence, i.e. some prior reads or atomics of the QP are running
+    pub fn is_fenced(&self) -> bool {
+        get_to_card_desc_common(&self.0)
+            .fence
+            .iter()
+            .any(|ctx| matches!(ctx.status(), CtxStatus::Running))
+    }
+
     /// Get the PSN of the descriptor
     pub fn get_psn(&self) -> Psn {
         match &*self.0 {
             ToCardWorkRbDesc::Read(desc) => desc.common.psn,
-            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) => desc.common.psn,
-            ToCardWorkRbDesc::WriteWithImm(desc) => desc.common.psn,
+            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) | ToCardWorkRbDesc::Send(desc) => {
+                desc.common.psn
+            }
+            ToCardWorkRbDesc::WriteWithImm(desc)
+            | ToCardWorkRbDesc::SendWithImm(desc)
+            | ToCardWorkRbDesc::SendWithInv(desc) => desc.common.psn,
+            ToCardWorkRbDesc::CompareSwap(desc) | ToCardWorkRbDesc::FetchAdd(desc) => desc.common.psn,
+            ToCardWorkRbDesc::Inline(desc) => desc.common.psn,
         }
     }
 }
@@ -341,8 +380,12 @@ fn get_first_schedule_segment_length(va: u64, scheduler_size: u32) -> u32 {
 fn get_to_card_desc_common(desc: &ToCardWorkRbDesc) -> &ToCardWorkRbDescCommon {
     match desc {
         ToCardWorkRbDesc::Read(req) => &req.common,
-        ToCardWorkRbDesc::Write(req) | ToCardWorkRbDesc::ReadResp(req) => &req.common,
-        ToCardWorkRbDesc::WriteWithImm(req) => &req.common,
+        ToCardWorkRbDesc::Write(req) | ToCardWorkRbDesc::ReadResp(req) | ToCardWorkRbDesc::Send(req) => &req.common,
+        ToCardWorkRbDesc::WriteWithImm(req)
+        | ToCardWorkRbDesc::SendWithImm(req)
+        | ToCardWorkRbDesc::SendWithInv(req) => &req.common,
+        ToCardWorkRbDesc::CompareSwap(req) | ToCardWorkRbDesc::FetchAdd(req) => &req.common,
+        ToCardWorkRbDesc::Inline(req) => &req.common,
     }
 }
 
@@ -350,7 +393,7 @@ fn get_to_card_desc_common(desc: &ToCardWorkRbDesc) -> &ToCardWorkRbDescCommon {
 // * `new_sgl_level` will always smaller than `origin_sgl` sgl level, which is less than `MAX_SGL_LENGTH`
 // * `current_level` won't be greater than `origin_sgl.len`, which is less than `MAX_SGL_LENGTH`
 #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
-fn cut_from_sgl(mut length: u32, origin_sgl: &mut SGList) -> SGList {
+pub(crate) fn cut_from_sgl(mut length: u32, origin_sgl: &mut SGList) -> SGList {
     let mut current_level = origin_sgl.cur_level as usize;
     let mut new_sgl = SGList::default();
     let mut new_sgl_level: usize = 0;
@@ -391,33 +434,63 @@ fn cut_from_sgl(mut length: u32, origin_sgl: &mut SGList) -> SGList {
 fn get_total_len(desc: &ToCardWorkRbDesc) -> u32 {
     match desc {
         ToCardWorkRbDesc::Read(req) => req.common.total_len,
-        ToCardWorkRbDesc::Write(req) | ToCardWorkRbDesc::ReadResp(req) => req.common.total_len,
-        ToCardWorkRbDesc::WriteWithImm(req) => req.common.total_len,
+        ToCardWorkRbDesc::Write(req) | ToCardWorkRbDesc::ReadResp(req) | ToCardWorkRbDesc::Send(req) => {
+            req.common.total_len
+        }
+        ToCardWorkRbDesc::WriteWithImm(req)
+        | ToCardWorkRbDesc::SendWithImm(req)
+        | ToCardWorkRbDesc::SendWithInv(req) => req.common.total_len,
+        ToCardWorkRbDesc::CompareSwap(req) | ToCardWorkRbDesc::FetchAdd(req) => req.common.total_len,
+        ToCardWorkRbDesc::Inline(req) => req.common.total_len,
     }
 }
 
 /// Split the descriptor into multiple descriptors if it is greater than the `scheduler_size` size.
 #[allow(clippy::linkedlist)]
 pub(crate) fn split_descriptor(desc: Box<ToCardWorkRbDesc>, scheduler_size: u32) -> LinkedList<SealedDesc> {
-    let is_read = matches!(*desc, ToCardWorkRbDesc::Read(_));
+    // A SEND message consumes exactly one receive buffer, so it can not be split into multiple messages.
+    // An atomic operation is always a single packet.
+    // An inline request carries its data in the descriptor, which can not be cut like a SGL.
+    let is_unsplittable = matches!(
+        *desc,
+        ToCardWorkRbDesc::Read(_)
+            | ToCardWorkRbDesc::Send(_)
+            | ToCardWorkRbDesc::SendWithImm(_)
+            | ToCardWorkRbDesc::SendWithInv(_)
+            | ToCardWorkRbDesc::CompareSwap(_)
+            | ToCardWorkRbDesc::FetchAdd(_)
+            | ToCardWorkRbDesc::Inline(_)
+    );
     let total_len = get_total_len(&desc);
     #[allow(clippy::cast_possible_truncation)]
-    if is_read || total_len < scheduler_size {
+    if is_unsplittable || total_len < scheduler_size {
         let mut list = LinkedList::new();
         list.push_back(SealedDesc(desc));
         return list;
     }
 
-    let (raddr, pmtu, psn, sge) = match &*desc {
-        ToCardWorkRbDesc::Read(_) => unreachable!(),
-        ToCardWorkRbDesc::Write(req) | ToCardWorkRbDesc::ReadResp(req) => {
-            (req.common.raddr, req.common.pmtu, req.common.psn, req.sge0)
-        }
-        ToCardWorkRbDesc::WriteWithImm(req) => (req.common.raddr, req.common.pmtu, req.common.psn, req.sge0),
+    let (raddr, pmtu, psn, mut sg_list) = match &*desc {
+        ToCardWorkRbDesc::Read(_)
+        | ToCardWorkRbDesc::Send(_)
+        | ToCardWorkRbDesc::SendWithImm(_)
+        | ToCardWorkRbDesc::SendWithInv(_)
+        | ToCardWorkRbDesc::CompareSwap(_)
+        | ToCardWorkRbDesc::FetchAdd(_)
+        | ToCardWorkRbDesc::Inline(_) => unreachable!(),
+        ToCardWorkRbDesc::Write(req) | ToCardWorkRbDesc::ReadResp(req) => (
+            req.common.raddr,
+            req.common.pmtu,
+            req.common.psn,
+            SGList::new_from_sges(req.sge0, req.sge1, req.sge2, req.sge3),
+        ),
+        ToCardWorkRbDesc::WriteWithImm(req) => (
+            req.common.raddr,
+            req.common.pmtu,
+            req.common.psn,
+            SGList::new_from_sges(req.sge0, req.sge1, req.sge2, req.sge3),
+        ),
     };
 
-    let mut sg_list = SGList::new_from_sge(sge);
-
     let mut descs = LinkedList::new();
     let mut this_length = get_first_schedule_segment_length(raddr, scheduler_size);
     let mut remain_data_length = total_len;
@@ -425,11 +498,17 @@ pub(crate) fn split_descriptor(desc: Box<ToCardWorkRbDesc>, scheduler_size: u32)
     let mut base_psn = psn;
     while remain_data_length > 0 {
         let mut new_desc = desc.clone();
-        let new_sge = cut_from_sgl(this_length, &mut sg_list).data[0];
+        let (sge0, sge1, sge2, sge3) = cut_from_sgl(this_length, &mut sg_list).into_sges();
         match &mut *new_desc {
-            ToCardWorkRbDesc::Read(_) => unreachable!(),
+            ToCardWorkRbDesc::Read(_)
+            | ToCardWorkRbDesc::Send(_)
+            | ToCardWorkRbDesc::SendWithImm(_)
+            | ToCardWorkRbDesc::SendWithInv(_)
+            | ToCardWorkRbDesc::CompareSwap(_)
+            | ToCardWorkRbDesc::FetchAdd(_)
+            | ToCardWorkRbDesc::Inline(_) => unreachable!(),
             ToCardWorkRbDesc::Write(ref mut req) | ToCardWorkRbDesc::ReadResp(ref mut req) => {
-                req.sge0 = new_sge;
+                (req.sge0, req.sge1, req.sge2, req.sge3) = (sge0, sge1, sge2, sge3);
                 req.common.total_len = this_length;
                 req.common.raddr = current_va;
                 req.common.psn = base_psn;
@@ -437,7 +516,7 @@ pub(crate) fn split_descriptor(desc: Box<ToCardWorkRbDesc>, scheduler_size: u32)
                 req.is_last = false;
             }
             ToCardWorkRbDesc::WriteWithImm(ref mut req) => {
-                req.sge0 = new_sge;
+                (req.sge0, req.sge1, req.sge2, req.sge3) = (sge0, sge1, sge2, sge3);
                 req.common.total_len = this_length;
                 req.common.raddr = current_va;
                 req.common.psn = base_psn;
@@ -458,7 +537,13 @@ pub(crate) fn split_descriptor(desc: Box<ToCardWorkRbDesc>, scheduler_size: u32)
     // The above code guarantee there at least 2 descriptors in the list
     if let Some(req) = descs.front_mut() {
         match &mut *req.0 {
-            ToCardWorkRbDesc::Read(_) => unreachable!(),
+            ToCardWorkRbDesc::Read(_)
+            | ToCardWorkRbDesc::Send(_)
+            | ToCardWorkRbDesc::SendWithImm(_)
+            | ToCardWorkRbDesc::SendWithInv(_)
+            | ToCardWorkRbDesc::CompareSwap(_)
+            | ToCardWorkRbDesc::FetchAdd(_)
+            | ToCardWorkRbDesc::Inline(_) => unreachable!(),
             ToCardWorkRbDesc::Write(ref mut req) | ToCardWorkRbDesc::ReadResp(ref mut req) => {
                 req.is_first = true;
                 req.common.total_len = total_len;
@@ -472,7 +557,13 @@ pub(crate) fn split_descriptor(desc: Box<ToCardWorkRbDesc>, scheduler_size: u32)
 
     if let Some(req) = descs.back_mut() {
         match &mut *req.0 {
-            ToCardWorkRbDesc::Read(_) => unreachable!(),
+            ToCardWorkRbDesc::Read(_)
+            | ToCardWorkRbDesc::Send(_)
+            | ToCardWorkRbDesc::SendWithImm(_)
+            | ToCardWorkRbDesc::SendWithInv(_)
+            | ToCardWorkRbDesc::CompareSwap(_)
+            | ToCardWorkRbDesc::FetchAdd(_)
+            | ToCardWorkRbDesc::Inline(_) => unreachable!(),
             ToCardWorkRbDesc::Write(ref mut req) | ToCardWorkRbDesc::ReadResp(ref mut req) => {
                 req.is_last = true;
             }
@@ -515,12 +606,13 @@ mod test {
 
     use parking_lot::lock_api::Mutex;
 
-    use super::{SGList, MAX_SGL_LENGTH};
+    use super::SGList;
     use crate::device::ringbuf::{CsrWriterAdaptor, Ringbuf};
     use crate::device::{
-        DescSge, DeviceError, ToCardRb, ToCardWorkRbDesc, ToCardWorkRbDescCommon, ToCardWorkRbDescWrite,
-        ToCardWorkRbDescWriteWithImm,
+        DescSge, DeviceError, ToCardRb, ToCardWorkRbDesc, ToCardWorkRbDescCommon, ToCardWorkRbDescInline,
+        ToCardWorkRbDescOpcode, ToCardWorkRbDescRead, ToCardWorkRbDescWrite, ToCardWorkRbDescWriteWithImm,
     };
+    use crate::op_ctx::OpCtx;
     use crate::types::{Key, Msn, Qpn, WorkReqSendFlag};
     use crate::utils::Buffer;
     use crate::SealedDesc;
@@ -545,13 +637,6 @@ mod test {
                 sg_list.data[sg_list.len as usize] = *sge;
                 sg_list.len += 1;
             }
-            while sg_list.len < MAX_SGL_LENGTH.try_into().unwrap() {
-                sg_list.data[sg_list.len as usize] = DescSge {
-                    addr: 0,
-                    len: 0,
-                    key: Key::default(),
-                };
-            }
             sg_list
         }
     }
@@ -577,6 +662,26 @@ mod test {
         assert_eq!(sgl.data[0].addr, 512);
     }
 
+    #[test]
+    fn test_cut_from_multi_sge_sgl() {
+        let mut sgl = SGListBuilder::new()
+            .with_sge(0, 1024, Key::default())
+            .with_sge(0x10000, 512, Key::new(1))
+            .with_sge(0x20000, 1024, Key::new(2))
+            .build();
+        let (sge0, sge1, sge2, sge3) = super::cut_from_sgl(1536 + 256, &mut sgl).into_sges();
+        assert_eq!((sge0.addr, sge0.len), (0, 1024));
+        let sge1 = sge1.unwrap();
+        assert_eq!((sge1.addr, sge1.len, sge1.key), (0x10000, 512, Key::new(1)));
+        let sge2 = sge2.unwrap();
+        assert_eq!((sge2.addr, sge2.len, sge2.key), (0x20000, 256, Key::new(2)));
+        assert!(sge3.is_none());
+
+        let (sge0, sge1, _, _) = super::cut_from_sgl(768, &mut sgl).into_sges();
+        assert_eq!((sge0.addr, sge0.len, sge0.key), (0x20100, 768, Key::new(2)));
+        assert!(sge1.is_none());
+    }
+
     #[allow(dead_code)]
     fn convert_list_to_vec<T>(list: LinkedList<T>) -> Vec<T> {
         let mut vec = Vec::new();
@@ -654,6 +759,85 @@ mod test {
         assert_eq!(head, 9 + 3); // 1 descriptor, which has 3 segments
     }
 
+    #[test]
+    fn test_fence() {
+        let strategy = super::round_robin::RoundRobinStrategy::new();
+        let buffer = Buffer::new(4096, false).unwrap();
+        let proxy = Proxy::default();
+        let ringbuf = Mutex::new(Ringbuf::<Proxy, Buffer, 128, 32, 4096>::new(proxy.clone(), buffer));
+        let scheduler = Arc::new(super::DescriptorScheduler::new(strategy, ringbuf, None, 1024 * 32));
+        let common = ToCardWorkRbDescCommon {
+            total_len: 1024,
+            dqpn: Qpn::new(2),
+            pmtu: crate::types::Pmtu::Mtu4096,
+            ..Default::default()
+        };
+        let sge0 = DescSge {
+            addr: 0,
+            len: 1024,
+            key: Key::new(3),
+        };
+        // read into the buffer, then write the buffer to the peer
+        let read = OpCtx::new_running();
+        let desc = ToCardWorkRbDesc::Read(ToCardWorkRbDescRead {
+            common: common.clone(),
+            sge0,
+            ..Default::default()
+        });
+        scheduler.push(desc.into()).unwrap();
+        let desc = ToCardWorkRbDesc::Write(ToCardWorkRbDescWrite {
+            common: ToCardWorkRbDescCommon {
+                msn: Msn::new(1),
+                flags: WorkReqSendFlag::IbvSendFence,
+                fence: vec![read.clone()],
+                ..common
+            },
+            sge0,
+            ..Default::default()
+        });
+        scheduler.push(desc.into()).unwrap();
+        sleep(std::time::Duration::from_millis(10));
+        // the write would send the stale buffer if it were not held until the read response is placed
+        assert_eq!(proxy.0.head.load(Ordering::Acquire), 3);
+
+        read.set_result(()).unwrap();
+        sleep(std::time::Duration::from_millis(10));
+        assert_eq!(proxy.0.head.load(Ordering::Acquire), 6);
+    }
+
+    #[test]
+    fn test_inline_data() {
+        let data = (0..40).collect::<Vec<u8>>();
+        let desc = ToCardWorkRbDesc::Inline(ToCardWorkRbDescInline {
+            common: ToCardWorkRbDescCommon {
+                total_len: 40,
+                pmtu: crate::types::Pmtu::Mtu256,
+                flags: WorkReqSendFlag::IbvSendInline,
+                ..Default::default()
+            },
+            opcode: ToCardWorkRbDescOpcode::Write,
+            imm: None,
+            data: data.clone(),
+        });
+        // the data is carried by 2 segments instead of the sges
+        assert_eq!(desc.serialized_desc_cnt(), 4);
+        let mut serialized = [0u8; 64];
+        desc.write_2(&mut serialized[..32]);
+        desc.write_3(&mut serialized[32..]);
+        assert_eq!(&serialized[..40], &data[..]);
+        assert!(serialized[40..].iter().all(|b| *b == 0));
+
+        let strategy = super::round_robin::RoundRobinStrategy::new();
+        let buffer = Buffer::new(4096, false).unwrap();
+        let proxy = Proxy::default();
+        let ringbuf = Mutex::new(Ringbuf::<Proxy, Buffer, 128, 32, 4096>::new(proxy.clone(), buffer));
+        let scheduler = Arc::new(super::DescriptorScheduler::new(strategy, ringbuf, None, 16));
+        // not split even if it is longer than the split size
+        scheduler.push(desc.into()).unwrap();
+        sleep(std::time::Duration::from_millis(10));
+        assert_eq!(proxy.0.head.load(Ordering::Acquire), 4);
+    }
+
     #[test]
     fn test() {
         let va = 128;
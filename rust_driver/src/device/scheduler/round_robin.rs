@@ -64,12 +64,20 @@ impl SchedulerStrategy for RoundRobinStrategy {
         let mut result = [ARRAY_REPEAT_VALUE; POP_BATCH_SIZE];
         let mut counter: u32 = 0;
         let guard = &mut self.0.lock().queue;
+        // the number of QPs skipped in a row, the batch is done once all of them are held by fences
+        let mut fenced_cnt = 0;
 
-        while !guard.is_empty() {
+        while fenced_cnt < guard.len() {
             if let Some((_, list)) = guard.front_mut() {
-                // the front_mut is existed,so the pop_front will not return None
-                result[counter as usize] = Some(list.pop_front().unwrap()); // counter is always less than POP_BATCH_SIZE
-                counter += 1;
+                // the list in the queue is never empty
+                if list.front().unwrap().is_fenced() {
+                    fenced_cnt += 1;
+                } else {
+                    // the front_mut is existed,so the pop_front will not return None
+                    result[counter as usize] = Some(list.pop_front().unwrap()); // counter is always less than POP_BATCH_SIZE
+                    counter += 1;
+                    fenced_cnt = 0;
+                }
             }
 
             // the front_mut is existed,so the pop_front will not return None
@@ -95,6 +103,7 @@ mod tests {
     use crate::device::scheduler::round_robin::RoundRobinStrategy;
     use crate::device::scheduler::SchedulerStrategy;
     use crate::device::{DescSge, ToCardWorkRbDesc, ToCardWorkRbDescCommon, ToCardWorkRbDescWrite};
+    use crate::op_ctx::OpCtx;
     use crate::types::{Key, Msn, Pmtu, Psn, QpType, Qpn, WorkReqSendFlag};
     use crate::SealedDesc;
 
@@ -112,6 +121,9 @@ mod tests {
                 qp_type: QpType::Rc,
                 psn: Psn::new(1234),
                 msn: Msn::new(0),
+                sqpn: Qpn::new(qpn),
+                qkey: 0,
+                fence: Vec::new(),
             },
             is_last: true,
             is_first: true,
@@ -166,4 +178,38 @@ mod tests {
         let result_dqpns = vec![1, 2, 1, 1, 1, 1, 1, 1];
         assert_eq!(descs, result_dqpns);
     }
+
+    #[test]
+    fn test_round_robin_fence() {
+        let round_robin = RoundRobinStrategy::new();
+        let read = OpCtx::new_running();
+        let mut qpn1_descs = generate_random_descriptors(1, 2);
+        let mut fenced = qpn1_descs.pop_front().unwrap().into_desc();
+        if let ToCardWorkRbDesc::Write(ref mut desc) = *fenced {
+            desc.common.flags = WorkReqSendFlag::IbvSendFence;
+            desc.common.fence = vec![read.clone()];
+        }
+        qpn1_descs.push_front(SealedDesc::from(fenced));
+        round_robin.push(Qpn::new(1), qpn1_descs.into_iter()).unwrap();
+        let qpn2_descs = generate_random_descriptors(2, 2).into_iter();
+        round_robin.push(Qpn::new(2), qpn2_descs).unwrap();
+
+        // the fenced write and the write behind it are held, while the other QP goes on
+        let (desc, n) = round_robin.pop_batch().unwrap();
+        assert_eq!(n, 2);
+        let descs = desc
+            .into_iter()
+            .flatten()
+            .map(|s| s.get_dqpn().get())
+            .collect::<Vec<u32>>();
+        assert_eq!(descs, vec![2, 2]);
+        let (_desc, n) = round_robin.pop_batch().unwrap();
+        assert_eq!(n, 0);
+
+        read.set_result(()).unwrap();
+        let (desc, n) = round_robin.pop_batch().unwrap();
+        assert_eq!(n, 2);
+        let descs = desc.into_iter().flatten().collect::<Vec<SealedDesc>>();
+        assert!(descs.iter().all(|s| s.get_dqpn().get() == 1 && !s.is_fenced()));
+    }
 }
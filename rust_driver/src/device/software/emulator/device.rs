@@ -1,4 +1,3 @@
-use core::net::IpAddr;
 use std::sync::Arc;
 
 use blue_rdma_device::device_api::csr::{RegistersQueue, RegistersQueueAddress};
@@ -65,13 +64,11 @@ pub(crate) struct EmulatorDeviceInner<S: SchedulerStrategy> {
 
 impl<S: SchedulerStrategy> EmulatorDevice<S> {
     pub(crate) fn new(
+        dev: Arc<Emulator>,
         strategy: S,
         core_id: Option<CoreId>,
         scheduler_size: u32,
-        tun_ip: IpAddr,
     ) -> Result<Self, DeviceError> {
-        let dev = Emulator::new_emulator(tun_ip);
-
         let buffer = AlignedMemory::new(constants::RINGBUF_PAGE_SIZE)?;
         dev.csrs()
             .cmd_request()
@@ -148,6 +145,10 @@ impl<S: SchedulerStrategy> DeviceAdaptor for EmulatorDevice<S> {
     fn use_hugepage(&self) -> bool {
         false
     }
+
+    fn shutdown(&self) {
+        self.0.dev.shutdown();
+    }
 }
 
 #[allow(clippy::unwrap_used, clippy::unwrap_in_result)]
@@ -168,7 +169,7 @@ impl ToHostRb<ToHostCtrlRbDesc> for Mutex<ToHostCtrlRb> {
     fn pop(&self) -> Result<ToHostCtrlRbDesc, DeviceError> {
         let mut guard = self.lock();
         let mut reader = guard.read();
-        let mem = reader.next()?;
+        let mem = reader.next_timeout(Some(constants::RINGBUF_POLL_TIMEOUT))?;
         let desc = ToHostCtrlRbDesc::read(mem)?;
         log::debug!("{:?}", &desc);
         Ok(desc)
@@ -180,7 +181,7 @@ impl ToHostRb<ToHostWorkRbDesc> for Mutex<ToHostWorkRb> {
         let mut guard = self.lock();
         let mut reader = guard.read();
 
-        let mem = reader.next()?;
+        let mem = reader.next_timeout(Some(constants::RINGBUF_POLL_TIMEOUT))?;
         let mut read_res = ToHostWorkRbDesc::read(mem);
 
         loop {
@@ -68,6 +68,8 @@ pub(crate) enum BlueRdmaLogicError {
     RawPacketLengthTooLong(u32, u32),
     #[error("Poison error")]
     Poison,
+    #[error("{0} is not supported by the software device")]
+    NotSupported(&'static str),
     #[error("Unreachable")]
     Unreachable,
 }
@@ -138,6 +140,7 @@ impl BlueRDMALogic {
         req: &ToCardReadDescriptor,
         mut common_meta: RdmaMessageMetaCommon,
     ) -> Result<(), BlueRdmaLogicError> {
+        // multi-SGE reads are rejected by `check_work_req`, for the read response only carries the first local address
         let local_sa = &req.sge.data[0];
         common_meta.opcode = ToHostWorkRbDescOpcode::RdmaReadRequest;
 
@@ -166,7 +169,7 @@ impl BlueRDMALogic {
     /// Convert a `ToCardWorkRbDesc` to a `RdmaMessage` and call the `net_send_agent` to send
     /// through the network.
     pub(crate) fn send(&self, desc: Box<ToCardWorkRbDesc>) -> Result<(), BlueRdmaLogicError> {
-        let desc = ToCardDescriptor::from(desc);
+        let desc = ToCardDescriptor::try_from(desc)?;
         // if it's a raw packet, send it directly
         if desc.is_raw_packet() {
             return self.send_raw_packet(desc);
@@ -312,6 +315,10 @@ impl BlueRDMALogic {
                 };
                 (desc.common.op_id, is_success)
             }
+            // The software device places data at the virtual address directly, which is unknown to zero based MRs
+            ToCardCtrlRbDesc::UpdateMrTable(desc) if desc.acc_flags.contains(MemAccessTypeFlag::IbvAccessZeroBased) => {
+                (desc.common.op_id, false)
+            }
             ToCardCtrlRbDesc::UpdateMrTable(desc) => {
                 let mut mr_table = self.mr_rkey_table.write()?;
                 let key = Key::new(desc.key.get());
@@ -338,6 +345,11 @@ impl BlueRDMALogic {
             ToCardCtrlRbDesc::SetNetworkParam(desc) => (desc.common.op_id, true),
             ToCardCtrlRbDesc::SetRawPacketReceiveMeta(desc) => (desc.common.op_id, true),
             ToCardCtrlRbDesc::UpdateErrorPsnRecoverPoint(desc) => (desc.common.op_id, true),
+            // The software device does not have a receive queue
+            ToCardCtrlRbDesc::PostRecv(desc) => (desc.common.op_id, false),
+            ToCardCtrlRbDesc::SrqManagement(desc) => (desc.common.op_id, false),
+            // The software device does not check the remote key of incoming requests
+            ToCardCtrlRbDesc::UpdateMwTable(desc) => (desc.common.op_id, false),
         };
         let resp_desc = ToHostCtrlRbDesc {
             common: ToHostCtrlRbDescCommon {
@@ -345,6 +357,7 @@ impl BlueRDMALogic {
                 is_success: is_succ,
                 opcode,
             },
+            page_fault: None,
         };
         #[allow(clippy::unwrap_used)] // if the pipe in software is broken, we should panic.
         {
@@ -468,6 +481,8 @@ impl NetReceiveLogic<'_> for BlueRDMALogic {
                             addr: header.reth.va,
                             len: header.reth.len,
                             key: header.reth.rkey.into(),
+                            can_auto_ack: false,
+                            recv_tag: 0,
                         })
                     }
                     ToHostWorkRbDescOpcode::RdmaReadRequest => {
@@ -484,8 +499,21 @@ impl NetReceiveLogic<'_> for BlueRDMALogic {
                             rkey: sec_reth.rkey.into(),
                         })
                     }
-                    ToHostWorkRbDescOpcode::Acknowledge => {
-                        unimplemented!()
+                    // SEND and atomic packets are rejected by `PacketProcessor`
+                    ToHostWorkRbDescOpcode::SendFirst
+                    | ToHostWorkRbDescOpcode::SendMiddle
+                    | ToHostWorkRbDescOpcode::SendLast
+                    | ToHostWorkRbDescOpcode::SendLastWithImmediate
+                    | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+                    | ToHostWorkRbDescOpcode::SendOnly
+                    | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                    | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+                    | ToHostWorkRbDescOpcode::Acknowledge
+                    | ToHostWorkRbDescOpcode::AtomicAcknowledge
+                    | ToHostWorkRbDescOpcode::CompareSwap
+                    | ToHostWorkRbDescOpcode::FetchAdd => {
+                        log::error!("Unexpected opcode {:?} of general message", header.common_meta.opcode);
+                        return;
                     }
                 }
             }
@@ -505,7 +533,8 @@ impl NetReceiveLogic<'_> for BlueRDMALogic {
                     }),
                     ToHostWorkRbDescAethCode::Rnr | ToHostWorkRbDescAethCode::Rsvd | ToHostWorkRbDescAethCode::Nak => {
                         // just ignore
-                        unimplemented!()
+                        log::warn!("Ignore the {:?} acknowledge", header.aeth_code);
+                        return;
                     }
                 }
             }
@@ -527,6 +556,9 @@ fn to_host_ctrl_opcode(desc: &ToCardCtrlRbDesc) -> CtrlRbDescOpcode {
         ToCardCtrlRbDesc::SetNetworkParam(_) => CtrlRbDescOpcode::SetNetworkParam,
         ToCardCtrlRbDesc::SetRawPacketReceiveMeta(_) => CtrlRbDescOpcode::SetRawPacketReceiveMeta,
         ToCardCtrlRbDesc::UpdateErrorPsnRecoverPoint(_) => CtrlRbDescOpcode::UpdateErrorPsnRecoverPoint,
+        ToCardCtrlRbDesc::PostRecv(_) => CtrlRbDescOpcode::PostRecv,
+        ToCardCtrlRbDesc::SrqManagement(_) => CtrlRbDescOpcode::SrqManagement,
+        ToCardCtrlRbDesc::UpdateMwTable(_) => CtrlRbDescOpcode::UpdateMwTable,
     }
 }
 
@@ -543,7 +575,7 @@ mod tests {
     use crate::device::{
         ToCardCtrlRbDesc, ToCardCtrlRbDescCommon, ToCardCtrlRbDescQpManagement, ToCardCtrlRbDescUpdateMrTable,
     };
-    use crate::types::{MemAccessTypeFlag, Pmtu, QpType};
+    use crate::types::{MemAccessTypeFlag, Pmtu, Psn, QpType};
 
     // test update mr table, qp table
     #[test]
@@ -569,12 +601,17 @@ mod tests {
             let desc = ToCardCtrlRbDesc::QpManagement(ToCardCtrlRbDescQpManagement {
                 common: ToCardCtrlRbDescCommon { op_id: 0 },
                 is_valid: true,
+                is_error: false,
                 qpn: crate::Qpn::new(1234),
                 pd_hdl: 1,
                 qp_type: QpType::Rc,
                 rq_acc_flags: MemAccessTypeFlag::IbvAccessRemoteWrite,
                 pmtu: Pmtu::Mtu1024,
+                min_rnr_timer: 0,
                 peer_qpn: crate::Qpn::new(1234),
+                qkey: 0,
+                rq_psn: Psn::default(),
+                srqn: None,
             });
             logic.update(desc).unwrap();
             {
@@ -590,12 +627,17 @@ mod tests {
             let desc = ToCardCtrlRbDesc::QpManagement(ToCardCtrlRbDescQpManagement {
                 common: ToCardCtrlRbDescCommon { op_id: 0 },
                 is_valid: true,
+                is_error: false,
                 qpn: crate::Qpn::new(1234),
                 pd_hdl: 1,
                 qp_type: QpType::Rc,
                 rq_acc_flags: MemAccessTypeFlag::IbvAccessRemoteWrite,
                 pmtu: Pmtu::Mtu2048,
+                min_rnr_timer: 0,
                 peer_qpn: crate::Qpn::new(1234),
+                qkey: 0,
+                rq_psn: Psn::default(),
+                srqn: None,
             });
             logic.update(desc).unwrap();
             {
@@ -618,6 +660,7 @@ mod tests {
                 pd_hdl: 0,
                 acc_flags: MemAccessTypeFlag::IbvAccessRemoteWrite,
                 pgt_offset: 0,
+                pg_size: 4096,
             });
             logic.update(desc).unwrap();
             {
@@ -640,6 +683,7 @@ mod tests {
                 pd_hdl: 0,
                 acc_flags: (MemAccessTypeFlag::IbvAccessRemoteWrite | MemAccessTypeFlag::IbvAccessRemoteRead),
                 pgt_offset: 0,
+                pg_size: 4096,
             });
             logic.update(desc).unwrap();
             {
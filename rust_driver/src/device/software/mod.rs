@@ -3,15 +3,16 @@ use std::net::Ipv4Addr;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
-use flume::{unbounded, Receiver};
+use flume::{unbounded, Receiver, RecvTimeoutError};
 use log::debug;
 
 use self::net_agent::udp_agent::{UDPReceiveAgent, UDPSendAgent};
 use super::scheduler::DescriptorScheduler;
 use super::{
-    DeviceAdaptor, DeviceError, ToCardCtrlRbDesc, ToCardRb, ToCardWorkRbDesc, ToHostCtrlRbDesc, ToHostRb,
-    ToHostWorkRbDesc,
+    constants, DeviceAdaptor, DeviceError, ToCardCtrlRbDesc, ToCardRb, ToCardWorkRbDesc, ToCardWorkRbDescOpcode,
+    ToHostCtrlRbDesc, ToHostRb, ToHostWorkRbDesc,
 };
+use crate::types::WorkReqSendFlag;
 use crate::SchedulerStrategy;
 
 mod logic;
@@ -108,6 +109,30 @@ impl<Strat: SchedulerStrategy> DeviceAdaptor for SoftwareDevice<Strat> {
     fn use_hugepage(&self) -> bool {
         false
     }
+
+    fn check_work_req(
+        &self,
+        opcode: &ToCardWorkRbDescOpcode,
+        flags: WorkReqSendFlag,
+        sge_cnt: usize,
+    ) -> Result<(), &'static str> {
+        if flags.contains(WorkReqSendFlag::IbvSendInline) {
+            return Err("inline data on the software device");
+        }
+        match opcode {
+            ToCardWorkRbDescOpcode::Send
+            | ToCardWorkRbDescOpcode::SendWithImm
+            | ToCardWorkRbDescOpcode::SendWithInv => Err("send on the software device"),
+            ToCardWorkRbDescOpcode::CompareSwap | ToCardWorkRbDescOpcode::FetchAdd => {
+                Err("atomic operation on the software device")
+            }
+            ToCardWorkRbDescOpcode::Read if sge_cnt > 1 => Err("read with more than one SGE on the software device"),
+            ToCardWorkRbDescOpcode::Write
+            | ToCardWorkRbDescOpcode::WriteWithImm
+            | ToCardWorkRbDescOpcode::Read
+            | ToCardWorkRbDescOpcode::ReadResp => Ok(()),
+        }
+    }
 }
 
 impl ToCardRb<ToCardCtrlRbDesc> for BlueRDMALogic {
@@ -119,13 +144,24 @@ impl ToCardRb<ToCardCtrlRbDesc> for BlueRDMALogic {
 
 impl ToHostRb<ToHostCtrlRbDesc> for ToHostCtrlRb {
     fn pop(&self) -> Result<ToHostCtrlRbDesc, DeviceError> {
-        self.0.recv().map_err(|e| DeviceError::Device(e.to_string()))
+        self.0
+            .recv_timeout(constants::RINGBUF_POLL_TIMEOUT)
+            .map_err(map_recv_error)
     }
 }
 
 impl ToHostRb<ToHostWorkRbDesc> for ToHostWorkRb {
     fn pop(&self) -> Result<ToHostWorkRbDesc, DeviceError> {
-        self.0.recv().map_err(|e| DeviceError::Device(e.to_string()))
+        self.0
+            .recv_timeout(constants::RINGBUF_POLL_TIMEOUT)
+            .map_err(map_recv_error)
+    }
+}
+
+fn map_recv_error(e: RecvTimeoutError) -> DeviceError {
+    match e {
+        RecvTimeoutError::Timeout => DeviceError::Timeout,
+        RecvTimeoutError::Disconnected => DeviceError::Device(e.to_string()),
     }
 }
 
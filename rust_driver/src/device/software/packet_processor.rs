@@ -66,7 +66,21 @@ impl PacketProcessor {
                 let header = RdmaAcknowledgeHeader::from_bytes(buf);
                 Ok(header.to_rdma_message(buf.len())?)
             }
-            Err(_) => Err(PacketError::InvalidOpcode),
+            // the software device has no receive queue, and does not execute atomic operations
+            Ok(
+                ToHostWorkRbDescOpcode::SendFirst
+                | ToHostWorkRbDescOpcode::SendMiddle
+                | ToHostWorkRbDescOpcode::SendLast
+                | ToHostWorkRbDescOpcode::SendLastWithImmediate
+                | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+                | ToHostWorkRbDescOpcode::SendOnly
+                | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+                | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+                | ToHostWorkRbDescOpcode::AtomicAcknowledge
+                | ToHostWorkRbDescOpcode::CompareSwap
+                | ToHostWorkRbDescOpcode::FetchAdd,
+            )
+            | Err(_) => Err(PacketError::InvalidOpcode),
         }
     }
 
@@ -120,6 +134,17 @@ impl PacketProcessor {
                 let header = RdmaAcknowledgeHeader::from_bytes(buf);
                 Ok(header.set_from_rdma_message(message)?)
             }
+            ToHostWorkRbDescOpcode::SendFirst
+            | ToHostWorkRbDescOpcode::SendMiddle
+            | ToHostWorkRbDescOpcode::SendLast
+            | ToHostWorkRbDescOpcode::SendLastWithImmediate
+            | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+            | ToHostWorkRbDescOpcode::SendOnly
+            | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+            | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+            | ToHostWorkRbDescOpcode::AtomicAcknowledge
+            | ToHostWorkRbDescOpcode::CompareSwap
+            | ToHostWorkRbDescOpcode::FetchAdd => Err(PacketError::InvalidOpcode),
         }
     }
 }
@@ -160,6 +160,9 @@ impl ToCardWorkRbDescBuilder {
             dqp_ip: Ipv4Addr::LOCALHOST,
             mac_addr: MacAddress::default(),
             msn: crate::types::Msn::new(0),
+            sqpn: crate::types::Qpn::new(self.dqpn.unwrap()),
+            qkey: 0,
+            fence: Vec::new(),
         };
         let (sge0, sge1, sge2, sge3) = self.sg_list.take().unwrap().into_four_sges();
         let desc = match self.opcode.clone().unwrap() {
@@ -172,7 +175,13 @@ impl ToCardWorkRbDescBuilder {
                 sge2,
                 sge3,
             }),
-            ToCardWorkRbDescOpcode::Read => ToCardWorkRbDesc::Read(ToCardWorkRbDescRead { common, sge: sge0 }),
+            ToCardWorkRbDescOpcode::Read => ToCardWorkRbDesc::Read(ToCardWorkRbDescRead {
+                common,
+                sge0,
+                sge1,
+                sge2,
+                sge3,
+            }),
             ToCardWorkRbDescOpcode::WriteWithImm => {
                 let imm = if matches!(common.qp_type, QpType::RawPacket) {
                     0
@@ -199,6 +208,14 @@ impl ToCardWorkRbDescBuilder {
                 sge2,
                 sge3,
             }),
+            ToCardWorkRbDescOpcode::Send
+            | ToCardWorkRbDescOpcode::SendWithImm
+            | ToCardWorkRbDescOpcode::SendWithInv => {
+                unimplemented!("the software device does not have a receive queue")
+            }
+            ToCardWorkRbDescOpcode::CompareSwap | ToCardWorkRbDescOpcode::FetchAdd => {
+                unimplemented!("the software device does not execute atomic operations")
+            }
         };
         Box::new(desc)
     }
@@ -318,17 +335,23 @@ impl ToCardCtrlRbDescBuilder {
                     pd_hdl: self.pd_hdl.unwrap(),
                     acc_flags: self.acc_flags.unwrap(),
                     pgt_offset: self.pgt_offset.unwrap(),
+                    pg_size: 4096,
                 })
             }
             ToCardCtrlRbDescBuilderType::QpManagement => ToCardCtrlRbDesc::QpManagement(ToCardCtrlRbDescQpManagement {
                 common,
                 is_valid: self.is_valid.unwrap(),
+                is_error: false,
                 qpn: crate::Qpn::new(self.qpn.unwrap()),
                 pd_hdl: self.pd_hdl.unwrap(),
                 qp_type: self.qp_type.unwrap(),
                 rq_acc_flags: self.rq_acc_flags.unwrap(),
                 pmtu: self.pmtu.unwrap(),
+                min_rnr_timer: 0,
                 peer_qpn: crate::Qpn::new(1234),
+                qkey: 0,
+                rq_psn: crate::types::Psn::default(),
+                srqn: None,
             }),
         }
     }
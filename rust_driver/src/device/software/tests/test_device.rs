@@ -101,6 +101,7 @@ fn test_loopback_software_device_write_and_read() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -113,6 +114,7 @@ fn test_loopback_software_device_write_and_read() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -161,6 +163,7 @@ fn test_loopback_software_device_write_and_read() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -173,6 +176,7 @@ fn test_loopback_software_device_write_and_read() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -185,6 +189,7 @@ fn test_loopback_software_device_write_and_read() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -284,6 +289,7 @@ fn test_loopback_software_device_with_scheudler() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -296,6 +302,7 @@ fn test_loopback_software_device_with_scheudler() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -345,6 +352,7 @@ fn test_loopback_software_device_with_scheudler() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -357,6 +365,7 @@ fn test_loopback_software_device_with_scheudler() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
@@ -369,6 +378,7 @@ fn test_loopback_software_device_with_scheudler() {
             }
             ToHostWorkRbDesc::Read(_)
             | ToHostWorkRbDesc::WriteWithImm(_)
+            | ToHostWorkRbDesc::Send(_)
             | ToHostWorkRbDesc::Ack(_)
             | ToHostWorkRbDesc::Raw(_) => panic!("unexpected descriptor"),
         }
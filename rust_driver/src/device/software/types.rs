@@ -388,19 +388,6 @@ impl SGList {
         }
     }
 
-    pub(crate) fn new_with_sge(sge: DescSge) -> Self {
-        SGList {
-            data: [
-                SGListElementWithKey::from(sge),
-                SGListElementWithKey::default(),
-                SGListElementWithKey::default(),
-                SGListElementWithKey::default(),
-            ],
-            cur_level: 0,
-            len: 1,
-        }
-    }
-
     pub(crate) fn get_total_length(&self) -> u32 {
         self.data.iter().map(|sge| sge.len).sum()
     }
@@ -623,9 +610,11 @@ pub(crate) struct ToCardReadDescriptor {
     pub(crate) sge: SGList,
 }
 
-impl From<Box<ToCardWorkRbDesc>> for ToCardDescriptor {
-    fn from(desc: Box<ToCardWorkRbDesc>) -> Self {
-        match *desc {
+impl TryFrom<Box<ToCardWorkRbDesc>> for ToCardDescriptor {
+    type Error = BlueRdmaLogicError;
+
+    fn try_from(desc: Box<ToCardWorkRbDesc>) -> Result<Self, Self::Error> {
+        let desc = match *desc {
             ToCardWorkRbDesc::Write(desc) => ToCardDescriptor::Write(ToCardWriteDescriptor {
                 opcode: ToCardWorkRbDescOpcode::Write,
                 common: desc.common,
@@ -636,7 +625,7 @@ impl From<Box<ToCardWorkRbDesc>> for ToCardDescriptor {
             }),
             ToCardWorkRbDesc::Read(desc) => ToCardDescriptor::Read(ToCardReadDescriptor {
                 common: desc.common,
-                sge: SGList::new_with_sge(desc.sge),
+                sge: SGList::new_with_sge_list(desc.sge0, desc.sge1, desc.sge2, desc.sge3),
             }),
             ToCardWorkRbDesc::WriteWithImm(desc) => ToCardDescriptor::Write(ToCardWriteDescriptor {
                 opcode: ToCardWorkRbDescOpcode::WriteWithImm,
@@ -654,7 +643,17 @@ impl From<Box<ToCardWorkRbDesc>> for ToCardDescriptor {
                 imm: None,
                 sg_list: SGList::new_with_sge_list(desc.sge0, desc.sge1, desc.sge2, desc.sge3),
             }),
-        }
+            ToCardWorkRbDesc::Send(_) | ToCardWorkRbDesc::SendWithImm(_) | ToCardWorkRbDesc::SendWithInv(_) => {
+                return Err(BlueRdmaLogicError::NotSupported("send"));
+            }
+            ToCardWorkRbDesc::CompareSwap(_) | ToCardWorkRbDesc::FetchAdd(_) => {
+                return Err(BlueRdmaLogicError::NotSupported("atomic operation"));
+            }
+            ToCardWorkRbDesc::Inline(_) => {
+                return Err(BlueRdmaLogicError::NotSupported("inline data"));
+            }
+        };
+        Ok(desc)
     }
 }
 
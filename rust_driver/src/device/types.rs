@@ -8,17 +8,23 @@ use num_enum::{IntoPrimitive, TryFromPrimitive};
 use super::layout::{
     CmdQueueDescCommonHead, MetaReportQueueDescBthReth, MetaReportQueueDescFragAETH, MetaReportQueueDescFragBTH,
     MetaReportQueueDescFragImmDT, MetaReportQueueDescFragRETH, SendQueueDescCommonHead, SendQueueReqDescFragSGE,
-    SendQueueReqDescSeg0, SendQueueReqDescSeg1,
+    SendQueueReqDescInlineData, SendQueueReqDescSeg0, SendQueueReqDescSeg1,
 };
 use crate::device::layout::{
-    CmdQueueReqDescQpManagementSeg0, CmdQueueReqDescSetNetworkParam, CmdQueueReqDescSetRawPacketReceiveMeta,
-    CmdQueueReqDescUpdateErrRecoverPoint, CmdQueueReqDescUpdateMrTable, CmdQueueReqDescUpdatePGT,
+    CmdQueueReqDescPostRecv, CmdQueueReqDescQpManagementSeg0, CmdQueueReqDescSetNetworkParam,
+    CmdQueueReqDescSetRawPacketReceiveMeta, CmdQueueReqDescSrqManagement, CmdQueueReqDescUpdateErrRecoverPoint,
+    CmdQueueReqDescUpdateMrTable, CmdQueueReqDescUpdateMwTable, CmdQueueReqDescUpdatePGT, CmdQueueRespDescPageFault,
     MetaReportQueueDescFragSecondaryRETH,
 };
+use crate::op_ctx::OpCtx;
 use crate::types::{Imm, Key, MemAccessTypeFlag, Msn, Pmtu, Psn, QpType, Qpn, Sge, WorkReqSendFlag};
 use crate::utils::u8_slice_to_u64;
 use crate::Error;
 
+const DATAGRAM_SOURCE_QPN_MASK: u32 = 0x00FF_FFFF;
+/// The bytes of inline data carried by each descriptor
+const INLINE_DATA_SEG_SIZE: usize = 32;
+
 #[derive(Debug)]
 pub(crate) enum ToCardCtrlRbDesc {
     UpdateMrTable(ToCardCtrlRbDescUpdateMrTable),
@@ -27,6 +33,9 @@ pub(crate) enum ToCardCtrlRbDesc {
     SetNetworkParam(ToCardCtrlRbDescSetNetworkParam),
     SetRawPacketReceiveMeta(ToCardCtrlRbDescSetRawPacketReceiveMeta),
     UpdateErrorPsnRecoverPoint(ToCardCtrlRbDescUpdateErrPsnRecoverPoint),
+    PostRecv(ToCardCtrlRbDescPostRecv),
+    SrqManagement(ToCardCtrlRbDescSrqManagement),
+    UpdateMwTable(ToCardCtrlRbDescUpdateMwTable),
 }
 
 impl ToCardCtrlRbDesc {
@@ -38,6 +47,9 @@ impl ToCardCtrlRbDesc {
             ToCardCtrlRbDesc::SetNetworkParam(desc) => desc.common.op_id = id,
             ToCardCtrlRbDesc::SetRawPacketReceiveMeta(desc) => desc.common.op_id = id,
             ToCardCtrlRbDesc::UpdateErrorPsnRecoverPoint(desc) => desc.common.op_id = id,
+            ToCardCtrlRbDesc::PostRecv(desc) => desc.common.op_id = id,
+            ToCardCtrlRbDesc::SrqManagement(desc) => desc.common.op_id = id,
+            ToCardCtrlRbDesc::UpdateMwTable(desc) => desc.common.op_id = id,
         }
     }
 }
@@ -45,6 +57,15 @@ impl ToCardCtrlRbDesc {
 #[derive(Debug)]
 pub(crate) struct ToHostCtrlRbDesc {
     pub(crate) common: ToHostCtrlRbDescCommon,
+    /// only for `CtrlRbDescOpcode::PageFault`, which is raised by the device instead of answering a request
+    pub(crate) page_fault: Option<ToHostCtrlRbDescPageFault>,
+}
+
+/// The device touched a page of an on-demand paging MR, which is not in the page table yet
+#[derive(Debug)]
+pub(crate) struct ToHostCtrlRbDescPageFault {
+    pub(crate) key: Key,
+    pub(crate) va: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -53,6 +74,14 @@ pub(crate) enum ToCardWorkRbDesc {
     Write(ToCardWorkRbDescWrite),
     WriteWithImm(ToCardWorkRbDescWriteWithImm),
     ReadResp(ToCardWorkRbDescWrite),
+    Send(ToCardWorkRbDescWrite),
+    SendWithImm(ToCardWorkRbDescWriteWithImm),
+    /// the `imm` carries the remote key invalidated by the peer
+    SendWithInv(ToCardWorkRbDescWriteWithImm),
+    CompareSwap(ToCardWorkRbDescAtomic),
+    FetchAdd(ToCardWorkRbDescAtomic),
+    /// a write or SEND posted with `IbvSendInline`
+    Inline(ToCardWorkRbDescInline),
 }
 
 #[derive(Debug)]
@@ -60,6 +89,7 @@ pub(crate) enum ToHostWorkRbDesc {
     Read(ToHostWorkRbDescRead),
     WriteOrReadResp(ToHostWorkRbDescWriteOrReadResp),
     WriteWithImm(ToHostWorkRbDescWriteWithImm),
+    Send(ToHostWorkRbDescSend),
     Ack(ToHostWorkRbDescAck),
     Raw(ToHostWorkRbDescRaw),
 }
@@ -70,6 +100,7 @@ impl ToHostWorkRbDesc {
             ToHostWorkRbDesc::Read(desc) => &desc.common.status,
             ToHostWorkRbDesc::WriteOrReadResp(desc) => &desc.common.status,
             ToHostWorkRbDesc::WriteWithImm(desc) => &desc.common.status,
+            ToHostWorkRbDesc::Send(desc) => &desc.common.status,
             ToHostWorkRbDesc::Ack(desc) => &desc.common.status,
             ToHostWorkRbDesc::Raw(desc) => &desc.common.status,
         }
@@ -90,6 +121,8 @@ pub(crate) struct ToCardCtrlRbDescUpdateMrTable {
     pub(crate) pd_hdl: u32,
     pub(crate) acc_flags: MemAccessTypeFlag,
     pub(crate) pgt_offset: u32,
+    /// the size of the pages mapped by each page table entry, a power of two
+    pub(crate) pg_size: u32,
 }
 
 #[derive(Debug)]
@@ -104,12 +137,20 @@ pub(crate) struct ToCardCtrlRbDescUpdatePageTable {
 pub(crate) struct ToCardCtrlRbDescQpManagement {
     pub(crate) common: ToCardCtrlRbDescCommon,
     pub(crate) is_valid: bool,
+    /// The QP is in `Err` state, the device drops the incoming packets of it
+    pub(crate) is_error: bool,
     pub(crate) qpn: Qpn,
     pub(crate) pd_hdl: u32,
     pub(crate) qp_type: QpType,
     pub(crate) rq_acc_flags: MemAccessTypeFlag,
     pub(crate) pmtu: Pmtu,
+    /// The RNR timer code replied in RNR NAK, when an incoming SEND finds no receive request
+    pub(crate) min_rnr_timer: u8,
     pub(crate) peer_qpn: Qpn,
+    pub(crate) qkey: u32,
+    pub(crate) rq_psn: Psn,
+    /// The shared receive queue the QP takes its receive buffers from
+    pub(crate) srqn: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -135,6 +176,39 @@ pub(crate) struct ToCardCtrlRbDescUpdateErrPsnRecoverPoint {
     pub(crate) recover_psn: Psn,
 }
 
+#[derive(Debug)]
+pub(crate) struct ToCardCtrlRbDescPostRecv {
+    pub(crate) common: ToCardCtrlRbDescCommon,
+    pub(crate) qpn: Qpn,
+    pub(crate) sge: DescSge,
+    /// Post to this shared receive queue instead of the receive queue of `qpn`
+    pub(crate) srqn: Option<u32>,
+    /// Echoed back by the device when the buffer is consumed, 0 for none
+    pub(crate) tag: u32,
+}
+
+#[derive(Debug)]
+pub(crate) struct ToCardCtrlRbDescSrqManagement {
+    pub(crate) common: ToCardCtrlRbDescCommon,
+    pub(crate) is_valid: bool,
+    pub(crate) srqn: u32,
+    pub(crate) pd_hdl: u32,
+}
+
+#[derive(Debug)]
+pub(crate) struct ToCardCtrlRbDescUpdateMwTable {
+    pub(crate) common: ToCardCtrlRbDescCommon,
+    /// bind the window if valid, otherwise invalidate it
+    pub(crate) is_valid: bool,
+    pub(crate) is_type2: bool,
+    pub(crate) addr: u64,
+    pub(crate) len: u32,
+    pub(crate) mw_key: Key,
+    /// the key of the memory region that the window is bound to
+    pub(crate) mr_key: Key,
+    pub(crate) acc_flags: MemAccessTypeFlag,
+}
+
 #[derive(Debug)]
 pub(crate) struct ToHostCtrlRbDescCommon {
     pub(crate) op_id: u32, // user_data
@@ -155,6 +229,13 @@ pub(crate) struct ToCardWorkRbDescCommon {
     pub(crate) qp_type: QpType,
     pub(crate) psn: Psn,
     pub(crate) msn: Msn,
+    /// The local QPN, which is carried by DETH of UD messages
+    pub(crate) sqpn: Qpn,
+    /// Q_Key of the destination UD QP
+    pub(crate) qkey: u32,
+    /// The reads and atomics of the QP posted before a request with `IbvSendFence`,
+    /// the scheduler holds the request until all of them are done
+    pub(crate) fence: Vec<OpCtx<()>>,
 }
 
 impl Default for ToCardWorkRbDescCommon {
@@ -171,6 +252,9 @@ impl Default for ToCardWorkRbDescCommon {
             qp_type: QpType::Rc,
             psn: Psn::default(),
             msn: Msn::default(),
+            sqpn: Qpn::default(),
+            qkey: 0,
+            fence: Vec::new(),
         }
     }
 }
@@ -178,7 +262,10 @@ impl Default for ToCardWorkRbDescCommon {
 #[derive(Default, Clone, Debug)]
 pub(crate) struct ToCardWorkRbDescRead {
     pub(crate) common: ToCardWorkRbDescCommon,
-    pub(crate) sge: DescSge,
+    pub(crate) sge0: DescSge,
+    pub(crate) sge1: Option<DescSge>,
+    pub(crate) sge2: Option<DescSge>,
+    pub(crate) sge3: Option<DescSge>,
 }
 
 #[derive(Default, Clone, Debug)]
@@ -204,6 +291,29 @@ pub(crate) struct ToCardWorkRbDescWriteWithImm {
     pub(crate) sge3: Option<DescSge>,
 }
 
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ToCardWorkRbDescAtomic {
+    pub(crate) common: ToCardWorkRbDescCommon,
+    /// the local buffer which receives the original value
+    pub(crate) sge: DescSge,
+    /// swap value of compare and swap, or add value of fetch and add
+    pub(crate) swap_add: u64,
+    /// compare value of compare and swap, ignored by fetch and add
+    pub(crate) compare: u64,
+}
+
+/// The payload is copied into the descriptor, so the device reads no memory region for it
+#[derive(Clone, Debug)]
+pub(crate) struct ToCardWorkRbDescInline {
+    pub(crate) common: ToCardWorkRbDescCommon,
+    /// one of `Write`, `WriteWithImm`, `Send`, `SendWithImm` and `SendWithInv`
+    pub(crate) opcode: ToCardWorkRbDescOpcode,
+    /// the immediate data, or the remote key to invalidate of `SendWithInv`
+    pub(crate) imm: Option<u32>,
+    /// at most `MAX_INLINE_DATA` bytes
+    pub(crate) data: Vec<u8>,
+}
+
 #[derive(Debug, Default, Clone)]
 pub(crate) struct ToHostWorkRbDescCommon {
     pub(crate) status: ToHostWorkRbDescStatus,
@@ -248,8 +358,7 @@ impl Default for ToHostWorkRbDescWriteOrReadResp {
     }
 }
 
-#[allow(unused)] // Currently we don't have write imm descriptor
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct ToHostWorkRbDescWriteWithImm {
     pub(crate) common: ToHostWorkRbDescCommon,
     pub(crate) write_type: ToHostWorkRbDescWriteType,
@@ -258,6 +367,62 @@ pub(crate) struct ToHostWorkRbDescWriteWithImm {
     pub(crate) addr: u64,
     pub(crate) len: u32,
     pub(crate) key: Key,
+    pub(crate) can_auto_ack: bool,
+    /// The tag of the consumed shared receive queue buffer, 0 for none
+    pub(crate) recv_tag: u32,
+}
+
+impl From<&ToHostWorkRbDescWriteWithImm> for ToHostWorkRbDescWriteOrReadResp {
+    fn from(desc: &ToHostWorkRbDescWriteWithImm) -> Self {
+        Self {
+            common: desc.common.clone(),
+            is_read_resp: false,
+            write_type: desc.write_type.clone(),
+            psn: desc.psn,
+            addr: desc.addr,
+            len: desc.len,
+            can_auto_ack: desc.can_auto_ack,
+        }
+    }
+}
+
+/// A completed incoming SEND message.
+///
+/// The device reports it only once the whole message has been placed in the receive buffer.
+#[derive(Debug, Clone)]
+pub(crate) struct ToHostWorkRbDescSend {
+    pub(crate) common: ToHostWorkRbDescCommon,
+    #[allow(unused)] // always `Last` or `Only`
+    pub(crate) write_type: ToHostWorkRbDescWriteType,
+    pub(crate) psn: Psn,
+    #[allow(unused)] // the address of the receive buffer
+    pub(crate) addr: u64,
+    pub(crate) len: u32,
+    pub(crate) imm: Option<u32>,
+    pub(crate) can_auto_ack: bool,
+    /// The sender (QPN, IP) of a UD message
+    pub(crate) src: Option<(Qpn, Ipv4Addr)>,
+    /// The tag of the consumed receive buffer, 0 for none
+    pub(crate) recv_tag: u32,
+    /// The remote key invalidated by a SEND with invalidate
+    pub(crate) invalidated_rkey: Option<Key>,
+}
+
+impl Default for ToHostWorkRbDescSend {
+    fn default() -> Self {
+        Self {
+            common: ToHostWorkRbDescCommon::default(),
+            write_type: ToHostWorkRbDescWriteType::Only,
+            psn: Psn::default(),
+            addr: 0,
+            len: 0,
+            imm: None,
+            can_auto_ack: false,
+            src: None,
+            recv_tag: 0,
+            invalidated_rkey: None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -366,6 +531,10 @@ pub(crate) enum CtrlRbDescOpcode {
     SetNetworkParam = 0x03,
     SetRawPacketReceiveMeta = 0x04,
     UpdateErrorPsnRecoverPoint = 0x05,
+    PostRecv = 0x06,
+    SrqManagement = 0x07,
+    UpdateMwTable = 0x08,
+    PageFault = 0x09,
 }
 
 #[derive(Debug, Clone, PartialEq, TryFromPrimitive, IntoPrimitive)]
@@ -388,7 +557,12 @@ pub(crate) enum ToCardWorkRbDescOpcode {
     // IBV_WR_ATOMIC_WRITE         = 15
     Write = 0,
     WriteWithImm = 1,
+    Send = 2,
+    SendWithImm = 3,
     Read = 4,
+    CompareSwap = 5,
+    FetchAdd = 6,
+    SendWithInv = 9,
     ReadResp = 12, // Not defined in rdma-core
 }
 
@@ -415,6 +589,12 @@ pub(crate) enum ToHostWorkRbDescOpcode {
     // Resync = 0x15,
     // SendLastWithInvalidate = 0x16,
     // SendOnlyWithInvalidate = 0x17,
+    SendFirst = 0x00,
+    SendMiddle = 0x01,
+    SendLast = 0x02,
+    SendLastWithImmediate = 0x03,
+    SendOnly = 0x04,
+    SendOnlyWithImmediate = 0x05,
     RdmaWriteFirst = 0x06,
     RdmaWriteMiddle = 0x07,
     RdmaWriteLast = 0x08,
@@ -427,13 +607,27 @@ pub(crate) enum ToHostWorkRbDescOpcode {
     RdmaReadResponseOnly = 0x10,
     RdmaReadRequest = 0x0c,
     Acknowledge = 0x11,
+    AtomicAcknowledge = 0x12,
+    CompareSwap = 0x13,
+    FetchAdd = 0x14,
+    SendLastWithInvalidate = 0x16,
+    SendOnlyWithInvalidate = 0x17,
 }
 
 impl ToHostWorkRbDescOpcode {
     pub(crate) fn is_first(&self) -> bool {
         match self {
-            ToHostWorkRbDescOpcode::RdmaWriteFirst | ToHostWorkRbDescOpcode::RdmaReadResponseFirst => true,
-            ToHostWorkRbDescOpcode::RdmaWriteMiddle
+            ToHostWorkRbDescOpcode::SendFirst
+            | ToHostWorkRbDescOpcode::RdmaWriteFirst
+            | ToHostWorkRbDescOpcode::RdmaReadResponseFirst => true,
+            ToHostWorkRbDescOpcode::SendMiddle
+            | ToHostWorkRbDescOpcode::SendLast
+            | ToHostWorkRbDescOpcode::SendLastWithImmediate
+            | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+            | ToHostWorkRbDescOpcode::SendOnly
+            | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+            | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+            | ToHostWorkRbDescOpcode::RdmaWriteMiddle
             | ToHostWorkRbDescOpcode::RdmaWriteLast
             | ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate
             | ToHostWorkRbDescOpcode::RdmaWriteOnly
@@ -442,7 +636,10 @@ impl ToHostWorkRbDescOpcode {
             | ToHostWorkRbDescOpcode::RdmaReadResponseLast
             | ToHostWorkRbDescOpcode::RdmaReadResponseOnly
             | ToHostWorkRbDescOpcode::RdmaReadRequest
-            | ToHostWorkRbDescOpcode::Acknowledge => false,
+            | ToHostWorkRbDescOpcode::Acknowledge
+            | ToHostWorkRbDescOpcode::AtomicAcknowledge
+            | ToHostWorkRbDescOpcode::CompareSwap
+            | ToHostWorkRbDescOpcode::FetchAdd => false,
         }
     }
 
@@ -468,19 +665,29 @@ impl ToHostWorkRbDescOpcode {
 
     pub(crate) fn write_type(&self) -> Option<ToHostWorkRbDescWriteType> {
         match self {
-            ToHostWorkRbDescOpcode::RdmaWriteFirst | ToHostWorkRbDescOpcode::RdmaReadResponseFirst => {
-                Some(ToHostWorkRbDescWriteType::First)
-            }
-            ToHostWorkRbDescOpcode::RdmaWriteMiddle | ToHostWorkRbDescOpcode::RdmaReadResponseMiddle => {
-                Some(ToHostWorkRbDescWriteType::Middle)
-            }
-            ToHostWorkRbDescOpcode::RdmaWriteLast
+            ToHostWorkRbDescOpcode::SendFirst
+            | ToHostWorkRbDescOpcode::RdmaWriteFirst
+            | ToHostWorkRbDescOpcode::RdmaReadResponseFirst => Some(ToHostWorkRbDescWriteType::First),
+            ToHostWorkRbDescOpcode::SendMiddle
+            | ToHostWorkRbDescOpcode::RdmaWriteMiddle
+            | ToHostWorkRbDescOpcode::RdmaReadResponseMiddle => Some(ToHostWorkRbDescWriteType::Middle),
+            ToHostWorkRbDescOpcode::SendLast
+            | ToHostWorkRbDescOpcode::SendLastWithImmediate
+            | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+            | ToHostWorkRbDescOpcode::RdmaWriteLast
             | ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate
             | ToHostWorkRbDescOpcode::RdmaReadResponseLast => Some(ToHostWorkRbDescWriteType::Last),
-            ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate
+            ToHostWorkRbDescOpcode::SendOnly
+            | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+            | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+            | ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate
             | ToHostWorkRbDescOpcode::RdmaWriteOnly
             | ToHostWorkRbDescOpcode::RdmaReadResponseOnly => Some(ToHostWorkRbDescWriteType::Only),
-            ToHostWorkRbDescOpcode::RdmaReadRequest | ToHostWorkRbDescOpcode::Acknowledge => None,
+            ToHostWorkRbDescOpcode::RdmaReadRequest
+            | ToHostWorkRbDescOpcode::Acknowledge
+            | ToHostWorkRbDescOpcode::AtomicAcknowledge
+            | ToHostWorkRbDescOpcode::CompareSwap
+            | ToHostWorkRbDescOpcode::FetchAdd => None,
         }
     }
 }
@@ -527,7 +734,8 @@ impl ToCardCtrlRbDesc {
 
         fn write_update_mr_table(dst: &mut [u8], desc: &ToCardCtrlRbDescUpdateMrTable) {
             // typedef struct {
-            //     ReservedZero#(7)            reserved1;
+            //     ReservedZero#(2)            reserved1;
+            //     Bit#(5)                     pgSizeLog;
             //     Bit#(17)                    pgtOffset;
             //     Bit#(8)                     accFlags;
             //     Bit#(32)                    pdHandler;
@@ -546,6 +754,8 @@ impl ToCardCtrlRbDesc {
             update_mr_table.set_pd_handler(desc.pd_hdl.into());
             update_mr_table.set_acc_flags(desc.acc_flags.bits().into());
             update_mr_table.set_pgt_offset(desc.pgt_offset.into());
+            // a removed MR has no page
+            update_mr_table.set_pg_size_log(desc.pg_size.checked_ilog2().unwrap_or(0).into());
         }
 
         fn write_update_page_table(dst: &mut [u8], desc: &ToCardCtrlRbDescUpdatePageTable) {
@@ -568,16 +778,19 @@ impl ToCardCtrlRbDesc {
 
         fn write_qp_management(dst: &mut [u8], desc: &ToCardCtrlRbDescQpManagement) {
             // typedef struct {
-            //     ReservedZero#(80)              reserved1;       // 80  bits
-            //     QPN                             qpn;            // 24  bits
-            //     ReservedZero#(5)                reserved2;      // 5   bits
+            //     SRQN                            srqn;           // 24  bits
+            //     PSN                             rqPsn;          // 24  bits
+            //     QKEY                            qkey;           // 32  bits
+            //     QPN                             peerQpn;        // 24  bits
+            //     RnrTimer                        minRnrTimer;    // 5   bits
             //     PMTU                            pmtu;           // 3   bits
             //     FlagsType#(MemAccessTypeFlag)   rqAccessFlags;  // 8   bits
             //     ReservedZero#(4)                reserved3;      // 4   bits
             //     TypeQP                          qpType;         // 4   bits
             //     HandlerPD                       pdHandler;      // 32  bits
             //     QPN                             qpn;            // 24  bits
-            //     ReservedZero#(6)                reserved4;      // 6   bits
+            //     ReservedZero#(5)                reserved4;      // 5   bits
+            //     Bool                            hasSrq;         // 1   bit
             //     Bool                            isError;        // 1   bit
             //     Bool                            isValid;        // 1   bit
             //     CmdQueueDescCommonHead          commonHeader;   // 64  bits
@@ -585,13 +798,18 @@ impl ToCardCtrlRbDesc {
 
             let mut seg0 = CmdQueueReqDescQpManagementSeg0(dst);
             seg0.set_is_valid(desc.is_valid);
-            seg0.set_is_error(false);
+            seg0.set_is_error(desc.is_error);
             seg0.set_qpn(desc.qpn.get().into());
             seg0.set_pd_handler(desc.pd_hdl.into());
             seg0.set_qp_type(desc.qp_type as u64);
             seg0.set_rq_access_flags(desc.rq_acc_flags.bits().into());
             seg0.set_pmtu(desc.pmtu as u64);
+            seg0.set_min_rnr_timer(desc.min_rnr_timer.into());
             seg0.set_peer_qpn(desc.peer_qpn.get().into());
+            seg0.set_qkey(desc.qkey.into());
+            seg0.set_rq_psn(desc.rq_psn.get().into());
+            seg0.set_has_srq(desc.srqn.is_some());
+            seg0.set_srqn(desc.srqn.unwrap_or(0).into());
         }
 
         fn write_set_network_param(dst: &mut [u8], desc: &ToCardCtrlRbDescSetNetworkParam) {
@@ -614,6 +832,46 @@ impl ToCardCtrlRbDesc {
             raw_packet_recv_meta.set_psn(desc.recover_psn.get());
         }
 
+        fn write_post_recv(dst: &mut [u8], desc: &ToCardCtrlRbDescPostRecv) {
+            let mut post_recv = CmdQueueReqDescPostRecv(dst);
+            post_recv.set_laddr(desc.sge.addr);
+            post_recv.set_len(desc.sge.len.into());
+            post_recv.set_lkey(desc.sge.key.get().into());
+            // the SRQN takes the place of the QPN when posting to a shared receive queue
+            post_recv.set_qpn(desc.srqn.unwrap_or(desc.qpn.get()).into());
+            post_recv.set_is_srq(desc.srqn.is_some());
+            post_recv.set_tag(desc.tag.into());
+        }
+
+        fn write_srq_management(dst: &mut [u8], desc: &ToCardCtrlRbDescSrqManagement) {
+            let mut srq_management = CmdQueueReqDescSrqManagement(dst);
+            srq_management.set_is_valid(desc.is_valid);
+            srq_management.set_srqn(desc.srqn.into());
+            srq_management.set_pd_handler(desc.pd_hdl.into());
+        }
+
+        fn write_update_mw_table(dst: &mut [u8], desc: &ToCardCtrlRbDescUpdateMwTable) {
+            // typedef struct {
+            //     ReservedZero#(22)           reserved1;
+            //     Bool                        isType2;
+            //     Bool                        isValid;
+            //     Bit#(8)                     accFlags;
+            //     Bit#(32)                    mrKey;
+            //     Bit#(32)                    mwKey;
+            //     Bit#(32)                    mwLength;
+            //     Bit#(64)                    mwBaseVA;
+            //     CmdQueueDescCommonHead      commonHeader;
+            // } CmdQueueReqDescUpdateMwTable deriving(Bits, FShow);
+            let mut update_mw_table = CmdQueueReqDescUpdateMwTable(dst);
+            update_mw_table.set_mw_base_va(desc.addr);
+            update_mw_table.set_mw_length(desc.len.into());
+            update_mw_table.set_mw_key(desc.mw_key.get().into());
+            update_mw_table.set_mr_key(desc.mr_key.get().into());
+            update_mw_table.set_acc_flags(desc.acc_flags.bits().into());
+            update_mw_table.set_is_valid(desc.is_valid);
+            update_mw_table.set_is_type2(desc.is_type2);
+        }
+
         match self {
             ToCardCtrlRbDesc::UpdateMrTable(desc) => {
                 write_common_header(dst, CtrlRbDescOpcode::UpdateMrTable, desc.common.op_id);
@@ -639,6 +897,18 @@ impl ToCardCtrlRbDesc {
                 write_common_header(dst, CtrlRbDescOpcode::UpdateErrorPsnRecoverPoint, desc.common.op_id);
                 write_update_err_psn_recover_point(dst, desc);
             }
+            ToCardCtrlRbDesc::PostRecv(desc) => {
+                write_common_header(dst, CtrlRbDescOpcode::PostRecv, desc.common.op_id);
+                write_post_recv(dst, desc);
+            }
+            ToCardCtrlRbDesc::SrqManagement(desc) => {
+                write_common_header(dst, CtrlRbDescOpcode::SrqManagement, desc.common.op_id);
+                write_srq_management(dst, desc);
+            }
+            ToCardCtrlRbDesc::UpdateMwTable(desc) => {
+                write_common_header(dst, CtrlRbDescOpcode::UpdateMwTable, desc.common.op_id);
+                write_update_mw_table(dst, desc);
+            }
         }
     }
 }
@@ -677,7 +947,15 @@ impl ToHostCtrlRbDesc {
             is_success,
         };
 
-        let desc = ToHostCtrlRbDesc { common };
+        let page_fault = matches!(common.opcode, CtrlRbDescOpcode::PageFault).then(|| {
+            let desc = CmdQueueRespDescPageFault(src);
+            // the key is 32 bits in the descriptor
+            #[allow(clippy::cast_possible_truncation)]
+            let key = Key::new(desc.get_mr_key() as u32);
+            ToHostCtrlRbDescPageFault { key, va: desc.get_va() }
+        });
+
+        let desc = ToHostCtrlRbDesc { common, page_fault };
         Ok(desc)
     }
 }
@@ -699,6 +977,23 @@ impl ToCardWorkRbDesc {
                 desc.is_first,
                 desc.is_last,
             ),
+            ToCardWorkRbDesc::Send(desc) => (&desc.common, ToCardWorkRbDescOpcode::Send, desc.is_first, desc.is_last),
+            ToCardWorkRbDesc::SendWithImm(desc) => (
+                &desc.common,
+                ToCardWorkRbDescOpcode::SendWithImm,
+                desc.is_first,
+                desc.is_last,
+            ),
+            ToCardWorkRbDesc::SendWithInv(desc) => (
+                &desc.common,
+                ToCardWorkRbDescOpcode::SendWithInv,
+                desc.is_first,
+                desc.is_last,
+            ),
+            ToCardWorkRbDesc::CompareSwap(desc) => (&desc.common, ToCardWorkRbDescOpcode::CompareSwap, true, true),
+            ToCardWorkRbDesc::FetchAdd(desc) => (&desc.common, ToCardWorkRbDescOpcode::FetchAdd, true, true),
+            // an inline request is never split
+            ToCardWorkRbDesc::Inline(desc) => (&desc.common, desc.opcode.clone(), true, true),
         };
 
         let mut head = SendQueueDescCommonHead(dst);
@@ -732,7 +1027,9 @@ impl ToCardWorkRbDesc {
 
     pub(super) fn write_1(&self, dst: &mut [u8]) {
         // typedef struct {
-        //     ReservedZero#(64)       reserved1;          // 64 bits
+        //     ReservedZero#(8)        reserved1;          // 8  bits
+        //     QPN                     sqpn;               // 24 bits
+        //     QKEY                    qkey;               // 32 bits
 
         //     IMM                     imm;                // 32 bits
 
@@ -760,15 +1057,22 @@ impl ToCardWorkRbDesc {
 
         #[allow(clippy::arithmetic_side_effects)]
         let (common, sge_cnt) = match self {
-            ToCardWorkRbDesc::Read(desc) => (&desc.common, 1),
-            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) => (
+            ToCardWorkRbDesc::Read(desc) => (
                 &desc.common,
                 1 + u8::from(desc.sge1.is_some()) + u8::from(desc.sge2.is_some()) + u8::from(desc.sge3.is_some()),
             ),
-            ToCardWorkRbDesc::WriteWithImm(desc) => (
+            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) | ToCardWorkRbDesc::Send(desc) => (
                 &desc.common,
                 1 + u8::from(desc.sge1.is_some()) + u8::from(desc.sge2.is_some()) + u8::from(desc.sge3.is_some()),
             ),
+            ToCardWorkRbDesc::WriteWithImm(desc)
+            | ToCardWorkRbDesc::SendWithImm(desc)
+            | ToCardWorkRbDesc::SendWithInv(desc) => (
+                &desc.common,
+                1 + u8::from(desc.sge1.is_some()) + u8::from(desc.sge2.is_some()) + u8::from(desc.sge3.is_some()),
+            ),
+            ToCardWorkRbDesc::CompareSwap(desc) | ToCardWorkRbDesc::FetchAdd(desc) => (&desc.common, 1),
+            ToCardWorkRbDesc::Inline(desc) => (&desc.common, 0),
         };
         let mut desc_common = SendQueueReqDescSeg1(dst);
         desc_common.set_pmtu(common.pmtu as u64);
@@ -779,9 +1083,16 @@ impl ToCardWorkRbDesc {
         desc_common.set_mac_addr(u8_slice_to_u64(common.mac_addr.as_bytes()));
 
         desc_common.set_dqpn(common.dqpn.get().into());
+        desc_common.set_sqpn(common.sqpn.get().into());
+        desc_common.set_qkey(common.qkey.into());
 
-        if let ToCardWorkRbDesc::WriteWithImm(desc) = self {
+        if let ToCardWorkRbDesc::WriteWithImm(desc)
+        | ToCardWorkRbDesc::SendWithImm(desc)
+        | ToCardWorkRbDesc::SendWithInv(desc) = self
+        {
             desc_common.set_imm(u64::from(desc.imm));
+        } else if let ToCardWorkRbDesc::Inline(desc) = self {
+            desc_common.set_imm(u64::from(desc.imm.unwrap_or(0)));
         } else {
             desc_common.set_imm(0);
         }
@@ -801,9 +1112,18 @@ impl ToCardWorkRbDesc {
         // } SendQueueReqDescVariableLenSGE deriving(Bits, FShow);
 
         let (sge0, sge1) = match self {
-            ToCardWorkRbDesc::Read(desc) => (&desc.sge, None),
-            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) => (&desc.sge0, desc.sge1.as_ref()),
-            ToCardWorkRbDesc::WriteWithImm(desc) => (&desc.sge0, desc.sge1.as_ref()),
+            ToCardWorkRbDesc::Read(desc) => (&desc.sge0, desc.sge1.as_ref()),
+            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) | ToCardWorkRbDesc::Send(desc) => {
+                (&desc.sge0, desc.sge1.as_ref())
+            }
+            ToCardWorkRbDesc::WriteWithImm(desc)
+            | ToCardWorkRbDesc::SendWithImm(desc)
+            | ToCardWorkRbDesc::SendWithInv(desc) => (&desc.sge0, desc.sge1.as_ref()),
+            ToCardWorkRbDesc::CompareSwap(desc) | ToCardWorkRbDesc::FetchAdd(desc) => (&desc.sge, None),
+            ToCardWorkRbDesc::Inline(desc) => {
+                desc.write_data(0, dst);
+                return;
+            }
         };
         // Note that the order of the sges is reversed in the struct
         let mut frag_sge = SendQueueReqDescFragSGE(&mut dst[16..32]);
@@ -834,12 +1154,32 @@ impl ToCardWorkRbDesc {
         //     SendQueueReqDescFragSGE     sge2;       // 128 bits
         // } SendQueueReqDescVariableLenSGE deriving(Bits, FShow);
 
+        // typedef struct {
+        //     ReservedZero#(128)      reserved1;  // 128 bits
+        //     Bit#(64)                compare;    // 64 bits
+        //     Bit#(64)                swapAdd;    // 64 bits
+        // } SendQueueReqDescAtomicOperands deriving(Bits, FShow);
+
+        if let ToCardWorkRbDesc::CompareSwap(desc) | ToCardWorkRbDesc::FetchAdd(desc) = self {
+            dst[0..8].copy_from_slice(&desc.swap_add.to_le_bytes());
+            dst[8..16].copy_from_slice(&desc.compare.to_le_bytes());
+            dst[16..32].copy_from_slice(&[0; 16]);
+            return;
+        }
+
         let (sge2, sge3) = match self {
-            ToCardWorkRbDesc::Read(_) => (None, None),
-            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) => {
+            ToCardWorkRbDesc::Read(desc) => (desc.sge2.as_ref(), desc.sge3.as_ref()),
+            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) | ToCardWorkRbDesc::Send(desc) => {
                 (desc.sge2.as_ref(), desc.sge3.as_ref())
             }
-            ToCardWorkRbDesc::WriteWithImm(desc) => (desc.sge2.as_ref(), desc.sge3.as_ref()),
+            ToCardWorkRbDesc::WriteWithImm(desc)
+            | ToCardWorkRbDesc::SendWithImm(desc)
+            | ToCardWorkRbDesc::SendWithInv(desc) => (desc.sge2.as_ref(), desc.sge3.as_ref()),
+            ToCardWorkRbDesc::CompareSwap(_) | ToCardWorkRbDesc::FetchAdd(_) => (None, None),
+            ToCardWorkRbDesc::Inline(desc) => {
+                desc.write_data(1, dst);
+                return;
+            }
         };
 
         let mut frag_sge = SendQueueReqDescFragSGE(&mut dst[0..16]);
@@ -868,15 +1208,43 @@ impl ToCardWorkRbDesc {
     #[allow(clippy::arithmetic_side_effects)]
     pub(super) fn serialized_desc_cnt(&self) -> u32 {
         let sge_desc_cnt = match self {
-            ToCardWorkRbDesc::Read(_) => 1,
-            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) => 1 + u32::from(desc.sge2.is_some()),
-            ToCardWorkRbDesc::WriteWithImm(desc) => 1 + u32::from(desc.sge2.is_some()),
+            ToCardWorkRbDesc::Read(desc) => 1 + u32::from(desc.sge2.is_some()),
+            ToCardWorkRbDesc::Write(desc) | ToCardWorkRbDesc::ReadResp(desc) | ToCardWorkRbDesc::Send(desc) => {
+                1 + u32::from(desc.sge2.is_some())
+            }
+            ToCardWorkRbDesc::WriteWithImm(desc)
+            | ToCardWorkRbDesc::SendWithImm(desc)
+            | ToCardWorkRbDesc::SendWithInv(desc) => 1 + u32::from(desc.sge2.is_some()),
+            // the operands are always placed in the last descriptor
+            ToCardWorkRbDesc::CompareSwap(_) | ToCardWorkRbDesc::FetchAdd(_) => 2,
+            // the data takes the place of the sges
+            ToCardWorkRbDesc::Inline(desc) => 1 + u32::from(desc.data.len() > INLINE_DATA_SEG_SIZE),
         };
 
         2 + sge_desc_cnt
     }
 }
 
+impl ToCardWorkRbDescInline {
+    /// Write the `idx`th segment of the data into `dst`, the tail is padded with zero
+    #[allow(clippy::indexing_slicing)]
+    fn write_data(&self, idx: usize, dst: &mut [u8]) {
+        let data = self.data.chunks(INLINE_DATA_SEG_SIZE).nth(idx).unwrap_or_default();
+        let mut words = [0; 4];
+        for (word, bytes) in words.iter_mut().zip(data.chunks(8)) {
+            let mut buf = [0; 8];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            *word = u64::from_le_bytes(buf);
+        }
+        let [data0, data1, data2, data3] = words;
+        let mut seg = SendQueueReqDescInlineData(dst);
+        seg.set_data0(data0);
+        seg.set_data1(data1);
+        seg.set_data2(data2);
+        seg.set_data3(data3);
+    }
+}
+
 impl ToHostWorkRbDesc {
     /// (addr, key, len)
     fn read_reth(src: &[u8]) -> (u64, Key, u32) {
@@ -899,19 +1267,19 @@ impl ToHostWorkRbDesc {
         (addr, key, len)
     }
 
-    // FIXME: imm will be in next desc
-    fn read_imm(src: &[u8]) -> u32 {
-        // typedef struct {
-        //     IMM                             data;           // 32
-        // } MetaReportQueueDescFragImmDT deriving(Bits, FShow);
-
-        // first 28 bytes are desc type, status, bth and reth
-        #[allow(clippy::indexing_slicing)]
-        let imm = MetaReportQueueDescFragImmDT(&src[28..32]);
-        // call the `to_be` to convert order
-        imm.get_imm()
+    /// UD SEND has no RETH, so the va of RETH carries the sender of the message instead,
+    /// the source IP is in the higher 32 bits and the source QPN is in the lower 24 bits.
+    #[allow(clippy::cast_possible_truncation)]
+    fn read_datagram_source(trans: ToHostWorkRbDescTransType, va: u64) -> Option<(Qpn, Ipv4Addr)> {
+        matches!(trans, ToHostWorkRbDescTransType::Ud).then(|| {
+            (
+                Qpn::new(va as u32 & DATAGRAM_SOURCE_QPN_MASK),
+                Ipv4Addr::from((va >> 32_i32) as u32),
+            )
+        })
     }
 
+    // FIXME: imm will be in next desc
     // (last_psn, msn, value, code)
     #[allow(clippy::cast_possible_truncation)]
     fn read_aeth(src: &[u8]) -> Result<(Psn, Msn, u8, ToHostWorkRbDescAethCode), DeviceError> {
@@ -1027,24 +1395,76 @@ impl ToHostWorkRbDesc {
                 }))
             }
             ToHostWorkRbDescOpcode::RdmaWriteLastWithImmediate | ToHostWorkRbDescOpcode::RdmaWriteOnlyWithImmediate => {
+                // the immediate data is reported in the next descriptor
                 let (addr, key, len) = Self::read_reth(src);
-                if matches!(common.trans, ToHostWorkRbDescTransType::DtldExtended) {
-                    Err(ToHostWorkRbDescError::Incomplete(IncompleteToHostWorkRbDesc {
-                        parsed: ToHostWorkRbDesc::Raw(ToHostWorkRbDescRaw { common, addr, len, key }),
-                        parsed_cnt: 1,
-                    }))
+                let parsed = if matches!(common.trans, ToHostWorkRbDescTransType::DtldExtended) {
+                    ToHostWorkRbDesc::Raw(ToHostWorkRbDescRaw { common, addr, len, key })
                 } else {
-                    let imm = Self::read_imm(src);
-                    Ok(ToHostWorkRbDesc::WriteWithImm(ToHostWorkRbDescWriteWithImm {
+                    ToHostWorkRbDesc::WriteWithImm(ToHostWorkRbDescWriteWithImm {
                         common,
                         write_type,
                         psn,
-                        imm,
+                        imm: 0,
                         addr,
                         len,
                         key,
-                    }))
-                }
+                        can_auto_ack,
+                        recv_tag: 0,
+                    })
+                };
+                Err(ToHostWorkRbDescError::Incomplete(IncompleteToHostWorkRbDesc {
+                    parsed,
+                    parsed_cnt: 1,
+                }))
+            }
+            ToHostWorkRbDescOpcode::SendFirst
+            | ToHostWorkRbDescOpcode::SendMiddle
+            | ToHostWorkRbDescOpcode::SendLast
+            | ToHostWorkRbDescOpcode::SendOnly => {
+                // the key of RETH carries the tag of the consumed receive buffer
+                let (addr, recv_tag, len) = Self::read_reth(src);
+                let source = Self::read_datagram_source(common.trans, addr);
+                Ok(ToHostWorkRbDesc::Send(ToHostWorkRbDescSend {
+                    common,
+                    write_type,
+                    psn,
+                    addr,
+                    len,
+                    imm: None,
+                    can_auto_ack,
+                    src: source,
+                    recv_tag: recv_tag.get(),
+                    invalidated_rkey: None,
+                }))
+            }
+            ToHostWorkRbDescOpcode::SendLastWithImmediate
+            | ToHostWorkRbDescOpcode::SendOnlyWithImmediate
+            | ToHostWorkRbDescOpcode::SendLastWithInvalidate
+            | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate => {
+                // the immediate data or the invalidated key is reported in the next descriptor
+                // the key of RETH carries the tag of the consumed receive buffer
+                let (addr, recv_tag, len) = Self::read_reth(src);
+                let source = Self::read_datagram_source(common.trans, addr);
+                let with_invalidate = matches!(
+                    opcode,
+                    ToHostWorkRbDescOpcode::SendLastWithInvalidate | ToHostWorkRbDescOpcode::SendOnlyWithInvalidate
+                );
+                Err(ToHostWorkRbDescError::Incomplete(IncompleteToHostWorkRbDesc {
+                    parsed: ToHostWorkRbDesc::Send(ToHostWorkRbDescSend {
+                        common,
+                        write_type,
+                        psn,
+                        addr,
+                        len,
+                        imm: None,
+                        can_auto_ack,
+                        src: source,
+                        recv_tag: recv_tag.get(),
+                        // placeholder, replaced by the key in the next descriptor
+                        invalidated_rkey: with_invalidate.then(Key::default),
+                    }),
+                    parsed_cnt: 1,
+                }))
             }
             ToHostWorkRbDescOpcode::RdmaReadRequest => {
                 let (addr, key, len) = Self::read_reth(src);
@@ -1061,6 +1481,14 @@ impl ToHostWorkRbDesc {
                     parsed_cnt: 1,
                 }))
             }
+            ToHostWorkRbDescOpcode::AtomicAcknowledge
+            | ToHostWorkRbDescOpcode::CompareSwap
+            | ToHostWorkRbDescOpcode::FetchAdd => {
+                // atomic requests are executed by hardware, and atomic acknowledges are reported as `Acknowledge`
+                Err(ToHostWorkRbDescError::DeviceError(DeviceError::ParseDesc(format!(
+                    "ToHostWorkRbDescOpcode = {opcode:?} should not be reported"
+                ))))
+            }
             ToHostWorkRbDescOpcode::Acknowledge => {
                 let (retry_psn, msn_in_ack, value, code) =
                     Self::read_aeth(src).map_err(ToHostWorkRbDescError::DeviceError)?;
@@ -1099,11 +1527,33 @@ impl IncompleteToHostWorkRbDesc {
                 desc.rkey = rkey;
                 Ok(ToHostWorkRbDesc::Read(desc))
             }
+            ToHostWorkRbDesc::Send(mut desc) => {
+                // typedef struct {
+                //     IMM                             data;           // 32
+                // } MetaReportQueueDescFragImmDT deriving(Bits, FShow);
+                #[allow(clippy::indexing_slicing)]
+                let imm = MetaReportQueueDescFragImmDT(&src[0..4]);
+                if desc.invalidated_rkey.is_some() {
+                    desc.invalidated_rkey = Some(Key::new(imm.get_imm()));
+                } else {
+                    desc.imm = Some(imm.get_imm());
+                }
+                Ok(ToHostWorkRbDesc::Send(desc))
+            }
+            ToHostWorkRbDesc::WriteWithImm(mut desc) => {
+                // typedef struct {
+                //     Bit#(32)                        recvTag;        // 32
+                //     IMM                             data;           // 32
+                // } MetaReportQueueDescFragImmDT deriving(Bits, FShow);
+                #[allow(clippy::indexing_slicing)]
+                let imm = MetaReportQueueDescFragImmDT(&src[0..8]);
+                desc.imm = imm.get_imm();
+                desc.recv_tag = imm.get_recv_tag();
+                Ok(ToHostWorkRbDesc::WriteWithImm(desc))
+            }
             ToHostWorkRbDesc::Raw(desc) => Ok(ToHostWorkRbDesc::Raw(desc)), // ignore the
             // redundant imm
-            ToHostWorkRbDesc::WriteOrReadResp(_) | ToHostWorkRbDesc::WriteWithImm(_) | ToHostWorkRbDesc::Ack(_) => {
-                unreachable!()
-            }
+            ToHostWorkRbDesc::WriteOrReadResp(_) | ToHostWorkRbDesc::Ack(_) => unreachable!(),
         }
     }
 }
@@ -1113,6 +1563,9 @@ pub(crate) struct ToCardWorkRbDescBuilder {
     common: Option<ToCardWorkRbDescCommon>,
     seg_list: Vec<Sge>,
     imm: Option<u32>,
+    /// (`swap_add`, `compare`)
+    atomic: Option<(u64, u64)>,
+    inline: Option<Vec<u8>>,
 }
 
 impl ToCardWorkRbDescBuilder {
@@ -1122,6 +1575,8 @@ impl ToCardWorkRbDescBuilder {
             common: None,
             seg_list: Vec::new(),
             imm: None,
+            atomic: None,
+            inline: None,
         }
     }
 
@@ -1140,14 +1595,50 @@ impl ToCardWorkRbDescBuilder {
         self
     }
 
-    pub(crate) fn build(mut self) -> Result<Box<ToCardWorkRbDesc>, Error> {
+    /// The remote key to invalidate shares the field of immediate data
+    pub(crate) fn with_invalidate_rkey(mut self, rkey: Key) -> Self {
+        self.imm = Some(rkey.get());
+        self
+    }
+
+    pub(crate) fn with_atomic(mut self, swap_add: u64, compare: u64) -> Self {
+        self.atomic = Some((swap_add, compare));
+        self
+    }
+
+    /// The data is carried by the descriptor instead of the sges
+    pub(crate) fn with_inline(mut self, data: Vec<u8>) -> Self {
+        self.inline = Some(data);
+        self
+    }
+
+    pub(crate) fn build(self) -> Result<Box<ToCardWorkRbDesc>, Error> {
         let common = self.common.ok_or_else(|| Error::BuildDescFailed("common"))?;
+        if let Some(data) = self.inline {
+            let imm = match self.type_ {
+                ToCardWorkRbDescOpcode::Write | ToCardWorkRbDescOpcode::Send => None,
+                ToCardWorkRbDescOpcode::WriteWithImm
+                | ToCardWorkRbDescOpcode::SendWithImm
+                | ToCardWorkRbDescOpcode::SendWithInv => Some(self.imm.ok_or_else(|| Error::BuildDescFailed("imm"))?),
+                ToCardWorkRbDescOpcode::Read
+                | ToCardWorkRbDescOpcode::ReadResp
+                | ToCardWorkRbDescOpcode::CompareSwap
+                | ToCardWorkRbDescOpcode::FetchAdd => return Err(Error::BuildDescFailed("inline")),
+            };
+            return Ok(Box::new(ToCardWorkRbDesc::Inline(ToCardWorkRbDescInline {
+                common,
+                opcode: self.type_,
+                imm,
+                data,
+            })));
+        }
+        let mut seg_list = self.seg_list.into_iter();
         let desc = match self.type_ {
             ToCardWorkRbDescOpcode::Write => {
-                let sge0 = self.seg_list.pop().ok_or_else(|| Error::BuildDescFailed("sge"))?;
-                let sge1 = self.seg_list.pop();
-                let sge2 = self.seg_list.pop();
-                let sge3 = self.seg_list.pop();
+                let sge0 = seg_list.next().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let sge1 = seg_list.next();
+                let sge2 = seg_list.next();
+                let sge3 = seg_list.next();
                 ToCardWorkRbDesc::Write(ToCardWorkRbDescWrite {
                     common,
                     is_last: true,
@@ -1159,10 +1650,10 @@ impl ToCardWorkRbDescBuilder {
                 })
             }
             ToCardWorkRbDescOpcode::WriteWithImm => {
-                let sge0 = self.seg_list.pop().ok_or_else(|| Error::BuildDescFailed("sge"))?;
-                let sge1 = self.seg_list.pop();
-                let sge2 = self.seg_list.pop();
-                let sge3 = self.seg_list.pop();
+                let sge0 = seg_list.next().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let sge1 = seg_list.next();
+                let sge2 = seg_list.next();
+                let sge3 = seg_list.next();
                 let imm = self.imm.ok_or_else(|| Error::BuildDescFailed("imm"))?;
                 ToCardWorkRbDesc::WriteWithImm(ToCardWorkRbDescWriteWithImm {
                     common,
@@ -1176,17 +1667,23 @@ impl ToCardWorkRbDescBuilder {
                 })
             }
             ToCardWorkRbDescOpcode::Read => {
-                let sge0 = self.seg_list.pop().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let sge0 = seg_list.next().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let sge1 = seg_list.next();
+                let sge2 = seg_list.next();
+                let sge3 = seg_list.next();
                 ToCardWorkRbDesc::Read(ToCardWorkRbDescRead {
                     common,
-                    sge: sge0.into(),
+                    sge0: sge0.into(),
+                    sge1: sge1.map(Into::into),
+                    sge2: sge2.map(Into::into),
+                    sge3: sge3.map(Into::into),
                 })
             }
             ToCardWorkRbDescOpcode::ReadResp => {
-                let sge0 = self.seg_list.pop().ok_or_else(|| Error::BuildDescFailed("sge"))?;
-                let sge1 = self.seg_list.pop();
-                let sge2 = self.seg_list.pop();
-                let sge3 = self.seg_list.pop();
+                let sge0 = seg_list.next().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let sge1 = seg_list.next();
+                let sge2 = seg_list.next();
+                let sge3 = seg_list.next();
                 ToCardWorkRbDesc::ReadResp(ToCardWorkRbDescWrite {
                     common,
                     is_last: true,
@@ -1197,6 +1694,70 @@ impl ToCardWorkRbDescBuilder {
                     sge3: sge3.map(Into::into),
                 })
             }
+            ToCardWorkRbDescOpcode::Send => {
+                let sge0 = seg_list.next().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let sge1 = seg_list.next();
+                let sge2 = seg_list.next();
+                let sge3 = seg_list.next();
+                ToCardWorkRbDesc::Send(ToCardWorkRbDescWrite {
+                    common,
+                    is_last: true,
+                    is_first: true,
+                    sge0: sge0.into(),
+                    sge1: sge1.map(Into::into),
+                    sge2: sge2.map(Into::into),
+                    sge3: sge3.map(Into::into),
+                })
+            }
+            ToCardWorkRbDescOpcode::SendWithImm => {
+                let sge0 = seg_list.next().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let sge1 = seg_list.next();
+                let sge2 = seg_list.next();
+                let sge3 = seg_list.next();
+                let imm = self.imm.ok_or_else(|| Error::BuildDescFailed("imm"))?;
+                ToCardWorkRbDesc::SendWithImm(ToCardWorkRbDescWriteWithImm {
+                    common,
+                    is_last: true,
+                    is_first: true,
+                    imm,
+                    sge0: sge0.into(),
+                    sge1: sge1.map(Into::into),
+                    sge2: sge2.map(Into::into),
+                    sge3: sge3.map(Into::into),
+                })
+            }
+            ToCardWorkRbDescOpcode::SendWithInv => {
+                let sge0 = seg_list.next().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let sge1 = seg_list.next();
+                let sge2 = seg_list.next();
+                let sge3 = seg_list.next();
+                let rkey = self.imm.ok_or_else(|| Error::BuildDescFailed("invalidate rkey"))?;
+                ToCardWorkRbDesc::SendWithInv(ToCardWorkRbDescWriteWithImm {
+                    common,
+                    is_last: true,
+                    is_first: true,
+                    imm: rkey,
+                    sge0: sge0.into(),
+                    sge1: sge1.map(Into::into),
+                    sge2: sge2.map(Into::into),
+                    sge3: sge3.map(Into::into),
+                })
+            }
+            ToCardWorkRbDescOpcode::CompareSwap | ToCardWorkRbDescOpcode::FetchAdd => {
+                let sge = seg_list.next().ok_or_else(|| Error::BuildDescFailed("sge"))?;
+                let (swap_add, compare) = self.atomic.ok_or_else(|| Error::BuildDescFailed("atomic"))?;
+                let desc = ToCardWorkRbDescAtomic {
+                    common,
+                    sge: sge.into(),
+                    swap_add,
+                    compare,
+                };
+                if matches!(self.type_, ToCardWorkRbDescOpcode::CompareSwap) {
+                    ToCardWorkRbDesc::CompareSwap(desc)
+                } else {
+                    ToCardWorkRbDesc::FetchAdd(desc)
+                }
+            }
         };
         Ok(Box::new(desc))
     }
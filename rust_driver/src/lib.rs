@@ -141,45 +141,63 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::net::{Ipv4Addr, SocketAddr};
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
+#[cfg(test)]
+use blue_rdma_device::emulator::LocalNetwork;
+use blue_rdma_device::Emulator;
 use buf::{PacketBuf, NIC_PACKET_BUFFER_SLOT_SIZE};
 use checker::{PacketChecker, PacketCheckerContext, RecvContextMap};
 use core_affinity::CoreId;
 use ctrl_poller::{ControlPoller, ControlPollerContext};
 use derive_builder::Builder;
+use device::scheduler::MAX_SGL_LENGTH;
 use device::software::emulator::EmulatorDevice;
 use device::{
-    ToCardCtrlRbDescCommon, ToCardCtrlRbDescSetNetworkParam, ToCardCtrlRbDescSetRawPacketReceiveMeta, ToCardWorkRbDesc,
-    ToCardWorkRbDescBuilder, ToCardWorkRbDescOpcode,
+    ToCardCtrlRbDescCommon, ToCardCtrlRbDescPostRecv, ToCardCtrlRbDescSetNetworkParam,
+    ToCardCtrlRbDescSetRawPacketReceiveMeta, ToCardWorkRbDesc, ToCardWorkRbDescBuilder, ToCardWorkRbDescOpcode,
 };
 use eui48::MacAddress;
 use flume::unbounded;
 use nic::NicInterface;
-use op_ctx::{CtrlOpCtx, OpCtx};
+use op_ctx::{CtrlOpCtx, CtxStatus, OpCtx};
 use parking_lot::{Mutex, RwLock};
 use qp::QpContext;
 use retry::{RetryMap, RetryMonitor, RetryMonitorContext};
 use thiserror::Error;
-use types::{Key, Msn, Psn, Qpn, RdmaDeviceNetworkParam, Sge, WorkReqSendFlag};
-use utils::{calculate_packet_cnt, Buffer};
+use types::{
+    AddressHandle, Imm, Key, Msn, Psn, QpType, Qpn, RdmaDeviceNetworkParam, RecvCompletion, Sge, WorkReqSendFlag,
+    MAX_INLINE_DATA,
+};
+use utils::{block_on, calculate_packet_cnt, Buffer};
 use work_poller::{WorkDescPoller, WorkDescPollerContext};
 
+use crate::cq::{CqTable, WorkCompletion, WorkCompletionOpcode, WorkCompletionStatus};
 use crate::device::{
     DeviceAdaptor, EmulatedDevice, HardwareDevice, SoftwareDevice, ToCardCtrlRbDesc, ToCardWorkRbDescCommon,
 };
-use crate::mr::{MrCtx, MrPgt, ACKNOWLEDGE_BUFFER_SIZE, NIC_BUFFER_SIZE};
+use crate::mr::{MrPgt, MrTable, ACKNOWLEDGE_BUFFER_SIZE, NIC_BUFFER_SIZE};
+use crate::mw::MwTable;
 use crate::pd::PdCtx;
+use crate::srq::SrqTable;
 
+/// completion queue
+pub mod cq;
 /// memory region
 pub mod mr;
+/// memory window
+pub mod mw;
 /// op context for user to track the status of the write/read/control operation
 pub mod op_ctx;
 /// protection domain
 pub mod pd;
 /// queue pair related structs and functions
 pub mod qp;
+/// shared receive queue
+pub mod srq;
 /// types exported to user
 pub mod types;
 
@@ -215,14 +233,114 @@ pub use retry::RetryConfig;
 pub use types::Error;
 pub use utils::{AlignedMemory, MmapMemory};
 
-pub use crate::mr::Mr;
+pub use crate::cq::Cq;
+pub use crate::mr::{Mr, MrConfig};
+pub use crate::mw::{Mw, MwType};
 pub use crate::pd::Pd;
+pub use crate::srq::Srq;
 
-const MR_KEY_IDX_BIT_CNT: usize = 8;
-const MR_TABLE_SIZE: usize = 64;
-const MR_PGT_LENGTH: usize = 1024;
 const MR_PGT_ENTRY_SIZE: usize = 8;
 const DEFAULT_RMDA_PORT: u16 = 4791;
+/// Atomic operations operate on a naturally aligned 8 bytes word
+const ATOMIC_OPERAND_SIZE: u32 = 8;
+/// How long `Device::shutdown` waits for the outstanding requests to finish
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+/// The failure cause of the requests flushed by `Device::shutdown`
+const SHUTDOWN_FLUSH_CAUSE: &str = "flushed by device shutdown";
+
+/// The local data of a work request
+#[derive(Clone, Copy, Debug)]
+enum WorkReqData<'a> {
+    /// gathered from or scattered into the registered memory by the device
+    Sgl(&'a [Sge]),
+    /// copied into the descriptor when posting
+    Inline(&'a [u8]),
+}
+
+/// A work request posted to the send queue of a QP, which is built by the posting methods of `Device`
+///
+/// The semantics shared by the posting methods:
+/// * `wr_id` is reported in the completion entry if the QP is bound to a send CQ and the request is signaled, by
+///   `WorkReqSendFlag::IbvSendSignaled` or `Qp::sq_sig_all`.
+/// * A SGL holds 1 to `MAX_SGL_LENGTH` SGEs, which are gathered or scattered in order, and `IbvSendInline` is rejected
+///   with it.
+/// * Inline data holds at most `MAX_INLINE_DATA` bytes, which are copied into the descriptor, so the lkeys are not
+///   involved and the buffer can be reused once posted. `IbvSendInline` is implied.
+/// * The returned context is finished once the peer acknowledges or responds to a request of a RC QP, or once the
+///   device accepts a request of a UC or UD QP.
+///
+/// Posting fails if the QP does not exist, or can't post in its state, or doesn't support the operation,
+/// if the send queue is full and `Qp::sq_blocking` is not set, if the data is out of the limits above,
+/// if the device doesn't support the request, or if the descriptor is not accepted by the device.
+#[derive(Debug)]
+struct WorkRequest<'a> {
+    wr_id: u64,
+    opcode: ToCardWorkRbDescOpcode,
+    dqpn: Qpn,
+    /// the remote address and key, or the key to invalidate of a SEND with invalidate
+    raddr: u64,
+    rkey: Key,
+    flags: WorkReqSendFlag,
+    data: WorkReqData<'a>,
+    imm: Option<Imm>,
+    /// the swap or add operand, and the compare operand of an atomic operation
+    atomic: Option<(u64, u64)>,
+    /// the destination of a SEND through a UD QP
+    ah: Option<&'a AddressHandle>,
+}
+
+impl<'a> WorkRequest<'a> {
+    fn new(
+        wr_id: u64,
+        opcode: ToCardWorkRbDescOpcode,
+        dqpn: Qpn,
+        flags: WorkReqSendFlag,
+        data: WorkReqData<'a>,
+    ) -> Self {
+        Self {
+            wr_id,
+            opcode,
+            dqpn,
+            raddr: 0,
+            rkey: Key::default(),
+            flags,
+            data,
+            imm: None,
+            atomic: None,
+            ah: None,
+        }
+    }
+
+    fn with_remote(mut self, raddr: u64, rkey: Key) -> Self {
+        self.raddr = raddr;
+        self.rkey = rkey;
+        self
+    }
+
+    fn with_imm(mut self, imm: Option<Imm>) -> Self {
+        self.imm = imm;
+        self
+    }
+
+    fn with_atomic(mut self, swap_add: u64, compare: u64) -> Self {
+        self.atomic = Some((swap_add, compare));
+        self
+    }
+
+    fn with_ah(mut self, ah: &'a AddressHandle) -> Self {
+        self.ah = Some(ah);
+        self
+    }
+}
+
+/// The opcode of a SEND with optional immediate data
+fn send_opcode(imm: Option<Imm>) -> ToCardWorkRbDescOpcode {
+    if imm.is_some() {
+        ToCardWorkRbDescOpcode::SendWithImm
+    } else {
+        ToCardWorkRbDescOpcode::Send
+    }
+}
 
 type ThreadSafeHashmap<K, V> = Arc<RwLock<HashMap<K, V>>>;
 
@@ -236,16 +354,22 @@ pub struct Device(Arc<DeviceInner<dyn DeviceAdaptor>>);
 
 struct DeviceInner<D: ?Sized> {
     pd: Mutex<HashMap<Pd, PdCtx>>,
-    mr_table: Mutex<[Option<MrCtx>; MR_TABLE_SIZE]>,
+    cq_table: CqTable,
+    srq_table: SrqTable,
+    mr_table: Mutex<MrTable>,
+    mw_table: MwTable,
     qp_table: ThreadSafeHashmap<Qpn, QpContext>,
     mr_pgt: Mutex<MrPgt>,
+    mr_config: MrConfig,
     user_op_ctx_map: ThreadSafeHashmap<(Qpn, Msn), OpCtx<()>>,
     ctrl_op_ctx_map: ThreadSafeHashmap<u32, CtrlOpCtx>,
     next_ctrl_op_id: AtomicU32,
-    work_desc_poller: OnceLock<WorkDescPoller>,
-    pkt_checker_thread: OnceLock<PacketChecker>,
-    retry_monitor: OnceLock<RetryMonitor>,
-    ctrl_desc_poller: OnceLock<ControlPoller>,
+    /// set by `Device::shutdown`, no more request is accepted
+    is_shutdown: AtomicBool,
+    work_desc_poller: Mutex<Option<WorkDescPoller>>,
+    pkt_checker_thread: Mutex<Option<PacketChecker>>,
+    retry_monitor: Mutex<Option<RetryMonitor>>,
+    ctrl_desc_poller: Mutex<Option<ControlPoller>>,
     local_network: RdmaDeviceNetworkParam,
     nic_device: Mutex<Option<NicInterface>>,
     buffer_keeper: Mutex<Vec<Buffer>>,
@@ -257,12 +381,17 @@ impl<D: ?Sized> Debug for DeviceInner<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DeviceInner")
             .field("pd", &self.pd)
+            .field("cq_table", &self.cq_table)
+            .field("srq_table", &self.srq_table)
             .field("mr_table", &self.mr_table)
+            .field("mw_table", &self.mw_table)
             .field("qp_table", &self.qp_table)
             .field("mr_pgt", &self.mr_pgt)
+            .field("mr_config", &self.mr_config)
             .field("user_op_ctx_map", &self.user_op_ctx_map)
             .field("ctrl_op_ctx_map", &self.ctrl_op_ctx_map)
             .field("next_ctrl_op_id", &self.next_ctrl_op_id)
+            .field("is_shutdown", &self.is_shutdown)
             .field("work_desc_poller", &self.work_desc_poller)
             .field("pkt_checker_thread", &self.pkt_checker_thread)
             .field("retry_monitor", &self.retry_monitor)
@@ -296,6 +425,10 @@ pub enum DeviceType {
 
     /// Pure software device, might be different from the hardware device
     Software,
+
+    /// The software device on a network in the process instead of a TUN device, see `LocalNetwork`
+    #[cfg(test)]
+    Local(Arc<LocalNetwork>),
 }
 
 /// Configuration of the device
@@ -316,15 +449,18 @@ pub struct DeviceConfig<Strat: SchedulerStrategy> {
 
     /// The scheduler chunk size
     scheduler_size: u32,
+
+    /// The size of the MR table and the page table
+    #[builder(default)]
+    mr_config: MrConfig,
 }
 
 impl Device {
-    const MR_TABLE_EMPTY_ELEM: Option<MrCtx> = None;
-
     /// # Errors
     ///
     /// Will return `Err` if the device failed to create the `adaptor` or the device failed to init.
     pub fn new<Strat: SchedulerStrategy>(config: DeviceConfig<Strat>) -> Result<Self, Error> {
+        let pgt_length = config.mr_config.pgt_length as usize;
         let mut core_ids = core_affinity::get_core_ids();
         let scheduler_core = core_ids.as_mut().and_then(|v| v.pop());
         let dev = match config.device_type {
@@ -332,21 +468,26 @@ impl Device {
                 let adaptor = HardwareDevice::new(device_path, config.strategy, scheduler_core, config.scheduler_size)
                     .map_err(|e| Error::Device(Box::new(e)))?;
                 let use_hugepage = adaptor.use_hugepage();
-                let pg_table_buf = Buffer::new(MR_PGT_LENGTH * MR_PGT_ENTRY_SIZE, use_hugepage)
+                let pg_table_buf = Buffer::new(pgt_length.saturating_mul(MR_PGT_ENTRY_SIZE), use_hugepage)
                     .map_err(|e| Error::ResourceNoAvailable(format!("hugepage {e}")))?;
                 Self(Arc::new(DeviceInner {
                     pd: Mutex::new(HashMap::new()),
-                    mr_table: Mutex::new([Self::MR_TABLE_EMPTY_ELEM; MR_TABLE_SIZE]),
+                    cq_table: Mutex::new(HashMap::new()),
+                    srq_table: Mutex::new(HashMap::new()),
+                    mr_table: Mutex::new(MrTable::new(config.mr_config.mr_table_size)),
+                    mw_table: Arc::new(Mutex::new(HashMap::new())),
                     qp_table: Arc::new(RwLock::new(HashMap::new())),
-                    mr_pgt: Mutex::new(MrPgt::new(pg_table_buf)),
+                    mr_pgt: Mutex::new(MrPgt::new(pg_table_buf, pgt_length)),
+                    mr_config: config.mr_config,
                     user_op_ctx_map: Arc::new(RwLock::new(HashMap::new())),
                     ctrl_op_ctx_map: Arc::new(RwLock::new(HashMap::new())),
                     next_ctrl_op_id: AtomicU32::new(0),
+                    is_shutdown: AtomicBool::new(false),
                     adaptor,
-                    retry_monitor: OnceLock::new(),
-                    pkt_checker_thread: OnceLock::new(),
-                    work_desc_poller: OnceLock::new(),
-                    ctrl_desc_poller: OnceLock::new(),
+                    retry_monitor: Mutex::new(None),
+                    pkt_checker_thread: Mutex::new(None),
+                    work_desc_poller: Mutex::new(None),
+                    ctrl_desc_poller: Mutex::new(None),
                     nic_device: Mutex::new(None),
                     buffer_keeper: Vec::new().into(),
                     local_network: config.network_config,
@@ -364,22 +505,30 @@ impl Device {
                     config.scheduler_size,
                 )
                 .map_err(|e| Error::Device(Box::new(e)))?;
+                adaptor
+                    .apply_mr_config(&config.mr_config)
+                    .map_err(|e| Error::Device(Box::new(e)))?;
                 let use_hugepage = adaptor.use_hugepage();
-                let pg_table_buf = Buffer::new(MR_PGT_LENGTH * MR_PGT_ENTRY_SIZE, use_hugepage)
+                let pg_table_buf = Buffer::new(pgt_length.saturating_mul(MR_PGT_ENTRY_SIZE), use_hugepage)
                     .map_err(|e| Error::ResourceNoAvailable(format!("hugepage {e}")))?;
                 Self(Arc::new(DeviceInner {
                     pd: Mutex::new(HashMap::new()),
-                    mr_table: Mutex::new([Self::MR_TABLE_EMPTY_ELEM; MR_TABLE_SIZE]),
+                    cq_table: Mutex::new(HashMap::new()),
+                    srq_table: Mutex::new(HashMap::new()),
+                    mr_table: Mutex::new(MrTable::new(config.mr_config.mr_table_size)),
+                    mw_table: Arc::new(Mutex::new(HashMap::new())),
                     qp_table: Arc::new(RwLock::new(HashMap::new())),
-                    mr_pgt: Mutex::new(MrPgt::new(pg_table_buf)),
+                    mr_pgt: Mutex::new(MrPgt::new(pg_table_buf, pgt_length)),
+                    mr_config: config.mr_config,
                     user_op_ctx_map: Arc::new(RwLock::new(HashMap::new())),
                     ctrl_op_ctx_map: Arc::new(RwLock::new(HashMap::new())),
                     next_ctrl_op_id: AtomicU32::new(0),
+                    is_shutdown: AtomicBool::new(false),
                     adaptor,
-                    retry_monitor: OnceLock::new(),
-                    pkt_checker_thread: OnceLock::new(),
-                    work_desc_poller: OnceLock::new(),
-                    ctrl_desc_poller: OnceLock::new(),
+                    retry_monitor: Mutex::new(None),
+                    pkt_checker_thread: Mutex::new(None),
+                    work_desc_poller: Mutex::new(None),
+                    ctrl_desc_poller: Mutex::new(None),
                     nic_device: Mutex::new(None),
                     buffer_keeper: Vec::new().into(),
                     local_network: config.network_config,
@@ -396,29 +545,12 @@ impl Device {
                 // .map_err(Error::Device)?;
                 let [a, b, c, _] = config.network_config.ipaddr.octets();
                 let tun_ip = Ipv4Addr::new(a, b, c, 233).into();
-                let adaptor = EmulatorDevice::new(config.strategy, scheduler_core, config.scheduler_size, tun_ip)
-                    .map_err(|e| Error::Device(Box::new(e)))?;
-                let use_hugepage = adaptor.use_hugepage();
-                let pg_table_buf = Buffer::new(MR_PGT_LENGTH * MR_PGT_ENTRY_SIZE, use_hugepage)
-                    .map_err(|e| Error::ResourceNoAvailable(format!("hugepage {e}")))?;
-                Self(Arc::new(DeviceInner {
-                    pd: Mutex::new(HashMap::new()),
-                    mr_table: Mutex::new([Self::MR_TABLE_EMPTY_ELEM; MR_TABLE_SIZE]),
-                    qp_table: Arc::new(RwLock::new(HashMap::new())),
-                    mr_pgt: Mutex::new(MrPgt::new(pg_table_buf)),
-                    user_op_ctx_map: Arc::new(RwLock::new(HashMap::new())),
-                    ctrl_op_ctx_map: Arc::new(RwLock::new(HashMap::new())),
-                    next_ctrl_op_id: AtomicU32::new(0),
-                    adaptor,
-                    retry_monitor: OnceLock::new(),
-                    pkt_checker_thread: OnceLock::new(),
-                    work_desc_poller: OnceLock::new(),
-                    ctrl_desc_poller: OnceLock::new(),
-                    nic_device: Mutex::new(None),
-                    buffer_keeper: Vec::new().into(),
-                    local_network: config.network_config,
-                    retry_map: RetryMap::new(config.retry_config.max_retry, config.retry_config.retry_timeout),
-                }))
+                return Self::new_software(config, Emulator::new_emulator(tun_ip), scheduler_core, core_ids);
+            }
+            #[cfg(test)]
+            DeviceType::Local(ref network) => {
+                let emulator = Emulator::new_local(Arc::clone(network));
+                return Self::new_software(config, emulator, scheduler_core, core_ids);
             }
         };
         dev.init(config.retry_config, core_ids)?;
@@ -426,102 +558,592 @@ impl Device {
         Ok(dev)
     }
 
-    fn write_or_read(
-        &self,
-        dqpn: Qpn,
-        raddr: u64,
-        rkey: Key,
-        flags: WorkReqSendFlag,
-        sge0: Sge,
-        is_read: bool,
-    ) -> Result<OpCtx<()>, Error> {
-        let (common, key) = {
-            let total_len = sge0.len;
+    /// create the software device, which runs the emulator in the process
+    fn new_software<Strat: SchedulerStrategy>(
+        config: DeviceConfig<Strat>,
+        emulator: Arc<Emulator>,
+        scheduler_core: Option<CoreId>,
+        core_ids: Option<Vec<CoreId>>,
+    ) -> Result<Self, Error> {
+        let pgt_length = config.mr_config.pgt_length as usize;
+        let adaptor = EmulatorDevice::new(emulator, config.strategy, scheduler_core, config.scheduler_size)
+            .map_err(|e| Error::Device(Box::new(e)))?;
+        let use_hugepage = adaptor.use_hugepage();
+        let pg_table_buf = Buffer::new(pgt_length.saturating_mul(MR_PGT_ENTRY_SIZE), use_hugepage)
+            .map_err(|e| Error::ResourceNoAvailable(format!("hugepage {e}")))?;
+        let dev = Self(Arc::new(DeviceInner {
+            pd: Mutex::new(HashMap::new()),
+            cq_table: Mutex::new(HashMap::new()),
+            srq_table: Mutex::new(HashMap::new()),
+            mr_table: Mutex::new(MrTable::new(config.mr_config.mr_table_size)),
+            mw_table: Arc::new(Mutex::new(HashMap::new())),
+            qp_table: Arc::new(RwLock::new(HashMap::new())),
+            mr_pgt: Mutex::new(MrPgt::new(pg_table_buf, pgt_length)),
+            mr_config: config.mr_config,
+            user_op_ctx_map: Arc::new(RwLock::new(HashMap::new())),
+            ctrl_op_ctx_map: Arc::new(RwLock::new(HashMap::new())),
+            next_ctrl_op_id: AtomicU32::new(0),
+            is_shutdown: AtomicBool::new(false),
+            adaptor,
+            retry_monitor: Mutex::new(None),
+            pkt_checker_thread: Mutex::new(None),
+            work_desc_poller: Mutex::new(None),
+            ctrl_desc_poller: Mutex::new(None),
+            nic_device: Mutex::new(None),
+            buffer_keeper: Vec::new().into(),
+            local_network: config.network_config,
+            retry_map: RetryMap::new(config.retry_config.max_retry, config.retry_config.retry_timeout),
+        }));
+        dev.init(config.retry_config, core_ids)?;
+
+        Ok(dev)
+    }
+
+    fn do_work_req(&self, wr: WorkRequest<'_>) -> Result<OpCtx<()>, Error> {
+        self.check_running()?;
+        let WorkRequest {
+            wr_id,
+            opcode,
+            dqpn,
+            raddr,
+            rkey,
+            flags,
+            data,
+            imm,
+            atomic,
+            ah,
+        } = wr;
+        // the request is completed by the response of the peer, instead of an acknowledge
+        let is_read = matches!(
+            opcode,
+            ToCardWorkRbDescOpcode::Read | ToCardWorkRbDescOpcode::CompareSwap | ToCardWorkRbDescOpcode::FetchAdd
+        );
+        let (sge_cnt, total_len, flags) = match data {
+            WorkReqData::Sgl(sgl) => {
+                if sgl.is_empty() || sgl.len() > MAX_SGL_LENGTH {
+                    return Err(Error::Invalid(format!("SGL length :{}", sgl.len())));
+                }
+                // the sges may point to any memory, only the device reads them through the lkeys
+                if flags.contains(WorkReqSendFlag::IbvSendInline) {
+                    return Err(Error::Invalid("IbvSendInline with a SGL".to_owned()));
+                }
+                let total_len = sgl
+                    .iter()
+                    .try_fold(0u32, |acc, sge| acc.checked_add(sge.len))
+                    .ok_or_else(|| Error::Invalid("SGL total length overflow".to_owned()))?;
+                (sgl.len(), total_len, flags)
+            }
+            WorkReqData::Inline(bytes) => {
+                let total_len = u32::try_from(bytes.len())
+                    .ok()
+                    .filter(|len| *len <= MAX_INLINE_DATA)
+                    .ok_or_else(|| Error::Invalid(format!("inline data length :{}", bytes.len())))?;
+                (0, total_len, flags | WorkReqSendFlag::IbvSendInline)
+            }
+        };
+        let wc_opcode = match opcode {
+            ToCardWorkRbDescOpcode::Write | ToCardWorkRbDescOpcode::WriteWithImm => WorkCompletionOpcode::RdmaWrite,
+            ToCardWorkRbDescOpcode::Read => WorkCompletionOpcode::RdmaRead,
+            ToCardWorkRbDescOpcode::Send
+            | ToCardWorkRbDescOpcode::SendWithImm
+            | ToCardWorkRbDescOpcode::SendWithInv => WorkCompletionOpcode::Send,
+            ToCardWorkRbDescOpcode::CompareSwap => WorkCompletionOpcode::CompareSwap,
+            ToCardWorkRbDescOpcode::FetchAdd => WorkCompletionOpcode::FetchAdd,
+            ToCardWorkRbDescOpcode::ReadResp => {
+                return Err(Error::Invalid("read response is not a work request".to_owned()))
+            }
+        };
+        self.0
+            .adaptor
+            .check_work_req(&opcode, flags, sge_cnt)
+            .map_err(Error::NotSupport)?;
+        let ctx = OpCtx::new_running();
+        // the credit is taken without holding the QP table, for a blocking post waits for the completions
+        let (send_credits, sq_blocking) = {
             let qp_guard = self.0.qp_table.read();
             let qp = qp_guard.get(&dqpn).ok_or(Error::Invalid(format!("Qpn :{dqpn:?}")))?;
+            (Arc::clone(&qp.send_credits), qp.sq_blocking)
+        };
+        let credit = if sq_blocking {
+            block_on(send_credits.acquire())
+        } else {
+            send_credits.try_acquire()?
+        };
+        ctx.set_credit(credit);
+        let (
+            mut common,
+            packet_cnt,
+            sending_psn,
+            key,
+            send_cq,
+            is_reliable,
+            retry,
+            invalidate_rkey,
+            is_signaled,
+            preceding,
+        ) = {
+            let qp_guard = self.0.qp_table.read();
+            let qp = qp_guard.get(&dqpn).ok_or(Error::Invalid(format!("Qpn :{dqpn:?}")))?;
+            if !qp.state().can_post_send() {
+                return Err(Error::Invalid(format!("send request in {:?} state", qp.state())));
+            }
+            // UC QP only supports SEND and RDMA WRITE, for there is no response to a lost request
+            if matches!(qp.qp_type, QpType::Uc) && is_read {
+                return Err(Error::Invalid(format!("{opcode:?} on UC QP")));
+            }
+            // UD QP is not connected, the destination is given by the address handle of each SEND
+            let is_datagram = matches!(qp.qp_type, QpType::Ud);
+            if is_datagram != ah.is_some() {
+                return Err(Error::Invalid(format!("address handle of {:?} QP", qp.qp_type)));
+            }
+            if is_datagram && total_len > u32::from(&qp.pmtu) {
+                return Err(Error::Invalid(format!("UD message length :{total_len}")));
+            }
+            if is_datagram && matches!(opcode, ToCardWorkRbDescOpcode::SendWithInv) {
+                return Err(Error::Invalid(format!("{opcode:?} on UD QP")));
+            }
+            let (dqp_ip, dest_qpn, mac_addr, qkey) = ah.map_or((qp.dqp_ip, qp.qpn, qp.dqp_mac_addr, 0), |ah| {
+                (ah.dqp_ip, ah.dqpn, ah.dqp_mac, ah.qkey)
+            });
             let msn = qp.next_msn();
-            let mut common = ToCardWorkRbDescCommon {
+            let fence = {
+                let mut outstanding_reads = qp.outstanding_reads.lock();
+                outstanding_reads.retain(|read| matches!(read.status(), CtxStatus::Running));
+                let fence = if flags.contains(WorkReqSendFlag::IbvSendFence) {
+                    outstanding_reads.clone()
+                } else {
+                    Vec::new()
+                };
+                if is_read {
+                    outstanding_reads.push(ctx.clone());
+                }
+                fence
+            };
+            let is_signaled = qp.sq_sig_all || flags.contains(WorkReqSendFlag::IbvSendSignaled);
+            // an unsignaled request of UC and UD QPs is done once it's posted, so there is nothing to complete
+            let preceding = if matches!(qp.qp_type, QpType::Rc) {
+                let mut unsignaled_reqs = qp.unsignaled_reqs.lock();
+                if is_signaled {
+                    unsignaled_reqs
+                        .drain(..)
+                        .filter(|req| matches!(req.status(), CtxStatus::Running))
+                        .collect()
+                } else {
+                    while unsignaled_reqs
+                        .front()
+                        .is_some_and(|req| !matches!(req.status(), CtxStatus::Running))
+                    {
+                        let _: Option<OpCtx<()>> = unsignaled_reqs.pop_front();
+                    }
+                    unsignaled_reqs.push_back(ctx.clone());
+                    Vec::new()
+                }
+            } else {
+                Vec::new()
+            };
+            // the key to invalidate is carried in the IETH rather than the RETH
+            let (rkey, invalidate_rkey) = if matches!(opcode, ToCardWorkRbDescOpcode::SendWithInv) {
+                (Key::default(), Some(rkey))
+            } else {
+                (rkey, None)
+            };
+            let common = ToCardWorkRbDescCommon {
                 total_len,
                 raddr,
                 rkey,
-                dqp_ip: qp.dqp_ip,
-                dqpn: qp.qpn,
-                mac_addr: qp.dqp_mac_addr,
+                dqp_ip,
+                dqpn: dest_qpn,
+                mac_addr,
                 pmtu: qp.pmtu,
                 flags,
                 qp_type: qp.qp_type,
                 psn: Psn::default(),
                 msn,
+                sqpn: qp.qpn,
+                qkey,
+                fence,
             };
             let packet_cnt = if !is_read {
                 calculate_packet_cnt(qp.pmtu, raddr, total_len)
             } else {
                 1
             };
-            let first_pkt_psn = {
-                let mut send_psn = qp.sending_psn.lock();
-                let first_pkt_psn = *send_psn;
-                *send_psn = send_psn.wrapping_add(packet_cnt);
-                first_pkt_psn
-            };
-            common.psn = first_pkt_psn;
-            let key = (common.dqpn, msn);
-            (common, key)
+            let key = (qp.qpn, msn);
+            (
+                common,
+                packet_cnt,
+                Arc::clone(&qp.sending_psn),
+                key,
+                qp.send_cq.as_deref().cloned(),
+                matches!(qp.qp_type, QpType::Rc),
+                (qp.max_retry, qp.retry_timeout, qp.rnr_retry),
+                invalidate_rkey,
+                is_signaled,
+                preceding,
+            )
         };
-        let opcode = if !is_read {
-            ToCardWorkRbDescOpcode::Write
-        } else {
-            ToCardWorkRbDescOpcode::Read
+        let mut builder = ToCardWorkRbDescBuilder::new(opcode);
+        match data {
+            WorkReqData::Sgl(sgl) => {
+                for sge in sgl {
+                    builder = builder.with_sge(*sge);
+                }
+            }
+            WorkReqData::Inline(bytes) => builder = builder.with_inline(bytes.to_vec()),
+        }
+        if let Some(imm) = imm {
+            builder = builder.with_imm(imm);
+        }
+        if let Some(invalidate_rkey) = invalidate_rkey {
+            builder = builder.with_invalidate_rkey(invalidate_rkey);
+        }
+        if let Some((swap_add, compare)) = atomic {
+            builder = builder.with_atomic(swap_add, compare);
+        }
+        // the PSNs are taken once the descriptor is sent, and the lock keeps the descriptors of the QP in PSN order
+        let mut send_psn = sending_psn.lock();
+        common.psn = *send_psn;
+        let desc = match builder.with_common(common).build() {
+            Ok(desc) => desc,
+            Err(e) => {
+                ctx.cancel("failed to build the descriptor");
+                return Err(e);
+            }
         };
-        let desc = ToCardWorkRbDescBuilder::new(opcode)
-            .with_common(common)
-            .with_sge(sge0)
-            .build()?;
         let clone_desc = desc.clone();
-        self.send_work_desc(desc)?;
+        if let Some(cq) = send_cq {
+            let wc = WorkCompletion::new(wr_id, wc_opcode, dqpn, total_len);
+            if is_signaled {
+                ctx.set_completion(cq, wc);
+            } else {
+                ctx.set_unsignaled_completion(cq, wc);
+            }
+        }
+        ctx.set_preceding(preceding);
+        if is_reliable {
+            // inserted before sending, so that the context is always found by the response
+            let mut user_op_ctx_map = self.0.user_op_ctx_map.write();
+            // the MSN is reused once the former request is done, the credits keep it from being outstanding
+            if user_op_ctx_map
+                .get(&key)
+                .is_some_and(|former| matches!(former.status(), CtxStatus::Running))
+            {
+                ctx.cancel("MSN in use");
+                return Err(Error::CreateOpCtxFailed);
+            }
+            let _: Option<OpCtx<()>> = user_op_ctx_map.insert(key, ctx.clone());
+        }
+        if let Err(e) = self.send_work_desc(desc) {
+            ctx.cancel("failed to send the descriptor");
+            return Err(e);
+        }
+        *send_psn = send_psn.wrapping_add(packet_cnt);
+        drop(send_psn);
 
-        let ctx = OpCtx::new_running();
+        if !is_reliable {
+            // UC and UD requests are never acknowledged, they are completed once the device accepts them,
+            // unless they are flushed meanwhile
+            let _ignore = ctx.set_result(());
+            return Ok(ctx);
+        }
 
-        self.0
-            .user_op_ctx_map
-            .write()
-            .insert(key, ctx.clone())
-            .map_or_else(|| Ok(()), |_| Err(Error::CreateOpCtxFailed))?;
-        let _ignore = self.0.retry_map.add((dqpn, key.1), clone_desc, !is_read);
+        let _ignore = self
+            .0
+            .retry_map
+            .add(key, clone_desc, !is_read, retry.0, retry.1, retry.2);
         Ok(ctx)
     }
 
-    /// RDMA write operation
+    /// RDMA write of `sgl` to `raddr` of the peer, see `WorkRequest`
+    pub fn write(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        raddr: u64,
+        rkey: Key,
+        flags: WorkReqSendFlag,
+        sgl: &[Sge],
+    ) -> Result<OpCtx<()>, Error> {
+        let wr = WorkRequest::new(wr_id, ToCardWorkRbDescOpcode::Write, dqpn, flags, WorkReqData::Sgl(sgl));
+        self.do_work_req(wr.with_remote(raddr, rkey))
+    }
+
+    /// RDMA write with immediate data, which consumes a receive request of the peer QP once the whole
+    /// message is written, see `WorkRequest`
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_with_imm(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        raddr: u64,
+        rkey: Key,
+        flags: WorkReqSendFlag,
+        sgl: &[Sge],
+        imm: Imm,
+    ) -> Result<OpCtx<()>, Error> {
+        let wr = WorkRequest::new(
+            wr_id,
+            ToCardWorkRbDescOpcode::WriteWithImm,
+            dqpn,
+            flags,
+            WorkReqData::Sgl(sgl),
+        );
+        self.do_work_req(wr.with_remote(raddr, rkey).with_imm(Some(imm)))
+    }
+
+    /// RDMA write of inline `data`, with immediate data like `write_with_imm` if `imm` is given, see `WorkRequest`
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_inline(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        raddr: u64,
+        rkey: Key,
+        flags: WorkReqSendFlag,
+        data: &[u8],
+        imm: Option<Imm>,
+    ) -> Result<OpCtx<()>, Error> {
+        let opcode = if imm.is_some() {
+            ToCardWorkRbDescOpcode::WriteWithImm
+        } else {
+            ToCardWorkRbDescOpcode::Write
+        };
+        let wr = WorkRequest::new(wr_id, opcode, dqpn, flags, WorkReqData::Inline(data));
+        self.do_work_req(wr.with_remote(raddr, rkey).with_imm(imm))
+    }
+
+    /// RDMA read from `raddr` of the peer into `sgl`, on RC QPs only, see `WorkRequest`
+    pub fn read(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        raddr: u64,
+        rkey: Key,
+        flags: WorkReqSendFlag,
+        sgl: &[Sge],
+    ) -> Result<OpCtx<()>, Error> {
+        let wr = WorkRequest::new(wr_id, ToCardWorkRbDescOpcode::Read, dqpn, flags, WorkReqData::Sgl(sgl));
+        self.do_work_req(wr.with_remote(raddr, rkey))
+    }
+
+    /// Atomic compare and swap of the 8 bytes aligned word at `raddr`, on RC QPs only
     ///
-    /// # Errors
+    /// The word is replaced with `swap` if it equals `compare`, and its original value is written into the
+    /// 8 bytes `sge` in native endian, see `WorkRequest`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn atomic_cas(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        raddr: u64,
+        rkey: Key,
+        flags: WorkReqSendFlag,
+        sge: Sge,
+        compare: u64,
+        swap: u64,
+    ) -> Result<OpCtx<()>, Error> {
+        check_atomic_args(raddr, &sge)?;
+        let sgl = [sge];
+        let wr = WorkRequest::new(
+            wr_id,
+            ToCardWorkRbDescOpcode::CompareSwap,
+            dqpn,
+            flags,
+            WorkReqData::Sgl(&sgl),
+        );
+        self.do_work_req(wr.with_remote(raddr, rkey).with_atomic(swap, compare))
+    }
+
+    /// Atomic fetch and add of the 8 bytes aligned word at `raddr`, on RC QPs only
     ///
-    /// Will return `Err` if:
-    /// * lock poisoned
-    /// * failed to create a descriptor
-    /// * failed to send a descriptor
-    /// * failed to create a operation context
-    pub fn write(
+    /// `add` is added to the word wrapping around, and its original value is written into the 8 bytes `sge`
+    /// in native endian, see `WorkRequest`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn atomic_fetch_add(
         &self,
+        wr_id: u64,
         dqpn: Qpn,
         raddr: u64,
         rkey: Key,
         flags: WorkReqSendFlag,
-        sge0: Sge,
+        sge: Sge,
+        add: u64,
     ) -> Result<OpCtx<()>, Error> {
-        self.write_or_read(dqpn, raddr, rkey, flags, sge0, false)
+        check_atomic_args(raddr, &sge)?;
+        let sgl = [sge];
+        let wr = WorkRequest::new(
+            wr_id,
+            ToCardWorkRbDescOpcode::FetchAdd,
+            dqpn,
+            flags,
+            WorkReqData::Sgl(&sgl),
+        );
+        self.do_work_req(wr.with_remote(raddr, rkey).with_atomic(add, 0))
     }
 
-    /// RDMA read operation
+    /// SEND of `sge` with optional immediate data, which consumes a receive request of the peer QP,
+    /// see `WorkRequest`
+    pub fn post_send(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        flags: WorkReqSendFlag,
+        sge: Sge,
+        imm: Option<Imm>,
+    ) -> Result<OpCtx<()>, Error> {
+        let sgl = [sge];
+        let wr = WorkRequest::new(wr_id, send_opcode(imm), dqpn, flags, WorkReqData::Sgl(&sgl));
+        self.do_work_req(wr.with_imm(imm))
+    }
+
+    /// SEND of inline `data` with optional immediate data, see `post_send` and `WorkRequest`
+    pub fn post_send_inline(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        flags: WorkReqSendFlag,
+        data: &[u8],
+        imm: Option<Imm>,
+    ) -> Result<OpCtx<()>, Error> {
+        let wr = WorkRequest::new(wr_id, send_opcode(imm), dqpn, flags, WorkReqData::Inline(data));
+        self.do_work_req(wr.with_imm(imm))
+    }
+
+    /// SEND of `sge` which invalidates the type 2 memory window `rkey` on the peer once placed,
+    /// not on UD QPs, see `WorkRequest`
+    pub fn post_send_with_invalidate(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        flags: WorkReqSendFlag,
+        sge: Sge,
+        rkey: Key,
+    ) -> Result<OpCtx<()>, Error> {
+        let sgl = [sge];
+        let wr = WorkRequest::new(
+            wr_id,
+            ToCardWorkRbDescOpcode::SendWithInv,
+            dqpn,
+            flags,
+            WorkReqData::Sgl(&sgl),
+        );
+        self.do_work_req(wr.with_remote(0, rkey))
+    }
+
+    /// SEND of inline `data` which invalidates `rkey` on the peer, see `post_send_with_invalidate` and
+    /// `WorkRequest`
+    pub fn post_send_with_invalidate_inline(
+        &self,
+        wr_id: u64,
+        dqpn: Qpn,
+        flags: WorkReqSendFlag,
+        data: &[u8],
+        rkey: Key,
+    ) -> Result<OpCtx<()>, Error> {
+        let wr = WorkRequest::new(
+            wr_id,
+            ToCardWorkRbDescOpcode::SendWithInv,
+            dqpn,
+            flags,
+            WorkReqData::Inline(data),
+        );
+        self.do_work_req(wr.with_remote(0, rkey))
+    }
+
+    /// SEND of `sge` through the UD QP `qpn` to the QP described by `ah`, in one packet no longer than the PMTU
+    ///
+    /// The datagram is dropped silently if the peer has no receive request or the Q_Key mismatches, see `WorkRequest`.
+    pub fn post_send_ud(
+        &self,
+        wr_id: u64,
+        qpn: Qpn,
+        ah: &AddressHandle,
+        flags: WorkReqSendFlag,
+        sge: Sge,
+        imm: Option<Imm>,
+    ) -> Result<OpCtx<()>, Error> {
+        let sgl = [sge];
+        let wr = WorkRequest::new(wr_id, send_opcode(imm), qpn, flags, WorkReqData::Sgl(&sgl));
+        self.do_work_req(wr.with_imm(imm).with_ah(ah))
+    }
+
+    /// SEND of inline `data` through a UD QP, see `post_send_ud` and `WorkRequest`
+    pub fn post_send_ud_inline(
+        &self,
+        wr_id: u64,
+        qpn: Qpn,
+        ah: &AddressHandle,
+        flags: WorkReqSendFlag,
+        data: &[u8],
+        imm: Option<Imm>,
+    ) -> Result<OpCtx<()>, Error> {
+        let wr = WorkRequest::new(wr_id, send_opcode(imm), qpn, flags, WorkReqData::Inline(data));
+        self.do_work_req(wr.with_imm(imm).with_ah(ah))
+    }
+
+    /// Post a receive request to the receive queue of a QP
+    ///
+    /// Incoming SEND messages consume the receive requests in the order they are posted.
+    /// The returned context is finished with a `RecvCompletion` once a message is placed in `sge`.
+    /// `wr_id` is reported in the completion entry if the QP is bound to a receive CQ.
     ///
     /// # Errors
     ///
     /// Will return `Err` if:
-    /// * lock poisoned
-    /// * failed to create a read descriptor
-    /// * failed to send a read descriptor
-    /// * failed to create a operation context
-    pub fn read(&self, dqpn: Qpn, raddr: u64, rkey: Key, flags: WorkReqSendFlag, sge: Sge) -> Result<OpCtx<()>, Error> {
-        self.write_or_read(dqpn, raddr, rkey, flags, sge, true)
+    /// * the QP does not exist
+    /// * the QP is in `Reset` or `Err` state
+    /// * failed to send the receive request to the device
+    /// * the device failed to accept the receive request
+    pub fn post_recv(&self, wr_id: u64, qpn: Qpn, sge: Sge) -> Result<OpCtx<RecvCompletion>, Error> {
+        block_on(self.post_recv_async(wr_id, qpn, sge))
+    }
+
+    /// Post a receive request without blocking the thread until the device accepts it, see `post_recv`
+    ///
+    /// # Errors
+    ///
+    /// The same as `post_recv`
+    pub async fn post_recv_async(&self, wr_id: u64, qpn: Qpn, sge: Sge) -> Result<OpCtx<RecvCompletion>, Error> {
+        self.check_running()?;
+        let ctx = OpCtx::new_running();
+        let op_id = self.get_ctrl_op_id();
+        let desc = ToCardCtrlRbDesc::PostRecv(ToCardCtrlRbDescPostRecv {
+            common: ToCardCtrlRbDescCommon { op_id },
+            qpn,
+            sge: sge.into(),
+            srqn: None,
+            tag: 0,
+        });
+        // hold the queue lock until the device accepts the request, so that the order
+        // of the receive queue is the same as the device.
+        let ctrl_ctx = {
+            let qp_guard = self.0.qp_table.read();
+            let qp = qp_guard.get(&qpn).ok_or(Error::Invalid(format!("Qpn :{qpn:?}")))?;
+            if qp.srq.is_some() {
+                return Err(Error::Invalid(format!("receive request to {qpn:?} attached to a SRQ")));
+            }
+            if !qp.state().can_post_recv() {
+                return Err(Error::Invalid(format!("receive request in {:?} state", qp.state())));
+            }
+            if let Some(cq) = &qp.recv_cq {
+                ctx.set_completion(
+                    Arc::clone(cq),
+                    WorkCompletion::new(wr_id, WorkCompletionOpcode::Recv, qpn, 0),
+                );
+            }
+            let mut recv_queue = qp.recv_queue.lock();
+            let ctrl_ctx = self.do_ctrl_op(op_id, desc)?;
+            recv_queue.push_back(ctx.clone());
+            ctrl_ctx
+        };
+
+        if let Err(e) = wait_ctrl_op(ctrl_ctx, "post recv").await {
+            if let Some(qp) = self.0.qp_table.read().get(&qpn) {
+                qp.recv_queue.lock().retain(|posted| !posted.ptr_eq(&ctx));
+            }
+            return Err(e);
+        }
+        Ok(ctx)
     }
 
     /// # Errors
@@ -535,6 +1157,7 @@ impl Device {
         }
     }
 
+    /// Send a control descriptor to the device, the returned context is finished by the response
     fn do_ctrl_op(&self, id: u32, desc: ToCardCtrlRbDesc) -> Result<CtrlOpCtx, Error> {
         // save operation context for unparking
         let ctrl_ctx = {
@@ -553,7 +1176,7 @@ impl Device {
             .adaptor
             .to_card_ctrl_rb()
             .push(desc)
-            .map_err(|_| Error::DeviceBusy)?;
+            .map_err(|_| Error::SendQueueFull)?;
 
         Ok(ctrl_ctx)
     }
@@ -562,19 +1185,16 @@ impl Device {
         self.0.next_ctrl_op_id.fetch_add(1, Ordering::AcqRel)
     }
 
-    #[allow(clippy::expect_used, clippy::unwrap_in_result)]
     fn init(&self, retry_config: RetryConfig, mut core_ids: Option<Vec<CoreId>>) -> Result<(), Error> {
         // enable ctrl desc poller module
         let ctrl_thread_ctx = ControlPollerContext {
             to_host_ctrl_rb: self.0.adaptor.to_host_ctrl_rb(),
             ctrl_op_ctx_map: Arc::<RwLock<HashMap<u32, CtrlOpCtx>>>::clone(&self.0.ctrl_op_ctx_map),
+            page_fault_handler: Arc::new(self.clone()),
         };
         let ctrl_queue_core = core_ids.as_mut().and_then(|v| v.pop());
         let ctrl_desc_poller = ControlPoller::new(ctrl_thread_ctx, ctrl_queue_core);
-        self.0
-            .ctrl_desc_poller
-            .set(ctrl_desc_poller)
-            .expect("ctrl_desc_poller has been set");
+        *self.0.ctrl_desc_poller.lock() = Some(ctrl_desc_poller);
 
         let use_hugepage = self.0.adaptor.use_hugepage();
         let mut buf = Buffer::new(ACKNOWLEDGE_BUFFER_SIZE, use_hugepage)
@@ -593,10 +1213,7 @@ impl Device {
 
         let work_queue_core = core_ids.as_mut().and_then(|v| v.pop());
         let work_desc_poller = WorkDescPoller::new(work_desc_poller_ctx, work_queue_core);
-        self.0
-            .work_desc_poller
-            .set(work_desc_poller)
-            .expect("work descriptor poller has been set");
+        *self.0.work_desc_poller.lock() = Some(work_desc_poller);
 
         // create nic send device, but we don't prepare receive buffer. So it won't work now.
         let mut tx_slot_buf = Buffer::new(NIC_BUFFER_SIZE, use_hugepage)
@@ -617,12 +1234,11 @@ impl Device {
             work_desc_sender: Arc::new(self.clone()),
             ack_buffers: ack_buf,
             retry_map: self.0.retry_map.clone(),
+            mw_table: Arc::clone(&self.0.mw_table),
+            mr_config: self.0.mr_config,
         };
         let pkt_checker_thread = PacketChecker::new(packet_checker_ctx);
-        self.0
-            .pkt_checker_thread
-            .set(pkt_checker_thread)
-            .expect("pkt_checker_thread has been set");
+        *self.0.pkt_checker_thread.lock() = Some(pkt_checker_thread);
 
         // install retry monitor
         let retry_context = RetryMonitorContext {
@@ -632,7 +1248,7 @@ impl Device {
             device: Arc::new(self.clone()),
         };
         let retry_monitor = RetryMonitor::new(retry_context);
-        self.0.retry_monitor.set(retry_monitor).expect("double init");
+        *self.0.retry_monitor.lock() = Some(retry_monitor);
 
         // set card network
         self.set_network(&self.0.local_network)?;
@@ -653,6 +1269,102 @@ impl Device {
         }
     }
 
+    /// Shut down the device
+    ///
+    /// The outstanding work requests are given a while to finish, the rest of them and the posted
+    /// receive requests are failed with `WorkCompletionStatus::WrFlushError`.
+    /// Then all the QPs, MRs, PDs and CQs are destroyed, and the threads of the device are stopped,
+    /// so that the device is released once the last handle is dropped.
+    ///
+    /// Requests on a shut down device return `Error::DeviceShutdown`, and shutting down again does nothing.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the device failed to destroy a resource.
+    /// The rest of the resources are still destroyed, and the first error is returned.
+    pub fn shutdown(&self) -> Result<(), Error> {
+        if self.0.is_shutdown.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        while start.elapsed() < SHUTDOWN_DRAIN_TIMEOUT && self.has_running_work_req() {
+            sleep(Duration::from_millis(1));
+        }
+
+        // stop the data path, the control path is still needed to destroy the resources
+        drop(self.0.retry_monitor.lock().take());
+        drop(self.0.work_desc_poller.lock().take());
+        drop(self.0.pkt_checker_thread.lock().take());
+        drop(self.0.nic_device.lock().take());
+
+        for (_, ctx) in self.0.user_op_ctx_map.write().drain() {
+            if matches!(ctx.status(), CtxStatus::Running) {
+                ctx.set_error(SHUTDOWN_FLUSH_CAUSE, WorkCompletionStatus::WrFlushError);
+            }
+        }
+
+        let mut first_err = None;
+        let mut keep_err = |res: Result<(), Error>| {
+            if let Err(e) = res {
+                log::error!("shutdown failed: {e}");
+                let _: &mut Error = first_err.get_or_insert(e);
+            }
+        };
+        let qpns: Vec<Qpn> = self.0.qp_table.read().keys().copied().collect();
+        for qpn in qpns {
+            if let Some(qpc) = self.0.qp_table.read().get(&qpn) {
+                self.flush_qp(qpc, SHUTDOWN_FLUSH_CAUSE);
+            }
+            keep_err(self.destroy_qp(qpn));
+        }
+        let srqs: Vec<Srq> = self.0.srq_table.lock().keys().copied().collect();
+        for srq in srqs {
+            keep_err(self.destroy_srq(srq));
+        }
+        let mws: Vec<Mw> = self.0.mw_table.lock().keys().copied().collect();
+        for mw in mws {
+            keep_err(self.dealloc_mw(mw));
+        }
+        let mrs: Vec<Mr> = self.0.pd.lock().values().flat_map(|pd| pd.mr.iter().copied()).collect();
+        for mr in mrs {
+            keep_err(self.dereg_mr(mr));
+        }
+        let pds: Vec<Pd> = self.0.pd.lock().keys().copied().collect();
+        for pd in pds {
+            keep_err(self.dealloc_pd(pd));
+        }
+        // the buffers are not accessed by the device since their MRs are deregistered
+        self.0.buffer_keeper.lock().clear();
+        self.0.cq_table.lock().clear();
+
+        drop(self.0.ctrl_desc_poller.lock().take());
+        for (_, ctx) in self.0.ctrl_op_ctx_map.write().drain() {
+            if matches!(ctx.status(), CtxStatus::Running) {
+                ctx.set_error(SHUTDOWN_FLUSH_CAUSE, WorkCompletionStatus::GeneralError);
+            }
+        }
+        self.0.adaptor.shutdown();
+
+        first_err.map_or(Ok(()), Err)
+    }
+
+    /// Returns `Err` if the device is shut down
+    pub(crate) fn check_running(&self) -> Result<(), Error> {
+        if self.0.is_shutdown.load(Ordering::Acquire) {
+            return Err(Error::DeviceShutdown);
+        }
+        Ok(())
+    }
+
+    fn has_running_work_req(&self) -> bool {
+        self.0
+            .user_op_ctx_map
+            .read()
+            .values()
+            .any(|ctx| matches!(ctx.status(), CtxStatus::Running))
+    }
+
     #[allow(clippy::unwrap_in_result, clippy::unwrap_used)]
     fn prepare_nic_recv_buf(&self, use_huge_page: bool) -> Result<(), Error> {
         // configure basic nic recv buffer
@@ -694,6 +1406,26 @@ impl Device {
     }
 }
 
+/// wait for the response of a control descriptor, `op_name` is reported if the device failed it
+async fn wait_ctrl_op(ctx: CtrlOpCtx, op_name: &'static str) -> Result<(), Error> {
+    if ctx.await? {
+        Ok(())
+    } else {
+        Err(Error::DeviceReturnFailed(op_name))
+    }
+}
+
+/// check the remote address and local buffer of an atomic operation
+fn check_atomic_args(raddr: u64, sge: &Sge) -> Result<(), Error> {
+    if sge.len != ATOMIC_OPERAND_SIZE {
+        return Err(Error::Invalid(format!("atomic local buffer length :{}", sge.len)));
+    }
+    if !raddr.is_multiple_of(u64::from(ATOMIC_OPERAND_SIZE)) {
+        return Err(Error::Invalid(format!("atomic remote address :{raddr:#x}")));
+    }
+    Ok(())
+}
+
 /// A interface that allows `DescResponser` to push the work descriptor to the device
 pub(crate) trait WorkDescriptorSender: Send + Sync {
     fn send_work_desc(&self, desc_builder: Box<ToCardWorkRbDesc>) -> Result<(), Error>;
@@ -703,13 +1435,18 @@ pub(crate) trait CtrlDescriptorSender: Send + Sync {
     fn send_ctrl_desc(&self, desc_builder: ToCardCtrlRbDesc) -> Result<CtrlOpCtx, Error>;
 }
 
+/// A interface that allows `ControlPoller` to resolve the page faults raised by the device
+pub(crate) trait PageFaultHandler: Send + Sync {
+    fn handle_page_fault(&self, key: Key, va: u64);
+}
+
 impl WorkDescriptorSender for Device {
     fn send_work_desc(&self, desc: Box<ToCardWorkRbDesc>) -> Result<(), Error> {
         self.0
             .adaptor
             .to_card_work_rb()
             .push(desc)
-            .map_err(|_| Error::DeviceBusy)?;
+            .map_err(|_| Error::SendQueueFull)?;
         Ok(())
     }
 }
@@ -718,17 +1455,15 @@ impl CtrlDescriptorSender for Device {
     fn send_ctrl_desc(&self, mut desc: ToCardCtrlRbDesc) -> Result<CtrlOpCtx, Error> {
         let id = self.get_ctrl_op_id();
         desc.set_id(id);
-        self.0
-            .adaptor
-            .to_card_ctrl_rb()
-            .push(desc)
-            .map_err(|_| Error::DeviceBusy)?;
-        let mut ctx = self.0.ctrl_op_ctx_map.write();
-        let ctrl_ctx = CtrlOpCtx::new_running();
+        // the context is saved before sending, for the response may come before it's saved otherwise
+        self.do_ctrl_op(id, desc)
+    }
+}
 
-        if ctx.insert(id, ctrl_ctx.clone()).is_some() {
-            return Err(Error::CreateOpCtxFailed);
+impl PageFaultHandler for Device {
+    fn handle_page_fault(&self, key: Key, va: u64) {
+        if let Err(e) = self.resolve_page_fault(key, va) {
+            log::error!("failed to resolve page fault at {va:#x} of {key:?}: {e:?}");
         }
-        Ok(ctrl_ctx)
     }
 }
@@ -1,19 +1,114 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::{Hash, Hasher};
-use std::ptr;
+use std::ops::Range;
 
+use bitflags::bitflags;
 use rand::RngCore as _;
 
 use crate::buf::PacketBuf;
 use crate::device::{
     ToCardCtrlRbDesc, ToCardCtrlRbDescCommon, ToCardCtrlRbDescUpdateMrTable, ToCardCtrlRbDescUpdatePageTable,
 };
+use crate::mw::is_mr_bound;
+use crate::op_ctx::CtrlOpCtx;
 use crate::types::{Key, MemAccessTypeFlag, PAGE_SIZE};
-use crate::utils::Buffer;
-use crate::{Device, Error, Pd, MR_PGT_ENTRY_SIZE};
+use crate::utils::{block_on, Buffer};
+use crate::{wait_ctrl_op, Device, Error, Pd, MR_PGT_ENTRY_SIZE};
 
 pub(crate) const ACKNOWLEDGE_BUFFER_SIZE: usize = PAGE_SIZE;
 pub(crate) const NIC_BUFFER_SIZE: usize = PAGE_SIZE;
 
+/// The low bits of a key kept for the secret, the memory windows rotate the low 8 bits on each bind
+const MR_KEY_MIN_SECRET_BIT_CNT: u32 = 8;
+/// The page table offset of a MR is a 17 bits field of the descriptor
+const MR_PGT_MAX_LENGTH: u32 = 1 << 17;
+/// The page sizes of a MR supported by the device: 4 KiB, 2 MiB and 1 GiB
+const MR_PAGE_SIZES: [u32; 3] = [1 << 12, 1 << 21, 1 << 30];
+
+/// The layout of the MR table and the page table of the device
+///
+/// The high `key_idx_bit_cnt` bits of a key index the MR table and the rest bits are a random
+/// secret. The MRs take the first `mr_table_size` indexes, and the memory windows take the rest.
+/// The `pgt_length` entries of the page table are shared by all the MRs.
+#[derive(Debug, Clone, Copy)]
+pub struct MrConfig {
+    pub(crate) key_idx_bit_cnt: u32,
+    pub(crate) mr_table_size: u32,
+    pub(crate) pgt_length: u32,
+}
+
+impl Default for MrConfig {
+    fn default() -> Self {
+        Self {
+            key_idx_bit_cnt: 8,
+            mr_table_size: 64,
+            pgt_length: 1024,
+        }
+    }
+}
+
+impl MrConfig {
+    /// Create a new MR config
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * `key_idx_bit_cnt` is 0, or leaves less than 8 bits for the secret of a key
+    /// * `mr_table_size` is 0, or more than the indexes of `key_idx_bit_cnt` bits
+    /// * `pgt_length` is 0, or more than `1 << 17` entries
+    pub fn new(key_idx_bit_cnt: u32, mr_table_size: u32, pgt_length: u32) -> Result<Self, Error> {
+        if key_idx_bit_cnt == 0 || key_idx_bit_cnt > u32::BITS - MR_KEY_MIN_SECRET_BIT_CNT {
+            return Err(Error::Invalid(format!("MR key index bits :{key_idx_bit_cnt}")));
+        }
+        if mr_table_size == 0 || mr_table_size > 1 << key_idx_bit_cnt {
+            return Err(Error::Invalid(format!("MR table size :{mr_table_size}")));
+        }
+        if pgt_length == 0 || pgt_length > MR_PGT_MAX_LENGTH {
+            return Err(Error::Invalid(format!("MR page table length :{pgt_length}")));
+        }
+        Ok(Self {
+            key_idx_bit_cnt,
+            mr_table_size,
+            pgt_length,
+        })
+    }
+
+    /// The number of key indexes, which are shared by the MRs and the memory windows
+    pub(crate) fn key_idx_cnt(&self) -> u32 {
+        1 << self.key_idx_bit_cnt
+    }
+
+    /// The index of `key` in the MR table, or of the memory window
+    #[allow(clippy::arithmetic_side_effects)]
+    pub(crate) fn key_idx(&self, key: Key) -> u32 {
+        key.get() >> (u32::BITS - self.key_idx_bit_cnt)
+    }
+
+    /// A new key of `idx`, whose low bits are a random secret
+    pub(crate) fn new_key(&self, idx: u32) -> Key {
+        #[allow(clippy::arithmetic_side_effects)]
+        let key_idx = idx << (u32::BITS - self.key_idx_bit_cnt);
+        let key_secret = rand::thread_rng().next_u32() >> self.key_idx_bit_cnt;
+        Key::new(key_idx | key_secret)
+    }
+
+    /// The key of the same index with another secret
+    pub(crate) fn rotate_key(&self, key: Key) -> Key {
+        let idx = self.key_idx(key);
+        loop {
+            let new_key = self.new_key(idx);
+            if new_key != key {
+                return new_key;
+            }
+        }
+    }
+
+    /// The key indexes of the memory windows, which are after the MRs
+    pub(crate) fn mw_idx_range(&self) -> Range<u32> {
+        self.mr_table_size..self.key_idx_cnt()
+    }
+}
+
 /// Memory Region
 ///
 /// User use `Device::alloc_mr(..)` to allocate a `Mr` and use `Device::dereg_mr(..)` to deallocate
@@ -31,32 +126,98 @@ impl Mr {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub(crate) struct MrCtx {
     pub(crate) pd: Pd,
+    pub(crate) addr: u64,
     pub(crate) len: u32,
+    pub(crate) acc_flags: MemAccessTypeFlag,
     pub(crate) pgt_offset: usize,
     pub(crate) pg_size: u32,
+    /// the MR is being reregistered, it can't be deregistered, reregistered or bound meanwhile
+    pub(crate) busy: bool,
 }
 
+/// Clears the busy flag of a MR once the reregistration is done, failed or dropped
+struct MrBusyGuard<'dev> {
+    dev: &'dev Device,
+    mr_idx: u32,
+}
+
+impl Drop for MrBusyGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mr_ctx) = self.dev.0.mr_table.lock().get_mut(self.mr_idx) {
+            mr_ctx.busy = false;
+        }
+    }
+}
+
+/// The MR table, whose free indexes are kept in a free list
 #[derive(Debug)]
-pub(crate) struct MrPgt {
-    table: Buffer,
-    free_blk_list: *mut MrPgtFreeBlk,
+pub(crate) struct MrTable {
+    slots: Vec<Option<MrCtx>>,
+    /// the free indexes, the lowest one is taken first
+    free_idx: Vec<u32>,
 }
 
-struct MrPgtFreeBlk {
-    idx: usize,
-    len: usize,
-    prev: *mut Self,
-    next: *mut Self,
+/// The page table shared by all the MRs
+///
+/// The free entries are kept as blocks, which are merged with their neighbours once freed. A MR
+/// takes the smallest block that fits, so that the large blocks are kept for the large MRs.
+#[derive(Debug)]
+pub(crate) struct MrPgt {
+    table: Buffer,
+    /// the length of the free blocks by their start index
+    free_blks: BTreeMap<usize, usize>,
+    /// the free blocks ordered by `(len, idx)`
+    free_blks_by_len: BTreeSet<(usize, usize)>,
 }
 
 impl Device {
-    fn register_page_table(&self, addr: u64, length: u32, pg_size: u32) -> Result<usize, Error> {
-        let mut mr_pgt = self.0.mr_pgt.lock();
+    async fn register_page_table(
+        &self,
+        addr: u64,
+        length: u32,
+        pg_size: u32,
+        acc_flags: MemAccessTypeFlag,
+    ) -> Result<usize, Error> {
+        if !MR_PAGE_SIZES.contains(&pg_size) {
+            return Err(Error::Invalid(format!("MR page size :{pg_size}")));
+        }
         let pgte_cnt = length.div_ceil(pg_size) as usize;
+        if acc_flags.contains(MemAccessTypeFlag::IbvAccessOnDemand) {
+            // the entries are pushed one by one on page faults, see `resolve_page_fault`
+            return self.0.mr_pgt.lock().alloc(pgte_cnt);
+        }
+        let (pgt_offset, update_pgt_ctx) = self.update_page_table(addr, pgte_cnt, pg_size)?;
+
+        if let Err(e) = wait_ctrl_op(update_pgt_ctx, "update page table").await {
+            self.0.mr_pgt.lock().dealloc(pgt_offset, pgte_cnt);
+            return Err(e);
+        }
+        Ok(pgt_offset)
+    }
+
+    /// fill the page table entries of a new MR, and send them to the device
+    fn update_page_table(&self, addr: u64, pgte_cnt: usize, pg_size: u32) -> Result<(usize, CtrlOpCtx), Error> {
+        let mut mr_pgt = self.0.mr_pgt.lock();
         let pgt_offset = mr_pgt.alloc(pgte_cnt)?;
+        let update_pgt_ctx = self.push_page_table(&mut mr_pgt, pgt_offset, addr, pgte_cnt, pg_size)?;
+        Ok((pgt_offset, update_pgt_ctx))
+    }
+
+    /// fill `pgte_cnt` entries from `pgt_offset` with the pages from `addr`, and send them to the device
+    ///
+    /// The entries are staged at their own offset, so that a page fault never overwrites the entries of a
+    /// registration which the device has not read yet.
+    fn push_page_table(
+        &self,
+        mr_pgt: &mut MrPgt,
+        pgt_offset: usize,
+        addr: u64,
+        pgte_cnt: usize,
+        pg_size: u32,
+    ) -> Result<CtrlOpCtx, Error> {
         for pgt_idx in 0..pgte_cnt {
             let va = addr.wrapping_add(((pg_size as usize).wrapping_mul(pgt_idx)) as u64);
             // Should we support 32 bit system?
@@ -68,17 +229,19 @@ impl Device {
                 .map_err(|e| Error::GetPhysAddrFailed(e.to_string()))?;
             // If we run with hardware DMA,
             // we must make sure va and pa are all allign to pg_size
-            // TODO(fh): Replace hard code page_size to inputs
-            if va_in_usize & (PAGE_SIZE - 1) != 0 {
+            // `pg_size` is one of `MR_PAGE_SIZES`, which is not zero
+            #[allow(clippy::arithmetic_side_effects)]
+            let pg_mask = pg_size as usize - 1;
+            if va_in_usize & pg_mask != 0 {
                 return Err(Error::AddressNotAlign("va", va_in_usize));
             }
-            if pa & (PAGE_SIZE - 1) != 0 {
+            if pa & pg_mask != 0 {
                 return Err(Error::AddressNotAlign("pa", pa));
             }
             // `mr_pgt.alloc(pgte_cnt)` has already checked that `pgt_offset + pgt_idx` is in range
             #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
             {
-                mr_pgt.table[pgt_idx] = pa as u64;
+                mr_pgt.table[pgt_offset + pgt_idx] = pa as u64;
             }
         }
 
@@ -93,21 +256,59 @@ impl Device {
             start_addr: self
                 .0
                 .adaptor
-                .get_phys_addr(mr_pgt.table.as_ref().as_ptr() as usize)
+                .get_phys_addr(mr_pgt.table.as_ref().as_ptr() as usize + pgt_offset * MR_PGT_ENTRY_SIZE)
                 .map_err(|e| Error::GetPhysAddrFailed(e.to_string()))? as u64,
             pgt_idx: pgt_offset as u32,
             pgte_cnt: pgte_cnt as u32,
         });
 
-        let update_pgt_ctx = self.do_ctrl_op(update_pgt_op_id, update_pgt_desc)?;
-
-        let update_pgt_result = update_pgt_ctx.wait_result()?.ok_or(Error::SetCtxResultFailed)?;
+        self.do_ctrl_op(update_pgt_op_id, update_pgt_desc)
+    }
 
-        if !update_pgt_result {
-            mr_pgt.dealloc(pgt_offset, pgte_cnt);
-            return Err(Error::DeviceReturnFailed("update page table"));
+    /// Push the page of an on-demand paging MR that the device faulted on at `va`
+    ///
+    /// It's called by the control poller, so the response is not waited for.
+    pub(crate) fn resolve_page_fault(&self, key: Key, va: u64) -> Result<(), Error> {
+        let mr_idx = self.0.mr_config.key_idx(key);
+        // the MR is locked until the entry is sent, so that its page table is not taken by another MR meanwhile
+        let mr_table = self.0.mr_table.lock();
+        let pd_pool = self.0.pd.lock();
+        let Some(mr_ctx) = mr_table.get(mr_idx) else {
+            return Err(Error::Invalid(format!("MR :{mr_idx}")));
+        };
+        // the device may fault on a key that has been deregistered or re-registered since
+        if !pd_pool
+            .get(&mr_ctx.pd)
+            .is_some_and(|pd_ctx| pd_ctx.mr.contains(&Mr { key }))
+        {
+            return Err(Error::Invalid(format!("MR :{mr_idx}")));
         }
-        Ok(pgt_offset)
+        if !mr_ctx.acc_flags.contains(MemAccessTypeFlag::IbvAccessOnDemand) {
+            return Err(Error::Invalid(format!("MR without on-demand paging :{mr_idx}")));
+        }
+        let Some(offset) = va
+            .checked_sub(mr_ctx.start_addr())
+            .filter(|offset| *offset < u64::from(mr_ctx.len))
+        else {
+            return Err(Error::Invalid(format!("page fault address :{va:#x}")));
+        };
+
+        // `pg_size` is not zero as the page table is allocated, and the page is within the MR
+        #[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+        let pgt_idx = offset / u64::from(mr_ctx.pg_size);
+        #[allow(clippy::arithmetic_side_effects)]
+        let page_addr = mr_ctx.addr + pgt_idx * u64::from(mr_ctx.pg_size);
+        let pgt_idx = usize::try_from(pgt_idx).map_err(|_| Error::NotSupport("32 bit System"))?;
+
+        let mut mr_pgt = self.0.mr_pgt.lock();
+        #[allow(clippy::arithmetic_side_effects)]
+        let ctx = self.push_page_table(&mut mr_pgt, mr_ctx.pgt_offset + pgt_idx, page_addr, 1, mr_ctx.pg_size)?;
+        ctx.set_handler(Box::new(move |is_succ| {
+            if !is_succ {
+                log::error!("device failed to update page table for {key:?} at {page_addr:#x}");
+            }
+        }));
+        Ok(())
     }
 
     fn deregister_page_table(&self, pgt_offset: usize, length: u32) -> Result<(), Error> {
@@ -119,79 +320,128 @@ impl Device {
         Ok(())
     }
 
+    /// Free the page table entries taken by a failed registration, the caller returns the original error
+    fn free_page_table_on_error(&self, pgt_offset: usize, length: u32) {
+        if let Err(e) = self.deregister_page_table(pgt_offset, length) {
+            log::error!("failed to free page table at {pgt_offset}: {e}");
+        }
+    }
+
     /// Register a Mr
     ///
+    /// `[addr, addr + len)` is mapped in pages of `pg_size`, which is 4 KiB, 2 MiB or 1 GiB, and
+    /// `addr` must be aligned to it. So both the ordinary heap memory and the hugepages can be
+    /// registered, and each MR takes `len / pg_size` entries of the page table.
+    ///
+    /// A MR registered with `IbvAccessZeroBased` is addressed by the offset from `addr`, both in
+    /// the local SGEs and by the remote peers, instead of the virtual address.
+    ///
+    /// The pages of a MR registered with `IbvAccessOnDemand` are not pushed to the device on
+    /// registration. The device raises a page fault when it touches a page for the first time,
+    /// which is resolved by pushing just that page, so a huge sparse range can be registered.
+    ///
     /// # Errors
     ///
     /// Will return `Err` if:
     /// * lock poisoned
+    /// * unsupported page size, or the address is not aligned to it
     /// * not have enough resouce to allocate a new pagetable
     /// * invalid pd
     /// * failed to communicate with card(including creating page table and creating mr)
     pub fn reg_mr(&self, pd: Pd, addr: u64, len: u32, pg_size: u32, acc_flags: MemAccessTypeFlag) -> Result<Mr, Error> {
-        let mut mr_table = self.0.mr_table.lock();
-
-        let mut pd_pool = self.0.pd.lock();
-
-        let Some(mr_idx) = mr_table
-            .iter()
-            .enumerate()
-            .find_map(|(idx, ctx)| ctx.is_none().then_some(idx))
-        else {
-            return Err(Error::ResourceNoAvailable("MR".to_owned()));
-        };
-
-        let pd_ctx = pd_pool.get_mut(&pd).ok_or(Error::Invalid(format!("PD :{pd:?}")))?;
-
-        let pgt_offset = self.register_page_table(addr, len, pg_size)?;
+        block_on(self.reg_mr_async(pd, addr, len, pg_size, acc_flags))
+    }
 
-        // mr_idx is smaller than `MR_TABLE_SIZE`. Currently, it's a relatively small number.
-        // And it's expected to smaller than 2^32 during transimission
-        #[allow(clippy::cast_possible_truncation, clippy::arithmetic_side_effects)]
-        let key_idx = (mr_idx as u32) << (u32::BITS as usize - crate::MR_KEY_IDX_BIT_CNT);
-        let key_secret = rand::thread_rng().next_u32() >> crate::MR_KEY_IDX_BIT_CNT;
-        let key = Key::new(key_idx | key_secret);
+    /// Register a Mr without blocking the thread, see `reg_mr`
+    ///
+    /// # Errors
+    ///
+    /// The same as `reg_mr`
+    pub async fn reg_mr_async(
+        &self,
+        pd: Pd,
+        addr: u64,
+        len: u32,
+        pg_size: u32,
+        acc_flags: MemAccessTypeFlag,
+    ) -> Result<Mr, Error> {
+        self.check_running()?;
+        if !self.0.pd.lock().contains_key(&pd) {
+            return Err(Error::Invalid(format!("PD :{pd:?}")));
+        }
 
-        let mr = Mr { key };
+        let pgt_offset = self.register_page_table(addr, len, pg_size, acc_flags).await?;
         let mr_ctx = MrCtx {
             pd,
+            addr,
             len,
+            acc_flags,
             pgt_offset,
             pg_size,
+            busy: false,
+        };
+        let (mr, update_mr_ctx) = match self.reserve_mr(mr_ctx) {
+            Ok(reserved) => reserved,
+            Err(e) => {
+                self.free_page_table_on_error(pgt_offset, len.div_ceil(pg_size));
+                return Err(e);
+            }
         };
 
-        let update_mr_op_id = self.get_ctrl_op_id();
-
-        #[allow(clippy::cast_possible_truncation)]
-        let update_mr_desc = ToCardCtrlRbDesc::UpdateMrTable(ToCardCtrlRbDescUpdateMrTable {
-            common: ToCardCtrlRbDescCommon { op_id: update_mr_op_id },
-            addr,
-            len,
-            key,
-            pd_hdl: mr_ctx.pd.handle,
-            acc_flags,
-            pgt_offset: pgt_offset as u32,
-        });
+        if let Err(e) = wait_ctrl_op(update_mr_ctx, "register mr table").await {
+            self.release_mr(mr)?;
+            return Err(e);
+        }
+        Ok(mr)
+    }
 
-        let update_mr_ctx = self.do_ctrl_op(update_mr_op_id, update_mr_desc)?;
+    /// Take a free slot of the MR table and send the MR to the device.
+    ///
+    /// The MR is added to the table and the PD before the device accepts it,
+    /// so that neither the slot nor the PD is taken away meanwhile.
+    fn reserve_mr(&self, mr_ctx: MrCtx) -> Result<(Mr, CtrlOpCtx), Error> {
+        let mut mr_table = self.0.mr_table.lock();
+        let mut pd_pool = self.0.pd.lock();
 
-        let update_mr_result = update_mr_ctx.wait_result()?.ok_or(Error::SetCtxResultFailed)?;
+        let pd = mr_ctx.pd;
+        let pd_ctx = pd_pool.get_mut(&pd).ok_or(Error::Invalid(format!("PD :{pd:?}")))?;
 
-        if !update_mr_result {
-            return Err(Error::DeviceReturnFailed("register mr table"));
-        }
+        let Some(mr_idx) = mr_table.alloc() else {
+            return Err(Error::ResourceNoAvailable("MR".to_owned()));
+        };
+        let key = self.0.mr_config.new_key(mr_idx);
 
-        #[allow(clippy::indexing_slicing)]
-        // `mr_idx` is allocated by `find_map` above, so it's safe to index
-        {
-            mr_table[mr_idx] = Some(mr_ctx);
-        }
+        let mr = Mr { key };
+        let update_mr_op_id = self.get_ctrl_op_id();
+        let update_mr_desc = mr_ctx.update_desc(update_mr_op_id, key);
 
         if !pd_ctx.mr.insert(mr) {
+            mr_table.free(mr_idx);
             return Err(Error::Invalid(format!("mr :{mr:?}")));
         }
+        let update_mr_ctx = match self.do_ctrl_op(update_mr_op_id, update_mr_desc) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                let _: bool = pd_ctx.mr.remove(&mr);
+                mr_table.free(mr_idx);
+                return Err(e);
+            }
+        };
+        mr_table.insert(mr_idx, mr_ctx);
 
-        Ok(mr)
+        Ok((mr, update_mr_ctx))
+    }
+
+    /// Remove the MR from the MR table and its PD, and free its page table entries
+    fn release_mr(&self, mr: Mr) -> Result<(), Error> {
+        let mut mr_table = self.0.mr_table.lock();
+        let mut pd_pool = self.0.pd.lock();
+        let mr_idx = self.0.mr_config.key_idx(mr.key);
+        let mr_ctx = mr_table.remove(mr_idx).ok_or(Error::Invalid(format!("MR :{mr_idx}")))?;
+        if let Some(pd_ctx) = pd_pool.get_mut(&mr_ctx.pd) {
+            let _: bool = pd_ctx.mr.remove(&mr);
+        }
+        self.deregister_page_table(mr_ctx.pgt_offset, mr_ctx.len.div_ceil(mr_ctx.pg_size))
     }
 
     pub(crate) fn init_buf<const SLOT_SIZE: usize>(
@@ -222,208 +472,405 @@ impl Device {
     ///
     /// Will return `Err` if:
     /// * lock poisoned
+    /// * memory windows are still bound to the MR
     /// * failed to communicate with card(including remove page table and remove mr)
     /// * Operating system not support
     /// * Setted context result failed
     pub fn dereg_mr(&self, mr: Mr) -> Result<(), Error> {
-        let mut mr_table = self.0.mr_table.lock();
-        let mut pd_pool = self.0.pd.lock();
-        #[allow(clippy::arithmetic_side_effects)]
-        let mr_idx = mr.key.get() >> (u32::BITS as usize - crate::MR_KEY_IDX_BIT_CNT);
-        let ctx_option = mr_table
-            .get_mut(mr_idx as usize)
-            .ok_or(Error::Invalid(format!("MR :{mr_idx}")))?;
-        let Some(mr_ctx) = ctx_option else {
-            return Err(Error::Invalid(format!("MR :{mr_idx}")));
-        };
+        block_on(self.dereg_mr_async(mr))
+    }
 
-        let pd_ctx = pd_pool
-            .get_mut(&mr_ctx.pd)
-            .ok_or(Error::Invalid(format!("PD :{:?}", &mr_ctx.pd)))?;
+    /// Remove a Mr without blocking the thread, see `dereg_mr`
+    ///
+    /// # Errors
+    ///
+    /// The same as `dereg_mr`
+    pub async fn dereg_mr_async(&self, mr: Mr) -> Result<(), Error> {
+        let ctx = {
+            let mw_table = self.0.mw_table.lock();
+            let mr_table = self.0.mr_table.lock();
+            let pd_pool = self.0.pd.lock();
+            let mr_idx = self.0.mr_config.key_idx(mr.key);
+            let Some(mr_ctx) = mr_table.get(mr_idx) else {
+                return Err(Error::Invalid(format!("MR :{mr_idx}")));
+            };
+            let pd_ctx = pd_pool
+                .get(&mr_ctx.pd)
+                .ok_or(Error::Invalid(format!("PD :{:?}", &mr_ctx.pd)))?;
+            if !pd_ctx.mr.contains(&mr) {
+                return Err(Error::Invalid(format!("MR :{mr_idx}")));
+            }
+            if mr_ctx.busy {
+                return Err(Error::Invalid(format!("MR is being reregistered :{mr_idx}")));
+            }
+            if is_mr_bound(&mw_table, mr) {
+                return Err(Error::Invalid(format!("MR bound to memory window :{mr_idx}")));
+            }
 
-        let op_id = self.get_ctrl_op_id();
+            let op_id = self.get_ctrl_op_id();
 
-        let desc = ToCardCtrlRbDesc::UpdateMrTable(ToCardCtrlRbDescUpdateMrTable {
-            common: ToCardCtrlRbDescCommon { op_id },
-            addr: 0,
-            len: 0,
-            key: mr.key,
-            pd_hdl: 0,
-            acc_flags: MemAccessTypeFlag::IbvAccessNoFlags,
-            pgt_offset: 0,
-        });
+            let desc = ToCardCtrlRbDesc::UpdateMrTable(ToCardCtrlRbDescUpdateMrTable {
+                common: ToCardCtrlRbDescCommon { op_id },
+                addr: 0,
+                len: 0,
+                key: mr.key,
+                pd_hdl: 0,
+                acc_flags: MemAccessTypeFlag::IbvAccessNoFlags,
+                pgt_offset: 0,
+                pg_size: 0,
+            });
 
-        let ctx = self.do_ctrl_op(op_id, desc)?;
+            self.do_ctrl_op(op_id, desc)?
+        };
+
+        // the MR stays in the table until the device removes it
+        wait_ctrl_op(ctx, "deregister mr table").await?;
+        self.release_mr(mr)
+    }
+
+    /// Re-register a Mr, and return the Mr with a new key
+    ///
+    /// The address range, access flags and PD are changed in place as `attr.flags` asks, and the
+    /// secret of the key is always rotated, so the old key is revoked without deregistering the MR.
+    /// The device switches to the new key, and the new page table if the range is changed, in one
+    /// update of its MR table. If it fails, the old `Mr` is still registered.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Mr or Pd
+    /// * memory windows are still bound to the MR
+    /// * not have enough resouce to allocate a new pagetable
+    /// * failed to communicate with card(including creating page table and updating mr)
+    pub fn rereg_mr(&self, mr: Mr, attr: &ReregMrAttr) -> Result<Mr, Error> {
+        block_on(self.rereg_mr_async(mr, attr))
+    }
 
-        let res = ctx.wait_result()?.ok_or(Error::SetCtxResultFailed)?;
+    /// Re-register a Mr without blocking the thread, see `rereg_mr`
+    ///
+    /// # Errors
+    ///
+    /// The same as `rereg_mr`
+    pub async fn rereg_mr_async(&self, mr: Mr, attr: &ReregMrAttr) -> Result<Mr, Error> {
+        self.check_running()?;
+        let mr_idx = self.0.mr_config.key_idx(mr.key);
+        let (old_ctx, _busy) = {
+            let mw_table = self.0.mw_table.lock();
+            let mut mr_table = self.0.mr_table.lock();
+            let pd_pool = self.0.pd.lock();
+            let Some(mr_ctx) = mr_table.get_mut(mr_idx) else {
+                return Err(Error::Invalid(format!("MR :{mr_idx}")));
+            };
+            if !pd_pool.get(&mr_ctx.pd).is_some_and(|pd_ctx| pd_ctx.mr.contains(&mr)) {
+                return Err(Error::Invalid(format!("MR :{mr_idx}")));
+            }
+            if mr_ctx.busy {
+                return Err(Error::Invalid(format!("MR is being reregistered :{mr_idx}")));
+            }
+            if is_mr_bound(&mw_table, mr) {
+                return Err(Error::Invalid(format!("MR bound to memory window :{mr_idx}")));
+            }
+            // the tables are released while waiting for the device, so the MR is kept from being
+            // deregistered, reregistered or bound until the device switches to the new key
+            mr_ctx.busy = true;
+            (*mr_ctx, MrBusyGuard { dev: self, mr_idx })
+        };
 
-        if !res {
-            return Err(Error::DeviceReturnFailed("deregister mr table"));
+        let mut new_ctx = old_ctx;
+        if attr.flags.contains(ReregMrFlags::IbvReregMrChangePd) {
+            new_ctx.pd = attr.pd;
+        }
+        if attr.flags.contains(ReregMrFlags::IbvReregMrChangeAccess) {
+            new_ctx.acc_flags = attr.acc_flags;
         }
+        let change_translation = attr.flags.contains(ReregMrFlags::IbvReregMrChangeTranslation);
+        if change_translation && attr.pg_size == 0 {
+            return Err(Error::Invalid("MR page size :0".to_owned()));
+        }
+        // the page table of an on-demand paging MR is filled in a different way
+        let toggle_on_demand = new_ctx.acc_flags.symmetric_difference(old_ctx.acc_flags);
+        if toggle_on_demand.contains(MemAccessTypeFlag::IbvAccessOnDemand) && !change_translation {
+            return Err(Error::Invalid("on-demand paging change without translation".to_owned()));
+        }
+        if change_translation {
+            // the new page table takes other entries, so the old one stays valid until the MR is updated
+            new_ctx.pgt_offset = self
+                .register_page_table(attr.addr, attr.len, attr.pg_size, new_ctx.acc_flags)
+                .await?;
+            new_ctx.addr = attr.addr;
+            new_ctx.len = attr.len;
+            new_ctx.pg_size = attr.pg_size;
+        }
+        let free_new_pgt = || {
+            if change_translation {
+                self.free_page_table_on_error(new_ctx.pgt_offset, new_ctx.len.div_ceil(new_ctx.pg_size));
+            }
+        };
 
-        self.deregister_page_table(mr_ctx.pgt_offset, mr_ctx.len.div_ceil(mr_ctx.pg_size))?;
+        let new_mr = Mr {
+            key: self.0.mr_config.rotate_key(mr.key),
+        };
+        let ctx = {
+            let mut pd_pool = self.0.pd.lock();
+            // the new PD holds the new MR before the device accepts it, so that it's not taken away meanwhile
+            let Some(pd_ctx) = pd_pool.get_mut(&new_ctx.pd) else {
+                drop(pd_pool);
+                free_new_pgt();
+                return Err(Error::Invalid(format!("PD :{:?}", new_ctx.pd)));
+            };
+            let _: bool = pd_ctx.mr.insert(new_mr);
+            let op_id = self.get_ctrl_op_id();
+            match self.do_ctrl_op(op_id, new_ctx.update_desc(op_id, new_mr.key)) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    let _: bool = pd_ctx.mr.remove(&new_mr);
+                    drop(pd_pool);
+                    free_new_pgt();
+                    return Err(e);
+                }
+            }
+        };
 
-        if !pd_ctx.mr.remove(&mr) {
-            return Err(Error::Invalid(format!("MR :{mr_idx}")));
+        if let Err(e) = wait_ctrl_op(ctx, "reregister mr table").await {
+            if let Some(pd_ctx) = self.0.pd.lock().get_mut(&new_ctx.pd) {
+                let _: bool = pd_ctx.mr.remove(&new_mr);
+            }
+            free_new_pgt();
+            return Err(e);
         }
-        *ctx_option = None;
 
-        Ok(())
+        {
+            let mut mr_table = self.0.mr_table.lock();
+            let mut pd_pool = self.0.pd.lock();
+            if let Some(pd_ctx) = pd_pool.get_mut(&old_ctx.pd) {
+                let _: bool = pd_ctx.mr.remove(&mr);
+            }
+            if let Some(mr_ctx) = mr_table.get_mut(mr_idx) {
+                // the busy flag is cleared once the guard is dropped
+                *mr_ctx = new_ctx;
+            }
+        }
+        if change_translation {
+            self.deregister_page_table(old_ctx.pgt_offset, old_ctx.len.div_ceil(old_ctx.pg_size))?;
+        }
+        Ok(new_mr)
     }
 }
 
-impl MrPgt {
-    pub(crate) fn new(buffer: Buffer) -> Self {
-        let len = buffer.size() / MR_PGT_ENTRY_SIZE;
-        let free_blk = Box::into_raw(Box::new(MrPgtFreeBlk {
-            idx: 0,
-            len,
-            prev: ptr::null_mut(),
-            next: ptr::null_mut(),
-        }));
-        assert!(
-            buffer.as_ref().as_ptr() as usize % PAGE_SIZE == 0,
-            "buffer should be aligned to PAGE_SIZE"
-        );
-        Self {
-            table: buffer,
-            free_blk_list: free_blk,
+impl MrCtx {
+    /// The address of the first byte seen by the users of the MR
+    pub(crate) fn start_addr(&self) -> u64 {
+        if self.acc_flags.contains(MemAccessTypeFlag::IbvAccessZeroBased) {
+            0
+        } else {
+            self.addr
         }
     }
 
-    #[allow(clippy::arithmetic_side_effects)]
-    fn alloc(&mut self, len: usize) -> Result<usize, Error> {
-        let mut ptr = self.free_blk_list;
-
-        while !ptr.is_null() {
-            let blk = unsafe { ptr.as_mut() };
-            let blk = unsafe { blk.unwrap_unchecked() };
-
-            if blk.len >= len {
-                let idx = blk.idx;
-
-                blk.idx += len; // idx, len are all smaller than `MR_PGT_SIZE`
-                blk.len -= len;
-
-                if blk.len == 0 {
-                    if blk.prev.is_null() {
-                        self.free_blk_list = blk.next;
-                    } else {
-                        let prev = unsafe { blk.prev.as_mut() };
-                        let prev = unsafe { prev.unwrap_unchecked() };
-                        prev.next = blk.next;
-                    }
-
-                    if !blk.next.is_null() {
-                        let next = unsafe { blk.next.as_mut() };
-                        let next = unsafe { next.unwrap_unchecked() };
-                        next.prev = blk.prev;
-                    }
-
-                    drop(unsafe { Box::from_raw(ptr) });
-                }
+    fn update_desc(&self, op_id: u32, key: Key) -> ToCardCtrlRbDesc {
+        // `pgt_offset` is smaller than the length of the page table, which is expected to fit in u32
+        #[allow(clippy::cast_possible_truncation)]
+        ToCardCtrlRbDesc::UpdateMrTable(ToCardCtrlRbDescUpdateMrTable {
+            common: ToCardCtrlRbDescCommon { op_id },
+            addr: self.addr,
+            len: self.len,
+            key,
+            pd_hdl: self.pd.handle,
+            acc_flags: self.acc_flags,
+            pgt_offset: self.pgt_offset as u32,
+            pg_size: self.pg_size,
+        })
+    }
+}
 
-                return Ok(idx);
-            }
+bitflags! {
+    /// The changes made by `rereg_mr`, the values are the same as `enum ibv_rereg_mr_flags` of rdma-core
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct ReregMrFlags: u32 {
+        /// Change the address range
+        const IbvReregMrChangeTranslation = 1; // (1 << 0)
 
-            ptr = blk.next;
-        }
+        /// Change the PD
+        const IbvReregMrChangePd = 2;          // (1 << 1)
 
-        Err(Error::ResourceNoAvailable("MR page table".to_owned()))
+        /// Change the access flags
+        const IbvReregMrChangeAccess = 4;      // (1 << 2)
     }
+}
 
-    fn dealloc(&mut self, idx: usize, len: usize) {
-        let mut prev_ptr = ptr::null_mut();
-        let mut ptr = self.free_blk_list;
-
-        while !ptr.is_null() {
-            let blk = unsafe { ptr.as_mut() };
-            let blk = unsafe { blk.unwrap_unchecked() };
-
-            if blk.len > len {
-                break;
-            }
+/// The attributes of `rereg_mr`
+///
+/// Only the attributes in `flags` are changed, which is set along with the attribute.
+/// The key is rotated even if `flags` is empty.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy)]
+pub struct ReregMrAttr {
+    /// The new PD
+    pub pd: Pd,
+    /// The start address of the new range
+    pub addr: u64,
+    /// The length of the new range
+    pub len: u32,
+    /// The page size of the new range
+    pub pg_size: u32,
+    /// The new access flags
+    pub acc_flags: MemAccessTypeFlag,
+    /// The changed attributes
+    pub flags: ReregMrFlags,
+}
 
-            prev_ptr = ptr;
-            ptr = blk.next;
+impl Default for ReregMrAttr {
+    fn default() -> Self {
+        Self {
+            pd: Pd::default(),
+            addr: 0,
+            len: 0,
+            pg_size: 0,
+            acc_flags: MemAccessTypeFlag::IbvAccessNoFlags,
+            flags: ReregMrFlags::empty(),
         }
+    }
+}
 
-        let new_ptr = Box::into_raw(Box::new(MrPgtFreeBlk {
-            idx,
-            len,
-            prev: prev_ptr,
-            next: ptr,
-        }));
+impl ReregMrAttr {
+    /// Create the attributes which only rotate the key
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let new = unsafe { new_ptr.as_mut() };
-        let new = unsafe { new.unwrap_unchecked() };
+    /// Move the MR to `[addr, addr + len)`
+    #[must_use]
+    pub fn with_translation(mut self, addr: u64, len: u32, pg_size: u32) -> Self {
+        self.addr = addr;
+        self.len = len;
+        self.pg_size = pg_size;
+        self.flags |= ReregMrFlags::IbvReregMrChangeTranslation;
+        self
+    }
 
-        if new.prev.is_null() {
-            self.free_blk_list = new_ptr;
-        } else {
-            let new_prev = unsafe { new.prev.as_mut() };
-            let new_prev = unsafe { new_prev.unwrap_unchecked() };
-            new_prev.next = new_ptr;
-        }
+    /// Move the MR to `pd`
+    #[must_use]
+    pub fn with_pd(mut self, pd: Pd) -> Self {
+        self.pd = pd;
+        self.flags |= ReregMrFlags::IbvReregMrChangePd;
+        self
+    }
 
-        if !new.next.is_null() {
-            let new_next = unsafe { new.next.as_mut() };
-            let new_next = unsafe { new_next.unwrap_unchecked() };
-            new_next.prev = new_ptr;
+    /// Change the access flags of the MR
+    #[must_use]
+    pub fn with_access(mut self, acc_flags: MemAccessTypeFlag) -> Self {
+        self.acc_flags = acc_flags;
+        self.flags |= ReregMrFlags::IbvReregMrChangeAccess;
+        self
+    }
+}
+
+impl MrTable {
+    pub(crate) fn new(size: u32) -> Self {
+        Self {
+            slots: vec![None; size as usize],
+            free_idx: (0..size).rev().collect(),
         }
+    }
 
-        while !new.prev.is_null() {
-            let new_prev = unsafe { new.prev.as_mut() };
-            let new_prev = unsafe { new_prev.unwrap_unchecked() };
+    /// Take a free index, which is filled by `insert` or given back by `free`
+    pub(crate) fn alloc(&mut self) -> Option<u32> {
+        self.free_idx.pop()
+    }
 
-            if new_prev.idx.wrapping_add(new_prev.len) != new.len {
-                break;
-            }
+    /// Give back an index taken by `alloc` that is not filled
+    pub(crate) fn free(&mut self, idx: u32) {
+        self.free_idx.push(idx);
+    }
 
-            new.idx = new_prev.idx;
-            new.len = new.len.wrapping_add(new_prev.len);
+    pub(crate) fn insert(&mut self, idx: u32, mr_ctx: MrCtx) {
+        if let Some(slot) = self.slots.get_mut(idx as usize) {
+            *slot = Some(mr_ctx);
+        }
+    }
 
-            let new_prev_prev_ptr = new_prev.prev;
-            drop(unsafe { Box::from_raw(new.prev) });
+    pub(crate) fn get(&self, idx: u32) -> Option<&MrCtx> {
+        self.slots.get(idx as usize).and_then(Option::as_ref)
+    }
 
-            if new_prev_prev_ptr.is_null() {
-                self.free_blk_list = new_ptr;
-            } else {
-                let new_prev_prev = unsafe { new_prev_prev_ptr.as_mut() };
-                let new_prev_prev = unsafe { new_prev_prev.unwrap_unchecked() };
-                new_prev_prev.next = new_ptr;
-            }
+    pub(crate) fn get_mut(&mut self, idx: u32) -> Option<&mut MrCtx> {
+        self.slots.get_mut(idx as usize).and_then(Option::as_mut)
+    }
 
-            new.prev = new_prev_prev_ptr;
-        }
+    /// Take the MR out of the table, and free its index
+    pub(crate) fn remove(&mut self, idx: u32) -> Option<MrCtx> {
+        let mr_ctx = self.slots.get_mut(idx as usize).and_then(Option::take)?;
+        self.free(idx);
+        Some(mr_ctx)
+    }
+}
 
-        while !new.next.is_null() {
-            let new_next = unsafe { new.next.as_mut() };
-            let new_next = unsafe { new_next.unwrap_unchecked() };
+impl MrPgt {
+    /// The page table of the first `len` entries of `buffer`
+    pub(crate) fn new(buffer: Buffer, len: usize) -> Self {
+        assert!(
+            buffer.as_ref().as_ptr() as usize % PAGE_SIZE == 0,
+            "buffer should be aligned to PAGE_SIZE"
+        );
+        assert!(
+            len.saturating_mul(MR_PGT_ENTRY_SIZE) <= buffer.size(),
+            "buffer should hold {len} entries"
+        );
+        let mut pgt = Self {
+            table: buffer,
+            free_blks: BTreeMap::new(),
+            free_blks_by_len: BTreeSet::new(),
+        };
+        pgt.insert_free_blk(0, len);
+        pgt
+    }
 
-            if new_next.idx != new.idx.wrapping_add(new.len) {
-                break;
-            }
+    fn insert_free_blk(&mut self, idx: usize, len: usize) {
+        if len != 0 {
+            let _: Option<usize> = self.free_blks.insert(idx, len);
+            let _: bool = self.free_blks_by_len.insert((len, idx));
+        }
+    }
 
-            new.len = new.len.wrapping_add(new_next.len);
+    fn remove_free_blk(&mut self, idx: usize, len: usize) {
+        let _: Option<usize> = self.free_blks.remove(&idx);
+        let _: bool = self.free_blks_by_len.remove(&(len, idx));
+    }
 
-            let new_next_next_ptr = new_next.next;
-            drop(unsafe { Box::from_raw(new.next) });
+    /// Take `len` continuous entries from the smallest free block that fits
+    #[allow(clippy::arithmetic_side_effects)]
+    fn alloc(&mut self, len: usize) -> Result<usize, Error> {
+        let Some(&(blk_len, idx)) = self.free_blks_by_len.range((len, 0)..).next() else {
+            return Err(Error::ResourceNoAvailable("MR page table".to_owned()));
+        };
+        self.remove_free_blk(idx, blk_len);
+        // the rest of the block is still free, `blk_len` is not smaller than `len`
+        self.insert_free_blk(idx + len, blk_len - len);
+        Ok(idx)
+    }
 
-            if !new_next_next_ptr.is_null() {
-                let new_next_next = unsafe { new_next_next_ptr.as_mut() };
-                let new_next_next = unsafe { new_next_next.unwrap_unchecked() };
-                new_next_next.prev = new_ptr;
+    /// Give back the entries taken by `alloc`, which are merged with the free blocks around them
+    #[allow(clippy::arithmetic_side_effects)]
+    fn dealloc(&mut self, mut idx: usize, mut len: usize) {
+        if len == 0 {
+            return;
+        }
+        if let Some((&prev_idx, &prev_len)) = self.free_blks.range(..idx).next_back() {
+            if prev_idx + prev_len == idx {
+                self.remove_free_blk(prev_idx, prev_len);
+                idx = prev_idx;
+                len += prev_len;
             }
-
-            new.next = new_next_next_ptr;
         }
+        if let Some(&next_len) = self.free_blks.get(&(idx + len)) {
+            self.remove_free_blk(idx + len, next_len);
+            len += next_len;
+        }
+        self.insert_free_blk(idx, len);
     }
 }
 
-unsafe impl Send for MrPgt {}
-unsafe impl Sync for MrPgt {}
-
 impl Hash for Mr {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.key.hash(state);
@@ -437,3 +884,116 @@ impl PartialEq for Mr {
 }
 
 impl Eq for Mr {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use blue_rdma_device::emulator::LocalNetwork;
+
+    use super::{MrBusyGuard, MrConfig, MrPgt, ReregMrAttr};
+    use crate::mw::MwType;
+    use crate::tests::test_fence::network_param;
+    use crate::types::{Key, MemAccessTypeFlag, PAGE_SIZE};
+    use crate::utils::Buffer;
+    use crate::{
+        AlignedMemory, Device, DeviceConfigBuilder, DeviceType, Error, RetryConfig, RoundRobinStrategy,
+        MR_PGT_ENTRY_SIZE,
+    };
+
+    #[test]
+    fn test_mr_key_rotation() {
+        let mr_config = MrConfig::default();
+        let key = Key::new(0x0512_3456);
+        for _ in 0..16 {
+            let new_key = mr_config.rotate_key(key);
+            assert_ne!(new_key, key);
+            // the MR stays in the same slot of the MR table
+            assert_eq!(new_key.get() >> 24, 0x05);
+        }
+
+        let mr_config = MrConfig::new(12, 1024, 1024).unwrap();
+        let key = mr_config.new_key(0x123);
+        assert_eq!(mr_config.key_idx(key), 0x123);
+        assert_eq!(mr_config.key_idx(mr_config.rotate_key(key)), 0x123);
+        assert_eq!(mr_config.mw_idx_range(), 1024..4096);
+    }
+
+    #[test]
+    fn test_mr_config_limits() {
+        assert!(MrConfig::new(0, 1, 1).is_err());
+        assert!(MrConfig::new(25, 1, 1).is_err());
+        assert!(MrConfig::new(8, 257, 1).is_err());
+        assert!(MrConfig::new(8, 256, 0).is_err());
+        assert!(MrConfig::new(8, 256, (1 << 17) + 1).is_err());
+        assert!(MrConfig::new(24, 1 << 24, 1 << 17).is_ok());
+    }
+
+    #[test]
+    fn test_mr_pgt_alloc() {
+        let buffer = Buffer::new(64 * MR_PGT_ENTRY_SIZE, false).unwrap();
+        let mut pgt = MrPgt::new(buffer, 64);
+        let a = pgt.alloc(8).unwrap();
+        let b = pgt.alloc(4).unwrap();
+        let c = pgt.alloc(16).unwrap();
+        let d = pgt.alloc(4).unwrap();
+        let e = pgt.alloc(4).unwrap();
+        assert_eq!((a, b, c, d, e), (0, 8, 12, 28, 32));
+
+        // the smallest hole that fits is taken, instead of the first one
+        pgt.dealloc(a, 8);
+        pgt.dealloc(d, 4);
+        assert_eq!(pgt.alloc(4).unwrap(), d);
+        assert_eq!(pgt.alloc(28).unwrap(), 36);
+        assert!(pgt.alloc(9).is_err());
+
+        // the freed entries are merged with both neighbours
+        pgt.dealloc(b, 4);
+        pgt.dealloc(c, 16);
+        assert_eq!(pgt.alloc(28).unwrap(), 0);
+        assert!(pgt.alloc(1).is_err());
+    }
+
+    #[test]
+    fn test_busy_mr_is_excluded() {
+        let config = DeviceConfigBuilder::default()
+            .network_config(network_param(2))
+            .device_type(DeviceType::Local(Arc::new(LocalNetwork::default())))
+            .strategy(RoundRobinStrategy::new())
+            .retry_config(RetryConfig::new(
+                false,
+                1,
+                Duration::from_secs(100),
+                Duration::from_millis(10),
+            ))
+            .scheduler_size(1024 * 32)
+            .build()
+            .unwrap();
+        let dev = Device::new(config).unwrap();
+        let pd = dev.alloc_pd().unwrap();
+        let mut buffer = AlignedMemory::new(PAGE_SIZE).unwrap();
+        let addr = buffer.as_mut().as_mut_ptr() as u64;
+        let acc_flags = MemAccessTypeFlag::IbvAccessLocalWrite | MemAccessTypeFlag::IbvAccessMwBind;
+        let mr = dev.reg_mr(pd, addr, 4096, PAGE_SIZE as u32, acc_flags).unwrap();
+        let mw = dev.alloc_mw(pd, MwType::Type2).unwrap();
+
+        // mark the MR busy as a reregistration waiting for the device does
+        let mr_idx = dev.0.mr_config.key_idx(mr.get_key());
+        dev.0.mr_table.lock().get_mut(mr_idx).unwrap().busy = true;
+        let busy = MrBusyGuard { dev: &dev, mr_idx };
+        assert!(matches!(dev.dereg_mr(mr), Err(Error::Invalid(_))));
+        assert!(matches!(dev.rereg_mr(mr, &ReregMrAttr::new()), Err(Error::Invalid(_))));
+        let remote_read = MemAccessTypeFlag::IbvAccessRemoteRead;
+        assert!(matches!(
+            dev.bind_mw(mw, mr, addr, 16, remote_read),
+            Err(Error::Invalid(_))
+        ));
+
+        drop(busy);
+        let new_mr = dev.rereg_mr(mr, &ReregMrAttr::new()).unwrap();
+        dev.dealloc_mw(mw).unwrap();
+        dev.dereg_mr(new_mr).unwrap();
+        dev.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::device::{ToCardCtrlRbDesc, ToCardCtrlRbDescCommon, ToCardCtrlRbDescUpdateMwTable};
+use crate::op_ctx::CtrlOpCtx;
+use crate::types::{Key, MemAccessTypeFlag};
+use crate::utils::block_on;
+use crate::{wait_ctrl_op, Device, Error, Mr, MrConfig, Pd};
+
+/// The low 8 bits of a key, which is rotated by each bind
+const MW_KEY_TAG_MASK: u32 = 0xFF;
+
+/// The access rights that can be granted through a memory window
+const MW_ACCESS_FLAGS: MemAccessTypeFlag = MemAccessTypeFlag::IbvAccessRemoteRead
+    .union(MemAccessTypeFlag::IbvAccessRemoteWrite)
+    .union(MemAccessTypeFlag::IbvAccessRemoteAtomic);
+
+/// The type of a memory window
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MwType {
+    /// Bound and unbound by the owner only, and may be rebound while bound
+    Type1,
+    /// Must be invalidated before the next bind, either locally or by the peer through a SEND with
+    /// invalidate
+    Type2,
+}
+
+/// Memory Window
+///
+/// A handle of a memory window allocated by `Device::alloc_mw`.
+/// A window grants remote access to a sub-range of a `Mr` through its own key, which is rotated by
+/// every bind, so that the access can be revoked without deregistering the MR.
+#[derive(Debug, Clone, Copy)]
+pub struct Mw {
+    pub(crate) idx: u32,
+}
+
+impl Hash for Mw {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.idx.hash(state);
+    }
+}
+
+impl PartialEq for Mw {
+    fn eq(&self, other: &Self) -> bool {
+        self.idx == other.idx
+    }
+}
+
+impl Eq for Mw {}
+
+impl Mw {
+    /// The memory window whose key is `key`, the window may not exist
+    fn from_key(mr_config: &MrConfig, key: Key) -> Self {
+        Self {
+            idx: mr_config.key_idx(key),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MwBinding {
+    mr: Mr,
+    addr: u64,
+    len: u32,
+    acc_flags: MemAccessTypeFlag,
+}
+
+/// MW context
+#[derive(Debug)]
+pub(crate) struct MwCtx {
+    pd: Pd,
+    mw_type: MwType,
+    /// the key of the current or the last binding
+    key: Key,
+    binding: Option<MwBinding>,
+}
+
+impl MwCtx {
+    fn update_desc(&self, op_id: u32, binding: Option<MwBinding>) -> ToCardCtrlRbDesc {
+        let (mr_key, addr, len, acc_flags) = binding
+            .map_or((Key::default(), 0, 0, MemAccessTypeFlag::IbvAccessNoFlags), |binding| {
+                (binding.mr.key, binding.addr, binding.len, binding.acc_flags)
+            });
+        ToCardCtrlRbDesc::UpdateMwTable(ToCardCtrlRbDescUpdateMwTable {
+            common: ToCardCtrlRbDescCommon { op_id },
+            is_valid: binding.is_some(),
+            is_type2: matches!(self.mw_type, MwType::Type2),
+            addr,
+            len,
+            mw_key: self.key,
+            mr_key,
+            acc_flags,
+        })
+    }
+}
+
+/// The memory windows, shared with the packet checker which handles the invalidation of the peer
+pub(crate) type MwTable = Arc<Mutex<HashMap<Mw, MwCtx>>>;
+
+/// Unbind the type 2 window invalidated by a SEND with invalidate from the peer
+pub(crate) fn invalidate_by_peer(mw_table: &MwTable, mr_config: &MrConfig, rkey: Key) {
+    let mut mw_table = mw_table.lock();
+    match mw_table.get_mut(&Mw::from_key(mr_config, rkey)) {
+        Some(ctx) if ctx.key == rkey && matches!(ctx.mw_type, MwType::Type2) => ctx.binding = None,
+        _ => log::warn!("peer invalidated unknown memory window key {rkey:?}"),
+    }
+}
+
+/// Whether any memory window is bound to `mr`
+///
+/// The MW table is locked before the MR table, as `bind_mw` does.
+pub(crate) fn is_mr_bound(mw_table: &HashMap<Mw, MwCtx>, mr: Mr) -> bool {
+    mw_table
+        .values()
+        .any(|ctx| ctx.binding.is_some_and(|binding| binding.mr == mr))
+}
+
+/// The next key of a window, which only differs in the low 8 bits like `ibv_inc_rkey`
+fn inc_rkey(key: Key) -> Key {
+    let tag = key.get().wrapping_add(1) & MW_KEY_TAG_MASK;
+    Key::new((key.get() & !MW_KEY_TAG_MASK) | tag)
+}
+
+impl Device {
+    /// allocate a memory window
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Pd
+    /// * no free memory window
+    pub fn alloc_mw(&self, pd: Pd, mw_type: MwType) -> Result<Mw, Error> {
+        self.check_running()?;
+        let mut mw_table = self.0.mw_table.lock();
+        let mut pd_pool = self.0.pd.lock();
+        let pd_ctx = pd_pool.get_mut(&pd).ok_or(Error::Invalid(format!("PD :{pd:?}")))?;
+
+        let Some(mw) = self
+            .0
+            .mr_config
+            .mw_idx_range()
+            .map(|idx| Mw { idx })
+            .find(|mw| !mw_table.contains_key(mw))
+        else {
+            return Err(Error::ResourceNoAvailable("MW".to_owned()));
+        };
+
+        let ctx = MwCtx {
+            pd,
+            mw_type,
+            key: self.0.mr_config.new_key(mw.idx),
+            binding: None,
+        };
+        let _: bool = pd_ctx.mw.insert(mw);
+        let _: Option<MwCtx> = mw_table.insert(mw, ctx);
+        Ok(mw)
+    }
+
+    /// deallocate a memory window, the window is invalidated first if it is bound
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Mw
+    /// * the device failed to invalidate the window
+    pub fn dealloc_mw(&self, mw: Mw) -> Result<(), Error> {
+        block_on(self.dealloc_mw_async(mw))
+    }
+
+    /// deallocate a memory window without blocking the thread, see `dealloc_mw`
+    ///
+    /// # Errors
+    ///
+    /// The same as `dealloc_mw`
+    pub async fn dealloc_mw_async(&self, mw: Mw) -> Result<(), Error> {
+        let ctrl_ctx = {
+            let mut mw_table = self.0.mw_table.lock();
+            let ctx = mw_table.get_mut(&mw).ok_or(Error::Invalid(format!("MW :{mw:?}")))?;
+            self.send_mw_update(ctx, None)?
+        };
+        if let Some(ctrl_ctx) = ctrl_ctx {
+            wait_ctrl_op(ctrl_ctx, "invalidate mw").await?;
+        }
+
+        let mut mw_table = self.0.mw_table.lock();
+        let mut pd_pool = self.0.pd.lock();
+        if let Some(ctx) = mw_table.remove(&mw) {
+            if let Some(pd_ctx) = pd_pool.get_mut(&ctx.pd) {
+                let _: bool = pd_ctx.mw.remove(&mw);
+            }
+        }
+        Ok(())
+    }
+
+    /// Bind a memory window to `[addr, addr + len)` of `mr`, and return the new remote key of the window
+    ///
+    /// The key is rotated by every bind, so the key of the previous binding is no longer accepted.
+    /// `acc_flags` can only hold remote access rights, and the remote write and atomic rights require
+    /// the MR to be locally writable.
+    /// A type 1 window is unbound by binding it with a zero `len`.
+    /// The binding is sent to the device through the command queue, so it is not ordered with the work
+    /// requests of any QP.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Mw or Mr
+    /// * the window and the MR are in different PDs
+    /// * the MR is not registered with `IbvAccessMwBind`
+    /// * the range is not within the MR
+    /// * invalid `acc_flags`
+    /// * a type 2 window is still bound
+    /// * the device failed to bind the window
+    pub fn bind_mw(&self, mw: Mw, mr: Mr, addr: u64, len: u32, acc_flags: MemAccessTypeFlag) -> Result<Key, Error> {
+        block_on(self.bind_mw_async(mw, mr, addr, len, acc_flags))
+    }
+
+    /// Bind a memory window without blocking the thread, see `bind_mw`
+    ///
+    /// # Errors
+    ///
+    /// The same as `bind_mw`
+    pub async fn bind_mw_async(
+        &self,
+        mw: Mw,
+        mr: Mr,
+        addr: u64,
+        len: u32,
+        acc_flags: MemAccessTypeFlag,
+    ) -> Result<Key, Error> {
+        self.check_running()?;
+        if !MW_ACCESS_FLAGS.contains(acc_flags) {
+            return Err(Error::Invalid(format!("MW access flags :{acc_flags:?}")));
+        }
+        let (key, ctrl_ctxs, prev) = {
+            let mut mw_table = self.0.mw_table.lock();
+            let ctx = mw_table.get_mut(&mw).ok_or(Error::Invalid(format!("MW :{mw:?}")))?;
+            if len == 0 && matches!(ctx.mw_type, MwType::Type2) {
+                return Err(Error::Invalid("MW length :0".to_owned()));
+            }
+            if ctx.binding.is_some() && matches!(ctx.mw_type, MwType::Type2) {
+                return Err(Error::Invalid(format!("MW is still bound :{mw:?}")));
+            }
+            let binding = if len == 0 {
+                None
+            } else {
+                self.check_mw_binding(ctx.pd, mr, addr, len, acc_flags)?;
+                Some(MwBinding {
+                    mr,
+                    addr,
+                    len,
+                    acc_flags,
+                })
+            };
+            let prev = (ctx.key, ctx.binding);
+            // the device looks up a window by the whole key, so the key of a type 1 window still bound is
+            // revoked before the rebind
+            let unbind_ctx = if binding.is_some() {
+                self.send_mw_update(ctx, None)?
+            } else {
+                None
+            };
+            if binding.is_some() {
+                ctx.key = inc_rkey(ctx.key);
+            }
+            // record the binding before the device accepts it, so that the MR can't be deregistered meanwhile
+            let ctrl_ctx = self.send_mw_update(ctx, binding).inspect_err(|_| ctx.key = prev.0)?;
+            (ctx.key, [unbind_ctx, ctrl_ctx], prev)
+        };
+
+        for ctrl_ctx in ctrl_ctxs.into_iter().flatten() {
+            if let Err(e) = wait_ctrl_op(ctrl_ctx, "bind mw").await {
+                if let Some(ctx) = self.0.mw_table.lock().get_mut(&mw) {
+                    (ctx.key, ctx.binding) = prev;
+                }
+                return Err(e);
+            }
+        }
+        Ok(key)
+    }
+
+    /// Invalidate a bound type 2 memory window, its key is no longer accepted by the device
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Mw
+    /// * the window is not a bound type 2 window
+    /// * the device failed to invalidate the window
+    pub fn invalidate_mw(&self, mw: Mw) -> Result<(), Error> {
+        block_on(self.invalidate_mw_async(mw))
+    }
+
+    /// Invalidate a memory window without blocking the thread, see `invalidate_mw`
+    ///
+    /// # Errors
+    ///
+    /// The same as `invalidate_mw`
+    pub async fn invalidate_mw_async(&self, mw: Mw) -> Result<(), Error> {
+        self.check_running()?;
+        let (ctrl_ctx, prev) = {
+            let mut mw_table = self.0.mw_table.lock();
+            let ctx = mw_table.get_mut(&mw).ok_or(Error::Invalid(format!("MW :{mw:?}")))?;
+            if !matches!(ctx.mw_type, MwType::Type2) {
+                return Err(Error::Invalid(format!("invalidate type 1 MW :{mw:?}")));
+            }
+            let prev = ctx.binding;
+            let ctrl_ctx = self
+                .send_mw_update(ctx, None)?
+                .ok_or(Error::Invalid(format!("MW is not bound :{mw:?}")))?;
+            (ctrl_ctx, prev)
+        };
+
+        if let Err(e) = wait_ctrl_op(ctrl_ctx, "invalidate mw").await {
+            if let Some(ctx) = self.0.mw_table.lock().get_mut(&mw) {
+                ctx.binding = prev;
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// check that a window of `pd` can be bound to the range of `mr`
+    fn check_mw_binding(&self, pd: Pd, mr: Mr, addr: u64, len: u32, acc_flags: MemAccessTypeFlag) -> Result<(), Error> {
+        let mr_table = self.0.mr_table.lock();
+        let mr_idx = self.0.mr_config.key_idx(mr.key);
+        let Some(mr_ctx) = mr_table.get(mr_idx) else {
+            return Err(Error::Invalid(format!("MR :{mr_idx}")));
+        };
+        if mr_ctx.pd != pd {
+            return Err(Error::Invalid(format!("MR of another PD :{mr_idx}")));
+        }
+        if mr_ctx.busy {
+            return Err(Error::Invalid(format!("MR is being reregistered :{mr_idx}")));
+        }
+        if !mr_ctx.acc_flags.contains(MemAccessTypeFlag::IbvAccessMwBind) {
+            return Err(Error::Invalid(format!("MR without MW bind access :{mr_idx}")));
+        }
+        let is_writable =
+            acc_flags.intersects(MemAccessTypeFlag::IbvAccessRemoteWrite | MemAccessTypeFlag::IbvAccessRemoteAtomic);
+        if is_writable && !mr_ctx.acc_flags.contains(MemAccessTypeFlag::IbvAccessLocalWrite) {
+            return Err(Error::Invalid(format!("MR without local write access :{mr_idx}")));
+        }
+        let mr_start = mr_ctx.start_addr();
+        let mr_end = mr_start.saturating_add(u64::from(mr_ctx.len));
+        let is_within = addr
+            .checked_add(u64::from(len))
+            .is_some_and(|end| mr_start <= addr && end <= mr_end);
+        if !is_within {
+            return Err(Error::Invalid(format!("MW range :{addr:#x}, {len:#x}")));
+        }
+        Ok(())
+    }
+
+    /// set the binding of the window and send it to the device, or nothing to send if both are unbound
+    fn send_mw_update(&self, ctx: &mut MwCtx, binding: Option<MwBinding>) -> Result<Option<CtrlOpCtx>, Error> {
+        if ctx.binding.is_none() && binding.is_none() {
+            return Ok(None);
+        }
+        let op_id = self.get_ctrl_op_id();
+        let desc = ctx.update_desc(op_id, binding);
+        let ctrl_ctx = self.do_ctrl_op(op_id, desc)?;
+        ctx.binding = binding;
+        Ok(Some(ctrl_ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{inc_rkey, Mw};
+    use crate::types::Key;
+    use crate::MrConfig;
+
+    #[test]
+    fn test_mw_key_rotation() {
+        let key = Key::new(0x4012_34FE);
+        let key = inc_rkey(key);
+        assert_eq!(key.get(), 0x4012_34FF);
+        // only the low 8 bits wrap around, the window is still found by the key
+        let key = inc_rkey(key);
+        assert_eq!(key.get(), 0x4012_3400);
+        assert!(Mw::from_key(&MrConfig::default(), key) == Mw { idx: 0x40 });
+    }
+}
@@ -1,9 +1,15 @@
 use std::fmt::Debug;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, OnceLock};
-use std::thread::{self, Thread};
+use std::task::{Context, Poll, Waker};
 
 use parking_lot::Mutex;
 
+use crate::cq::{CqContext, WorkCompletion, WorkCompletionStatus};
+use crate::qp::SendCredit;
+use crate::utils::block_on;
 use crate::Error;
 
 /// The status of operations.
@@ -28,6 +34,8 @@ pub enum CtxStatus {
 ///
 /// You can wait for the operation to finish by calling the `wait` method and get the result by
 /// calling the `get_result` method.
+///
+/// The context is also a `Future` resolving to the result, which works with any async runtime.
 #[derive(Debug, Clone)]
 pub struct OpCtx<Payload>(Arc<OpCtxWrapper<Payload>>);
 
@@ -36,6 +44,15 @@ struct OpCtxWrapper<Payload> {
     inner: Mutex<OpCtxInner>,
     payload: OnceLock<Payload>,
     handler: Mutex<Option<Box<dyn Fn(bool) + Sync + Send>>>,
+    /// the completion entry delivered to the bound CQ when the operation is done
+    completion: Mutex<Option<(Arc<CqContext>, WorkCompletion)>>,
+    /// `false` if the completion entry is only delivered when the operation fails
+    signaled: AtomicBool,
+    /// the unsignaled requests posted before this one, which are done once this one succeeds,
+    /// for the peer handles the requests of a QP in order
+    preceding: Mutex<Vec<OpCtx<()>>>,
+    /// the send queue credit taken by the request, given back once it's done
+    credit: Mutex<Option<SendCredit>>,
 }
 
 impl<Payload> Debug for OpCtxWrapper<Payload> {
@@ -46,7 +63,8 @@ impl<Payload> Debug for OpCtxWrapper<Payload> {
 
 #[derive(Debug)]
 struct OpCtxInner {
-    thread: Option<Thread>,
+    /// the threads and tasks waiting for the operation
+    wakers: Vec<Waker>,
     status: CtxStatus,
 }
 
@@ -59,13 +77,17 @@ impl<Payload> OpCtx<Payload> {
     #[must_use]
     pub fn new_running() -> Self {
         let inner = OpCtxInner {
-            thread: None,
+            wakers: Vec::new(),
             status: CtxStatus::Running,
         };
         let wrapper = OpCtxWrapper {
             inner: Mutex::new(inner),
             payload: OnceLock::new(),
             handler: Mutex::new(None),
+            completion: Mutex::new(None),
+            signaled: AtomicBool::new(true),
+            preceding: Mutex::new(Vec::new()),
+            credit: Mutex::new(None),
         };
         Self(Arc::new(wrapper))
     }
@@ -73,32 +95,62 @@ impl<Payload> OpCtx<Payload> {
     /// # Errors
     /// Returns an error if the operation context is poisoned.
     pub fn wait(&self) -> Result<(), Error> {
+        let _: CtxStatus = block_on(poll_fn(|cx| self.poll_done(cx)));
+        Ok(())
+    }
+
+    /// Ready with the final status once the operation is not running,
+    /// otherwise the waker of `cx` is woken when it is done.
+    fn poll_done(&self, cx: &mut Context<'_>) -> Poll<CtxStatus> {
         let mut guard = self.0.inner.lock();
-        if matches!(guard.status, CtxStatus::Running) {
-            guard.thread = Some(thread::current());
-            drop(guard);
-            thread::park();
+        if !matches!(guard.status, CtxStatus::Running) {
+            return Poll::Ready(guard.status);
         }
-        Ok(())
+        if !guard.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            guard.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    /// move the running operation to the final status with its result, and wake up all the waiters
+    ///
+    /// Returns `false` if the operation is already done, the first one to finish it wins.
+    fn finish(&self, status: CtxStatus, result: Option<Payload>) -> bool {
+        let wakers = {
+            let mut guard = self.0.inner.lock();
+            if !matches!(guard.status, CtxStatus::Running) {
+                return false;
+            }
+            if let Some(result) = result {
+                // the payload is only set here, and only once as the status leaves running once
+                let _ignore = self.0.payload.set(result);
+            }
+            guard.status = status;
+            std::mem::take(&mut guard.wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+        drop(self.0.credit.lock().take());
+        true
     }
 
     // TODO: use enum rather than str
-    pub(crate) fn set_error(&self, cause: &'static str) {
-        // set only once
-        let mut guard = self.0.inner.lock();
-        guard.status = CtxStatus::Failed(cause);
-        if let Some(thread) = guard.thread.take() {
-            thread.unpark();
+    /// `status` is reported to the bound CQ, unless the operation is already done.
+    pub(crate) fn set_error(&self, cause: &'static str, status: WorkCompletionStatus) {
+        if self.finish(CtxStatus::Failed(cause), None) {
+            self.complete(status);
         }
     }
 
+    /// # Errors
+    /// Returns an error if the operation is already done.
     pub(crate) fn set_result(&self, result: Payload) -> Result<(), Error> {
-        self.0.payload.set(result).map_err(|_| Error::SetCtxResultFailed)?;
-        // set only once
-        let mut guard = self.0.inner.lock();
-        guard.status = CtxStatus::Finished;
-        if let Some(thread) = guard.thread.take() {
-            thread.unpark();
+        if !self.finish(CtxStatus::Finished, Some(result)) {
+            return Err(Error::SetCtxResultFailed);
+        }
+        self.complete(WorkCompletionStatus::Success);
+        for ctx in std::mem::take(&mut *self.0.preceding.lock()) {
+            // the request may be completed by its own acknowledge at the same time
+            let _ignore = ctx.set_result(());
         }
         Ok(())
     }
@@ -131,12 +183,74 @@ impl<Payload> OpCtx<Payload> {
         *guard = Some(handler);
     }
 
+    /// Returns `true` if both contexts track the same operation.
+    pub(crate) fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Bind the operation to a CQ, `wc` is pushed to the CQ once the operation is done.
+    pub(crate) fn set_completion(&self, cq: Arc<CqContext>, wc: WorkCompletion) {
+        *self.0.completion.lock() = Some((cq, wc));
+    }
+
+    /// Like `set_completion`, but `wc` is only pushed to the CQ if the operation fails.
+    pub(crate) fn set_unsignaled_completion(&self, cq: Arc<CqContext>, wc: WorkCompletion) {
+        self.0.signaled.store(false, Ordering::Relaxed);
+        self.set_completion(cq, wc);
+    }
+
+    /// Hold `credit` until the operation is done.
+    pub(crate) fn set_credit(&self, credit: SendCredit) {
+        *self.0.credit.lock() = Some(credit);
+    }
+
+    /// Fail the operation without a completion entry, for the request is not posted at all.
+    pub(crate) fn cancel(&self, cause: &'static str) {
+        drop(self.0.completion.lock().take());
+        let _: bool = self.finish(CtxStatus::Failed(cause), None);
+    }
+
+    /// Complete `preceding` once this operation succeeds.
+    pub(crate) fn set_preceding(&self, preceding: Vec<OpCtx<()>>) {
+        *self.0.preceding.lock() = preceding;
+    }
+
+    /// Update the pending completion entry with the information only known at completion time.
+    pub(crate) fn update_completion(&self, f: impl FnOnce(&mut WorkCompletion)) {
+        if let Some((_, wc)) = self.0.completion.lock().as_mut() {
+            f(wc);
+        }
+    }
+
+    fn complete(&self, status: WorkCompletionStatus) {
+        if let Some((cq, mut wc)) = self.0.completion.lock().take() {
+            if matches!(status, WorkCompletionStatus::Success) && !self.0.signaled.load(Ordering::Relaxed) {
+                return;
+            }
+            wc.status = status;
+            cq.push(wc);
+        }
+    }
+
     pub(crate) fn take_handler(&self) -> Option<Box<(dyn Fn(bool) + Send + Sync)>> {
         let mut guard = self.0.handler.lock();
         guard.take()
     }
 }
 
+impl<Payload: Clone> Future for OpCtx<Payload> {
+    type Output = Result<Payload, Error>;
+
+    /// Resolves to the result once the operation is finished, or the cause if it failed.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll_done(cx).map(|status| match status {
+            CtxStatus::Finished => self.0.payload.get().cloned().ok_or(Error::SetCtxResultFailed),
+            CtxStatus::Failed(reason) => Err(Error::Device(reason.into())),
+            CtxStatus::Invalid | CtxStatus::Running => Err(Error::SetCtxResultFailed),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -160,4 +274,97 @@ mod tests {
         let _ = ctx.wait_result();
         assert_eq!(ctx.get_result(), Some(false).as_ref());
     }
+
+    #[test]
+    fn test_op_ctx_future() {
+        let ctx = super::OpCtx::new_running();
+        let ctx_clone = ctx.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            ctx_clone.set_result(true).unwrap();
+        });
+        assert!(crate::utils::block_on(ctx.clone()).unwrap());
+        // a finished context is ready immediately
+        assert!(crate::utils::block_on(ctx).unwrap());
+
+        let ctx = super::OpCtx::<bool>::new_running();
+        let ctx_clone = ctx.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            ctx_clone.set_error("failed", crate::cq::WorkCompletionStatus::RetryExceeded);
+        });
+        assert!(crate::utils::block_on(ctx).is_err());
+    }
+
+    #[test]
+    fn test_unsignaled_op_ctx() {
+        use std::sync::Arc;
+
+        use crate::cq::{CqContext, WorkCompletion, WorkCompletionOpcode, WorkCompletionStatus};
+        use crate::types::Qpn;
+
+        let cq = Arc::new(CqContext::new(8, false).unwrap());
+        let new_unsignaled = |wr_id| {
+            let ctx = super::OpCtx::<()>::new_running();
+            let wc = WorkCompletion::new(wr_id, WorkCompletionOpcode::RdmaWrite, Qpn::new(3), 0x10);
+            ctx.set_unsignaled_completion(Arc::clone(&cq), wc);
+            ctx
+        };
+        let unsignaled = (0..3).map(new_unsignaled).collect::<Vec<_>>();
+        // the failed request is reported even if it's unsignaled
+        unsignaled[1].set_error("failed", WorkCompletionStatus::RetryExceeded);
+        let polled = cq.poll(8).unwrap();
+        assert_eq!(polled.len(), 1);
+        assert_eq!(polled[0].wr_id, 1);
+        assert_eq!(polled[0].status, WorkCompletionStatus::RetryExceeded);
+
+        let signaled = super::OpCtx::<()>::new_running();
+        let wc = WorkCompletion::new(3, WorkCompletionOpcode::RdmaWrite, Qpn::new(3), 0x10);
+        signaled.set_completion(Arc::clone(&cq), wc);
+        signaled.set_preceding(unsignaled.clone());
+        signaled.set_result(()).unwrap();
+        // the preceding requests are done with the signaled one, which is the only completion
+        assert!(matches!(unsignaled[0].status(), super::CtxStatus::Finished));
+        assert!(matches!(unsignaled[1].status(), super::CtxStatus::Failed(_)));
+        assert!(matches!(unsignaled[2].status(), super::CtxStatus::Finished));
+        let polled = cq.poll(8).unwrap();
+        assert_eq!(polled.len(), 1);
+        assert_eq!(polled[0].wr_id, 3);
+        assert_eq!(polled[0].status, WorkCompletionStatus::Success);
+    }
+
+    #[test]
+    fn test_op_ctx_first_writer_wins() {
+        use std::sync::Arc;
+
+        use crate::cq::{CqContext, WorkCompletion, WorkCompletionOpcode, WorkCompletionStatus};
+        use crate::types::Qpn;
+
+        let cq = Arc::new(CqContext::new(8, false).unwrap());
+        let new_signaled = |wr_id| {
+            let ctx = super::OpCtx::<()>::new_running();
+            let wc = WorkCompletion::new(wr_id, WorkCompletionOpcode::RdmaWrite, Qpn::new(3), 0x10);
+            ctx.set_completion(Arc::clone(&cq), wc);
+            ctx
+        };
+
+        // a late failure, e.g. a flush racing with the acknowledge, is ignored
+        let finished = new_signaled(0);
+        finished.set_result(()).unwrap();
+        finished.set_error("flushed", WorkCompletionStatus::WrFlushError);
+        assert!(matches!(finished.status(), super::CtxStatus::Finished));
+
+        // and so is a late result, which doesn't set the payload either
+        let failed = new_signaled(1);
+        failed.set_error("failed", WorkCompletionStatus::RetryExceeded);
+        failed.set_error("flushed", WorkCompletionStatus::WrFlushError);
+        assert!(failed.set_result(()).is_err());
+        assert!(matches!(failed.status(), super::CtxStatus::Failed("failed")));
+        assert!(failed.get_result().is_none());
+
+        let polled = cq.poll(8).unwrap();
+        assert_eq!(polled.len(), 2);
+        assert_eq!(polled[0].status, WorkCompletionStatus::Success);
+        assert_eq!(polled[1].status, WorkCompletionStatus::RetryExceeded);
+    }
 }
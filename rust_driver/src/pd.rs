@@ -4,7 +4,7 @@ use std::hash::{Hash, Hasher};
 use rand::RngCore as _;
 
 use crate::types::Qpn;
-use crate::{Device, Error, Mr};
+use crate::{Device, Error, Mr, Mw, Srq};
 
 // TODO: PD will be shared by multi function call. Use reference counter?
 /// Protection Domain
@@ -17,6 +17,8 @@ pub struct Pd {
 pub(crate) struct PdCtx {
     pub(crate) mr: HashSet<Mr>,
     pub(crate) qp: HashSet<Qpn>,
+    pub(crate) srq: HashSet<Srq>,
+    pub(crate) mw: HashSet<Mw>,
 }
 
 impl Device {
@@ -27,6 +29,7 @@ impl Device {
     /// Will return `Err` if:
     /// * lock poisoned
     pub fn alloc_pd(&self) -> Result<Pd, Error> {
+        self.check_running()?;
         let mut pool = self.0.pd.lock();
 
         let pd = Pd {
@@ -38,6 +41,8 @@ impl Device {
             PdCtx {
                 mr: HashSet::new(),
                 qp: HashSet::new(),
+                srq: HashSet::new(),
+                mw: HashSet::new(),
             },
         );
 
@@ -55,7 +60,7 @@ impl Device {
     /// Will return `Err` if:
     /// * lock poisoned
     /// * invalid Pd
-    /// * mr or qp is in use
+    /// * mr, qp, srq or mw is in use
     pub fn dealloc_pd(&self, pd: Pd) -> Result<(), Error> {
         let mut pool = self.0.pd.lock();
         let pd_ctx = pool.get(&pd).ok_or(Error::Invalid(format!("PD :{pd:?}")))?;
@@ -68,6 +73,14 @@ impl Device {
             return Err(Error::PdInUse(format!("qp is not empty:{:?}", pd_ctx.qp)));
         }
 
+        if !pd_ctx.srq.is_empty() {
+            return Err(Error::PdInUse(format!("srq is not empty:{:?}", pd_ctx.srq)));
+        }
+
+        if !pd_ctx.mw.is_empty() {
+            return Err(Error::PdInUse(format!("mw is not empty:{:?}", pd_ctx.mw)));
+        }
+
         let _: Option<PdCtx> = pool.remove(&pd);
 
         Ok(())
@@ -1,16 +1,37 @@
+use std::collections::VecDeque;
+use std::future::poll_fn;
 use std::hash::{Hash, Hasher};
 use std::net::Ipv4Addr;
 use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 
 use atomic_enum::atomic_enum;
+use bitflags::bitflags;
 use eui48::MacAddress;
 use parking_lot::Mutex;
 
+use crate::cq::{BoundCq, WorkCompletionStatus};
 use crate::device::{ToCardCtrlRbDesc, ToCardCtrlRbDescCommon, ToCardCtrlRbDescQpManagement};
-use crate::types::{MemAccessTypeFlag, Msn, Pmtu, Psn, Qp, QpType, Qpn};
-use crate::{Device, Error, Pd};
+use crate::op_ctx::{CtrlOpCtx, CtxStatus, OpCtx};
+use crate::retry::INFINITE_RNR_RETRY;
+use crate::srq::SrqContext;
+use crate::types::{MemAccessTypeFlag, Msn, Pmtu, Psn, Qp, QpType, Qpn, RecvCompletion, MAX_SEND_WR};
+use crate::utils::block_on;
+use crate::{wait_ctrl_op, Device, Error, Pd};
 
 const QP_MAX_CNT: usize = 1024;
+/// The retry count is a 3 bits field in IB spec
+const QP_MAX_RETRY_CNT: u8 = 7;
+/// The local ACK timeout is a 5 bits field in IB spec
+const QP_MAX_TIMEOUT: u8 = 31;
+/// The local ACK timeout is `4.096us * 2^timeout`
+const QP_TIMEOUT_UNIT_NS: u64 = 4096;
+/// The RNR timer is a 5 bits field in IB spec
+const QP_MAX_MIN_RNR_TIMER: u8 = 31;
+/// The shortest RNR timer, which is 0.01ms
+const QP_DEFAULT_MIN_RNR_TIMER: u8 = 1;
 
 /// The status of current QP
 #[atomic_enum]
@@ -35,6 +56,277 @@ impl Default for QpStatus {
     }
 }
 
+/// The state of a QP, the values are the same as `enum ibv_qp_state` of rdma-core
+#[atomic_enum]
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Default)]
+pub enum QpState {
+    /// The QP is just created or reset, no request is processed
+    #[default]
+    Reset = 0,
+    /// Receive requests can be posted, but incoming messages are not processed
+    Init = 1,
+    /// Ready to receive
+    Rtr = 2,
+    /// Ready to send
+    Rts = 3,
+    /// The send queue is drained, no more send request is accepted
+    Sqd = 4,
+    /// Outstanding requests are flushed with error, and no request is accepted
+    Err = 6,
+}
+
+impl QpState {
+    /// Send requests are only accepted in `Rts`
+    pub(crate) fn can_post_send(self) -> bool {
+        matches!(self, QpState::Rts)
+    }
+
+    pub(crate) fn can_post_recv(self) -> bool {
+        !matches!(self, QpState::Reset | QpState::Err)
+    }
+
+    /// Incoming messages are only processed once the QP is ready to receive
+    pub(crate) fn can_receive(self) -> bool {
+        matches!(self, QpState::Rtr | QpState::Rts | QpState::Sqd)
+    }
+
+    /// The attributes which can be modified along with the transition from `self` to `next`,
+    /// `None` if the transition is illegal.
+    fn allowed_attr_mask(self, next: QpState) -> Option<QpAttrMask> {
+        let retry = QpAttrMask::IbvQpTimeout | QpAttrMask::IbvQpRetryCnt | QpAttrMask::IbvQpRnrRetry;
+        let mask = match (self, next) {
+            (_, QpState::Reset | QpState::Err) | (QpState::Reset | QpState::Init, QpState::Init) => QpAttrMask::empty(),
+            (QpState::Init, QpState::Rtr) => {
+                QpAttrMask::IbvQpAv
+                    | QpAttrMask::IbvQpDestQpn
+                    | QpAttrMask::IbvQpPathMtu
+                    | QpAttrMask::IbvQpRqPsn
+                    | QpAttrMask::IbvQpMinRnrTimer
+            }
+            (QpState::Rtr, QpState::Rts) => QpAttrMask::IbvQpSqPsn | QpAttrMask::IbvQpMinRnrTimer | retry,
+            (QpState::Rts | QpState::Sqd, QpState::Rts) | (QpState::Sqd, QpState::Sqd) => {
+                QpAttrMask::IbvQpMinRnrTimer | retry
+            }
+            (QpState::Rts, QpState::Sqd) => QpAttrMask::empty(),
+            _ => return None,
+        };
+        Some(mask | QpAttrMask::IbvQpState)
+    }
+}
+
+bitflags! {
+    /// The attributes to be modified by `modify_qp`, the values are the same as `enum ibv_qp_attr_mask` of rdma-core
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct QpAttrMask: u32 {
+        /// QP state
+        const IbvQpState = 1;          // (1 << 0)
+
+        /// Address vector, i.e. the IP and MAC of the peer
+        const IbvQpAv = 128;           // (1 << 7)
+
+        /// Path MTU
+        const IbvQpPathMtu = 256;      // (1 << 8)
+
+        /// Local ACK timeout
+        const IbvQpTimeout = 512;      // (1 << 9)
+
+        /// Retry count
+        const IbvQpRetryCnt = 1024;    // (1 << 10)
+
+        /// RNR retry count
+        const IbvQpRnrRetry = 2048;    // (1 << 11)
+
+        /// The PSN of the first packet expected to be received
+        const IbvQpRqPsn = 4096;       // (1 << 12)
+
+        /// Minimum RNR NAK timer
+        const IbvQpMinRnrTimer = 0x8000; // (1 << 15)
+
+        /// The PSN of the first packet to be sent
+        const IbvQpSqPsn = 0x1_0000;   // (1 << 16)
+
+        /// Peer QPN
+        const IbvQpDestQpn = 0x10_0000; // (1 << 20)
+    }
+}
+
+/// The attributes of `modify_qp`
+///
+/// Only the attributes in `attr_mask` are modified, which is set along with the attribute.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy)]
+pub struct QpAttr {
+    /// The state to move to
+    pub qp_state: QpState,
+    /// The PSN of the first packet to be sent
+    pub sq_psn: Psn,
+    /// The PSN of the first packet expected to be received
+    pub rq_psn: Psn,
+    /// Peer Queue Pair Number
+    pub peer_qpn: Qpn,
+    /// IP of the peer
+    pub dqp_ip: Ipv4Addr,
+    /// MAC of the peer
+    pub dqp_mac: MacAddress,
+    /// Packet MTU
+    pub pmtu: Pmtu,
+    /// Local ACK timeout of RC QP, which is `4.096us * 2^timeout`. 0 means the timeout of the device is used.
+    pub timeout: u8,
+    /// Max retry count of RC QP, 0 means the retry count of the device is used.
+    pub retry_cnt: u8,
+    /// Max retry count of RC QP on RNR NAK, 7 means retrying infinitely.
+    pub rnr_retry: u8,
+    /// The RNR timer code of RC QP replied in RNR NAK, which is the time the requester waits before retrying.
+    /// The values are the same as IB spec, from 1 for 0.01ms to 31 for 491.52ms, and 0 for 655.36ms.
+    pub min_rnr_timer: u8,
+    /// The modified attributes
+    pub attr_mask: QpAttrMask,
+}
+
+impl Default for QpAttr {
+    fn default() -> Self {
+        Self {
+            qp_state: QpState::default(),
+            sq_psn: Psn::default(),
+            rq_psn: Psn::default(),
+            peer_qpn: Qpn::default(),
+            dqp_ip: Ipv4Addr::UNSPECIFIED,
+            dqp_mac: MacAddress::default(),
+            pmtu: Pmtu::default(),
+            timeout: 0,
+            retry_cnt: 0,
+            rnr_retry: 0,
+            min_rnr_timer: 0,
+            attr_mask: QpAttrMask::empty(),
+        }
+    }
+}
+
+impl QpAttr {
+    /// Create the attributes moving the QP to `qp_state`
+    #[must_use]
+    pub fn new(qp_state: QpState) -> Self {
+        Self {
+            qp_state,
+            attr_mask: QpAttrMask::IbvQpState,
+            ..Default::default()
+        }
+    }
+
+    /// Set the PSN of the first packet to be sent
+    #[must_use]
+    pub fn with_sq_psn(mut self, sq_psn: Psn) -> Self {
+        self.sq_psn = sq_psn;
+        self.attr_mask |= QpAttrMask::IbvQpSqPsn;
+        self
+    }
+
+    /// Set the PSN of the first packet expected to be received
+    #[must_use]
+    pub fn with_rq_psn(mut self, rq_psn: Psn) -> Self {
+        self.rq_psn = rq_psn;
+        self.attr_mask |= QpAttrMask::IbvQpRqPsn;
+        self
+    }
+
+    /// Set the peer QPN
+    #[must_use]
+    pub fn with_peer_qpn(mut self, peer_qpn: Qpn) -> Self {
+        self.peer_qpn = peer_qpn;
+        self.attr_mask |= QpAttrMask::IbvQpDestQpn;
+        self
+    }
+
+    /// Set the address vector of the peer
+    #[must_use]
+    pub fn with_av(mut self, dqp_ip: Ipv4Addr, dqp_mac: MacAddress) -> Self {
+        self.dqp_ip = dqp_ip;
+        self.dqp_mac = dqp_mac;
+        self.attr_mask |= QpAttrMask::IbvQpAv;
+        self
+    }
+
+    /// Set the packet MTU
+    #[must_use]
+    pub fn with_pmtu(mut self, pmtu: Pmtu) -> Self {
+        self.pmtu = pmtu;
+        self.attr_mask |= QpAttrMask::IbvQpPathMtu;
+        self
+    }
+
+    /// Set the local ACK timeout
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: u8) -> Self {
+        self.timeout = timeout;
+        self.attr_mask |= QpAttrMask::IbvQpTimeout;
+        self
+    }
+
+    /// Set the max retry count
+    #[must_use]
+    pub fn with_retry_cnt(mut self, retry_cnt: u8) -> Self {
+        self.retry_cnt = retry_cnt;
+        self.attr_mask |= QpAttrMask::IbvQpRetryCnt;
+        self
+    }
+
+    /// Set the max retry count on RNR NAK
+    #[must_use]
+    pub fn with_rnr_retry(mut self, rnr_retry: u8) -> Self {
+        self.rnr_retry = rnr_retry;
+        self.attr_mask |= QpAttrMask::IbvQpRnrRetry;
+        self
+    }
+
+    /// Set the RNR timer replied in RNR NAK
+    #[must_use]
+    pub fn with_min_rnr_timer(mut self, min_rnr_timer: u8) -> Self {
+        self.min_rnr_timer = min_rnr_timer;
+        self.attr_mask |= QpAttrMask::IbvQpMinRnrTimer;
+        self
+    }
+
+    /// Validate the attributes against the type and current state of the QP
+    fn check(&self, qp_type: QpType, cur_state: QpState) -> Result<(), Error> {
+        let next_state = self.qp_state;
+        let mut allowed = cur_state
+            .allowed_attr_mask(next_state)
+            .ok_or_else(|| Error::Invalid(format!("QP state transition {cur_state:?} -> {next_state:?}")))?;
+        // UD QP is not connected, and only RC QP is acknowledged
+        if matches!(qp_type, QpType::Ud) {
+            allowed.remove(QpAttrMask::IbvQpAv | QpAttrMask::IbvQpDestQpn | QpAttrMask::IbvQpRqPsn);
+        }
+        if !matches!(qp_type, QpType::Rc) {
+            allowed.remove(
+                QpAttrMask::IbvQpTimeout
+                    | QpAttrMask::IbvQpRetryCnt
+                    | QpAttrMask::IbvQpRnrRetry
+                    | QpAttrMask::IbvQpMinRnrTimer,
+            );
+        }
+        if !allowed.contains(self.attr_mask) {
+            return Err(Error::Invalid(format!(
+                "QP attribute {:?} of {cur_state:?} -> {next_state:?}",
+                self.attr_mask.difference(allowed)
+            )));
+        }
+        if self.timeout > QP_MAX_TIMEOUT || self.retry_cnt > QP_MAX_RETRY_CNT {
+            return Err(Error::Invalid(format!(
+                "QP timeout :{}, retry count :{}",
+                self.timeout, self.retry_cnt
+            )));
+        }
+        if self.rnr_retry > QP_MAX_RETRY_CNT || self.min_rnr_timer > QP_MAX_MIN_RNR_TIMER {
+            return Err(Error::Invalid(format!(
+                "QP RNR retry count :{}, min RNR timer :{}",
+                self.rnr_retry, self.min_rnr_timer
+            )));
+        }
+        Ok(())
+    }
+}
+
 /// QP context
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
@@ -43,22 +335,50 @@ pub struct QpContext {
     pub(crate) qpn: Qpn,
     pub(crate) peer_qpn: Qpn,
     pub(crate) qp_type: QpType,
-    #[allow(unused)] // a field of QP, we may use it later
     pub(crate) rq_acc_flags: MemAccessTypeFlag,
     pub(crate) pmtu: Pmtu,
     pub(crate) local_mac: MacAddress,
     pub(crate) local_ip: Ipv4Addr,
     pub(crate) dqp_ip: Ipv4Addr,
     pub(crate) dqp_mac_addr: MacAddress,
-    pub(crate) sending_psn: Mutex<Psn>,
+    pub(crate) qkey: u32,
+    /// the PSN of the next packet to send, locked until the descriptor taking the PSNs is sent
+    pub(crate) sending_psn: Arc<Mutex<Psn>>,
+    /// the PSN of the first packet expected to be received
+    pub(crate) rq_psn: Psn,
+    pub(crate) state: AtomicQpState,
     pub(crate) status: AtomicQpStatus,
+    /// set while a `modify_qp` waits for the device, so the modifications of the QP are done one by one
+    pub(crate) modifying: Arc<AtomicBool>,
+    /// the max retry count of the requests, `None` to use the device default
+    pub(crate) max_retry: Option<u32>,
+    /// the local ACK timeout of the requests, `None` to use the device default
+    pub(crate) retry_timeout: Option<Duration>,
+    /// the max retry count of the requests on RNR NAK, `INFINITE_RNR_RETRY` to retry infinitely
+    pub(crate) rnr_retry: u8,
+    /// the RNR timer code replied to the peer when there is no receive request
+    pub(crate) min_rnr_timer: u8,
     pub(crate) _next_msn: AtomicU16,
+    /// the reads and atomics which may be still running, waited by the requests with `IbvSendFence`
+    pub(crate) outstanding_reads: Mutex<Vec<OpCtx<()>>>,
+    pub(crate) sq_sig_all: bool,
+    /// the reliable unsignaled requests posted after the last signaled one, which are completed with the next
+    /// signaled request
+    pub(crate) unsignaled_reqs: Mutex<VecDeque<OpCtx<()>>>,
+    pub(crate) send_credits: Arc<SendCredits>,
+    pub(crate) sq_blocking: bool,
+    /// posted receive requests, consumed in order by incoming SEND messages
+    pub(crate) recv_queue: Mutex<VecDeque<OpCtx<RecvCompletion>>>,
+    pub(crate) send_cq: Option<BoundCq>,
+    pub(crate) recv_cq: Option<BoundCq>,
+    /// the shared receive queue consumed by incoming messages instead of `recv_queue`
+    pub(crate) srq: Option<Arc<SrqContext>>,
 }
 
 impl QpContext {
     /// create a qp context
     ///
-    /// currently, `sending_psn` is set to 0 at begining
+    /// `sending_psn` starts at the initial SQ PSN of the QP
     #[must_use]
     pub fn new(qp: &Qp, local_ip: Ipv4Addr, local_mac: MacAddress) -> Self {
         Self {
@@ -72,15 +392,152 @@ impl QpContext {
             local_mac,
             dqp_ip: qp.dqp_ip,
             dqp_mac_addr: qp.dqp_mac,
-            sending_psn: Mutex::new(Psn::new(0)),
+            qkey: qp.qkey,
+            sending_psn: Arc::new(Mutex::new(qp.sq_psn)),
+            rq_psn: qp.rq_psn,
+            state: AtomicQpState::new(QpState::Reset),
             status: AtomicQpStatus::new(QpStatus::Normal),
+            modifying: Arc::new(AtomicBool::new(false)),
+            max_retry: None,
+            retry_timeout: None,
+            rnr_retry: INFINITE_RNR_RETRY,
+            min_rnr_timer: QP_DEFAULT_MIN_RNR_TIMER,
             _next_msn: AtomicU16::default(),
+            outstanding_reads: Mutex::new(Vec::new()),
+            sq_sig_all: qp.sq_sig_all,
+            unsignaled_reqs: Mutex::new(VecDeque::new()),
+            send_credits: SendCredits::new(qp.max_send_wr),
+            sq_blocking: qp.sq_blocking,
+            recv_queue: Mutex::new(VecDeque::new()),
+            send_cq: None,
+            recv_cq: None,
+            srq: None,
         }
     }
 
     pub(crate) fn next_msn(&self) -> Msn {
         Msn::new(self._next_msn.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub(crate) fn state(&self) -> QpState {
+        self.state.load(Ordering::Acquire)
+    }
+
+    fn management_desc(&self, op_id: u32, attrs: &QpModifiableAttrs, is_valid: bool) -> ToCardCtrlRbDesc {
+        ToCardCtrlRbDesc::QpManagement(ToCardCtrlRbDescQpManagement {
+            common: ToCardCtrlRbDescCommon { op_id },
+            is_valid,
+            is_error: attrs.is_error,
+            qpn: self.qpn,
+            pd_hdl: if is_valid { self.pd.handle } else { 0 },
+            qp_type: self.qp_type,
+            rq_acc_flags: if is_valid {
+                self.rq_acc_flags
+            } else {
+                MemAccessTypeFlag::IbvAccessNoFlags
+            },
+            pmtu: attrs.pmtu,
+            min_rnr_timer: attrs.min_rnr_timer,
+            peer_qpn: attrs.peer_qpn,
+            qkey: self.qkey,
+            rq_psn: attrs.rq_psn,
+            srqn: self.srq.as_ref().map(|srq| srq.srqn),
+        })
+    }
+}
+
+/// The attributes of a QP changed by `modify_qp`, staged until the device accepts them
+#[derive(Debug, Clone, Copy)]
+struct QpModifiableAttrs {
+    dqp_ip: Ipv4Addr,
+    dqp_mac_addr: MacAddress,
+    peer_qpn: Qpn,
+    pmtu: Pmtu,
+    /// `None` to keep sending from the current PSN
+    sq_psn: Option<Psn>,
+    rq_psn: Psn,
+    retry_timeout: Option<Duration>,
+    max_retry: Option<u32>,
+    rnr_retry: u8,
+    min_rnr_timer: u8,
+    /// the QP is in `Err` state, which the device knows to stop responding to the peer
+    is_error: bool,
+}
+
+impl From<&QpContext> for QpModifiableAttrs {
+    fn from(qpc: &QpContext) -> Self {
+        Self {
+            dqp_ip: qpc.dqp_ip,
+            dqp_mac_addr: qpc.dqp_mac_addr,
+            peer_qpn: qpc.peer_qpn,
+            pmtu: qpc.pmtu,
+            sq_psn: None,
+            rq_psn: qpc.rq_psn,
+            retry_timeout: qpc.retry_timeout,
+            max_retry: qpc.max_retry,
+            rnr_retry: qpc.rnr_retry,
+            min_rnr_timer: qpc.min_rnr_timer,
+            is_error: matches!(qpc.state(), QpState::Err),
+        }
+    }
+}
+
+impl QpModifiableAttrs {
+    /// the attributes of `qpc` once `attr` takes effect
+    fn staged(qpc: &QpContext, attr: &QpAttr) -> Self {
+        let mut attrs = Self::from(qpc);
+        let mask = attr.attr_mask;
+        if mask.contains(QpAttrMask::IbvQpAv) {
+            attrs.dqp_ip = attr.dqp_ip;
+            attrs.dqp_mac_addr = attr.dqp_mac;
+        }
+        if mask.contains(QpAttrMask::IbvQpDestQpn) {
+            attrs.peer_qpn = attr.peer_qpn;
+        }
+        if mask.contains(QpAttrMask::IbvQpPathMtu) {
+            attrs.pmtu = attr.pmtu;
+        }
+        if mask.contains(QpAttrMask::IbvQpSqPsn) {
+            attrs.sq_psn = Some(attr.sq_psn);
+        }
+        if mask.contains(QpAttrMask::IbvQpRqPsn) {
+            attrs.rq_psn = attr.rq_psn;
+        }
+        if mask.contains(QpAttrMask::IbvQpTimeout) {
+            attrs.retry_timeout =
+                (attr.timeout != 0).then(|| Duration::from_nanos(QP_TIMEOUT_UNIT_NS.wrapping_shl(attr.timeout.into())));
+        }
+        if mask.contains(QpAttrMask::IbvQpRetryCnt) {
+            attrs.max_retry = (attr.retry_cnt != 0).then_some(attr.retry_cnt.into());
+        }
+        if mask.contains(QpAttrMask::IbvQpRnrRetry) {
+            attrs.rnr_retry = attr.rnr_retry;
+        }
+        if mask.contains(QpAttrMask::IbvQpMinRnrTimer) {
+            attrs.min_rnr_timer = attr.min_rnr_timer;
+        }
+        if matches!(attr.qp_state, QpState::Reset) {
+            attrs.sq_psn = Some(Psn::default());
+            attrs.rq_psn = Psn::default();
+        }
+        attrs.is_error = matches!(attr.qp_state, QpState::Err);
+        attrs
+    }
+
+    fn apply(self, qpc: &mut QpContext) {
+        qpc.dqp_ip = self.dqp_ip;
+        qpc.dqp_mac_addr = self.dqp_mac_addr;
+        qpc.peer_qpn = self.peer_qpn;
+        qpc.pmtu = self.pmtu;
+        if let Some(sq_psn) = self.sq_psn {
+            *qpc.sending_psn.lock() = sq_psn;
+        }
+        qpc.rq_psn = self.rq_psn;
+        qpc.retry_timeout = self.retry_timeout;
+        qpc.max_retry = self.max_retry;
+        qpc.rnr_retry = self.rnr_retry;
+        qpc.min_rnr_timer = self.min_rnr_timer;
+    }
 }
 
 impl Default for QpContext {
@@ -96,16 +553,115 @@ impl Default for QpContext {
             local_ip: Ipv4Addr::LOCALHOST,
             dqp_ip: Ipv4Addr::LOCALHOST,
             dqp_mac_addr: Default::default(),
+            qkey: 0,
             sending_psn: Default::default(),
+            rq_psn: Psn::default(),
+            state: AtomicQpState::new(QpState::Rts),
             status: AtomicQpStatus::new(QpStatus::Normal),
+            modifying: Arc::new(AtomicBool::new(false)),
+            max_retry: None,
+            retry_timeout: None,
+            rnr_retry: INFINITE_RNR_RETRY,
+            min_rnr_timer: QP_DEFAULT_MIN_RNR_TIMER,
             _next_msn: Default::default(),
+            outstanding_reads: Mutex::new(Vec::new()),
+            sq_sig_all: true,
+            unsignaled_reqs: Mutex::new(VecDeque::new()),
+            send_credits: SendCredits::new(MAX_SEND_WR),
+            sq_blocking: false,
+            recv_queue: Mutex::new(VecDeque::new()),
+            send_cq: None,
+            recv_cq: None,
+            srq: None,
+        }
+    }
+}
+
+/// Clears the modifying flag of a QP once the modification is done, failed or dropped
+struct ModifyingGuard(Arc<AtomicBool>);
+
+impl Drop for ModifyingGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// The credits of the send queue of a QP, one for each outstanding request
+#[derive(Debug)]
+pub(crate) struct SendCredits(Mutex<SendCreditsInner>);
+
+#[derive(Debug)]
+struct SendCreditsInner {
+    available: u32,
+    /// the threads and tasks waiting for a credit
+    wakers: Vec<Waker>,
+}
+
+impl SendCredits {
+    pub(crate) fn new(max_send_wr: u32) -> Arc<Self> {
+        Arc::new(Self(Mutex::new(SendCreditsInner {
+            available: max_send_wr,
+            wakers: Vec::new(),
+        })))
+    }
+
+    /// Take a credit, which is given back once the returned `SendCredit` is dropped.
+    ///
+    /// Returns `Error::SendQueueFull` if there is none.
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Result<SendCredit, Error> {
+        let mut guard = self.0.lock();
+        if guard.available == 0 {
+            return Err(Error::SendQueueFull);
+        }
+        guard.available = guard.available.wrapping_sub(1);
+        Ok(SendCredit(Arc::clone(self)))
+    }
+
+    /// Take a credit, waiting for a request to be done if there is none
+    pub(crate) async fn acquire(self: &Arc<Self>) -> SendCredit {
+        loop {
+            if let Ok(credit) = self.try_acquire() {
+                return credit;
+            }
+            // another waiter may take the credit released meanwhile, then wait again
+            poll_fn(|cx| self.poll_available(cx)).await;
         }
     }
+
+    /// Ready once there is a credit, otherwise the waker of `cx` is woken when one is given back
+    pub(crate) fn poll_available(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut guard = self.0.lock();
+        if guard.available != 0 {
+            return Poll::Ready(());
+        }
+        if !guard.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            guard.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// A credit of the send queue, held by the operation context of a request until it's done
+#[derive(Debug)]
+pub(crate) struct SendCredit(Arc<SendCredits>);
+
+impl Drop for SendCredit {
+    fn drop(&mut self) {
+        let wakers = {
+            let mut guard = (self.0).0.lock();
+            guard.available = guard.available.wrapping_add(1);
+            std::mem::take(&mut guard.wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
 }
 
 impl Device {
     /// create a qp
     ///
+    /// The QP is ready to post in `Rts` state with the attributes of `qp`, unless `qp.ready` is unset,
+    /// then it is created in `Reset` state and brought up by `modify_qp`.
+    ///
     /// # Errors
     ///
     /// Will return `Err` if:
@@ -113,44 +669,170 @@ impl Device {
     /// * opeartion failed
     /// * Operating system not support
     /// * Setted context result failed
+    /// * invalid CQ
+    /// * invalid SRQ, or the SRQ belongs to another PD
+    /// * `max_send_wr` is larger than `MAX_SEND_WR`
     pub fn create_qp(&self, qp: &Qp) -> Result<(), Error> {
-        let mut qp_pool = self.0.qp_table.write();
-        let mut pd_pool = self.0.pd.lock();
-        let pd = &qp.pd;
-        let pd_ctx = pd_pool.get_mut(pd).ok_or(Error::Invalid(format!("PD :{pd:?}")))?;
+        block_on(self.create_qp_async(qp))
+    }
 
-        let qpc = QpContext::new(qp, self.0.local_network.ipaddr, self.0.local_network.macaddr);
-        let op_id = self.get_ctrl_op_id();
+    /// create a qp without blocking the thread, see `create_qp`
+    ///
+    /// # Errors
+    ///
+    /// The same as `create_qp`
+    pub async fn create_qp_async(&self, qp: &Qp) -> Result<(), Error> {
+        self.check_running()?;
+        if qp.max_send_wr > MAX_SEND_WR {
+            return Err(Error::Invalid(format!("max_send_wr :{}", qp.max_send_wr)));
+        }
+        // the CQs are unbound once the context is dropped, including on the failures below
+        let mut qpc = QpContext::new(qp, self.0.local_network.ipaddr, self.0.local_network.macaddr);
+        qpc.send_cq = qp.send_cq.map(|cq| self.bind_cq_ctx(cq)).transpose()?;
+        qpc.recv_cq = qp.recv_cq.map(|cq| self.bind_cq_ctx(cq)).transpose()?;
+        qpc.srq = qp.srq.map(|srq| self.get_srq_ctx(srq)).transpose()?;
+        if qpc.srq.as_ref().is_some_and(|srq| srq.pd != qp.pd) {
+            return Err(Error::Invalid(format!("SRQ of another PD :{:?}", qp.srq)));
+        }
+        // the QP is added in `Reset` state before the device accepts it, so that the QPN is not taken meanwhile
+        let ctx = {
+            let mut qp_pool = self.0.qp_table.write();
+            let mut pd_pool = self.0.pd.lock();
+            let pd = &qp.pd;
+            let pd_ctx = pd_pool.get_mut(pd).ok_or(Error::Invalid(format!("PD :{pd:?}")))?;
+            if qp_pool.contains_key(&qp.qpn) || pd_ctx.qp.contains(&qp.qpn) {
+                return Err(Error::Invalid(format!("qp :{0:?}", qp.qpn)));
+            }
 
-        let desc = ToCardCtrlRbDesc::QpManagement(ToCardCtrlRbDescQpManagement {
-            common: ToCardCtrlRbDescCommon { op_id },
-            is_valid: true,
-            qpn: qp.qpn,
-            pd_hdl: qp.pd.handle,
-            qp_type: qp.qp_type,
-            rq_acc_flags: qp.rq_acc_flags,
-            pmtu: qp.pmtu,
-            peer_qpn: qp.peer_qpn,
-        });
+            let ctx = self.send_qp_management(&qpc, &QpModifiableAttrs::from(&qpc), true)?;
+            let _: bool = pd_ctx.qp.insert(qp.qpn);
+            let _: Option<QpContext> = qp_pool.insert(qp.qpn, qpc);
+            ctx
+        };
 
-        let ctx = self.do_ctrl_op(op_id, desc)?;
+        if let Err(e) = wait_ctrl_op(ctx, "create qp").await {
+            self.remove_qp(qp.qpn);
+            return Err(e);
+        }
+        if qp.ready {
+            if let Some(qp_ctx) = self.0.qp_table.read().get(&qp.qpn) {
+                qp_ctx.state.store(QpState::Rts, Ordering::Release);
+            }
+        }
+        Ok(())
+    }
 
-        let res = ctx.wait_result()?.ok_or(Error::SetCtxResultFailed)?;
+    /// modify the state and attributes of a qp
+    ///
+    /// The QP moves through `Reset -> Init -> Rtr -> Rts` to get ready, and the attributes allowed
+    /// by each transition are the same as verbs. Moving to `Err` fails all outstanding requests
+    /// with `WrFlushError` and stops the device from responding to the peer, and moving to `Reset`
+    /// clears the QP as if it is newly created.
+    /// The QP keeps its state and attributes if the device fails to update it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * the QP does not exist
+    /// * the QP is being modified by another call
+    /// * the state transition is illegal
+    /// * an attribute in `attr.attr_mask` is not allowed by the transition or the QP type
+    /// * the device failed to update the QP
+    pub fn modify_qp(&self, qpn: Qpn, attr: &QpAttr) -> Result<(), Error> {
+        block_on(self.modify_qp_async(qpn, attr))
+    }
 
-        if !res {
-            return Err(Error::DeviceReturnFailed("create qp"));
-        }
+    /// modify the state and attributes of a qp without blocking the thread, see `modify_qp`
+    ///
+    /// # Errors
+    ///
+    /// The same as `modify_qp`
+    pub async fn modify_qp_async(&self, qpn: Qpn, attr: &QpAttr) -> Result<(), Error> {
+        self.check_running()?;
+        let next_state = attr.qp_state;
+        let (ctxs, attrs, modifying) = {
+            let mut qp_pool = self.0.qp_table.write();
+            let qpc = qp_pool.get_mut(&qpn).ok_or(Error::Invalid(format!("Qpn :{qpn:?}")))?;
+            // the table lock is released while waiting for the device, so another modification is rejected
+            // until this one is done, instead of being checked against the state about to change
+            if qpc.modifying.swap(true, Ordering::AcqRel) {
+                return Err(Error::Invalid(format!("Qpn :{qpn:?} is being modified")));
+            }
+            let modifying = ModifyingGuard(Arc::clone(&qpc.modifying));
+            attr.check(qpc.qp_type, qpc.state())?;
+
+            // the QP keeps the current attributes until the device accepts the new ones
+            let attrs = QpModifiableAttrs::staged(qpc, attr);
+            let mut ctxs = Vec::new();
+            #[allow(clippy::else_if_without_else)]
+            if matches!(next_state, QpState::Reset) {
+                // the device drops the posted receive requests and the receiving status along with the QP
+                ctxs.push(self.send_qp_management(qpc, &attrs, false)?);
+                ctxs.push(self.send_qp_management(qpc, &attrs, true)?);
+            } else if attr.attr_mask.intersects(
+                QpAttrMask::IbvQpDestQpn
+                    | QpAttrMask::IbvQpPathMtu
+                    | QpAttrMask::IbvQpRqPsn
+                    | QpAttrMask::IbvQpMinRnrTimer,
+            ) || attrs.is_error != matches!(qpc.state(), QpState::Err)
+            {
+                ctxs.push(self.send_qp_management(qpc, &attrs, true)?);
+            }
+
+            if ctxs.is_empty() {
+                attrs.apply(qpc);
+                self.enter_qp_state(qpc, next_state);
+                return Ok(());
+            }
+            (ctxs, attrs, modifying)
+        };
 
-        let pd_res = pd_ctx.qp.insert(qp.qpn);
-        if !pd_res {
-            return Err(Error::Invalid(format!("qp :{0:?}", qp.qpn)));
+        // the QP stays in the current state until the device is updated
+        let op_name = if matches!(next_state, QpState::Reset) {
+            "reset qp"
+        } else {
+            "modify qp"
+        };
+        for ctx in ctxs {
+            wait_ctrl_op(ctx, op_name).await?;
         }
+        let mut qp_pool = self.0.qp_table.write();
+        // the QP may be destroyed and created again meanwhile
+        let qpc = qp_pool
+            .get_mut(&qpn)
+            .filter(|qpc| Arc::ptr_eq(&qpc.modifying, &modifying.0))
+            .ok_or(Error::Invalid(format!("Qpn :{qpn:?}")))?;
+        attrs.apply(qpc);
+        self.enter_qp_state(qpc, next_state);
+        Ok(())
+    }
 
-        let qp_res = qp_pool.insert(qp.qpn, qpc);
-        if qp_res.is_some() {
-            return Err(Error::Invalid(format!("qp :{0:?}", qp.qpn)));
+    fn enter_qp_state(&self, qpc: &QpContext, next_state: QpState) {
+        if matches!(next_state, QpState::Err | QpState::Reset) {
+            self.flush_qp(qpc, "flushed by QP error");
         }
+        if matches!(next_state, QpState::Reset) {
+            qpc._next_msn.store(0, Ordering::Relaxed);
+            qpc.status.store(QpStatus::Normal, Ordering::Release);
+        }
+        qpc.state.store(next_state, Ordering::Release);
+    }
 
+    /// Wait until the send queue of a QP is not full, for an async task posting to a QP without `Qp::sq_blocking`
+    ///
+    /// A post that returned `Error::SendQueueFull` is retried after it, the free slot may be taken by another post
+    /// meanwhile.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the QP does not exist
+    pub async fn send_queue_ready_async(&self, qpn: Qpn) -> Result<(), Error> {
+        let send_credits = {
+            let qp_pool = self.0.qp_table.read();
+            let qp_ctx = qp_pool.get(&qpn).ok_or(Error::Invalid(format!("Qpn :{qpn:?}")))?;
+            Arc::clone(&qp_ctx.send_credits)
+        };
+        poll_fn(|cx| send_credits.poll_available(cx)).await;
         Ok(())
     }
 
@@ -163,42 +845,71 @@ impl Device {
     /// * opeartion failed
     /// * Setted context result failed
     pub fn destroy_qp(&self, qp: Qpn) -> Result<(), Error> {
-        let mut qp_pool = self.0.qp_table.write();
-        let mut pd_pool = self.0.pd.lock();
+        block_on(self.destroy_qp_async(qp))
+    }
 
-        let op_id = self.get_ctrl_op_id();
+    /// destory a qp without blocking the thread, see `destroy_qp`
+    ///
+    /// # Errors
+    ///
+    /// The same as `destroy_qp`
+    pub async fn destroy_qp_async(&self, qp: Qpn) -> Result<(), Error> {
+        let ctx = {
+            let qp_pool = self.0.qp_table.read();
+            let pd_pool = self.0.pd.lock();
 
-        let (pd_ctx, desc) = if let Some(qp_ctx) = qp_pool.get(&qp) {
-            let pd_ctx = pd_pool
-                .get_mut(&qp_ctx.pd)
-                .ok_or(Error::Invalid(format!("PD :{:?}", &qp_ctx.pd)))?;
-            let desc = ToCardCtrlRbDesc::QpManagement(ToCardCtrlRbDescQpManagement {
-                common: ToCardCtrlRbDescCommon { op_id },
-                is_valid: false,
-                qpn: qp_ctx.qpn,
-                pd_hdl: 0,
-                qp_type: qp_ctx.qp_type,
-                rq_acc_flags: MemAccessTypeFlag::IbvAccessNoFlags,
-                pmtu: qp_ctx.pmtu,
-                peer_qpn: qp_ctx.peer_qpn,
-            });
-            (pd_ctx, desc)
-        } else {
-            return Err(Error::Invalid(format!("Qpn :{qp:?}")));
-        };
+            let Some(qp_ctx) = qp_pool.get(&qp) else {
+                return Err(Error::Invalid(format!("Qpn :{qp:?}")));
+            };
+            if !pd_pool.contains_key(&qp_ctx.pd) {
+                return Err(Error::Invalid(format!("PD :{:?}", &qp_ctx.pd)));
+            }
 
-        let ctx = self.do_ctrl_op(op_id, desc)?;
+            self.send_qp_management(qp_ctx, &QpModifiableAttrs::from(qp_ctx), false)?
+        };
 
-        let res = ctx.wait_result()?.ok_or(Error::SetCtxResultFailed)?;
+        wait_ctrl_op(ctx, "destroy qp").await?;
+        self.remove_qp(qp);
+        Ok(())
+    }
 
-        if !res {
-            return Err(Error::DeviceReturnFailed("destroy qp"));
+    /// remove the QP from the QP table and its PD
+    fn remove_qp(&self, qpn: Qpn) {
+        let mut qp_pool = self.0.qp_table.write();
+        let mut pd_pool = self.0.pd.lock();
+        if let Some(qpc) = qp_pool.remove(&qpn) {
+            if let Some(pd_ctx) = pd_pool.get_mut(&qpc.pd) {
+                let _: bool = pd_ctx.qp.remove(&qpn);
+            }
         }
+    }
 
-        let _: bool = pd_ctx.qp.remove(&qp);
-        let _: Option<QpContext> = qp_pool.remove(&qp);
+    fn send_qp_management(
+        &self,
+        qpc: &QpContext,
+        attrs: &QpModifiableAttrs,
+        is_valid: bool,
+    ) -> Result<CtrlOpCtx, Error> {
+        let op_id = self.get_ctrl_op_id();
+        let desc = qpc.management_desc(op_id, attrs, is_valid);
+        self.do_ctrl_op(op_id, desc)
+    }
 
-        Ok(())
+    /// fail the outstanding send and receive requests of the QP
+    pub(crate) fn flush_qp(&self, qpc: &QpContext, cause: &'static str) {
+        self.0.user_op_ctx_map.write().retain(|&(qpn, msn), ctx| {
+            if qpn != qpc.qpn {
+                return true;
+            }
+            let _ignore = self.0.retry_map.cancel((qpn, msn));
+            if matches!(ctx.status(), CtxStatus::Running) {
+                ctx.set_error(cause, WorkCompletionStatus::WrFlushError);
+            }
+            false
+        });
+        for ctx in qpc.recv_queue.lock().drain(..) {
+            ctx.set_error(cause, WorkCompletionStatus::WrFlushError);
+        }
     }
 }
 
@@ -276,3 +987,119 @@ impl Default for QpManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use eui48::MacAddress;
+
+    use super::{QpAttr, QpState, SendCredits};
+    use crate::op_ctx::OpCtx;
+    use crate::types::{MemAccessTypeFlag, Pmtu, Psn, Qp, QpType, Qpn, Sge};
+    use crate::utils::block_on;
+    use crate::{Device, Error, Mr, Pd};
+
+    #[test]
+    fn test_qp_state_transition() {
+        let bring_up = [
+            (QpState::Reset, QpAttr::new(QpState::Init)),
+            (
+                QpState::Init,
+                QpAttr::new(QpState::Rtr)
+                    .with_peer_qpn(Qpn::new(3))
+                    .with_av(Ipv4Addr::new(10, 0, 0, 2), MacAddress::default())
+                    .with_pmtu(Pmtu::Mtu1024)
+                    .with_rq_psn(Psn::new(0xff_fff0)),
+            ),
+            (
+                QpState::Rtr,
+                QpAttr::new(QpState::Rts)
+                    .with_sq_psn(Psn::new(0x10))
+                    .with_timeout(14)
+                    .with_retry_cnt(7),
+            ),
+            (QpState::Rts, QpAttr::new(QpState::Sqd)),
+            (QpState::Sqd, QpAttr::new(QpState::Rts)),
+            (QpState::Rts, QpAttr::new(QpState::Err)),
+            (QpState::Err, QpAttr::new(QpState::Reset)),
+        ];
+        for (cur, attr) in bring_up {
+            assert!(attr.check(QpType::Rc, cur).is_ok(), "{cur:?} -> {:?}", attr.qp_state);
+        }
+
+        // illegal transitions
+        assert!(QpAttr::new(QpState::Rts).check(QpType::Rc, QpState::Reset).is_err());
+        assert!(QpAttr::new(QpState::Rtr).check(QpType::Rc, QpState::Rts).is_err());
+        assert!(QpAttr::new(QpState::Rts).check(QpType::Rc, QpState::Err).is_err());
+        assert!(QpAttr::new(QpState::Sqd).check(QpType::Rc, QpState::Rtr).is_err());
+
+        // attributes not allowed by the transition
+        let attr = QpAttr::new(QpState::Rts).with_peer_qpn(Qpn::new(3));
+        assert!(attr.check(QpType::Rc, QpState::Rtr).is_err());
+        let attr = QpAttr::new(QpState::Rts).with_sq_psn(Psn::new(0x10));
+        assert!(attr.check(QpType::Rc, QpState::Rts).is_err());
+        let attr = QpAttr::new(QpState::Rts).with_rq_psn(Psn::new(0x10));
+        assert!(attr.check(QpType::Rc, QpState::Rtr).is_err());
+
+        // attributes not allowed by the QP type
+        let attr = QpAttr::new(QpState::Rtr).with_peer_qpn(Qpn::new(3));
+        assert!(attr.check(QpType::Ud, QpState::Init).is_err());
+        let attr = QpAttr::new(QpState::Rtr).with_rq_psn(Psn::new(0x10));
+        assert!(attr.check(QpType::Ud, QpState::Init).is_err());
+        let attr = QpAttr::new(QpState::Rtr).with_av(Ipv4Addr::LOCALHOST, MacAddress::default());
+        assert!(attr.check(QpType::Ud, QpState::Init).is_err());
+        let attr = QpAttr::new(QpState::Rts).with_retry_cnt(3);
+        assert!(attr.check(QpType::Uc, QpState::Rtr).is_err());
+
+        // out of range
+        let attr = QpAttr::new(QpState::Rts).with_retry_cnt(8);
+        assert!(attr.check(QpType::Rc, QpState::Rtr).is_err());
+        let attr = QpAttr::new(QpState::Rts).with_timeout(32);
+        assert!(attr.check(QpType::Rc, QpState::Rtr).is_err());
+    }
+
+    #[test]
+    fn test_send_credits() {
+        let credits = SendCredits::new(2);
+        let reqs = (0..2)
+            .map(|_| {
+                let ctx = OpCtx::<()>::new_running();
+                ctx.set_credit(credits.try_acquire().unwrap());
+                ctx
+            })
+            .collect::<Vec<_>>();
+        assert!(matches!(credits.try_acquire(), Err(Error::SendQueueFull)));
+
+        // the credit is given back once the request is done, whether it succeeds or not
+        reqs[0].set_result(()).unwrap();
+        let credit = credits.try_acquire().unwrap();
+        assert!(matches!(credits.try_acquire(), Err(Error::SendQueueFull)));
+        drop(credit);
+
+        // a blocking post waits for a request to be done
+        let _credit = credits.try_acquire().unwrap();
+        let req = reqs[1].clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            req.set_error("failed", crate::cq::WorkCompletionStatus::RetryExceeded);
+        });
+        let _credit = block_on(credits.acquire());
+        handle.join().unwrap();
+        assert!(matches!(credits.try_acquire(), Err(Error::SendQueueFull)));
+    }
+
+    #[test]
+    fn test_async_apis_are_send() {
+        fn assert_send<T: Send>(_: &T) {}
+        // only checked by the compiler, so that the futures can be spawned to a multi-threaded runtime
+        let _check = |dev: &Device, pd: Pd, qp: &Qp, mr: Mr, sge: Sge, attr: &QpAttr| {
+            assert_send(&dev.reg_mr_async(pd, 0, 0, 0, MemAccessTypeFlag::empty()));
+            assert_send(&dev.dereg_mr_async(mr));
+            assert_send(&dev.create_qp_async(qp));
+            assert_send(&dev.modify_qp_async(qp.qpn, attr));
+            assert_send(&dev.destroy_qp_async(qp.qpn));
+            assert_send(&dev.post_recv_async(0, qp.qpn, sge));
+        };
+    }
+}
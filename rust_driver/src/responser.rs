@@ -13,6 +13,15 @@ use crate::types::{Imm, Key, Msn, Psn, QpType, Qpn, WorkReqSendFlag};
 use crate::utils::calculate_packet_cnt;
 use crate::{Error, Sge, ThreadSafeHashmap};
 
+/// The AETH value of a NAK, which asks the requester to retry from the expected PSN
+const NAK_PSN_SEQUENCE_ERROR: u8 = 0b0_0000;
+/// The AETH value of a NAK, which means the request is invalid, such as a SEND longer than the receive request
+pub(crate) const NAK_INVALID_REQUEST: u8 = 0b0_0001;
+/// The AETH value of a NAK, which means the responder denied the access to its memory region
+pub(crate) const NAK_REMOTE_ACCESS_ERROR: u8 = 0b0_0010;
+/// The AETH value of a NAK, which means the responder failed to complete a valid request
+pub(crate) const NAK_REMOTE_OPERATIONAL_ERROR: u8 = 0b0_0011;
+
 /// make an ack packet in the buffer, and return a work descriptor
 ///
 /// The slot can be allocated by `PacketBuf::recycle_buf`
@@ -37,7 +46,28 @@ pub(crate) fn make_nack(
     start_psn: Psn,
     end_psn: Psn,
 ) -> Result<Box<ToCardWorkRbDesc>, Error> {
-    make_ack_or_nack(ack_buf, qp_table, qpn, msn, start_psn, Some(end_psn))
+    make_ack_or_nack(
+        ack_buf,
+        qp_table,
+        qpn,
+        msn,
+        start_psn,
+        Some((NAK_PSN_SEQUENCE_ERROR, end_psn)),
+    )
+}
+
+/// make a nack packet which rejects the request at `psn` with `code`, and return a work descriptor
+///
+/// The request is not retried, the requester completes it with the error of `code`.
+pub(crate) fn make_nack_with_code(
+    ack_buf: Slot<RDMA_ACK_BUFFER_SLOT_SIZE>,
+    qp_table: &ThreadSafeHashmap<Qpn, QpContext>,
+    qpn: Qpn,
+    msn: Msn,
+    psn: Psn,
+    code: u8,
+) -> Result<Box<ToCardWorkRbDesc>, Error> {
+    make_ack_or_nack(ack_buf, qp_table, qpn, msn, psn, Some((code, psn)))
 }
 
 fn make_ack_or_nack(
@@ -46,7 +76,7 @@ fn make_ack_or_nack(
     qpn: Qpn,
     msn: Msn,
     psn: Psn,
-    expected_psn: Option<Psn>,
+    nak: Option<(u8, Psn)>,
 ) -> Result<Box<ToCardWorkRbDesc>, Error> {
     #[allow(clippy::unwrap_used)]
     let (src_mac, src_ip, dst_mac, dst_ip, common) = {
@@ -69,6 +99,9 @@ fn make_ack_or_nack(
                 qp_type: QpType::RawPacket,
                 psn: Psn::default(),
                 msn,
+                sqpn: qpn,
+                qkey: 0,
+                fence: Vec::new(),
             };
             (src_mac, src_ip, dst_mac, dst_ip, common)
         } else {
@@ -82,7 +115,7 @@ fn make_ack_or_nack(
         qpn,
         msn,
         psn,
-        expected_psn,
+        nak,
     );
     #[allow(clippy::cast_possible_truncation)]
     let sge = ack_buf.into_sge(ACKPACKET_SIZE as u32);
@@ -118,6 +151,9 @@ pub(crate) fn make_read_resp(
             qp_type: qp.qp_type,
             psn: Psn::default(),
             msn,
+            sqpn: dqpn,
+            qkey: 0,
+            fence: Vec::new(),
         };
         let packet_cnt = calculate_packet_cnt(qp.pmtu, raddr, len);
         let first_pkt_psn = {
@@ -164,7 +200,7 @@ fn write_packet(
     dpqn: Qpn,
     msg_seq_num: Msn,
     psn: Psn,
-    expected_psn: Option<Psn>,
+    nak: Option<(u8, Psn)>,
 ) {
     let buf = &mut buf[..ACKPACKET_SIZE];
     let (src_mac, src_ip) = src;
@@ -219,25 +255,22 @@ fn write_packet(
     bth_header.set_dqpn(dpqn.into_be());
     bth_header.set_psn(psn.into_be());
 
-    let is_nak = expected_psn.is_some();
     let aeth_hdr_buf = &mut mac_header.0[MAC_HEADER_SIZE + IPV4_HEADER_SIZE + UDP_HEADER_SIZE + BTH_HEADER_SIZE..];
     let mut aeth_header = Aeth(aeth_hdr_buf);
-    if is_nak {
+    if let Some((code, _)) = nak {
         aeth_header.set_aeth_code(ToHostWorkRbDescAethCode::Nak as u32);
+        aeth_header.set_aeth_value(code.into());
     } else {
         aeth_header.set_aeth_code(ToHostWorkRbDescAethCode::Ack as u32);
+        aeth_header.set_aeth_value(0);
     }
-    aeth_header.set_aeth_value(0);
     aeth_header.set_msn(msg_seq_num.into_be().into());
 
     let mut nreth_header = NReth(
         &mut mac_header.0[MAC_HEADER_SIZE + IPV4_HEADER_SIZE + UDP_HEADER_SIZE + BTH_HEADER_SIZE + AETH_HEADER_SIZE..],
     );
-    if is_nak {
-        // we have checked the option before.
-        #[allow(clippy::unwrap_used)]
-        let expected_psn = expected_psn.unwrap().into_be();
-        nreth_header.set_last_retry_psn(expected_psn);
+    if let Some((_, retry_psn)) = nak {
+        nreth_header.set_last_retry_psn(retry_psn.into_be());
     } else {
         nreth_header.set_last_retry_psn(0);
     }
@@ -9,18 +9,41 @@ use std::u128;
 
 use parking_lot::{Mutex, RwLock};
 
+use crate::cq::WorkCompletionStatus;
+use crate::device::scheduler::{cut_from_sgl, SGList};
 use crate::device::ToCardWorkRbDesc;
 use crate::op_ctx::OpCtx;
 use crate::types::{Msn, Pmtu, Qpn};
 use crate::utils::{calculate_packet_cnt, get_first_packet_max_length};
 use crate::{Error, ThreadSafeHashmap, WorkDescriptorSender};
 
+/// The RNR retry count of 7 means retrying infinitely
+pub(crate) const INFINITE_RNR_RETRY: u8 = 7;
+
+/// The RNR timer of IB spec in us, indexed by the 5 bits timer code
+#[allow(clippy::decimal_literal_representation)]
+const RNR_TIMER_US: [u64; 32] = [
+    655_360, 10, 20, 30, 40, 60, 80, 120, 160, 240, 320, 480, 640, 960, 1280, 1920, 2560, 3840, 5120, 7680, 10240,
+    15360, 20480, 30720, 40960, 61440, 81920, 122_880, 163_840, 245_760, 327_680, 491_520,
+];
+
+/// The time to wait before retrying a request rejected by RNR NAK carrying `timer`
+pub(crate) fn rnr_timer_duration(timer: u8) -> Duration {
+    // the timer code is 5 bits, the higher bits are ignored
+    let timer = usize::from(timer & 0x1f);
+    Duration::from_micros(RNR_TIMER_US.get(timer).copied().unwrap_or_default())
+}
+
 #[derive(Debug)]
 pub(crate) struct RetryContext {
     descriptor: Box<ToCardWorkRbDesc>,
     retry_counter: u32,
+    retry_timeout: u128,
     next_timeout: u128,
     is_initiative: bool,
+    rnr_retry_counter: u8,
+    /// the request is rejected by RNR NAK, and is resent once `next_timeout` is reached
+    is_rnr_waiting: bool,
 }
 
 #[allow(clippy::type_complexity)]
@@ -54,15 +77,29 @@ impl RetryMap {
         }
     }
 
-    pub(crate) fn add(&self, key: (Qpn, Msn), descriptor: Box<ToCardWorkRbDesc>, is_initiative: bool) -> bool {
+    /// `max_retry` and `retry_timeout` override the default of the map, which are configured per QP along with
+    /// `rnr_retry`
+    pub(crate) fn add(
+        &self,
+        key: (Qpn, Msn),
+        descriptor: Box<ToCardWorkRbDesc>,
+        is_initiative: bool,
+        max_retry: Option<u32>,
+        retry_timeout: Option<Duration>,
+        rnr_retry: u8,
+    ) -> bool {
         let mut guard = self.map.write();
-        let next_timeout = get_current_time().wrapping_add(self.retry_timeout);
+        let retry_timeout = retry_timeout.map_or(self.retry_timeout, |timeout| timeout.as_millis());
+        let next_timeout = get_current_time().wrapping_add(retry_timeout);
         if let Entry::Vacant(entry) = guard.entry(key) {
             let _inner = entry.insert(Arc::new(Mutex::new(RetryContext {
                 descriptor,
-                retry_counter: self.max_retry,
+                retry_counter: max_retry.unwrap_or(self.max_retry),
+                retry_timeout,
                 next_timeout,
                 is_initiative,
+                rnr_retry_counter: rnr_retry,
+                is_rnr_waiting: false,
             })));
             false
         } else {
@@ -75,6 +112,28 @@ impl RetryMap {
         map.remove(&key).is_none()
     }
 
+    /// Delay the request rejected by RNR NAK for `delay`, then it is resent by the monitor without
+    /// consuming the retry count.
+    ///
+    /// Return false if the RNR retry count is exhausted
+    pub(crate) fn wait_rnr(&self, key: (Qpn, Msn), delay: Duration) -> bool {
+        let guard = self.map.read();
+        let Some(ctx) = guard.get(&key) else {
+            // the request is already finished
+            return true;
+        };
+        let mut inner = ctx.lock();
+        if inner.rnr_retry_counter == 0 {
+            return false;
+        }
+        if inner.rnr_retry_counter != INFINITE_RNR_RETRY {
+            inner.rnr_retry_counter = inner.rnr_retry_counter.wrapping_sub(1);
+        }
+        inner.next_timeout = get_current_time().wrapping_add(delay.as_millis());
+        inner.is_rnr_waiting = true;
+        true
+    }
+
     /// fetch the descriptor from the map.
     ///
     /// If a range is given, the descriptor will be cut into the range
@@ -91,26 +150,59 @@ impl RetryMap {
             return Ok(None);
         };
         if let Some((from, to)) = range {
-            if let ToCardWorkRbDesc::Write(ref mut write_desc) = *desc {
-                let pmtu = write_desc.common.pmtu;
-                let max_pkt = calculate_packet_cnt(pmtu, write_desc.common.raddr, write_desc.common.total_len);
-                if from > to || from >= max_pkt || to >= max_pkt {
-                    return Err(Error::Invalid("Invalid psn range".to_owned()));
+            let (common, is_first, is_last, sge0, sge1, sge2, sge3) = match *desc {
+                ToCardWorkRbDesc::Write(ref mut desc) => (
+                    &mut desc.common,
+                    &mut desc.is_first,
+                    &mut desc.is_last,
+                    &mut desc.sge0,
+                    &mut desc.sge1,
+                    &mut desc.sge2,
+                    &mut desc.sge3,
+                ),
+                ToCardWorkRbDesc::WriteWithImm(ref mut desc) => (
+                    &mut desc.common,
+                    &mut desc.is_first,
+                    &mut desc.is_last,
+                    &mut desc.sge0,
+                    &mut desc.sge1,
+                    &mut desc.sge2,
+                    &mut desc.sge3,
+                ),
+                // an inline message takes at most two packets, so it is retried as a whole,
+                // and the duplicate packets are dropped by the receiver
+                ToCardWorkRbDesc::Inline(_) => return Ok(Some(desc)),
+                ToCardWorkRbDesc::Read(_)
+                | ToCardWorkRbDesc::ReadResp(_)
+                | ToCardWorkRbDesc::Send(_)
+                | ToCardWorkRbDesc::SendWithImm(_)
+                | ToCardWorkRbDesc::SendWithInv(_)
+                | ToCardWorkRbDesc::CompareSwap(_)
+                | ToCardWorkRbDesc::FetchAdd(_) => {
+                    return Err(Error::Invalid("Invalid descriptor type".to_owned()));
                 }
-                let start_offset = psn_addr_offset(write_desc.sge0.addr, pmtu, from);
-                let end_offset = psn_addr_offset(write_desc.sge0.addr, pmtu, to);
-                let new_length = end_offset.wrapping_sub(start_offset);
-
-                write_desc.is_first = from == 0;
-                write_desc.is_last = max_pkt.wrapping_sub(1) == to;
-                write_desc.sge0.addr = write_desc.sge0.addr.wrapping_add(start_offset);
-                write_desc.sge0.len = new_length as u32;
-                write_desc.common.psn = write_desc.common.psn.wrapping_add(from);
-                write_desc.common.total_len = new_length as u32;
-                write_desc.common.raddr = write_desc.common.raddr.wrapping_add(start_offset);
-            } else {
-                return Err(Error::Invalid("Invalid descriptor type".to_owned()));
+            };
+            let pmtu = common.pmtu;
+            let max_pkt = calculate_packet_cnt(pmtu, common.raddr, common.total_len);
+            if from > to || from >= max_pkt || to >= max_pkt {
+                return Err(Error::Invalid("Invalid psn range".to_owned()));
+            }
+            let start_offset = psn_addr_offset(common.raddr, pmtu, from);
+            let end_offset = psn_addr_offset(common.raddr, pmtu, to);
+            let new_length = end_offset.wrapping_sub(start_offset) as u32;
+
+            // skip the acknowledged part of the sgl, and take the part to be retried
+            let mut sg_list = SGList::new_from_sges(*sge0, *sge1, *sge2, *sge3);
+            if start_offset > 0 {
+                let _: SGList = cut_from_sgl(start_offset as u32, &mut sg_list);
             }
+            (*sge0, *sge1, *sge2, *sge3) = cut_from_sgl(new_length, &mut sg_list).into_sges();
+            // only the retried range containing the last packet carries the immediate data
+            *is_first = from == 0;
+            *is_last = max_pkt.wrapping_sub(1) == to;
+            common.psn = common.psn.wrapping_add(from);
+            common.total_len = new_length;
+            common.raddr = common.raddr.wrapping_add(start_offset);
         };
         Ok(Some(desc))
     }
@@ -209,6 +301,20 @@ impl RetryMonitor {
     }
 }
 
+impl Drop for RetryMonitor {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // panicking in drop may abort the process, and the thread is gone anyway
+            if let Err(e) = thread.join() {
+                log::error!("RetryMonitor thread join failed: {e:?}");
+                return;
+            }
+            log::info!("RetryMonitor thread is normally stopped");
+        }
+    }
+}
+
 impl RetryMonitorContext {
     #[allow(clippy::arithmetic_side_effects)]
     fn check_timeout(&mut self) {
@@ -216,10 +322,21 @@ impl RetryMonitorContext {
         let mut has_removed = false;
         for (qpn, msn, ctx) in self.map.iter_key_and_value() {
             let mut guard = ctx.lock();
+            if guard.is_rnr_waiting && guard.next_timeout <= now {
+                // the responder was not ready, which is not counted as a retry
+                guard.is_rnr_waiting = false;
+                guard.next_timeout = now + guard.retry_timeout;
+                if self.device.send_work_desc(guard.descriptor.clone()).is_err() {
+                    log::error!("Retry send work descriptor failed")
+                } else {
+                    log::warn!("Retry desc after RNR NAK:{:?}", guard.descriptor);
+                }
+                continue;
+            }
             if guard.is_initiative && guard.next_timeout <= now {
                 if guard.retry_counter > 0 {
                     guard.retry_counter -= 1;
-                    guard.next_timeout = now + self.config.retry_timeout.as_millis();
+                    guard.next_timeout = now + guard.retry_timeout;
                     if self.device.send_work_desc(guard.descriptor.clone()).is_err() {
                         log::error!("Retry send work descriptor failed")
                     } else {
@@ -230,7 +347,7 @@ impl RetryMonitorContext {
                     has_removed = true;
                     let user_op_ctx_guard = self.user_op_ctx_map.write();
                     if let Some(user_op_ctx) = user_op_ctx_guard.get(&(qpn, msn)) {
-                        user_op_ctx.set_error("exceed max retry count");
+                        user_op_ctx.set_error("exceed max retry count", WorkCompletionStatus::RetryExceeded);
                     } else {
                         log::warn!("Remove retry record failed: Can not find {:?}", (qpn, msn));
                     }
@@ -262,7 +379,7 @@ mod test {
     use parking_lot::lock_api::RwLock;
     use parking_lot::{Mutex, RawRwLock};
 
-    use super::{psn_addr_offset, RetryConfig, RetryMonitorContext};
+    use super::{psn_addr_offset, rnr_timer_duration, RetryConfig, RetryMonitorContext, INFINITE_RNR_RETRY};
     use crate::device::{DescSge, ToCardWorkRbDesc, ToCardWorkRbDescCommon, ToCardWorkRbDescWrite};
     use crate::op_ctx::{self, CtxStatus};
     use crate::retry::RetryMap;
@@ -313,7 +430,7 @@ mod test {
             sge3: None,
         }));
         // for _i in 0..4 {
-        retry_map.add((Qpn::default(), Msn::default()), desc.clone(), true);
+        retry_map.add((Qpn::default(), Msn::default()), desc.clone(), true, None, None, 0);
 
         // should send first retry
         std::thread::sleep(Duration::from_millis(1020));
@@ -334,5 +451,57 @@ mod test {
         device.0.lock().clear();
         std::thread::sleep(Duration::from_millis(1000));
     }
+
+    #[test]
+    fn test_rnr_retry() {
+        assert_eq!(rnr_timer_duration(0), Duration::from_micros(655_360));
+        assert_eq!(rnr_timer_duration(1), Duration::from_micros(10));
+        assert_eq!(rnr_timer_duration(14), Duration::from_micros(1280));
+        assert_eq!(rnr_timer_duration(31), Duration::from_micros(491_520));
+
+        let map = Arc::new(RwLock::new(HashMap::new()));
+        let device = Arc::new(MockDevice(Vec::new().into()));
+        // no retry on timeout, so every resent request is triggered by RNR NAK
+        let retry_map = RetryMap::new(0, Duration::from_secs(1000));
+        let mut context = RetryMonitorContext {
+            map: retry_map.clone(),
+            device: Arc::<MockDevice>::clone(&device),
+            user_op_ctx_map: Arc::<RwLock<RawRwLock, HashMap<(ThreeBytesStruct, Msn), op_ctx::OpCtx<()>>>>::clone(&map),
+            config: RetryConfig::new(true, 0, Duration::from_secs(1000), Duration::from_millis(10)),
+        };
+        let desc = Box::new(ToCardWorkRbDesc::Write(ToCardWorkRbDescWrite {
+            common: ToCardWorkRbDescCommon { ..Default::default() },
+            is_last: true,
+            is_first: true,
+            sge0: DescSge {
+                addr: 0x1000,
+                len: 512,
+                key: Key::new(0x1234_u32),
+            },
+            sge1: None,
+            sge2: None,
+            sge3: None,
+        }));
+        let key = (Qpn::default(), Msn::default());
+        let ctx = op_ctx::OpCtx::new_running();
+        let _: Option<op_ctx::OpCtx<()>> = map.write().insert(key, ctx.clone());
+        let _: bool = retry_map.add(key, desc.clone(), true, None, None, 2);
+
+        for i in 1..=2 {
+            assert!(retry_map.wait_rnr(key, Duration::ZERO));
+            context.check_timeout();
+            assert_eq!(device.0.lock().len(), i);
+            // waiting for the responder does not consume the retry count
+            assert!(matches!(ctx.status(), CtxStatus::Running));
+        }
+        assert!(!retry_map.wait_rnr(key, Duration::ZERO));
+
+        // the request is retried as long as the responder is not ready
+        let infinite_key = (Qpn::new(1), Msn::default());
+        let _: bool = retry_map.add(infinite_key, desc, true, None, None, INFINITE_RNR_RETRY);
+        for _ in 0..16 {
+            assert!(retry_map.wait_rnr(infinite_key, Duration::ZERO));
+        }
+    }
     // }
 }
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use rand::RngCore as _;
+
+use crate::cq::{CqContext, WorkCompletion, WorkCompletionOpcode, WorkCompletionStatus};
+use crate::device::{
+    ToCardCtrlRbDesc, ToCardCtrlRbDescCommon, ToCardCtrlRbDescPostRecv, ToCardCtrlRbDescSrqManagement,
+};
+use crate::op_ctx::{CtrlOpCtx, OpCtx};
+use crate::types::{Qpn, RecvCompletion, Sge};
+use crate::utils::{block_on, clear_eventfd, new_eventfd, notify_eventfd};
+use crate::{wait_ctrl_op, Device, Error, Pd};
+
+/// The SRQN is 24 bits wide in the descriptors
+const SRQN_MASK: u32 = 0x00FF_FFFF;
+
+/// Shared Receive Queue
+///
+/// A handle of a shared receive queue created by `Device::create_srq`.
+/// QPs take their receive buffers from it instead of their own receive queues once attached
+/// through `Qp::srq`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Srq {
+    pub(crate) srqn: u32,
+}
+
+impl Hash for Srq {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.srqn.hash(state);
+    }
+}
+
+impl PartialEq for Srq {
+    fn eq(&self, other: &Self) -> bool {
+        self.srqn == other.srqn
+    }
+}
+
+impl Eq for Srq {}
+
+/// A receive request posted to a shared receive queue
+#[derive(Debug)]
+struct SrqRecv {
+    wr_id: u64,
+    ctx: OpCtx<RecvCompletion>,
+}
+
+#[derive(Debug)]
+struct SrqRecvQueue {
+    /// posted receive requests indexed by their tags
+    ///
+    /// The device may consume the buffers of different QPs out of order, so a consumed buffer is
+    /// identified by the tag echoed back by the device instead of its position.
+    posted: HashMap<u32, SrqRecv>,
+    next_tag: u32,
+    /// the armed limit, the limit event fires once the number of posted requests drops below it
+    limit: Option<usize>,
+}
+
+impl SrqRecvQueue {
+    /// allocate a tag which is not in use, 0 is reserved for none
+    fn alloc_tag(&mut self) -> u32 {
+        loop {
+            let tag = self.next_tag;
+            self.next_tag = self.next_tag.wrapping_add(1);
+            if tag != 0 && !self.posted.contains_key(&tag) {
+                return tag;
+            }
+        }
+    }
+}
+
+/// SRQ context
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug)]
+pub(crate) struct SrqContext {
+    pub(crate) srqn: u32,
+    pub(crate) pd: Pd,
+    max_wr: usize,
+    recv_queue: Mutex<SrqRecvQueue>,
+    /// an eventfd which becomes readable once the limit event fires
+    event: OwnedFd,
+}
+
+impl SrqContext {
+    pub(crate) fn new(srqn: u32, pd: Pd, max_wr: usize) -> Result<Self, Error> {
+        Ok(Self {
+            srqn,
+            pd,
+            max_wr,
+            recv_queue: Mutex::new(SrqRecvQueue {
+                posted: HashMap::with_capacity(max_wr),
+                next_tag: 1,
+                limit: None,
+            }),
+            event: new_eventfd("SRQ event")?,
+        })
+    }
+
+    /// take the receive request consumed by an incoming message of `qpn`
+    ///
+    /// The completion of the request is reported to `recv_cq` of the QP.
+    pub(crate) fn take_recv(
+        &self,
+        tag: u32,
+        qpn: Qpn,
+        recv_cq: Option<&Arc<CqContext>>,
+    ) -> Option<OpCtx<RecvCompletion>> {
+        let mut recv_queue = self.recv_queue.lock();
+        let recv = recv_queue.posted.remove(&tag)?;
+        if recv_queue.limit.is_some_and(|limit| recv_queue.posted.len() < limit) {
+            recv_queue.limit = None;
+            notify_eventfd(&self.event);
+        }
+        if let Some(cq) = recv_cq {
+            recv.ctx.set_completion(
+                Arc::clone(cq),
+                WorkCompletion::new(recv.wr_id, WorkCompletionOpcode::Recv, qpn, 0),
+            );
+        }
+        Some(recv.ctx)
+    }
+
+    /// fail all the posted receive requests
+    pub(crate) fn flush(&self, cause: &'static str) {
+        for (_, recv) in self.recv_queue.lock().posted.drain() {
+            recv.ctx.set_error(cause, WorkCompletionStatus::WrFlushError);
+        }
+    }
+
+    fn arm_limit(&self, limit: usize) {
+        let mut recv_queue = self.recv_queue.lock();
+        clear_eventfd(&self.event);
+        recv_queue.limit = (limit != 0).then_some(limit);
+    }
+
+    fn management_desc(&self, op_id: u32, is_valid: bool) -> ToCardCtrlRbDesc {
+        ToCardCtrlRbDesc::SrqManagement(ToCardCtrlRbDescSrqManagement {
+            common: ToCardCtrlRbDescCommon { op_id },
+            is_valid,
+            srqn: self.srqn,
+            pd_hdl: if is_valid { self.pd.handle } else { 0 },
+        })
+    }
+}
+
+pub(crate) type SrqTable = Mutex<HashMap<Srq, Arc<SrqContext>>>;
+
+impl Device {
+    /// create a shared receive queue
+    ///
+    /// `max_wr` is the max number of receive requests that can be posted to the queue.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Pd
+    /// * `max_wr` is zero
+    /// * failed to create the event fd
+    /// * the device failed to create the queue
+    pub fn create_srq(&self, pd: Pd, max_wr: usize) -> Result<Srq, Error> {
+        block_on(self.create_srq_async(pd, max_wr))
+    }
+
+    /// create a shared receive queue without blocking the thread, see `create_srq`
+    ///
+    /// # Errors
+    ///
+    /// The same as `create_srq`
+    pub async fn create_srq_async(&self, pd: Pd, max_wr: usize) -> Result<Srq, Error> {
+        self.check_running()?;
+        if max_wr == 0 {
+            return Err(Error::Invalid("SRQ max_wr: 0".to_owned()));
+        }
+
+        let (srq, ctrl_ctx) = {
+            let mut pool = self.0.srq_table.lock();
+            let mut pd_pool = self.0.pd.lock();
+            let pd_ctx = pd_pool.get_mut(&pd).ok_or(Error::Invalid(format!("PD :{pd:?}")))?;
+            // the SRQN is drawn again on collision, the table lock keeps it unused until inserted
+            let srq = loop {
+                let srq = Srq {
+                    srqn: rand::thread_rng().next_u32() & SRQN_MASK,
+                };
+                if !pool.contains_key(&srq) {
+                    break srq;
+                }
+            };
+            let ctx = SrqContext::new(srq.srqn, pd, max_wr)?;
+            let ctrl_ctx = self.send_srq_management(&ctx, true)?;
+            let _: bool = pd_ctx.srq.insert(srq);
+            let _: Option<Arc<SrqContext>> = pool.insert(srq, Arc::new(ctx));
+            (srq, ctrl_ctx)
+        };
+
+        if let Err(e) = wait_ctrl_op(ctrl_ctx, "create srq").await {
+            let _: Option<Arc<SrqContext>> = self.remove_srq(srq);
+            return Err(e);
+        }
+        Ok(srq)
+    }
+
+    /// destroy a shared receive queue
+    ///
+    /// The receive requests still posted to the queue are flushed with `WrFlushError`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Srq
+    /// * the SRQ is still attached to a QP
+    /// * the device failed to destroy the queue
+    pub fn destroy_srq(&self, srq: Srq) -> Result<(), Error> {
+        block_on(self.destroy_srq_async(srq))
+    }
+
+    /// destroy a shared receive queue without blocking the thread, see `destroy_srq`
+    ///
+    /// # Errors
+    ///
+    /// The same as `destroy_srq`
+    pub async fn destroy_srq_async(&self, srq: Srq) -> Result<(), Error> {
+        let ctrl_ctx = {
+            let pool = self.0.srq_table.lock();
+            let ctx = pool.get(&srq).ok_or(Error::Invalid(format!("SRQ :{srq:?}")))?;
+            if Arc::strong_count(ctx) > 1 {
+                return Err(Error::Invalid(format!("SRQ in use :{srq:?}")));
+            }
+            self.send_srq_management(ctx, false)?
+        };
+
+        wait_ctrl_op(ctrl_ctx, "destroy srq").await?;
+        if let Some(ctx) = self.remove_srq(srq) {
+            ctx.flush("flushed by SRQ destroyed");
+        }
+        Ok(())
+    }
+
+    /// Post a receive request to a shared receive queue
+    ///
+    /// The request is consumed by an incoming SEND or RDMA write with immediate data of any QP
+    /// attached to the queue, and its completion is reported to the receive CQ of that QP.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Srq
+    /// * the queue already holds `max_wr` requests
+    /// * failed to send the receive request to the device
+    /// * the device failed to accept the receive request
+    pub fn post_srq_recv(&self, wr_id: u64, srq: Srq, sge: Sge) -> Result<OpCtx<RecvCompletion>, Error> {
+        block_on(self.post_srq_recv_async(wr_id, srq, sge))
+    }
+
+    /// Post a receive request to a shared receive queue without blocking the thread until the device
+    /// accepts it, see `post_srq_recv`
+    ///
+    /// # Errors
+    ///
+    /// The same as `post_srq_recv`
+    pub async fn post_srq_recv_async(&self, wr_id: u64, srq: Srq, sge: Sge) -> Result<OpCtx<RecvCompletion>, Error> {
+        self.check_running()?;
+        let srq_ctx = self.get_srq_ctx(srq)?;
+        let ctx = OpCtx::new_running();
+        let op_id = self.get_ctrl_op_id();
+        // hold the queue lock until the request is recorded, so that the tag can't be consumed meanwhile
+        let (tag, ctrl_ctx) = {
+            let mut recv_queue = srq_ctx.recv_queue.lock();
+            if recv_queue.posted.len() >= srq_ctx.max_wr {
+                return Err(Error::ResourceNoAvailable(format!("SRQ is full :{srq:?}")));
+            }
+            let tag = recv_queue.alloc_tag();
+            let desc = ToCardCtrlRbDesc::PostRecv(ToCardCtrlRbDescPostRecv {
+                common: ToCardCtrlRbDescCommon { op_id },
+                qpn: Qpn::default(),
+                sge: sge.into(),
+                srqn: Some(srq.srqn),
+                tag,
+            });
+            let ctrl_ctx = self.do_ctrl_op(op_id, desc)?;
+            let _: Option<SrqRecv> = recv_queue.posted.insert(
+                tag,
+                SrqRecv {
+                    wr_id,
+                    ctx: ctx.clone(),
+                },
+            );
+            (tag, ctrl_ctx)
+        };
+
+        if let Err(e) = wait_ctrl_op(ctrl_ctx, "post srq recv").await {
+            let _: Option<SrqRecv> = srq_ctx.recv_queue.lock().posted.remove(&tag);
+            return Err(e);
+        }
+        Ok(ctx)
+    }
+
+    /// arm the limit event of a shared receive queue
+    ///
+    /// The event fires once when the number of posted receive requests drops below `limit`,
+    /// then the queue has to be armed again. A zero `limit` disarms the queue.
+    /// Arming also clears the readiness of the event fd, see `Device::srq_event_fd`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Srq
+    /// * `limit` is larger than `max_wr` of the queue
+    pub fn arm_srq_limit(&self, srq: Srq, limit: usize) -> Result<(), Error> {
+        let ctx = self.get_srq_ctx(srq)?;
+        if limit > ctx.max_wr {
+            return Err(Error::Invalid(format!("SRQ limit :{limit}")));
+        }
+        ctx.arm_limit(limit);
+        Ok(())
+    }
+
+    /// get the event fd of a shared receive queue
+    ///
+    /// The returned fd can be registered to `epoll`. It becomes readable once the limit event
+    /// fires, and the readiness is cleared by `Device::arm_srq_limit`.
+    /// The fd is owned by the device and is closed when the SRQ is destroyed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// * invalid Srq
+    pub fn srq_event_fd(&self, srq: Srq) -> Result<RawFd, Error> {
+        let ctx = self.get_srq_ctx(srq)?;
+        Ok(ctx.event.as_raw_fd())
+    }
+
+    pub(crate) fn get_srq_ctx(&self, srq: Srq) -> Result<Arc<SrqContext>, Error> {
+        self.0
+            .srq_table
+            .lock()
+            .get(&srq)
+            .map(Arc::clone)
+            .ok_or(Error::Invalid(format!("SRQ :{srq:?}")))
+    }
+
+    /// remove the SRQ from the SRQ table and its PD
+    fn remove_srq(&self, srq: Srq) -> Option<Arc<SrqContext>> {
+        let mut pool = self.0.srq_table.lock();
+        let mut pd_pool = self.0.pd.lock();
+        let ctx = pool.remove(&srq)?;
+        if let Some(pd_ctx) = pd_pool.get_mut(&ctx.pd) {
+            let _: bool = pd_ctx.srq.remove(&srq);
+        }
+        Some(ctx)
+    }
+
+    fn send_srq_management(&self, ctx: &SrqContext, is_valid: bool) -> Result<CtrlOpCtx, Error> {
+        let op_id = self.get_ctrl_op_id();
+        let desc = ctx.management_desc(op_id, is_valid);
+        self.do_ctrl_op(op_id, desc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::AsRawFd;
+    use std::sync::Arc;
+
+    use super::SrqContext;
+    use crate::cq::CqContext;
+    use crate::op_ctx::OpCtx;
+    use crate::types::Qpn;
+    use crate::Pd;
+
+    #[test]
+    fn test_srq_take_by_tag_and_limit() {
+        let srq = SrqContext::new(1, Pd::default(), 4).unwrap();
+        let fd = srq.event.as_raw_fd();
+        let is_readable = || {
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            unsafe { libc::poll(&mut pollfd, 1, 0) == 1 }
+        };
+        let tags: Vec<u32> = (0..3)
+            .map(|wr_id| {
+                let mut recv_queue = srq.recv_queue.lock();
+                let tag = recv_queue.alloc_tag();
+                let recv = super::SrqRecv {
+                    wr_id,
+                    ctx: OpCtx::new_running(),
+                };
+                assert!(recv_queue.posted.insert(tag, recv).is_none());
+                tag
+            })
+            .collect();
+        assert!(!tags.contains(&0));
+        srq.arm_limit(2);
+
+        // consumed out of order by two QPs
+        let cq = Arc::new(CqContext::new(4, false).unwrap());
+        assert!(srq.take_recv(tags[1], Qpn::new(3), Some(&cq)).is_some());
+        assert!(srq.take_recv(tags[1], Qpn::new(3), Some(&cq)).is_none());
+        assert!(!is_readable());
+        assert!(srq.take_recv(tags[0], Qpn::new(4), None).is_some());
+        assert!(is_readable());
+
+        // the event fires only once until armed again
+        srq.arm_limit(2);
+        assert!(!is_readable());
+        assert!(srq.take_recv(tags[2], Qpn::new(4), None).is_some());
+        assert!(is_readable());
+    }
+}
@@ -1,3 +1,5 @@
 mod test_checker;
+pub(crate) mod test_fence;
 mod test_gen_response;
+mod test_on_demand;
 mod test_work_poller;
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::net::Ipv4Addr;
 use std::slice::from_raw_parts;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
@@ -11,17 +12,20 @@ use parking_lot::Mutex;
 
 use crate::buf::{PacketBuf, RDMA_ACK_BUFFER_SLOT_SIZE};
 use crate::checker::{PacketCheckEvent, PacketCheckerContext, RecvContextMap};
+use crate::cq::{CqContext, WorkCompletion, WorkCompletionOpcode, WorkCompletionStatus};
 use crate::device::layout::Aeth;
 use crate::device::{
-    ToCardCtrlRbDesc, ToCardWorkRbDesc, ToHostWorkRbDescAethCode, ToHostWorkRbDescCommon, ToHostWorkRbDescRead,
-    ToHostWorkRbDescWriteOrReadResp, ToHostWorkRbDescWriteType,
+    DescSge, ToCardCtrlRbDesc, ToCardWorkRbDesc, ToCardWorkRbDescCommon, ToCardWorkRbDescWrite, ToHostWorkRbDescAck,
+    ToHostWorkRbDescAethCode, ToHostWorkRbDescCommon, ToHostWorkRbDescRead, ToHostWorkRbDescSend,
+    ToHostWorkRbDescStatus, ToHostWorkRbDescTransType, ToHostWorkRbDescWriteOrReadResp, ToHostWorkRbDescWriteType,
+    ToHostWorkRbDescWriteWithImm,
 };
-use crate::op_ctx::CtrlOpCtx;
-use crate::qp::{QpContext, QpStatus};
+use crate::op_ctx::{CtrlOpCtx, CtxStatus, OpCtx};
+use crate::qp::{QpContext, QpState, QpStatus};
 use crate::retry::RetryMap;
-use crate::types::{Key, Msn, Pmtu, Psn, QpType, Qpn};
+use crate::types::{Key, Msn, Pmtu, Psn, QpType, Qpn, RecvCompletion};
 use crate::utils::{calculate_packet_cnt, get_first_packet_max_length};
-use crate::{CtrlDescriptorSender, WorkDescriptorSender};
+use crate::{CtrlDescriptorSender, MrConfig, WorkDescriptorSender};
 
 macro_rules! construct_context {
     ($context:ident, $device:ident, $qpn:ident = $qpn_val:expr) => {
@@ -47,6 +51,8 @@ macro_rules! construct_context {
             work_desc_sender,
             ack_buffers,
             retry_map: RetryMap::new(0, Duration::new(0, 0)),
+            mw_table: Arc::default(),
+            mr_config: MrConfig::default(),
         };
         let $qpn = Qpn::new($qpn_val);
         $context.qp_table.write().insert(
@@ -121,6 +127,389 @@ fn test_checker_on_recv_read_request() {
     }
 }
 
+#[test]
+fn test_checker_on_recv_send() {
+    construct_context!(context, device, qpn = 0x1234);
+    let first = OpCtx::new_running();
+    let second = OpCtx::new_running();
+    {
+        let qp_table = context.qp_table.read();
+        let mut recv_queue = qp_table.get(&qpn).unwrap().recv_queue.lock();
+        recv_queue.push_back(first.clone());
+        recv_queue.push_back(second.clone());
+    }
+
+    let event = PacketCheckEvent::Send(ToHostWorkRbDescSend {
+        common: ToHostWorkRbDescCommon {
+            dqpn: qpn,
+            msn: Msn::new(0x1234),
+            ..Default::default()
+        },
+        len: 0x100,
+        imm: Some(0xdead_beef),
+        can_auto_ack: true,
+        ..Default::default()
+    });
+    context.handle_check_event(event);
+    let completion = first.get_result().expect("first receive should be completed");
+    assert_eq!(completion.byte_len, 0x100);
+    assert_eq!(completion.imm.map(|imm| imm.get()), Some(0xdead_beef));
+    assert!(second.get_result().is_none());
+    assert!(device.work_pop().is_none(), "auto acked message should not send ack");
+
+    let event = PacketCheckEvent::Send(ToHostWorkRbDescSend {
+        common: ToHostWorkRbDescCommon {
+            dqpn: qpn,
+            msn: Msn::new(0x1235),
+            status: ToHostWorkRbDescStatus::InvMrRegion,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    context.handle_check_event(event);
+    assert!(matches!(second.status(), CtxStatus::Failed(_)));
+    assert!(context.qp_table.read().get(&qpn).unwrap().recv_queue.lock().is_empty());
+    // the failed receive is reported to the requester by a NAK
+    let nak = device.work_pop().expect("failed receive should send a nak");
+    assert!(matches!(check_aeth_code(&nak), Some(ToHostWorkRbDescAethCode::Nak)));
+    assert_eq!(check_aeth_value(&nak), Some(0b0_0010));
+}
+
+#[test]
+fn test_checker_on_recv_datagram() {
+    construct_context!(context, device, qpn = 0x1234);
+    let recv = OpCtx::new_running();
+    context
+        .qp_table
+        .read()
+        .get(&qpn)
+        .unwrap()
+        .recv_queue
+        .lock()
+        .push_back(recv.clone());
+
+    let src_ip = Ipv4Addr::new(192, 168, 0, 2);
+    let event = PacketCheckEvent::Send(ToHostWorkRbDescSend {
+        common: ToHostWorkRbDescCommon {
+            dqpn: qpn,
+            msn: Msn::new(0x1234),
+            trans: ToHostWorkRbDescTransType::Ud,
+            ..Default::default()
+        },
+        len: 0x40,
+        src: Some((Qpn::new(0x56), src_ip)),
+        ..Default::default()
+    });
+    context.handle_check_event(event);
+    let completion = recv.get_result().expect("receive should be completed");
+    assert_eq!(completion.byte_len, 0x40);
+    assert_eq!(completion.src_qpn, Some(Qpn::new(0x56)));
+    assert_eq!(completion.src_ip, Some(src_ip));
+    assert!(device.work_pop().is_none(), "datagram should not be acked");
+}
+
+#[test]
+fn test_checker_unreliable_connection() {
+    construct_context!(context, device, qpn = 0x1234);
+    let recv = OpCtx::new_running();
+    {
+        let mut qp_table = context.qp_table.write();
+        let qp = qp_table.get_mut(&qpn).unwrap();
+        qp.qp_type = QpType::Uc;
+        qp.recv_queue.lock().push_back(recv.clone());
+    }
+
+    // a psn gap neither triggers a nack nor makes the qp enter error status
+    let write: PacketCheckEvent = PacketWriteBuilder::create_empty()
+        .dqpn(qpn)
+        .msn(Msn::new(0x1234))
+        .psn(Psn::new(5))
+        .expected_psn(Psn::new(1))
+        .write_type(ToHostWorkRbDescWriteType::Only)
+        .addr(0x1000_u64)
+        .len(0x100_u32)
+        .can_auto_ack(false)
+        .build()
+        .unwrap()
+        .into();
+    context.handle_check_event(write);
+    assert!(context
+        .qp_table
+        .read()
+        .get(&qpn)
+        .unwrap()
+        .status
+        .load(Ordering::Acquire)
+        .is_normal());
+
+    let event = PacketCheckEvent::Send(ToHostWorkRbDescSend {
+        common: ToHostWorkRbDescCommon {
+            dqpn: qpn,
+            msn: Msn::new(0x1235),
+            trans: ToHostWorkRbDescTransType::Uc,
+            ..Default::default()
+        },
+        len: 0x40,
+        can_auto_ack: false,
+        ..Default::default()
+    });
+    context.handle_check_event(event);
+    assert_eq!(recv.get_result().expect("receive should be completed").byte_len, 0x40);
+    assert!(device.work_pop().is_none(), "UC message should not be acked");
+}
+
+#[test]
+fn test_checker_drop_before_ready_to_receive() {
+    construct_context!(context, device, qpn = 0x1234);
+    let recv = OpCtx::new_running();
+    {
+        let qp_table = context.qp_table.read();
+        let qp = qp_table.get(&qpn).unwrap();
+        qp.state.store(QpState::Init, Ordering::Release);
+        qp.recv_queue.lock().push_back(recv.clone());
+    }
+    let event = PacketCheckEvent::Send(ToHostWorkRbDescSend {
+        common: ToHostWorkRbDescCommon {
+            dqpn: qpn,
+            msn: Msn::new(0x1234),
+            ..Default::default()
+        },
+        len: 0x40,
+        ..Default::default()
+    });
+    context.handle_check_event(event);
+    assert!(recv.get_result().is_none());
+    assert!(device.work_pop().is_none(), "dropped message should not be acked");
+}
+
+#[test]
+fn test_checker_recv_completion_to_cq() {
+    construct_context!(context, device, qpn = 0x1234);
+    let cq = Arc::new(CqContext::new(16, false).unwrap());
+    for wr_id in [7, 8] {
+        let ctx = OpCtx::new_running();
+        ctx.set_completion(
+            Arc::clone(&cq),
+            WorkCompletion::new(wr_id, WorkCompletionOpcode::Recv, qpn, 0),
+        );
+        context
+            .qp_table
+            .read()
+            .get(&qpn)
+            .unwrap()
+            .recv_queue
+            .lock()
+            .push_back(ctx);
+    }
+
+    for (msn, status) in [
+        (0x1234, ToHostWorkRbDescStatus::Normal),
+        (0x1235, ToHostWorkRbDescStatus::InvMrKey),
+    ] {
+        let event = PacketCheckEvent::Send(ToHostWorkRbDescSend {
+            common: ToHostWorkRbDescCommon {
+                dqpn: qpn,
+                msn: Msn::new(msn),
+                status,
+                ..Default::default()
+            },
+            len: 0x100,
+            imm: Some(0xdead_beef),
+            can_auto_ack: true,
+            ..Default::default()
+        });
+        context.handle_check_event(event);
+    }
+    assert!(device.work_pop().is_none());
+
+    let wcs = cq.poll(16).unwrap();
+    assert_eq!(wcs.len(), 2);
+    assert_eq!(wcs[0].wr_id, 7);
+    assert_eq!(wcs[0].status, WorkCompletionStatus::Success);
+    assert_eq!(wcs[0].opcode, WorkCompletionOpcode::RecvWithImm);
+    assert_eq!(wcs[0].qpn, qpn);
+    assert_eq!(wcs[0].byte_len, 0x100);
+    assert_eq!(wcs[0].imm.map(|imm| imm.get()), Some(0xdead_beef));
+    assert_eq!(wcs[1].wr_id, 8);
+    assert_eq!(wcs[1].status, WorkCompletionStatus::LocalProtectionError);
+}
+
+#[test]
+fn test_checker_rnr_nak() {
+    construct_context!(context, device, qpn = 0x1234);
+    let cq = Arc::new(CqContext::new(16, false).unwrap());
+    let msn = Msn::new(0x10);
+    let ctx = OpCtx::new_running();
+    ctx.set_completion(
+        Arc::clone(&cq),
+        WorkCompletion::new(7, WorkCompletionOpcode::Send, qpn, 0x100),
+    );
+    let _: Option<OpCtx<()>> = context.user_op_ctx_map.write().insert((qpn, msn), ctx.clone());
+    let desc = Box::new(ToCardWorkRbDesc::Write(ToCardWorkRbDescWrite {
+        common: ToCardWorkRbDescCommon {
+            dqpn: qpn,
+            msn,
+            total_len: 0x100,
+            ..Default::default()
+        },
+        is_last: true,
+        is_first: true,
+        sge0: DescSge {
+            addr: 0x1000,
+            len: 0x100,
+            key: Key::new(0x1000),
+        },
+        sge1: None,
+        sge2: None,
+        sge3: None,
+    }));
+    let _: bool = context.retry_map.add((qpn, msn), desc, true, None, None, 1);
+
+    let rnr_nak = PacketCheckEvent::Ack(ToHostWorkRbDescAck {
+        common: ToHostWorkRbDescCommon {
+            dqpn: qpn,
+            ..Default::default()
+        },
+        msn,
+        code: ToHostWorkRbDescAethCode::Rnr,
+        value: 14,
+        ..Default::default()
+    });
+    // the request is resent by the retry monitor after the RNR timer, rather than immediately
+    context.handle_check_event(rnr_nak.clone());
+    assert!(device.work_pop().is_none());
+    assert!(matches!(ctx.status(), CtxStatus::Running));
+    assert!(cq.poll(16).unwrap().is_empty());
+
+    context.handle_check_event(rnr_nak);
+    assert!(matches!(ctx.status(), CtxStatus::Failed(_)));
+    let wcs = cq.poll(16).unwrap();
+    assert_eq!(wcs.len(), 1);
+    assert_eq!(wcs[0].wr_id, 7);
+    assert_eq!(wcs[0].status, WorkCompletionStatus::RnrRetryExceeded);
+    // the retry record is already removed
+    assert!(context.retry_map.cancel((qpn, msn)));
+}
+
+#[test]
+fn test_checker_nak_remote_error() {
+    construct_context!(context, device, qpn = 0x1234);
+    let cq = Arc::new(CqContext::new(16, false).unwrap());
+    for (msn, value, status) in [
+        (0x10, 0b0_0001, WorkCompletionStatus::RemoteInvalidRequestError),
+        (0x11, 0b0_0010, WorkCompletionStatus::RemoteAccessError),
+        (0x12, 0b0_0011, WorkCompletionStatus::RemoteOperationError),
+    ] {
+        let msn = Msn::new(msn);
+        let ctx = OpCtx::new_running();
+        ctx.set_completion(
+            Arc::clone(&cq),
+            WorkCompletion::new(7, WorkCompletionOpcode::Send, qpn, 0x100),
+        );
+        let _: Option<OpCtx<()>> = context.user_op_ctx_map.write().insert((qpn, msn), ctx.clone());
+        let desc = Box::new(ToCardWorkRbDesc::Write(ToCardWorkRbDescWrite {
+            common: ToCardWorkRbDescCommon {
+                dqpn: qpn,
+                msn,
+                total_len: 0x100,
+                ..Default::default()
+            },
+            is_last: true,
+            is_first: true,
+            sge0: DescSge {
+                addr: 0x1000,
+                len: 0x100,
+                key: Key::new(0x1000),
+            },
+            sge1: None,
+            sge2: None,
+            sge3: None,
+        }));
+        let _: bool = context.retry_map.add((qpn, msn), desc, true, None, None, 1);
+
+        context.handle_check_event(PacketCheckEvent::Ack(ToHostWorkRbDescAck {
+            common: ToHostWorkRbDescCommon {
+                dqpn: qpn,
+                ..Default::default()
+            },
+            msn,
+            code: ToHostWorkRbDescAethCode::Nak,
+            value,
+            ..Default::default()
+        }));
+        // a remote error is not retried
+        assert!(device.work_pop().is_none());
+        assert!(matches!(ctx.status(), CtxStatus::Failed(_)));
+        let wcs = cq.poll(16).unwrap();
+        assert_eq!(wcs.len(), 1);
+        assert_eq!(wcs[0].status, status);
+        // the retry record is already removed
+        assert!(context.retry_map.cancel((qpn, msn)));
+    }
+}
+
+#[test]
+fn test_checker_on_recv_write_with_imm() {
+    construct_context!(context, device, qpn = 0x1234);
+    let recv_ctxs: Vec<OpCtx<RecvCompletion>> = (0..3).map(|_| OpCtx::new_running()).collect();
+    {
+        let qp_table = context.qp_table.read();
+        let mut recv_queue = qp_table.get(&qpn).unwrap().recv_queue.lock();
+        for ctx in &recv_ctxs {
+            recv_queue.push_back(ctx.clone());
+        }
+    }
+    let write_with_imm = |msn: u16, psn: u32, write_type: ToHostWorkRbDescWriteType, imm: u32| {
+        PacketCheckEvent::WriteWithImm(ToHostWorkRbDescWriteWithImm {
+            common: ToHostWorkRbDescCommon {
+                dqpn: qpn,
+                msn: Msn::new(msn),
+                expected_psn: Psn::new(psn),
+                ..Default::default()
+            },
+            write_type,
+            psn: Psn::new(psn),
+            imm,
+            addr: 0x1000,
+            len: 0x100,
+            key: Key::default(),
+            can_auto_ack: true,
+            recv_tag: 0,
+        })
+    };
+
+    // write only with immediate
+    context.handle_check_event(write_with_imm(0x1234, 0, ToHostWorkRbDescWriteType::Only, 0xdead_beef));
+    let completion = recv_ctxs[0].get_result().expect("first receive should be completed");
+    assert_eq!(completion.byte_len, 0x100);
+    assert_eq!(completion.imm.map(|imm| imm.get()), Some(0xdead_beef));
+
+    // the retransmitted packet should not consume another receive request
+    context.handle_check_event(write_with_imm(0x1234, 0, ToHostWorkRbDescWriteType::Only, 0xdead_beef));
+    assert!(recv_ctxs[1].get_result().is_none());
+
+    // the immediate data is delivered with the last packet
+    let first: PacketCheckEvent = PacketWriteBuilder::create_empty()
+        .dqpn(qpn)
+        .msn(Msn::new(0x1235))
+        .psn(Psn::new(1))
+        .expected_psn(Psn::new(1))
+        .write_type(ToHostWorkRbDescWriteType::First)
+        .addr(0x1000_u64)
+        .len(0x100_u32)
+        .can_auto_ack(true)
+        .build()
+        .unwrap()
+        .into();
+    context.handle_check_event(first);
+    assert!(recv_ctxs[1].get_result().is_none());
+    context.handle_check_event(write_with_imm(0x1235, 2, ToHostWorkRbDescWriteType::Last, 0x1234_5678));
+    let completion = recv_ctxs[1].get_result().expect("second receive should be completed");
+    assert_eq!(completion.imm.map(|imm| imm.get()), Some(0x1234_5678));
+    assert!(recv_ctxs[2].get_result().is_none());
+    assert!(device.work_pop().is_none(), "auto acked message should not send ack");
+}
+
 #[test]
 fn test_checker_normal() {
     construct_context!(context, device, qpn = 0x1234);
@@ -740,6 +1129,16 @@ fn check_aeth_code(desc: &ToCardWorkRbDesc) -> Option<ToHostWorkRbDescAethCode>
         None
     }
 }
+
+fn check_aeth_value(desc: &ToCardWorkRbDesc) -> Option<u8> {
+    if let ToCardWorkRbDesc::WriteWithImm(ref raw) = *desc {
+        let buf = &unsafe { from_raw_parts(raw.sge0.addr as *const u8, raw.sge0.len.try_into().unwrap()) }[54..];
+        let aeth_header = Aeth(buf);
+        Some(aeth_header.get_aeth_value() as u8)
+    } else {
+        None
+    }
+}
 impl CtrlDescriptorSender for MockCtrlDescSender {
     fn send_ctrl_desc(&self, desc: ToCardCtrlRbDesc) -> Result<CtrlOpCtx, crate::Error> {
         let ctx = CtrlOpCtx::new_running();
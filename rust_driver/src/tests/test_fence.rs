@@ -0,0 +1,127 @@
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+
+use blue_rdma_device::emulator::LocalNetwork;
+use eui48::MacAddress;
+
+use crate::types::{
+    MemAccessTypeFlag, Pmtu, QpBuilder, QpType, Qpn, RdmaDeviceNetworkParam, RdmaDeviceNetworkParamBuilder, Sge,
+    WorkReqSendFlag, PAGE_SIZE,
+};
+use crate::{AlignedMemory, Device, DeviceConfigBuilder, DeviceType, Mr, RetryConfig, RoundRobinStrategy};
+
+const FENCED_LEN: usize = 4096;
+
+pub(crate) fn network_param(host: u8) -> RdmaDeviceNetworkParam {
+    RdmaDeviceNetworkParamBuilder::default()
+        .gateway(Ipv4Addr::new(10, 0, 0, 1))
+        .netmask(Ipv4Addr::new(255, 255, 255, 0))
+        .ipaddr(Ipv4Addr::new(10, 0, 0, host))
+        .macaddr(MacAddress::new([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, host]))
+        .build()
+        .unwrap()
+}
+
+/// create a device on `network` with a QP to `remote`, and register `buffer` on it,
+/// which is paged on demand if `on_demand` is set
+pub(super) fn create_device(
+    network: &Arc<LocalNetwork>,
+    local: RdmaDeviceNetworkParam,
+    remote: RdmaDeviceNetworkParam,
+    qpn: Qpn,
+    buffer: &mut AlignedMemory,
+    on_demand: bool,
+) -> (Device, Mr) {
+    let config = DeviceConfigBuilder::default()
+        .network_config(local)
+        .device_type(DeviceType::Local(Arc::clone(network)))
+        .strategy(RoundRobinStrategy::new())
+        .retry_config(RetryConfig::new(
+            false,
+            1,
+            Duration::from_secs(100),
+            Duration::from_millis(10),
+        ))
+        .scheduler_size(1024 * 32)
+        .build()
+        .unwrap();
+    let dev = Device::new(config).unwrap();
+    let pd = dev.alloc_pd().unwrap();
+    let access_flag = MemAccessTypeFlag::IbvAccessRemoteRead
+        | MemAccessTypeFlag::IbvAccessRemoteWrite
+        | MemAccessTypeFlag::IbvAccessLocalWrite;
+    let mr_flag = if on_demand {
+        access_flag | MemAccessTypeFlag::IbvAccessOnDemand
+    } else {
+        access_flag
+    };
+    let mr = dev
+        .reg_mr(
+            pd,
+            buffer.as_mut().as_mut_ptr() as u64,
+            buffer.len() as u32,
+            PAGE_SIZE as u32,
+            mr_flag,
+        )
+        .unwrap();
+    let qp = QpBuilder::default()
+        .pd(pd)
+        .qpn(qpn)
+        .peer_qpn(qpn)
+        .qp_type(QpType::Rc)
+        .rq_acc_flags(access_flag)
+        .pmtu(Pmtu::Mtu1024)
+        .dqp_ip(remote.ipaddr)
+        .dqp_mac(remote.macaddr)
+        .build()
+        .unwrap();
+    dev.create_qp(&qp).unwrap();
+    (dev, mr)
+}
+
+#[test]
+fn test_fenced_write_after_delayed_read() {
+    let network = Arc::new(LocalNetwork::default());
+    let (a_network, b_network) = (network_param(2), network_param(3));
+    let qpn = Qpn::new(2);
+    let mut buffer_a = AlignedMemory::new(FENCED_LEN).unwrap();
+    let mut buffer_b = AlignedMemory::new(FENCED_LEN * 2).unwrap();
+    let (dev_a, mr_a) = create_device(&network, a_network, b_network, qpn, &mut buffer_a, false);
+    let (dev_b, mr_b) = create_device(&network, b_network, a_network, qpn, &mut buffer_b, false);
+
+    buffer_a.as_mut().fill(0);
+    for (idx, item) in buffer_b.as_mut()[..FENCED_LEN].iter_mut().enumerate() {
+        *item = idx as u8;
+    }
+    buffer_b.as_mut()[FENCED_LEN..].fill(0);
+    let sge = Sge::new(buffer_a.as_ref().as_ptr() as u64, FENCED_LEN as u32, mr_a.get_key());
+    let raddr = buffer_b.as_ref().as_ptr() as u64;
+
+    // read the first half of B into A, then write it back to the second half of B.
+    // The read response is held, so the write would gather A before it is placed unless it is fenced.
+    network.pause();
+    let read_ctx = dev_a
+        .read(1, qpn, raddr, mr_b.get_key(), WorkReqSendFlag::IbvSendSignaled, &[sge])
+        .unwrap();
+    let write_ctx = dev_a
+        .write(
+            2,
+            qpn,
+            raddr + FENCED_LEN as u64,
+            mr_b.get_key(),
+            WorkReqSendFlag::IbvSendFence | WorkReqSendFlag::IbvSendSignaled,
+            &[sge],
+        )
+        .unwrap();
+    sleep(Duration::from_millis(50));
+    network.resume();
+
+    let _ = read_ctx.wait_result().unwrap();
+    let _ = write_ctx.wait_result().unwrap();
+    assert_eq!(buffer_b.as_ref()[..FENCED_LEN], buffer_b.as_ref()[FENCED_LEN..]);
+
+    dev_a.shutdown().unwrap();
+    dev_b.shutdown().unwrap();
+}
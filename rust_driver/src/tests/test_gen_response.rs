@@ -46,7 +46,13 @@ fn test_make_ack() {
         }
         crate::device::ToCardWorkRbDesc::Read(_)
         | crate::device::ToCardWorkRbDesc::Write(_)
-        | crate::device::ToCardWorkRbDesc::ReadResp(_) => {
+        | crate::device::ToCardWorkRbDesc::ReadResp(_)
+        | crate::device::ToCardWorkRbDesc::Send(_)
+        | crate::device::ToCardWorkRbDesc::SendWithImm(_)
+        | crate::device::ToCardWorkRbDesc::SendWithInv(_)
+        | crate::device::ToCardWorkRbDesc::CompareSwap(_)
+        | crate::device::ToCardWorkRbDesc::FetchAdd(_)
+        | crate::device::ToCardWorkRbDesc::Inline(_) => {
             panic!("Unexpected desc type");
         }
     }
@@ -99,7 +105,13 @@ fn test_make_nack() {
         }
         crate::device::ToCardWorkRbDesc::Read(_)
         | crate::device::ToCardWorkRbDesc::Write(_)
-        | crate::device::ToCardWorkRbDesc::ReadResp(_) => {
+        | crate::device::ToCardWorkRbDesc::ReadResp(_)
+        | crate::device::ToCardWorkRbDesc::Send(_)
+        | crate::device::ToCardWorkRbDesc::SendWithImm(_)
+        | crate::device::ToCardWorkRbDesc::SendWithInv(_)
+        | crate::device::ToCardWorkRbDesc::CompareSwap(_)
+        | crate::device::ToCardWorkRbDesc::FetchAdd(_)
+        | crate::device::ToCardWorkRbDesc::Inline(_) => {
             panic!("Unexpected desc type");
         }
     }
@@ -125,7 +137,7 @@ fn test_make_read_resp() {
         QpContext {
             pd: crate::Pd { handle: 1 },
             qpn,
-            sending_psn: Mutex::new(start_psn),
+            sending_psn: std::sync::Arc::new(Mutex::new(start_psn)),
             ..Default::default()
         },
     );
@@ -164,7 +176,13 @@ fn test_make_read_resp() {
         }
         crate::device::ToCardWorkRbDesc::Read(_)
         | crate::device::ToCardWorkRbDesc::Write(_)
-        | crate::device::ToCardWorkRbDesc::WriteWithImm(_) => {
+        | crate::device::ToCardWorkRbDesc::WriteWithImm(_)
+        | crate::device::ToCardWorkRbDesc::Send(_)
+        | crate::device::ToCardWorkRbDesc::SendWithImm(_)
+        | crate::device::ToCardWorkRbDesc::SendWithInv(_)
+        | crate::device::ToCardWorkRbDesc::CompareSwap(_)
+        | crate::device::ToCardWorkRbDesc::FetchAdd(_)
+        | crate::device::ToCardWorkRbDesc::Inline(_) => {
             panic!("Unexpected desc type");
         }
     }
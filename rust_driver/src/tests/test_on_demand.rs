@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use blue_rdma_device::emulator::LocalNetwork;
+
+use super::test_fence::{create_device, network_param};
+use crate::types::{Qpn, Sge, WorkReqSendFlag, PAGE_SIZE};
+use crate::AlignedMemory;
+
+const LEN: usize = 4096;
+
+#[test]
+fn test_write_and_read_on_demand_region() {
+    let network = Arc::new(LocalNetwork::default());
+    let (a_network, b_network) = (network_param(2), network_param(3));
+    let qpn = Qpn::new(2);
+    let mut buffer_a = AlignedMemory::new(LEN * 2).unwrap();
+    let mut buffer_b = AlignedMemory::new(PAGE_SIZE * 2).unwrap();
+    let (dev_a, mr_a) = create_device(&network, a_network, b_network, qpn, &mut buffer_a, false);
+    let (dev_b, mr_b) = create_device(&network, b_network, a_network, qpn, &mut buffer_b, true);
+
+    for (idx, item) in buffer_a.as_mut()[..LEN].iter_mut().enumerate() {
+        *item = idx as u8;
+    }
+    buffer_a.as_mut()[LEN..].fill(0);
+    buffer_b.as_mut().fill(0);
+    for (idx, item) in buffer_b.as_mut()[PAGE_SIZE..PAGE_SIZE + LEN].iter_mut().enumerate() {
+        *item = !(idx as u8);
+    }
+    let a_addr = buffer_a.as_ref().as_ptr() as u64;
+    let b_addr = buffer_b.as_ref().as_ptr() as u64;
+
+    // none of the pages of B is pushed to the device, so the packets of the write fault on the first page,
+    // and the read response is gathered from the second page after it faults
+    let sge = Sge::new(a_addr, LEN as u32, mr_a.get_key());
+    let write_ctx = dev_a
+        .write(1, qpn, b_addr, mr_b.get_key(), WorkReqSendFlag::IbvSendSignaled, &[sge])
+        .unwrap();
+    let _ = write_ctx.wait_result().unwrap();
+    assert_eq!(buffer_a.as_ref()[..LEN], buffer_b.as_ref()[..LEN]);
+
+    let sge = Sge::new(a_addr + LEN as u64, LEN as u32, mr_a.get_key());
+    let read_ctx = dev_a
+        .read(
+            2,
+            qpn,
+            b_addr + PAGE_SIZE as u64,
+            mr_b.get_key(),
+            WorkReqSendFlag::IbvSendSignaled,
+            &[sge],
+        )
+        .unwrap();
+    let _ = read_ctx.wait_result().unwrap();
+    assert_eq!(buffer_a.as_ref()[LEN..], buffer_b.as_ref()[PAGE_SIZE..PAGE_SIZE + LEN]);
+
+    dev_a.shutdown().unwrap();
+    dev_b.shutdown().unwrap();
+}
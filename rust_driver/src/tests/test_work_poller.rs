@@ -147,3 +147,29 @@ fn test_work_desc_poller() {
         panic!("not a ack event");
     }
 }
+
+struct IdleToHostRb;
+
+impl ToHostRb<ToHostWorkRbDesc> for IdleToHostRb {
+    fn pop(&self) -> Result<ToHostWorkRbDesc, DeviceError> {
+        sleep(std::time::Duration::from_millis(10));
+        Err(DeviceError::Timeout)
+    }
+}
+
+#[test]
+fn test_work_desc_poller_stop_when_idle() {
+    let (checker_channel, checker_recv_queue) = flume::unbounded();
+    let (nic_channel, _nic_recv_queue) = flume::unbounded();
+    let work_ctx = WorkDescPollerContext {
+        work_rb: Arc::new(IdleToHostRb),
+        checker_channel,
+        nic_channel,
+    };
+    let poller = WorkDescPoller::new(work_ctx, None);
+    // the poller keeps running on timeout, and stops once it is dropped
+    sleep(std::time::Duration::from_millis(50));
+    drop(poller);
+    assert!(checker_recv_queue.is_empty());
+    assert!(checker_recv_queue.is_disconnected());
+}
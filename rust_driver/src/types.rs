@@ -7,11 +7,16 @@ use num_enum::TryFromPrimitive;
 use serde::ser::StdError;
 use thiserror::Error;
 
-use crate::Pd;
+use crate::{Cq, Pd, Srq};
 
 /// page size is 2MB.
 pub const PAGE_SIZE: usize = 1024 * 1024 * 2;
 pub(crate) const PSN_MAX_WINDOW_SIZE: u32 = 1 << 23_i32;
+/// Max length of the data posted with `WorkReqSendFlag::IbvSendInline`, which is copied into the descriptor
+pub const MAX_INLINE_DATA: u32 = 64;
+/// Max depth of the send queue of a QP, which is less than the range of MSN so that the outstanding requests
+/// never share a MSN
+pub const MAX_SEND_WR: u32 = 1 << 15_i32;
 
 /// Type for `Imm`
 #[derive(Debug, Clone, Copy, Hash, Default)]
@@ -262,9 +267,9 @@ bitflags! {
     pub struct WorkReqSendFlag: u8 {
         /// No flags
         const IbvSendNoFlags  = 0; // Not defined in rdma-core
-        /// Send fence
+        /// Send fence, the request is not sent until the prior reads and atomics of the QP are done
         const IbvSendFence     = 1;
-        /// Send signaled
+        /// Send signaled, the request generates a completion even if `sq_sig_all` of the QP is not set
         const IbvSendSignaled  = 2;
         /// Send solicited
         const IbvSendSolicited = 4;
@@ -365,6 +370,50 @@ impl Sge {
     }
 }
 
+/// The result of a receive work request that consumed a incoming SEND message
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy)]
+pub struct RecvCompletion {
+    /// The number of bytes written into the receive buffer,
+    /// or the length of the RDMA write if it is consumed by a `RDMA write with immediate`
+    pub byte_len: u32,
+    /// The immediate data carried by `SEND with immediate` or `RDMA write with immediate`
+    pub imm: Option<Imm>,
+    /// The QPN of the sender, only reported by UD QPs
+    pub src_qpn: Option<Qpn>,
+    /// The IP address of the sender, only reported by UD QPs
+    pub src_ip: Option<Ipv4Addr>,
+    /// The memory window key invalidated by `SEND with invalidate`
+    pub invalidated_rkey: Option<Key>,
+}
+
+/// Address handle, which describes the destination of a UD SEND
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy)]
+pub struct AddressHandle {
+    /// Destination QPN
+    pub dqpn: Qpn,
+    /// Destination IP
+    pub dqp_ip: Ipv4Addr,
+    /// Destination MAC
+    pub dqp_mac: MacAddress,
+    /// Q_Key of the destination QP
+    pub qkey: u32,
+}
+
+impl AddressHandle {
+    /// Create a new `AddressHandle`
+    #[must_use]
+    pub fn new(dqpn: Qpn, dqp_ip: Ipv4Addr, dqp_mac: MacAddress, qkey: u32) -> Self {
+        Self {
+            dqpn,
+            dqp_ip,
+            dqp_mac,
+            qkey,
+        }
+    }
+}
+
 /// RDMA network param
 #[derive(Debug, Builder, Clone, Copy)]
 #[non_exhaustive]
@@ -399,6 +448,41 @@ pub struct Qp {
     pub dqp_ip: Ipv4Addr,
     /// Destination MAC
     pub dqp_mac: MacAddress,
+    /// Q_Key of a UD QP, incoming datagrams carrying other Q_Key are dropped
+    #[builder(default)]
+    pub qkey: u32,
+    /// The PSN of the first packet to be sent
+    #[builder(default)]
+    pub sq_psn: Psn,
+    /// The PSN of the first packet expected to be received
+    #[builder(default)]
+    pub rq_psn: Psn,
+    /// The CQ receiving the completions of the send queue
+    #[builder(default)]
+    pub send_cq: Option<Cq>,
+    /// The CQ receiving the completions of the receive queue
+    #[builder(default)]
+    pub recv_cq: Option<Cq>,
+    /// The SRQ the QP takes its receive buffers from, `Device::post_recv` is not allowed once set
+    #[builder(default)]
+    pub srq: Option<Srq>,
+    /// Every send request generates a completion, otherwise only the requests with `IbvSendSignaled` and
+    /// the failed ones do
+    #[builder(default = "true")]
+    pub sq_sig_all: bool,
+    /// The max number of outstanding send requests, no more than `MAX_SEND_WR`
+    #[builder(default = "MAX_SEND_WR")]
+    pub max_send_wr: u32,
+    /// Posting to a full send queue waits for a request to be done, instead of returning `Error::SendQueueFull`
+    ///
+    /// The wait parks the posting thread, so it must not be set for a QP posted to from an async task,
+    /// which waits by `Device::send_queue_ready_async` instead.
+    #[builder(default)]
+    pub sq_blocking: bool,
+    /// The QP is created in `Rts` state with the attributes above, ready to post.
+    /// Unset it to create the QP in `Reset` state and bring it up by `Device::modify_qp`, like verbs.
+    #[builder(default = "true")]
+    pub ready: bool,
 }
 
 /// Error type for RDMA user space driver library
@@ -409,9 +493,9 @@ pub enum Error {
     #[error(transparent)]
     Device(Box<dyn StdError>),
 
-    /// Device busy. Typeically ringbuffer is full
-    #[error("device busy")]
-    DeviceBusy,
+    /// The send queue of the QP has `max_send_wr` outstanding requests, or the ring buffer to the device is full
+    #[error("send queue full")]
+    SendQueueFull,
 
     /// Adaptor device return a failed status
     #[error("device return failed in : {0}")]
@@ -456,6 +540,14 @@ pub enum Error {
     /// Pipe broken
     #[error("Pipe brocken : {0}")]
     PipeBroken(&'static str),
+
+    /// The device is shut down by `Device::shutdown`
+    #[error("device is shut down")]
+    DeviceShutdown,
+
+    /// The completion queue held more entries than its depth
+    #[error("completion queue overrun")]
+    CqOverrun,
 }
 
 #[cfg(test)]
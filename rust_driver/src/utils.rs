@@ -1,13 +1,80 @@
 use std::alloc::{alloc, dealloc, Layout};
 use std::fs::File;
+use std::future::Future;
 use std::io;
 use std::ops::{Index, IndexMut};
-use std::os::fd::AsRawFd;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::pin;
 use std::slice::from_raw_parts_mut;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
 
 use log::error;
 
 use crate::types::{Pmtu, PAGE_SIZE};
+use crate::Error;
+
+/// Wake up the thread blocked in `block_on`
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run a future to completion on the current thread, which parks while the future is pending.
+///
+/// The blocking APIs are built on their async variants with it.
+pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+        // a spurious wake up only leads to another poll
+        thread::park();
+    }
+}
+
+/// Create a non-blocking eventfd, `what` describes its user in the error
+pub(crate) fn new_eventfd(what: &str) -> Result<OwnedFd, Error> {
+    // SAFETY: eventfd has no memory safety requirement, and the fd is owned by us on success
+    let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+    if fd < 0_i32 {
+        return Err(Error::ResourceNoAvailable(format!(
+            "{what}: {}",
+            io::Error::last_os_error()
+        )));
+    }
+    // SAFETY: fd is a valid eventfd just created above
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Make the eventfd readable
+pub(crate) fn notify_eventfd(fd: &OwnedFd) {
+    let value: u64 = 1;
+    // SAFETY: the buffer is a valid u64 which lives across the call
+    let ret = unsafe { libc::write(fd.as_raw_fd(), std::ptr::addr_of!(value).cast(), 8) };
+    if ret < 0 {
+        error!("Notify eventfd failed: {}", io::Error::last_os_error());
+    }
+}
+
+/// Clear the readiness of the eventfd
+pub(crate) fn clear_eventfd(fd: &OwnedFd) {
+    let mut value: u64 = 0;
+    // SAFETY: the buffer is a valid u64 which lives across the call.
+    // The fd is non-blocking, so it returns `EAGAIN` if the counter is already zero.
+    let _: isize = unsafe { libc::read(fd.as_raw_fd(), std::ptr::addr_of_mut!(value).cast(), 8) };
+}
 
 /// Get the length of the first packet.
 ///
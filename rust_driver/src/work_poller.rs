@@ -8,9 +8,7 @@ use log::{debug, error, info};
 
 use crate::buf::Slot;
 use crate::checker::PacketCheckEvent;
-use crate::device::{
-    DeviceError, ToHostRb, ToHostWorkRbDesc, ToHostWorkRbDescRaw, ToHostWorkRbDescStatus, ToHostWorkRbDescWriteWithImm,
-};
+use crate::device::{DeviceError, ToHostRb, ToHostWorkRbDesc, ToHostWorkRbDescRaw, ToHostWorkRbDescStatus};
 use crate::nic::NicRecvNotification;
 use crate::Error;
 
@@ -59,13 +57,15 @@ impl WorkDescPollerContext {
                     error!("parse descriptor failed : {:?}", e);
                     continue;
                 }
+                Err(DeviceError::Timeout) => continue,
                 Err(e) => {
                     error!("WorkDescPoller is stopped due to : {:?}", e);
                     return;
                 }
             };
             debug!("driver read from card RQ: {:?}", &desc);
-            if !matches!(desc.status(), ToHostWorkRbDescStatus::Normal) {
+            // A failed SEND still consumes a receive request, so let the checker complete it with an error.
+            if !matches!(desc.status(), ToHostWorkRbDescStatus::Normal) && !matches!(desc, ToHostWorkRbDesc::Send(_)) {
                 error!("desc status is {:?}", desc.status());
                 continue;
             }
@@ -73,7 +73,8 @@ impl WorkDescPollerContext {
             let result = match desc {
                 ToHostWorkRbDesc::Read(desc) => ctx.handle_work_desc_to_checker(desc),
                 ToHostWorkRbDesc::WriteOrReadResp(desc) => ctx.handle_work_desc_to_checker(desc),
-                ToHostWorkRbDesc::WriteWithImm(desc) => ctx.handle_work_desc_write_with_imm(&desc),
+                ToHostWorkRbDesc::WriteWithImm(desc) => ctx.handle_work_desc_to_checker(desc),
+                ToHostWorkRbDesc::Send(desc) => ctx.handle_work_desc_to_checker(desc),
                 ToHostWorkRbDesc::Ack(desc) => ctx.handle_work_desc_to_checker(desc),
                 ToHostWorkRbDesc::Raw(desc) => ctx.handle_work_desc_raw(&desc),
             };
@@ -95,10 +96,6 @@ impl WorkDescPollerContext {
             .map_err(|_| Error::PipeBroken("work polling thread to checker"))
     }
 
-    fn handle_work_desc_write_with_imm(&self, _desc: &ToHostWorkRbDescWriteWithImm) -> Result<(), Error> {
-        todo!()
-    }
-
     #[inline]
     fn handle_work_desc_raw(&self, desc: &ToHostWorkRbDescRaw) -> Result<(), Error> {
         let slot = unsafe { Slot::from_raw_parts_mut(desc.addr as *mut u8, desc.key) };
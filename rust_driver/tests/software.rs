@@ -97,7 +97,7 @@ fn test_software_write_and_read() {
     let qp_manager = QpManager::new();
     let qpn = qp_manager.alloc().unwrap();
     let (dev_a, _pd_a, mr_a, mut mr_buffer_a) = create_and_init_card(0, qpn, a_network, &b_network);
-    let (_dev_b, _pd_b, mr_b, mut mr_buffer_b) = create_and_init_card(1, qpn, b_network, &a_network);
+    let (dev_b, _pd_b, mr_b, mut mr_buffer_b) = create_and_init_card(1, qpn, b_network, &a_network);
     let dpqn = qpn;
     for (idx, item) in mr_buffer_a.as_mut().iter_mut().enumerate() {
         *item = idx as u8;
@@ -121,20 +121,22 @@ fn test_software_write_and_read() {
     // test write
     let ctx1 = dev_a
         .write(
+            1,
             dpqn,
             mr_buffer_b.as_ref().as_ptr() as usize as u64,
             mr_b.get_key(),
             WorkReqSendFlag::empty(),
-            sge0,
+            &[sge0],
         )
         .unwrap();
     let ctx2 = dev_a
         .write(
+            2,
             dpqn,
             &mr_buffer_b.as_ref()[SEND_CNT] as *const u8 as usize as u64,
             mr_b.get_key(),
             WorkReqSendFlag::empty(),
-            sge1,
+            &[sge1],
         )
         .unwrap();
 
@@ -166,13 +168,17 @@ fn test_software_write_and_read() {
 
     let ctx1 = dev_a
         .read(
+            3,
             dpqn,
             mr_buffer_b.as_ref().as_ptr() as usize as u64,
             mr_b.get_key(),
             WorkReqSendFlag::empty(),
-            sge_read,
+            &[sge_read],
         )
         .unwrap();
     let _ = ctx1.wait();
     info!("Read succ");
+
+    dev_a.shutdown().unwrap();
+    dev_b.shutdown().unwrap();
 }